@@ -0,0 +1,254 @@
+// Build script for the hackrf crate
+// Licensed under MIT license
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    // With dlopen, libhackrf is loaded at runtime rather than linked.
+    if cfg!(feature = "dlopen") && STATIC {
+        panic!("the dlopen and static features cannot be used together");
+    }
+    // docs.rs has no libhackrf, and documenting needs no linking.
+    println!("cargo:rerun-if-env-changed=DOCS_RS");
+    let include_dirs = if cfg!(feature = "dlopen") || env::var_os("DOCS_RS").is_some() {
+        Vec::new()
+    } else {
+        find_library()
+    };
+    #[cfg(feature = "bundle-dll")]
+    bundle_dll();
+    #[cfg(feature = "bindgen")]
+    bindings::generate(&include_dirs);
+    #[cfg(not(feature = "bindgen"))]
+    let _ = include_dirs;
+}
+
+// Whether to link libhackrf, and with `static-libusb` libusb, statically.
+const STATIC: bool = cfg!(feature = "static");
+const STATIC_LIBUSB: bool = cfg!(feature = "static-libusb");
+
+/// Tell rustc how to link libhackrf and return the directories holding its
+/// headers. `HACKRF_LIB_DIR` overrides discovery; otherwise pkg-config is
+/// used, or vcpkg on Windows with MSVC. If discovery fails, libhackrf is
+/// linked by name, so that `cargo check` and `cargo doc` still work and
+/// only the final link fails.
+fn find_library() -> Vec<PathBuf> {
+    println!("cargo:rerun-if-env-changed=HACKRF_LIB_DIR");
+    if let Some(dir) = env::var_os("HACKRF_LIB_DIR") {
+        let dir = PathBuf::from(dir);
+        println!("cargo:rustc-link-search=native={}", dir.display());
+        if STATIC {
+            println!("cargo:rustc-link-lib=static={}", link_name(&dir));
+            link_libusb();
+        } else {
+            println!("cargo:rustc-link-lib={}", link_name(&dir));
+        }
+        return Vec::new();
+    }
+    // The target's, not this build script's, as when cross-compiling.
+    let msvc = env::var("CARGO_CFG_TARGET_ENV").is_ok_and(|env| env == "msvc");
+    if msvc {
+        // vcpkg links statically, libusb included, when the static CRT is.
+        let crt_static = env::var("CARGO_CFG_TARGET_FEATURE")
+            .is_ok_and(|features| features.split(',').any(|f| f == "crt-static"));
+        if STATIC && !crt_static {
+            panic!("the static feature needs the static CRT on MSVC: build with RUSTFLAGS=\"-C target-feature=+crt-static\"");
+        }
+        match vcpkg::find_package("hackrf") {
+            Ok(lib) => return lib.include_paths,
+            Err(err) => {
+                fail(&format!("vcpkg could not find hackrf ({})", err));
+                println!("cargo:rustc-link-lib=hackrf");
+                return Vec::new();
+            }
+        }
+    }
+    // pkg-config only links statically outside the system directories,
+    // so in static builds the library is located here but linked below.
+    match pkg_config::Config::new()
+        .cargo_metadata(!STATIC)
+        .probe("libhackrf")
+    {
+        Ok(lib) => {
+            if STATIC {
+                for dir in &lib.link_paths {
+                    println!("cargo:rustc-link-search=native={}", dir.display());
+                }
+                println!("cargo:rustc-link-lib=static=hackrf");
+                link_libusb();
+            }
+            lib.include_paths
+        }
+        Err(err) => {
+            fail(&format!("pkg-config could not find libhackrf ({})", err));
+            println!("cargo:rustc-link-lib=hackrf");
+            Vec::new()
+        }
+    }
+}
+
+/// Link libusb, which a static libhackrf leaves unresolved: statically,
+/// with what it in turn needs, under `static-libusb`, else dynamically.
+fn link_libusb() {
+    let windows = env::var("CARGO_CFG_TARGET_OS").is_ok_and(|os| os == "windows");
+    let name = if windows { "libusb-1.0" } else { "usb-1.0" };
+    if !STATIC_LIBUSB {
+        println!("cargo:rustc-link-lib={}", name);
+        return;
+    }
+    match pkg_config::Config::new()
+        .statik(true)
+        .cargo_metadata(false)
+        .probe("libusb-1.0")
+    {
+        Ok(lib) => {
+            for dir in &lib.link_paths {
+                println!("cargo:rustc-link-search=native={}", dir.display());
+            }
+            for lib_name in &lib.libs {
+                if lib_name == name {
+                    println!("cargo:rustc-link-lib=static={}", lib_name);
+                } else {
+                    println!("cargo:rustc-link-lib={}", lib_name);
+                }
+            }
+            for framework in &lib.frameworks {
+                println!("cargo:rustc-link-lib=framework={}", framework);
+            }
+        }
+        Err(err) => {
+            fail(&format!("pkg-config could not find libusb-1.0 ({})", err));
+            println!("cargo:rustc-link-lib={}", name);
+        }
+    }
+}
+
+/// The name to link libhackrf by from `dir`. MSVC builds of libhackrf name
+/// the import library either hackrf.lib or, from some CMake setups,
+/// libhackrf.lib, and the static library hackrf_static.lib; the MSVC
+/// linker takes the name as given.
+fn link_name(dir: &Path) -> &'static str {
+    let msvc = env::var("CARGO_CFG_TARGET_ENV").is_ok_and(|env| env == "msvc");
+    let candidates: &[&'static str] = match (msvc, STATIC) {
+        (false, _) => &["hackrf"],
+        (true, false) => &["hackrf", "libhackrf"],
+        (true, true) => &["hackrf_static", "hackrf", "libhackrf"],
+    };
+    candidates
+        .iter()
+        .find(|name| dir.join(format!("{}.lib", name)).exists())
+        .unwrap_or(&candidates[0])
+}
+
+/// Copy hackrf.dll and the DLLs it depends on next to the binaries being
+/// built, so they run without libhackrf on the PATH. The DLLs are looked
+/// for in `HACKRF_DLL_DIR`, else in `HACKRF_LIB_DIR` and its sibling bin
+/// directory.
+#[cfg(feature = "bundle-dll")]
+fn bundle_dll() {
+    const DLLS: [&str; 3] = ["hackrf.dll", "libusb-1.0.dll", "pthreadVC2.dll"];
+    println!("cargo:rerun-if-env-changed=HACKRF_DLL_DIR");
+    if env::var("CARGO_CFG_TARGET_OS").map_or(true, |os| os != "windows") {
+        return;
+    }
+    let lib_dir = env::var_os("HACKRF_LIB_DIR").map(PathBuf::from);
+    let dll_dir = env::var_os("HACKRF_DLL_DIR")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(lib_dir.iter().cloned())
+        .chain(lib_dir.iter().filter_map(|dir| Some(dir.parent()?.join("bin"))))
+        .find(|dir| dir.join(DLLS[0]).exists())
+        .unwrap_or_else(|| {
+            panic!("bundle-dll: hackrf.dll not found: set HACKRF_DLL_DIR to the directory containing it")
+        });
+    // OUT_DIR is <target>/<profile>/build/<crate>-<hash>/out.
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let profile_dir = out.ancestors().nth(3).unwrap();
+    for dll in DLLS.iter() {
+        let from = dll_dir.join(dll);
+        if from.exists() {
+            println!("cargo:rerun-if-changed={}", from.display());
+            std::fs::copy(&from, profile_dir.join(dll))
+                .unwrap_or_else(|err| panic!("copying {}: {}", from.display(), err));
+        }
+    }
+}
+
+// Explain a failed discovery as build warnings, leaving the linker to
+// report the missing library.
+fn fail(reason: &str) {
+    let help = [
+        "Install libhackrf with its development files, for example:",
+        "    Debian/Ubuntu: apt install libhackrf-dev pkg-config",
+        "    Fedora:        dnf install hackrf-devel pkgconf",
+        "    Arch:          pacman -S hackrf",
+        "    macOS:         brew install hackrf pkg-config",
+        "    Windows:       vcpkg install hackrf",
+        "or set HACKRF_LIB_DIR to the directory containing the library.",
+    ];
+    // pkg-config's errors run over several lines.
+    for line in reason.lines().chain(help.iter().copied()) {
+        println!("cargo:warning={}", line);
+    }
+}
+
+/// Raw bindings regenerated from the installed hackrf.h, for `hackrf::sys`.
+#[cfg(feature = "bindgen")]
+mod bindings {
+    use std::env;
+    use std::path::PathBuf;
+
+    // Where distributions and package managers install the header, should
+    // library discovery not report it.
+    const INCLUDE_DIRS: [&str; 4] = [
+        "/usr/include/libhackrf",
+        "/usr/local/include/libhackrf",
+        "/opt/homebrew/include/libhackrf",
+        "/opt/local/include/libhackrf",
+    ];
+
+    fn find_header(include_dirs: &[PathBuf]) -> PathBuf {
+        println!("cargo:rerun-if-env-changed=HACKRF_INCLUDE_DIR");
+        env::var_os("HACKRF_INCLUDE_DIR")
+            .map(PathBuf::from)
+            .into_iter()
+            .chain(include_dirs.iter().cloned())
+            .chain(include_dirs.iter().map(|dir| dir.join("libhackrf")))
+            .chain(INCLUDE_DIRS.iter().map(PathBuf::from))
+            .map(|dir| dir.join("hackrf.h"))
+            .find(|header| header.exists())
+            .unwrap_or_else(|| {
+                panic!(
+                    "hackrf.h not found: install libhackrf's development headers \
+                     or set HACKRF_INCLUDE_DIR to the directory containing it"
+                )
+            })
+    }
+
+    pub fn generate(include_dirs: &[PathBuf]) {
+        let header = find_header(include_dirs);
+        println!("cargo:rerun-if-changed={}", header.display());
+        let bindings = bindgen::Builder::default()
+            .header(header.to_string_lossy())
+            .clang_args(
+                include_dirs
+                    .iter()
+                    .map(|dir| format!("-I{}", dir.display())),
+            )
+            .allowlist_function("hackrf_.*")
+            .allowlist_type("hackrf_.*|rf_path_filter|operacake_.*|sweep_style")
+            .allowlist_var(
+                "HACKRF_.*|BOARD_ID_.*|USB_BOARD_ID_.*|RF_PATH_FILTER_.*|OPERACAKE_.*|\
+                 SAMPLES_PER_BLOCK|BYTES_PER_BLOCK|MAX_SWEEP_RANGES",
+            )
+            // Name enum constants as in C, e.g. HACKRF_ERROR_BUSY.
+            .prepend_enum_name(false)
+            .generate()
+            .unwrap_or_else(|err| panic!("generating bindings for {}: {}", header.display(), err));
+        let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("hackrf_sys.rs");
+        bindings
+            .write_to_file(&out)
+            .unwrap_or_else(|err| panic!("writing {}: {}", out.display(), err));
+    }
+}
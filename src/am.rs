@@ -0,0 +1,113 @@
+// AM and SSB demodulation to audio
+// Licensed under MIT license
+
+use std::f64::consts::PI;
+
+use super::decimate::lowpass;
+
+// Time constant of the AM carrier level tracking.
+const CARRIER_TAU_S: f64 = 0.1;
+// Lowest audio frequency an SSB demodulator passes.
+const SSB_LOW_CUT_HZ: f64 = 300.0;
+// Longest filter an SSB demodulator uses.
+const MAX_SSB_TAPS: usize = 1023;
+
+/// Demodulates AM by envelope detection, taking I/Q pairs, such as from
+/// `Decimator::process_f32`, at a rate a few times the signal's bandwidth.
+/// The audio is normalised to the carrier level, so 100% modulation gives
+/// +-1.0 whatever the signal strength.
+pub struct AmDemodulator {
+    alpha: f32,
+    carrier: f32,
+}
+
+impl AmDemodulator {
+    /// Demodulate samples at `sample_rate`.
+    pub fn new(sample_rate: f64) -> AmDemodulator {
+        AmDemodulator {
+            alpha: (1.0 - (-1.0 / (CARRIER_TAU_S * sample_rate)).exp()) as f32,
+            carrier: 0.0,
+        }
+    }
+
+    /// Demodulate `iq`, appending audio samples, at the same rate, to
+    /// `audio`.
+    pub fn process(&mut self, iq: &[[f32; 2]], audio: &mut Vec<f32>) {
+        for &[i, q] in iq {
+            let envelope = (i * i + q * q).sqrt();
+            self.carrier += self.alpha * (envelope - self.carrier);
+            audio.push(if self.carrier > 0.0 {
+                (envelope - self.carrier) / self.carrier
+            } else {
+                0.0
+            });
+        }
+    }
+}
+
+/// Which sideband an `SsbDemodulator` receives.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Sideband {
+    Upper,
+    Lower,
+}
+
+/// Demodulates SSB by the filter method: a complex bandpass filter keeps
+/// audio frequencies on one side of the carrier, which must be at the centre
+/// of the band, and rejects the other, leaving the audio as the real part.
+/// Takes I/Q pairs, such as from `Decimator::process_f32`, at a rate a few
+/// times the bandwidth.
+pub struct SsbDemodulator {
+    // Complex filter taps, in the order of the samples they weight.
+    taps: Vec<[f32; 2]>,
+    history: Vec<[f32; 2]>,
+}
+
+impl SsbDemodulator {
+    /// Demodulate `sideband` at `sample_rate`, passing audio from 300Hz to
+    /// `bandwidth_hz`, such as 2700Hz for voice.
+    pub fn new(sideband: Sideband, sample_rate: f64, bandwidth_hz: f64) -> SsbDemodulator {
+        assert!(bandwidth_hz > SSB_LOW_CUT_HZ && bandwidth_hz < sample_rate / 2.0);
+        let width = bandwidth_hz - SSB_LOW_CUT_HZ;
+        let count = ((8.0 * sample_rate / width) as usize | 1).min(MAX_SSB_TAPS);
+        let centre = match sideband {
+            Sideband::Upper => (SSB_LOW_CUT_HZ + bandwidth_hz) / 2.0,
+            Sideband::Lower => -(SSB_LOW_CUT_HZ + bandwidth_hz) / 2.0,
+        };
+        // Shift a lowpass filter up or down to the sideband.
+        let middle = (count - 1) as f64 / 2.0;
+        let step = 2.0 * PI * centre / sample_rate;
+        let taps = lowpass(count, width / 2.0 / sample_rate)
+            .into_iter()
+            .enumerate()
+            .rev()
+            .map(|(n, tap)| {
+                let angle = step * (n as f64 - middle);
+                [tap * angle.cos() as f32, tap * angle.sin() as f32]
+            })
+            .collect();
+        SsbDemodulator {
+            taps,
+            history: vec![[0.0; 2]; count - 1],
+        }
+    }
+
+    /// Demodulate `iq`, appending audio samples, at the same rate, to
+    /// `audio`.
+    pub fn process(&mut self, iq: &[[f32; 2]], audio: &mut Vec<f32>) {
+        self.history.extend_from_slice(iq);
+        let n = self.taps.len();
+        for window in self.history.windows(n) {
+            // Real part of the filtered sample.
+            let sample: f32 = self
+                .taps
+                .iter()
+                .zip(window)
+                .map(|(tap, iq)| tap[0] * iq[0] - tap[1] * iq[1])
+                .sum();
+            audio.push(sample);
+        }
+        let consumed = self.history.len() - (n - 1);
+        self.history.drain(..consumed);
+    }
+}
@@ -0,0 +1,116 @@
+// The baseband filter bandwidths the MAX2837 supports
+// Licensed under MIT license
+
+/// A bandwidth the MAX2837 transceiver's baseband filter can be set to.
+/// `set_baseband_filter_bandwidth` takes any value in Hz and libhackrf
+/// rounds it to one of these.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BasebandFilterBandwidth {
+    Mhz1_75,
+    Mhz2_5,
+    Mhz3_5,
+    Mhz5,
+    Mhz5_5,
+    Mhz6,
+    Mhz7,
+    Mhz8,
+    Mhz9,
+    Mhz10,
+    Mhz12,
+    Mhz14,
+    Mhz15,
+    Mhz20,
+    Mhz24,
+    Mhz28,
+}
+
+/// Which way `BasebandFilterBandwidth::closest` rounds a bandwidth that is
+/// not one of the filter's.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RoundPolicy {
+    Nearest,
+    /// The widest bandwidth no wider than asked for, or the narrowest.
+    Down,
+    /// The narrowest bandwidth no narrower than asked for, or the widest.
+    Up,
+}
+
+impl BasebandFilterBandwidth {
+    /// Every bandwidth, narrowest first.
+    pub const ALL: [BasebandFilterBandwidth; 16] = [
+        BasebandFilterBandwidth::Mhz1_75,
+        BasebandFilterBandwidth::Mhz2_5,
+        BasebandFilterBandwidth::Mhz3_5,
+        BasebandFilterBandwidth::Mhz5,
+        BasebandFilterBandwidth::Mhz5_5,
+        BasebandFilterBandwidth::Mhz6,
+        BasebandFilterBandwidth::Mhz7,
+        BasebandFilterBandwidth::Mhz8,
+        BasebandFilterBandwidth::Mhz9,
+        BasebandFilterBandwidth::Mhz10,
+        BasebandFilterBandwidth::Mhz12,
+        BasebandFilterBandwidth::Mhz14,
+        BasebandFilterBandwidth::Mhz15,
+        BasebandFilterBandwidth::Mhz20,
+        BasebandFilterBandwidth::Mhz24,
+        BasebandFilterBandwidth::Mhz28,
+    ];
+
+    pub fn hz(self) -> u32 {
+        match self {
+            BasebandFilterBandwidth::Mhz1_75 => 1_750_000,
+            BasebandFilterBandwidth::Mhz2_5 => 2_500_000,
+            BasebandFilterBandwidth::Mhz3_5 => 3_500_000,
+            BasebandFilterBandwidth::Mhz5 => 5_000_000,
+            BasebandFilterBandwidth::Mhz5_5 => 5_500_000,
+            BasebandFilterBandwidth::Mhz6 => 6_000_000,
+            BasebandFilterBandwidth::Mhz7 => 7_000_000,
+            BasebandFilterBandwidth::Mhz8 => 8_000_000,
+            BasebandFilterBandwidth::Mhz9 => 9_000_000,
+            BasebandFilterBandwidth::Mhz10 => 10_000_000,
+            BasebandFilterBandwidth::Mhz12 => 12_000_000,
+            BasebandFilterBandwidth::Mhz14 => 14_000_000,
+            BasebandFilterBandwidth::Mhz15 => 15_000_000,
+            BasebandFilterBandwidth::Mhz20 => 20_000_000,
+            BasebandFilterBandwidth::Mhz24 => 24_000_000,
+            BasebandFilterBandwidth::Mhz28 => 28_000_000,
+        }
+    }
+
+    /// The bandwidth closest to `bandwidth_hz` in the direction `policy`
+    /// gives. Ties in `Nearest` go to the wider.
+    pub fn closest(bandwidth_hz: u32, policy: RoundPolicy) -> BasebandFilterBandwidth {
+        let all = BasebandFilterBandwidth::ALL;
+        match policy {
+            RoundPolicy::Down => all
+                .iter()
+                .rev()
+                .find(|bw| bw.hz() <= bandwidth_hz)
+                .copied()
+                .unwrap_or(all[0]),
+            RoundPolicy::Up => all
+                .iter()
+                .find(|bw| bw.hz() >= bandwidth_hz)
+                .copied()
+                .unwrap_or(all[all.len() - 1]),
+            RoundPolicy::Nearest => all
+                .iter()
+                .rev()
+                .min_by_key(|bw| (bw.hz() as i64 - bandwidth_hz as i64).abs())
+                .copied()
+                .unwrap_or(all[0]),
+        }
+    }
+
+    /// The bandwidth libhackrf sets along with a sample rate: the widest at
+    /// or below three quarters of it.
+    pub fn for_sample_rate(sample_rate: f64) -> BasebandFilterBandwidth {
+        BasebandFilterBandwidth::closest((sample_rate * 0.75) as u32, RoundPolicy::Down)
+    }
+}
+
+impl From<BasebandFilterBandwidth> for u32 {
+    fn from(bandwidth: BasebandFilterBandwidth) -> u32 {
+        bandwidth.hz()
+    }
+}
@@ -0,0 +1,65 @@
+// Delivering received transfers to callbacks in batches
+// Licensed under MIT license
+
+use std::time::{Duration, Instant};
+
+use super::sdr::RxCallback;
+use super::{start_rx_owned, HackRFDevice, HackRFError};
+
+/// Gathers received transfers into batches, so that a callback with a
+/// high fixed cost per call, such as handing work to a GPU or another
+/// thread, runs a few times a second instead of 150 times at 20Msps. A
+/// batch is delivered as one contiguous buffer once it holds `transfers`
+/// transfers, or once the oldest in it has waited `max_latency`, whichever
+/// comes first. The latency is checked as each transfer arrives, so a batch
+/// can wait up to a transfer's duration longer if the stream slows, and a
+/// partial batch is discarded when streaming stops.
+pub struct Batcher {
+    transfers: usize,
+    max_latency: Duration,
+}
+
+impl Batcher {
+    pub fn new(transfers: usize, max_latency: Duration) -> Batcher {
+        assert!(transfers > 0);
+        Batcher {
+            transfers,
+            max_latency,
+        }
+    }
+
+    /// Wrap `callback` into an RX callback that receives batches, for use
+    /// with `RxChannel::start_rx` and the adapters built on it.
+    pub fn wrap(self, mut callback: RxCallback) -> RxCallback {
+        let mut batch = Vec::new();
+        let mut count = 0;
+        let mut oldest = Instant::now();
+        Box::new(move |rx: &[u8]| -> bool {
+            if count == 0 {
+                oldest = Instant::now();
+                batch.reserve(rx.len() * self.transfers);
+            }
+            batch.extend_from_slice(rx);
+            count += 1;
+            if count < self.transfers && oldest.elapsed() < self.max_latency {
+                return true;
+            }
+            let more = callback(&batch);
+            batch.clear();
+            count = 0;
+            more
+        })
+    }
+}
+
+/// Receive with `callback` called on batches of up to `transfers` USB
+/// transfers, delivered no later than `max_latency` after the first in
+/// each arrived. See `Batcher`. Stop with `stop_rx`.
+pub fn start_rx_batched(
+    device: &mut HackRFDevice,
+    transfers: usize,
+    max_latency: Duration,
+    callback: RxCallback,
+) -> Result<(), HackRFError> {
+    start_rx_owned(device, Batcher::new(transfers, max_latency).wrap(callback))
+}
@@ -0,0 +1,85 @@
+// hackrf_clock equivalent built on the hackrf crate
+// Licensed under MIT license
+
+extern crate hackrf;
+
+use std::process;
+
+const USAGE: &str = "Usage: hackrf-clock-rs [options]
+    -i            Show whether a reference clock is present on CLKIN
+    -o <0|1>      Disable/enable the 10MHz reference on CLKOUT
+    -d <serial>   Open the board with this serial number
+
+With no options, shows the CLKIN status.";
+
+struct Args {
+    clkin: bool,
+    clkout: Option<bool>,
+    serial: Option<String>,
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("hackrf-clock-rs: {}", msg);
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        clkin: false,
+        clkout: None,
+        serial: None,
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(flag) = argv.next() {
+        match flag.as_str() {
+            "-i" => args.clkin = true,
+            "-o" => {
+                args.clkout = match argv.next().as_deref() {
+                    Some("0") => Some(false),
+                    Some("1") => Some(true),
+                    _ => fail("-o needs 0 or 1"),
+                }
+            }
+            "-d" => args.serial = Some(argv.next().unwrap_or_else(|| fail("-d needs a serial"))),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            other => fail(&format!("unknown option {}", other)),
+        }
+    }
+    if args.clkout.is_none() {
+        args.clkin = true;
+    }
+    args
+}
+
+fn run(args: Args) -> Result<(), hackrf::HackRFError> {
+    hackrf::init()?;
+    let mut device = match args.serial {
+        Some(ref serial) => hackrf::open_by_serial(serial)?,
+        None => hackrf::open()?,
+    };
+    if let Some(on) = args.clkout {
+        hackrf::set_clkout_enable(&mut device, on)?;
+        println!("CLKOUT {}", if on { "enabled" } else { "disabled" });
+    }
+    if args.clkin {
+        if hackrf::clkin_status(&mut device)? {
+            println!("CLKIN status: clock signal detected");
+        } else {
+            println!("CLKIN status: no clock signal detected");
+        }
+    }
+    hackrf::close(device)?;
+    hackrf::exit()
+}
+
+fn main() {
+    let args = parse_args();
+    if let Err(err) = run(args) {
+        eprintln!("hackrf-clock-rs: {:?}", err);
+        process::exit(1);
+    }
+}
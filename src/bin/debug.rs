@@ -0,0 +1,130 @@
+// hackrf_debug equivalent built on the hackrf crate
+// Licensed under MIT license
+
+extern crate hackrf;
+
+use std::process;
+
+use hackrf::debug::{self, Chip};
+
+const USAGE: &str = "Usage: hackrf-debug-rs <chip> [options]
+    -m            MAX2837 transceiver
+    -s            Si5351C clock generator
+    -f            RFFC5071 mixer
+    -n <reg>      Register number (default: all registers)
+    -r            Read register(s) (default)
+    -w <value>    Write <value> to the register given by -n
+    -d <serial>   Open the board with this serial number
+
+Numbers may be given in decimal or as 0x-prefixed hex.";
+
+struct Args {
+    chip: Option<Chip>,
+    register: Option<u16>,
+    write: Option<u16>,
+    serial: Option<String>,
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("hackrf-debug-rs: {}", msg);
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn number(flag: &str, value: Option<String>) -> u16 {
+    let value = value.unwrap_or_else(|| fail(&format!("{} needs a value", flag)));
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.unwrap_or_else(|_| fail(&format!("invalid value for {}: {}", flag, value)))
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        chip: None,
+        register: None,
+        write: None,
+        serial: None,
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(flag) = argv.next() {
+        match flag.as_str() {
+            "-m" => args.chip = Some(Chip::Max2837),
+            "-s" => args.chip = Some(Chip::Si5351C),
+            "-f" => args.chip = Some(Chip::Rffc5071),
+            "-n" => args.register = Some(number("-n", argv.next())),
+            "-r" => args.write = None,
+            "-w" => args.write = Some(number("-w", argv.next())),
+            "-d" => args.serial = Some(argv.next().unwrap_or_else(|| fail("-d needs a serial"))),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            other => fail(&format!("unknown option {}", other)),
+        }
+    }
+    let chip = args
+        .chip
+        .unwrap_or_else(|| fail("choose a chip with -m, -s or -f"));
+    if let Some(register) = args.register {
+        if register >= chip.register_count() {
+            fail(&format!(
+                "{:?} only has registers 0-{}",
+                chip,
+                chip.register_count() - 1
+            ));
+        }
+    }
+    if let Some(value) = args.write {
+        if args.register.is_none() {
+            fail("-w needs a register number given with -n");
+        }
+        if value > chip.max_value() {
+            fail(&format!(
+                "{:?} registers only hold values up to 0x{:x}",
+                chip,
+                chip.max_value()
+            ));
+        }
+    }
+    args
+}
+
+fn print_register(chip: Chip, register: u16, value: u16) {
+    match chip {
+        Chip::Si5351C => println!("[{:3}] -> 0x{:02x}", register, value),
+        _ => println!("[{:2}] -> 0x{:04x}", register, value),
+    }
+}
+
+fn run(args: Args) -> Result<(), hackrf::HackRFError> {
+    let chip = args.chip.unwrap();
+    hackrf::init()?;
+    let mut device = match args.serial {
+        Some(ref serial) => hackrf::open_by_serial(serial)?,
+        None => hackrf::open()?,
+    };
+    match (args.register, args.write) {
+        (Some(register), Some(value)) => debug::register_write(&mut device, chip, register, value)?,
+        (Some(register), None) => {
+            let value = debug::register_read(&mut device, chip, register)?;
+            print_register(chip, register, value);
+        }
+        (None, _) => {
+            for (register, &value) in debug::register_dump(&mut device, chip)?.iter().enumerate() {
+                print_register(chip, register as u16, value);
+            }
+        }
+    }
+    hackrf::close(device)?;
+    hackrf::exit()
+}
+
+fn main() {
+    let args = parse_args();
+    if let Err(err) = run(args) {
+        eprintln!("hackrf-debug-rs: {:?}", err);
+        process::exit(1);
+    }
+}
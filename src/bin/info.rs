@@ -0,0 +1,70 @@
+// hackrf_info equivalent built on the hackrf crate
+// Licensed under MIT license
+
+extern crate hackrf;
+
+use std::process;
+
+fn print_device(index: usize, info: &hackrf::DeviceInfo) -> Result<(), hackrf::HackRFError> {
+    println!("Found {}", info.usb_board_name);
+    println!("Index: {}", index);
+    println!("Serial number: {}", info.serial);
+
+    let mut device = hackrf::open_by_serial(&info.serial)?;
+    let (id, name) = hackrf::board_id_read(&mut device)?;
+    println!("Board ID Number: {} ({})", id, name);
+    println!(
+        "Firmware Version: {}",
+        hackrf::version_string_read(&mut device)?
+    );
+    let (part_id, _) = hackrf::board_partid_serialno_read(&mut device)?;
+    println!("Part ID Number: 0x{:08x} 0x{:08x}", part_id[0], part_id[1]);
+
+    // Older boards and firmware can't report these, which isn't an error.
+    if let Ok((_, rev)) = hackrf::board_rev_read(&mut device) {
+        println!("Hardware Revision: {}", rev);
+    }
+    if let Ok(platforms) = hackrf::supported_platform_read(&mut device) {
+        println!("Hardware supported by installed firmware:");
+        let names = [
+            (hackrf::PLATFORM_JAWBREAKER, "Jawbreaker"),
+            (hackrf::PLATFORM_RAD1O, "rad1o"),
+            (
+                hackrf::PLATFORM_HACKRF1_OG | hackrf::PLATFORM_HACKRF1_R9,
+                "HackRF One",
+            ),
+        ];
+        for &(mask, name) in names.iter() {
+            if platforms & mask != 0 {
+                println!("    {}", name);
+            }
+        }
+    }
+    hackrf::close(device)
+}
+
+fn run() -> Result<(), hackrf::HackRFError> {
+    hackrf::init()?;
+    println!("hackrf-info-rs");
+    let (version, release) = hackrf::library_version();
+    println!("libhackrf version: {} ({})", release, version);
+
+    let devices = hackrf::device_list()?;
+    if devices.is_empty() {
+        println!("No HackRF boards found.");
+    }
+    for (index, info) in devices.iter().enumerate() {
+        println!();
+        if let Err(err) = print_device(index, info) {
+            eprintln!("hackrf-info-rs: {:?}", err);
+        }
+    }
+    hackrf::exit()
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("hackrf-info-rs: {:?}", err);
+        process::exit(1);
+    }
+}
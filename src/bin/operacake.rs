@@ -0,0 +1,196 @@
+// hackrf_operacake equivalent built on the hackrf crate
+// Licensed under MIT license
+
+extern crate hackrf;
+
+use std::process;
+
+use hackrf::operacake::{self, Mode, Port};
+
+const USAGE: &str = "Usage: hackrf-operacake-rs [options]
+    -l                     List attached Opera Cake boards
+    -o <address>           Opera Cake to configure (default 0)
+    -m <mode>              Switching mode: manual, frequency or time
+    -a <port>              Port to connect to A in manual mode (default A1)
+    -b <port>              Port to connect to B in manual mode (default B1)
+    -f <port:min:max>      Frequency mode range in MHz; may be repeated
+    -t <port:dwell>        Time mode dwell in samples; may be repeated
+    -w <dwell>             Default dwell for -t entries without one
+    -g                     Run the GPIO self test
+    -d <serial>            Open the board with this serial number
+
+Ports are A1-A4 and B1-B4.";
+
+struct Args {
+    list: bool,
+    address: u8,
+    mode: Option<Mode>,
+    port_a: Option<Port>,
+    port_b: Option<Port>,
+    freq_ranges: Vec<(u16, u16, Port)>,
+    dwell_times: Vec<(Option<u32>, Port)>,
+    default_dwell: Option<u32>,
+    gpio_test: bool,
+    serial: Option<String>,
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("hackrf-operacake-rs: {}", msg);
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn value(flag: &str, value: Option<String>) -> String {
+    value.unwrap_or_else(|| fail(&format!("{} needs a value", flag)))
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("invalid value for {}: {}", flag, value)))
+}
+
+fn port(flag: &str, name: &str) -> Port {
+    Port::from_name(name).unwrap_or_else(|| fail(&format!("invalid port for {}: {}", flag, name)))
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        list: false,
+        address: 0,
+        mode: None,
+        port_a: None,
+        port_b: None,
+        freq_ranges: Vec::new(),
+        dwell_times: Vec::new(),
+        default_dwell: None,
+        gpio_test: false,
+        serial: None,
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(flag) = argv.next() {
+        match flag.as_str() {
+            "-l" => args.list = true,
+            "-o" => args.address = number("-o", &value("-o", argv.next())),
+            "-m" => {
+                args.mode = match value("-m", argv.next()).as_str() {
+                    "manual" => Some(Mode::Manual),
+                    "frequency" => Some(Mode::Frequency),
+                    "time" => Some(Mode::Time),
+                    other => fail(&format!("invalid mode {}", other)),
+                }
+            }
+            "-a" => args.port_a = Some(port("-a", &value("-a", argv.next()))),
+            "-b" => args.port_b = Some(port("-b", &value("-b", argv.next()))),
+            "-f" => {
+                let spec = value("-f", argv.next());
+                let parts: Vec<&str> = spec.split(':').collect();
+                if parts.len() != 3 {
+                    fail(&format!("-f expects port:min:max, not {}", spec));
+                }
+                let (min, max) = (number("-f", parts[1]), number("-f", parts[2]));
+                if min > max {
+                    fail(&format!("-f range {} has min above max", spec));
+                }
+                args.freq_ranges.push((min, max, port("-f", parts[0])));
+            }
+            "-t" => {
+                let spec = value("-t", argv.next());
+                let mut parts = spec.splitn(2, ':');
+                let p = port("-t", parts.next().unwrap());
+                args.dwell_times
+                    .push((parts.next().map(|d| number("-t", d)), p));
+            }
+            "-w" => args.default_dwell = Some(number("-w", &value("-w", argv.next()))),
+            "-g" => args.gpio_test = true,
+            "-d" => args.serial = Some(value("-d", argv.next())),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            other => fail(&format!("unknown option {}", other)),
+        }
+    }
+    if args.freq_ranges.len() > operacake::MAX_FREQ_RANGES {
+        fail(&format!(
+            "at most {} -f ranges are allowed",
+            operacake::MAX_FREQ_RANGES
+        ));
+    }
+    if args.dwell_times.len() > operacake::MAX_DWELL_TIMES {
+        fail(&format!(
+            "at most {} -t dwell times are allowed",
+            operacake::MAX_DWELL_TIMES
+        ));
+    }
+    if args.dwell_times.iter().any(|d| d.0.is_none()) && args.default_dwell.is_none() {
+        fail("-t entries without a dwell need a default given with -w");
+    }
+    if args.port_a.is_some() || args.port_b.is_some() {
+        let a = args.port_a.unwrap_or(Port::A1);
+        let b = args.port_b.unwrap_or(Port::B1);
+        if a.is_a_side() == b.is_a_side() {
+            fail("-a and -b must be on opposite sides (one A port and one B port)");
+        }
+    }
+    args
+}
+
+fn run(args: Args) -> Result<(), hackrf::HackRFError> {
+    hackrf::init()?;
+    let mut device = match args.serial {
+        Some(ref serial) => hackrf::open_by_serial(serial)?,
+        None => hackrf::open()?,
+    };
+
+    if args.list {
+        let boards = operacake::boards(&mut device)?;
+        if boards.is_empty() {
+            println!("No Opera Cake boards found.");
+        }
+        for address in boards {
+            let mode = operacake::mode(&mut device, address)?;
+            println!("Opera Cake at address {}: {:?} mode", address, mode);
+        }
+    }
+
+    if let Some(mode) = args.mode {
+        operacake::set_mode(&mut device, args.address, mode)?;
+    }
+    if args.port_a.is_some() || args.port_b.is_some() {
+        let a = args.port_a.unwrap_or(Port::A1);
+        let b = args.port_b.unwrap_or(Port::B1);
+        operacake::set_ports(&mut device, args.address, a, b)?;
+    }
+    if !args.freq_ranges.is_empty() {
+        operacake::set_freq_ranges(&mut device, &args.freq_ranges)?;
+    }
+    if !args.dwell_times.is_empty() {
+        let default = args.default_dwell.unwrap_or(0);
+        let dwell_times: Vec<(u32, Port)> = args
+            .dwell_times
+            .iter()
+            .map(|&(dwell, port)| (dwell.unwrap_or(default), port))
+            .collect();
+        operacake::set_dwell_times(&mut device, &dwell_times)?;
+    }
+
+    if args.gpio_test {
+        match operacake::gpio_test(&mut device, args.address)? {
+            0 => println!("GPIO test passed"),
+            0xFFFF => println!("GPIO test could not run; is the Opera Cake in test mode?"),
+            result => println!("GPIO test failed: 0x{:04x}", result),
+        }
+    }
+
+    hackrf::close(device)?;
+    hackrf::exit()
+}
+
+fn main() {
+    let args = parse_args();
+    if let Err(err) = run(args) {
+        eprintln!("hackrf-operacake-rs: {:?}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,220 @@
+// hackrf_spiflash equivalent built on the hackrf crate
+// Licensed under MIT license
+
+extern crate hackrf;
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::process;
+
+use hackrf::spiflash;
+
+const USAGE: &str = "Usage: hackrf-spiflash-rs <action> [options]
+    -r <file>     Read flash contents into <file>
+    -w <file>     Erase the flash, write <file> to it and verify
+    -V <file>     Verify the flash matches <file>
+    -a <address>  Flash address to start at (default 0)
+    -l <bytes>    Number of bytes to read (default: the whole flash)
+    -i            Skip the firmware image checks before writing
+    -s            Print the flash status registers
+    -c            Clear the flash status registers
+    -R            Reset the board when done
+    -d <serial>   Open the board with this serial number
+
+Numbers may be given in decimal or as 0x-prefixed hex.";
+
+enum Action {
+    Read(String),
+    Write(String),
+    Verify(String),
+}
+
+struct Args {
+    action: Option<Action>,
+    address: u32,
+    length: Option<usize>,
+    force: bool,
+    status: bool,
+    clear_status: bool,
+    reset: bool,
+    serial: Option<String>,
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("hackrf-spiflash-rs: {}", msg);
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn number(flag: &str, value: Option<String>) -> u32 {
+    let value = value.unwrap_or_else(|| fail(&format!("{} needs a value", flag)));
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.unwrap_or_else(|_| fail(&format!("invalid value for {}: {}", flag, value)))
+}
+
+fn path(flag: &str, value: Option<String>) -> String {
+    value.unwrap_or_else(|| fail(&format!("{} needs a file", flag)))
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        action: None,
+        address: 0,
+        length: None,
+        force: false,
+        status: false,
+        clear_status: false,
+        reset: false,
+        serial: None,
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(flag) = argv.next() {
+        let action = match flag.as_str() {
+            "-r" => Some(Action::Read(path("-r", argv.next()))),
+            "-w" => Some(Action::Write(path("-w", argv.next()))),
+            "-V" => Some(Action::Verify(path("-V", argv.next()))),
+            "-a" => {
+                args.address = number("-a", argv.next());
+                None
+            }
+            "-l" => {
+                args.length = Some(number("-l", argv.next()) as usize);
+                None
+            }
+            "-i" => {
+                args.force = true;
+                None
+            }
+            "-s" => {
+                args.status = true;
+                None
+            }
+            "-c" => {
+                args.clear_status = true;
+                None
+            }
+            "-R" => {
+                args.reset = true;
+                None
+            }
+            "-d" => {
+                args.serial = Some(argv.next().unwrap_or_else(|| fail("-d needs a serial")));
+                None
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            other => fail(&format!("unknown option {}", other)),
+        };
+        if action.is_some() {
+            if args.action.is_some() {
+                fail("only one of -r, -w and -V may be given");
+            }
+            args.action = action;
+        }
+    }
+    if args.action.is_none() && !args.status && !args.clear_status && !args.reset {
+        fail("nothing to do");
+    }
+    if args.address as usize >= spiflash::FLASH_SIZE {
+        fail(&format!(
+            "address must be below 0x{:x}",
+            spiflash::FLASH_SIZE
+        ));
+    }
+    args
+}
+
+/// Compare the flash at `address` with `expected`, returning the offset of
+/// the first mismatch.
+fn verify(
+    device: &mut hackrf::HackRFDevice,
+    address: u32,
+    expected: &[u8],
+) -> Result<Option<usize>, hackrf::HackRFError> {
+    let mut actual = vec![0; expected.len()];
+    spiflash::read(device, address, &mut actual)?;
+    Ok(actual.iter().zip(expected).position(|(a, e)| a != e))
+}
+
+fn run(args: Args) -> Result<(), hackrf::HackRFError> {
+    // Validate files before touching the board.
+    let image = match args.action {
+        Some(Action::Write(ref path)) | Some(Action::Verify(ref path)) => {
+            let image = fs::read(path)?;
+            if !args.force {
+                spiflash::check_image(args.address, &image)?;
+            }
+            Some(image)
+        }
+        _ => None,
+    };
+
+    hackrf::init()?;
+    let mut device = match args.serial {
+        Some(ref serial) => hackrf::open_by_serial(serial)?,
+        None => hackrf::open()?,
+    };
+
+    if args.status {
+        let status = spiflash::status(&mut device)?;
+        println!("Status: 0x{:02x} 0x{:02x}", status[0], status[1]);
+    }
+    if args.clear_status {
+        spiflash::clear_status(&mut device)?;
+    }
+
+    match (args.action, image) {
+        (Some(Action::Read(ref path)), _) => {
+            let length = args
+                .length
+                .unwrap_or(spiflash::FLASH_SIZE - args.address as usize);
+            let mut data = vec![0; length];
+            spiflash::read(&mut device, args.address, &mut data)?;
+            File::create(path)?.write_all(&data)?;
+            eprintln!("Read {} bytes from 0x{:x}", length, args.address);
+        }
+        (Some(Action::Write(_)), Some(image)) => {
+            eprintln!("Erasing flash");
+            spiflash::erase(&mut device)?;
+            eprintln!("Writing {} bytes at 0x{:x}", image.len(), args.address);
+            spiflash::write(&mut device, args.address, &image)?;
+            if let Some(offset) = verify(&mut device, args.address, &image)? {
+                eprintln!(
+                    "Verify failed at 0x{:x}; do not reset the board, write it again",
+                    args.address as usize + offset
+                );
+                process::exit(1);
+            }
+            eprintln!("Verified");
+        }
+        (Some(Action::Verify(_)), Some(image)) => {
+            match verify(&mut device, args.address, &image)? {
+                Some(offset) => {
+                    eprintln!("Mismatch at 0x{:x}", args.address as usize + offset);
+                    process::exit(1);
+                }
+                None => eprintln!("Flash matches"),
+            }
+        }
+        _ => (),
+    }
+
+    if args.reset {
+        hackrf::reset(&mut device)?;
+    }
+    hackrf::close(device)?;
+    hackrf::exit()
+}
+
+fn main() {
+    let args = parse_args();
+    if let Err(err) = run(args) {
+        eprintln!("hackrf-spiflash-rs: {:?}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,188 @@
+// hackrf_sweep equivalent built on the hackrf crate
+// Licensed under MIT license
+
+extern crate hackrf;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::time::{Duration, SystemTime};
+
+use hackrf::sweep::{self, SweepProcessor, SweepStyle};
+
+const USAGE: &str = "Usage: hackrf-sweep-rs [options]
+    -f <min:max>  Frequency range in MHz (default 0:6000)
+    -l <db>       RX LNA gain, 0-40dB in 8dB steps (default 16)
+    -g <db>       RX VGA gain, 0-62dB in 2dB steps (default 20)
+    -a <0|1>      RF amplifier off/on
+    -w <hz>       FFT bin width in Hz (default 1000000)
+    -1            One shot: stop after a single sweep
+    -N <count>    Stop after <count> sweeps
+    -r <file>     Write CSV to <file> instead of stdout";
+
+// hackrf_sweep's fixed tuning plan: 20Msps, retuning in 20MHz steps with
+// the wanted spectrum offset 7.5MHz from the LO.
+const SAMPLE_RATE_HZ: u32 = 20_000_000;
+const BASEBAND_FILTER_HZ: u32 = 15_000_000;
+const TUNE_STEP_MHZ: u16 = 20;
+const OFFSET_HZ: u32 = 7_500_000;
+
+struct Args {
+    freq_min_mhz: u16,
+    freq_max_mhz: u16,
+    lna_gain: u32,
+    vga_gain: u32,
+    amp: bool,
+    bin_width_hz: u32,
+    sweeps: Option<u64>,
+    output: Option<String>,
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("hackrf-sweep-rs: {}", msg);
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn number(flag: &str, value: Option<String>) -> u64 {
+    let value = value.unwrap_or_else(|| fail(&format!("{} needs a value", flag)));
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("invalid value for {}: {}", flag, value)))
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        freq_min_mhz: 0,
+        freq_max_mhz: 6000,
+        lna_gain: 16,
+        vga_gain: 20,
+        amp: false,
+        bin_width_hz: 1_000_000,
+        sweeps: None,
+        output: None,
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(flag) = argv.next() {
+        match flag.as_str() {
+            "-f" => {
+                let range = argv.next().unwrap_or_else(|| fail("-f needs a range"));
+                let mut parts = range.splitn(2, ':').map(|p| p.parse::<u16>());
+                match (parts.next(), parts.next()) {
+                    (Some(Ok(min)), Some(Ok(max))) if min < max && max <= 7250 => {
+                        args.freq_min_mhz = min;
+                        args.freq_max_mhz = max;
+                    }
+                    _ => fail(&format!("invalid frequency range {}", range)),
+                }
+            }
+            "-l" => args.lna_gain = number("-l", argv.next()) as u32,
+            "-g" => args.vga_gain = number("-g", argv.next()) as u32,
+            "-a" => args.amp = number("-a", argv.next()) != 0,
+            "-w" => args.bin_width_hz = number("-w", argv.next()) as u32,
+            "-1" => args.sweeps = Some(1),
+            "-N" => args.sweeps = Some(number("-N", argv.next())),
+            "-r" => args.output = Some(argv.next().unwrap_or_else(|| fail("-r needs a file"))),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            other => fail(&format!("unknown option {}", other)),
+        }
+    }
+    if args.lna_gain > 40 || !args.lna_gain.is_multiple_of(8) {
+        fail("-l must be 0-40 in steps of 8");
+    }
+    if args.vga_gain > 62 || !args.vga_gain.is_multiple_of(2) {
+        fail("-g must be 0-62 in steps of 2");
+    }
+    if args.bin_width_hz < 2445 || args.bin_width_hz > 5_000_000 {
+        fail("-w must be between 2445 and 5000000");
+    }
+    args
+}
+
+fn run(args: Args) -> Result<(), hackrf::HackRFError> {
+    let mut out: Box<dyn Write + Send> = match args.output {
+        Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+
+    hackrf::init()?;
+    let mut device = hackrf::open()?;
+    hackrf::set_sample_rate_manual(&mut device, SAMPLE_RATE_HZ, 1)?;
+    hackrf::set_baseband_filter_bandwidth(&mut device, BASEBAND_FILTER_HZ)?;
+    hackrf::set_amp_enable(&mut device, args.amp)?;
+    hackrf::set_lna_gain(&mut device, args.lna_gain)?;
+    hackrf::set_vga_gain(&mut device, args.vga_gain)?;
+
+    // Round the range up to a whole number of tuning steps.
+    let steps = 1 + (args.freq_max_mhz - args.freq_min_mhz - 1) / TUNE_STEP_MHZ;
+    let freq_max_mhz = args.freq_min_mhz + steps * TUNE_STEP_MHZ;
+    sweep::init_sweep(
+        &mut device,
+        &[(args.freq_min_mhz, freq_max_mhz)],
+        sweep::BYTES_PER_BLOCK as u32,
+        TUNE_STEP_MHZ as u32 * 1_000_000,
+        OFFSET_HZ,
+        SweepStyle::Interleaved,
+    )?;
+
+    let mut processor = SweepProcessor::new(SAMPLE_RATE_HZ, args.bin_width_hz);
+    let first_hz = args.freq_min_mhz as u64 * 1_000_000;
+    let limit = args.sweeps;
+    let stop = hackrf::StopHandle::new();
+    let cb_stop = stop.clone();
+    let mut started = false;
+    let mut completed = 0;
+    let mut error = None;
+    let mut callback = |buffer: &[u8]| -> bool {
+        if cb_stop.is_stopped() {
+            return false;
+        }
+        let now = SystemTime::now();
+        for block in sweep::sweep_blocks(buffer) {
+            // Only output whole sweeps, starting from the bottom of the range.
+            if block.frequency_hz == first_hz {
+                if started {
+                    completed += 1;
+                    if limit.is_some_and(|n| completed >= n) {
+                        cb_stop.stop();
+                        return false;
+                    }
+                }
+                started = true;
+            }
+            if !started {
+                continue;
+            }
+            for row in processor.process(&block, now).iter() {
+                if let Err(err) = row.write_csv(&mut out) {
+                    error = Some(err);
+                    cb_stop.stop();
+                    return false;
+                }
+            }
+        }
+        true
+    };
+    sweep::start_rx_sweep(&mut device, &mut callback)?;
+    while !stop.is_stopped() && hackrf::is_streaming(&mut device)? {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    hackrf::stop_rx(&mut device)?;
+    if let Some(err) = error {
+        return Err(err.into());
+    }
+    out.flush()?;
+    hackrf::close(device)?;
+    hackrf::exit()
+}
+
+fn main() {
+    let args = parse_args();
+    if let Err(err) = run(args) {
+        eprintln!("hackrf-sweep-rs: {:?}", err);
+        process::exit(1);
+    }
+}
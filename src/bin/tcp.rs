@@ -0,0 +1,87 @@
+// rtl_tcp compatible server built on the hackrf crate
+// Licensed under MIT license
+
+extern crate hackrf;
+
+use std::process;
+
+const USAGE: &str = "Usage: hackrf-tcp-rs [options]
+    -a <address>  Address to listen on (default 127.0.0.1)
+    -p <port>     Port to listen on (default 1234)
+    -f <hz>       Initial frequency in Hz (default 100000000)
+    -s <hz>       Initial sample rate in Hz (default 2048000)
+    -d <serial>   Open the board with this serial number
+
+Clients can change frequency, sample rate, gain and bias tee.";
+
+struct Args {
+    address: String,
+    port: u16,
+    freq_hz: u64,
+    sample_rate: f64,
+    serial: Option<String>,
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("hackrf-tcp-rs: {}", msg);
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+    let value = value.unwrap_or_else(|| fail(&format!("{} needs a value", flag)));
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("invalid value for {}: {}", flag, value)))
+}
+
+fn parse_args() -> Args {
+    let mut args = Args {
+        address: "127.0.0.1".to_string(),
+        port: 1234,
+        freq_hz: 100_000_000,
+        sample_rate: 2_048_000.0,
+        serial: None,
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(flag) = argv.next() {
+        match flag.as_str() {
+            "-a" => args.address = argv.next().unwrap_or_else(|| fail("-a needs an address")),
+            "-p" => args.port = number("-p", argv.next()),
+            "-f" => args.freq_hz = number("-f", argv.next()),
+            "-s" => args.sample_rate = number("-s", argv.next()),
+            "-d" => args.serial = Some(argv.next().unwrap_or_else(|| fail("-d needs a serial"))),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            other => fail(&format!("unknown option {}", other)),
+        }
+    }
+    args
+}
+
+fn run(args: Args) -> Result<(), hackrf::HackRFError> {
+    hackrf::init()?;
+    let mut device = match args.serial {
+        Some(ref serial) => hackrf::open_by_serial(serial)?,
+        None => hackrf::open()?,
+    };
+    hackrf::set_freq(&mut device, args.freq_hz)?;
+    hackrf::set_sample_rate(&mut device, args.sample_rate)?;
+    let bw = hackrf::compute_baseband_filter_bw((args.sample_rate * 0.75) as u32);
+    hackrf::set_baseband_filter_bandwidth(&mut device, bw)?;
+
+    eprintln!("Listening on {}:{}", args.address, args.port);
+    hackrf::net::serve(&mut device, (args.address.as_str(), args.port))?;
+    hackrf::close(device)?;
+    hackrf::exit()
+}
+
+fn main() {
+    let args = parse_args();
+    if let Err(err) = run(args) {
+        eprintln!("hackrf-tcp-rs: {:?}", err);
+        process::exit(1);
+    }
+}
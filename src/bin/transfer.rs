@@ -0,0 +1,229 @@
+// hackrf_transfer equivalent built on the hackrf crate
+// Licensed under MIT license
+
+extern crate hackrf;
+
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: hackrf-transfer-rs <-r <file> | -t <file> | -S <secs>> [options]
+    -r <file>   Receive cs8 samples into <file>
+    -t <file>   Transmit cs8 samples from <file>
+    -S <secs>   Receive at 20Msps into nothing for <secs> and report the
+                rate sustained and samples lost, to check the USB link
+    -f <hz>     Centre frequency in Hz
+    -s <hz>     Sample rate in Hz (default 10000000)
+    -l <db>     RX LNA gain, 0-40dB in 8dB steps
+    -g <db>     RX VGA gain, 0-62dB in 2dB steps
+    -x <db>     TX VGA gain, 0-47dB in 1dB steps
+    -a <0|1>    RF amplifier off/on
+    -n <count>  Number of samples to transfer (default unlimited)
+    -R          Repeat the TX file continuously";
+
+enum Mode {
+    Receive(PathBuf),
+    Transmit(PathBuf),
+    Throughput(Duration),
+}
+
+struct Args {
+    mode: Mode,
+    freq_hz: Option<u64>,
+    sample_rate: f64,
+    lna_gain: Option<u32>,
+    vga_gain: Option<u32>,
+    txvga_gain: Option<u32>,
+    amp: Option<bool>,
+    samples: Option<u64>,
+    repeat: bool,
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("hackrf-transfer-rs: {}", msg);
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn number(flag: &str, value: Option<OsString>) -> f64 {
+    let value = value.unwrap_or_else(|| fail(&format!("{} needs a value", flag)));
+    match value.to_str().map(str::parse::<f64>) {
+        Some(Ok(v)) if v >= 0.0 => v,
+        _ => fail(&format!(
+            "invalid value for {}: {}",
+            flag,
+            value.to_string_lossy()
+        )),
+    }
+}
+
+fn gain(flag: &str, value: Option<OsString>, max: u32, step: u32) -> u32 {
+    let v = number(flag, value) as u32;
+    if v > max || !v.is_multiple_of(step) {
+        fail(&format!("{} must be 0-{} in steps of {}", flag, max, step));
+    }
+    v
+}
+
+fn parse_args() -> Args {
+    let mut mode = None;
+    let mut args = Args {
+        mode: Mode::Receive(PathBuf::new()),
+        freq_hz: None,
+        sample_rate: 10e6,
+        lna_gain: None,
+        vga_gain: None,
+        txvga_gain: None,
+        amp: None,
+        samples: None,
+        repeat: false,
+    };
+    // Paths are taken as OsStrings, as a file name need not be valid
+    // Unicode, on Windows or elsewhere.
+    let mut argv = std::env::args_os().skip(1);
+    while let Some(flag) = argv.next() {
+        let flag = flag.to_string_lossy().into_owned();
+        match flag.as_str() {
+            "-r" | "-t" => {
+                let path = PathBuf::from(
+                    argv.next()
+                        .unwrap_or_else(|| fail(&format!("{} needs a file", flag))),
+                );
+                if mode.is_some() {
+                    fail("only one of -r, -t and -S may be given");
+                }
+                mode = Some(if flag == "-r" {
+                    Mode::Receive(path)
+                } else {
+                    Mode::Transmit(path)
+                });
+            }
+            "-S" => {
+                if mode.is_some() {
+                    fail("only one of -r, -t and -S may be given");
+                }
+                let secs = number("-S", argv.next());
+                mode = Some(Mode::Throughput(Duration::from_secs_f64(secs)));
+            }
+            "-f" => args.freq_hz = Some(number("-f", argv.next()) as u64),
+            "-s" => args.sample_rate = number("-s", argv.next()),
+            "-l" => args.lna_gain = Some(gain("-l", argv.next(), 40, 8)),
+            "-g" => args.vga_gain = Some(gain("-g", argv.next(), 62, 2)),
+            "-x" => args.txvga_gain = Some(gain("-x", argv.next(), 47, 1)),
+            "-a" => args.amp = Some(number("-a", argv.next()) != 0.0),
+            "-n" => args.samples = Some(number("-n", argv.next()) as u64),
+            "-R" => args.repeat = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            other => fail(&format!("unknown option {}", other)),
+        }
+    }
+    args.mode = mode.unwrap_or_else(|| fail("one of -r, -t or -S is required"));
+    args
+}
+
+fn run(args: Args) -> Result<(), hackrf::HackRFError> {
+    hackrf::init()?;
+    let mut device = hackrf::open()?;
+
+    hackrf::set_sample_rate(&mut device, args.sample_rate)?;
+    let bw = hackrf::compute_baseband_filter_bw(args.sample_rate as u32);
+    hackrf::set_baseband_filter_bandwidth(&mut device, bw)?;
+    if let Some(freq) = args.freq_hz {
+        hackrf::set_freq(&mut device, freq)?;
+    }
+    if let Some(amp) = args.amp {
+        hackrf::set_amp_enable(&mut device, amp)?;
+    }
+    if let Some(lna) = args.lna_gain {
+        hackrf::set_lna_gain(&mut device, lna)?;
+    }
+    if let Some(vga) = args.vga_gain {
+        hackrf::set_vga_gain(&mut device, vga)?;
+    }
+    if let Some(txvga) = args.txvga_gain {
+        hackrf::set_txvga_gain(&mut device, txvga)?;
+    }
+
+    let rate = args.sample_rate;
+    let mut passed = true;
+    // Ctrl-C ends receiving or transmitting early, leaving a complete file.
+    let stop = hackrf::stop_on_ctrlc()?;
+    match args.mode {
+        Mode::Receive(path) => {
+            let recorder = hackrf::Recorder::start(&mut device, &path)?;
+            // Run a little over time, as streaming takes a moment to start,
+            // then trim any excess so the file holds exactly -n samples.
+            let deadline = args
+                .samples
+                .map(|n| Duration::from_secs_f64(n as f64 / rate + 0.25));
+            let mut elapsed = Duration::from_secs(0);
+            let tick = Duration::from_millis(100);
+            while deadline.is_none_or(|d| elapsed < d)
+                && !stop.is_stopped()
+                && hackrf::is_streaming(&mut device)?
+            {
+                std::thread::sleep(tick);
+                elapsed += tick;
+                if elapsed.subsec_millis() == 0 && recorder.dropped() > 0 {
+                    eprintln!("{} transfers dropped so far", recorder.dropped());
+                }
+            }
+            let written = recorder.stop(&mut device)?;
+            if let Some(n) = args.samples {
+                if written > n * 2 {
+                    OpenOptions::new().write(true).open(&path)?.set_len(n * 2)?;
+                }
+            }
+        }
+        Mode::Transmit(path) => {
+            let limit_stop = stop.clone();
+            let limit = args.samples;
+            let mut source = hackrf::FileSource::open(&path, hackrf::SampleFormat::Cs8)?
+                .looping(args.repeat)
+                .on_progress(move |sent| {
+                    if limit.is_some_and(|n| sent >= n) {
+                        limit_stop.stop();
+                    }
+                });
+            hackrf::transmit_file(&mut device, &mut source, &stop)?;
+        }
+        Mode::Throughput(duration) => {
+            let report = hackrf::throughput_test(&mut device, duration)?;
+            println!(
+                "{} samples in {:.2}s: {:.3}Msps sustained",
+                report.samples,
+                report.elapsed.as_secs_f64(),
+                report.sustained_rate / 1e6
+            );
+            println!("{} transfers dropped", report.dropped_transfers);
+            match report.shortfalls {
+                Some((count, longest)) => {
+                    println!("{} M0 shortfalls, longest {} bytes", count, longest)
+                }
+                None => println!("M0 shortfalls not reported by this firmware"),
+            }
+            println!("{}", if report.passed { "PASS" } else { "FAIL" });
+            passed = report.passed;
+        }
+    }
+
+    hackrf::close(device)?;
+    hackrf::exit()?;
+    if !passed {
+        process::exit(2);
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = parse_args();
+    if let Err(err) = run(args) {
+        eprintln!("hackrf-transfer-rs: {:?}", err);
+        process::exit(1);
+    }
+}
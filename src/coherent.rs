@@ -0,0 +1,118 @@
+// Phase-coherent capture with two boards
+// Licensed under MIT license
+
+use std::thread;
+use std::time::Duration;
+
+use super::sdr::RxCallback;
+use super::{
+    clkin_status, compute_baseband_filter_bw, ffi, set_baseband_filter_bandwidth,
+    set_clkout_enable, set_freq, set_hw_sync_mode, set_sample_rate, start_rx_owned, stop_rx,
+    HackRFDevice, HackRFError,
+};
+
+// How long the slave may take to detect and switch to the master's clock.
+const CLOCK_TIMEOUT: Duration = Duration::from_secs(1);
+const CLOCK_POLL: Duration = Duration::from_millis(50);
+
+/// Two boards set up for phase-coherent receiving: the slave runs from the
+/// master's 10MHz reference, and both start streaming on the same sample.
+///
+/// Cable the master's CLKOUT to the slave's CLKIN, and the master's trigger
+/// output to the slave's trigger input on the P28 header. The slave is put
+/// in hardware sync mode, so it waits for the pulse the master sends as it
+/// starts streaming. Gains and other settings can be changed through
+/// `master` and `slave`; the frequency and sample rate should be set on the
+/// pair, so that both boards agree.
+pub struct CoherentPair {
+    master: HackRFDevice,
+    slave: HackRFDevice,
+}
+
+impl CoherentPair {
+    /// Share the master's clock with the slave and arm the slave's trigger.
+    /// Fails if the slave does not detect the master's clock on CLKIN.
+    pub fn setup(
+        mut master: HackRFDevice,
+        mut slave: HackRFDevice,
+    ) -> Result<CoherentPair, HackRFError> {
+        set_hw_sync_mode(&mut master, false)?;
+        set_clkout_enable(&mut master, true)?;
+        let mut waited = Duration::from_secs(0);
+        while !clkin_status(&mut slave)? {
+            if waited >= CLOCK_TIMEOUT {
+                return Err(HackRFError {
+                    errno: ffi::HACKRF_ERROR_OTHER,
+                    errstr: "no reference clock on the slave's CLKIN".to_string(),
+                    context: None,
+                });
+            }
+            thread::sleep(CLOCK_POLL);
+            waited += CLOCK_POLL;
+        }
+        set_hw_sync_mode(&mut slave, true)?;
+        Ok(CoherentPair { master, slave })
+    }
+
+    /// Tune both boards to `freq_hz`.
+    pub fn set_freq(&mut self, freq_hz: u64) -> Result<(), HackRFError> {
+        set_freq(&mut self.master, freq_hz)?;
+        set_freq(&mut self.slave, freq_hz)
+    }
+
+    /// Set both boards' sample rate, and the baseband filter to suit it.
+    pub fn set_sample_rate(&mut self, sample_rate: f64) -> Result<(), HackRFError> {
+        let bw = compute_baseband_filter_bw((sample_rate * 0.75) as u32);
+        for device in [&mut self.master, &mut self.slave].iter_mut() {
+            set_sample_rate(device, sample_rate)?;
+            set_baseband_filter_bandwidth(device, bw)?;
+        }
+        Ok(())
+    }
+
+    /// Start receiving on both boards, so that the first buffer each
+    /// callback sees begins on the same sample. The slave is armed first
+    /// and begins when the master starts.
+    pub fn start_rx(
+        &mut self,
+        master_callback: RxCallback,
+        slave_callback: RxCallback,
+    ) -> Result<(), HackRFError> {
+        start_rx_owned(&mut self.slave, slave_callback)?;
+        if let Err(err) = start_rx_owned(&mut self.master, master_callback) {
+            let _ = stop_rx(&mut self.slave);
+            self.slave.rx_callback = None;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Stop receiving on both boards. The slave stays armed for the next
+    /// `start_rx`.
+    pub fn stop_rx(&mut self) -> Result<(), HackRFError> {
+        let master = stop_rx(&mut self.master);
+        let slave = stop_rx(&mut self.slave);
+        self.master.rx_callback = None;
+        self.slave.rx_callback = None;
+        master.and(slave)
+    }
+
+    /// The board providing the clock and trigger.
+    pub fn master(&mut self) -> &mut HackRFDevice {
+        &mut self.master
+    }
+
+    /// The board following the master's clock and trigger.
+    pub fn slave(&mut self) -> &mut HackRFDevice {
+        &mut self.slave
+    }
+
+    /// Stop streaming, turn off the master's clock output and the slave's
+    /// hardware sync mode, and return the master and slave.
+    pub fn into_inner(mut self) -> Result<(HackRFDevice, HackRFDevice), HackRFError> {
+        self.stop_rx()?;
+        set_clkout_enable(&mut self.master, false)?;
+        set_hw_sync_mode(&mut self.slave, false)?;
+        Ok((self.master, self.slave))
+    }
+}
@@ -0,0 +1,58 @@
+// Device settings that can be reapplied as a whole
+// Licensed under MIT license
+
+use super::{
+    compute_baseband_filter_bw, set_amp_enable, set_antenna_enable, set_baseband_filter_bandwidth,
+    set_freq, set_lna_gain, set_sample_rate, set_txvga_gain, set_vga_gain, HackRFDevice,
+    HackRFError,
+};
+
+/// A set of board settings, for applying in one go, such as to a board that
+/// has been reopened. Settings left as `None` are not changed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceConfig {
+    pub freq_hz: Option<u64>,
+    pub sample_rate: Option<f64>,
+    /// Baseband filter bandwidth in Hz. If unset but `sample_rate` is set,
+    /// the filter is chosen to suit the sample rate.
+    pub baseband_filter_hz: Option<u32>,
+    pub lna_gain: Option<u32>,
+    pub vga_gain: Option<u32>,
+    pub txvga_gain: Option<u32>,
+    pub amp: Option<bool>,
+    pub antenna_power: Option<bool>,
+}
+
+impl DeviceConfig {
+    /// Apply the settings to `device`, stopping at the first that fails.
+    pub fn apply(&self, device: &mut HackRFDevice) -> Result<(), HackRFError> {
+        if let Some(rate) = self.sample_rate {
+            set_sample_rate(device, rate)?;
+        }
+        let filter = self
+            .baseband_filter_hz
+            .or_else(|| Some(compute_baseband_filter_bw(self.sample_rate? as u32)));
+        if let Some(bw) = filter {
+            set_baseband_filter_bandwidth(device, bw)?;
+        }
+        if let Some(freq) = self.freq_hz {
+            set_freq(device, freq)?;
+        }
+        if let Some(amp) = self.amp {
+            set_amp_enable(device, amp)?;
+        }
+        if let Some(lna) = self.lna_gain {
+            set_lna_gain(device, lna)?;
+        }
+        if let Some(vga) = self.vga_gain {
+            set_vga_gain(device, vga)?;
+        }
+        if let Some(txvga) = self.txvga_gain {
+            set_txvga_gain(device, txvga)?;
+        }
+        if let Some(power) = self.antenna_power {
+            set_antenna_enable(device, power)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+// Batched setting changes while streaming
+// Licensed under MIT license
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use super::config::DeviceConfig;
+use super::gainfade::{GainFade, GainFadeHandle};
+use super::ramp::RampLength;
+use super::sdr::{RxCallback, TxCallback};
+use super::{start_rx_owned, start_tx_owned, stop_rx, stop_tx, HackRFDevice, HackRFError};
+
+// Gain of the RF amplifier when on, in dB.
+const AMP_DB: i64 = 14;
+
+fn lock<T>(device: &Mutex<T>) -> MutexGuard<'_, T> {
+    device.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Receive gains last set through `update`, if known.
+#[derive(Default)]
+struct RxGains {
+    lna: Option<u32>,
+    vga: Option<u32>,
+    amp: Option<bool>,
+}
+
+impl RxGains {
+    // Record the gains `config` sets and return the total change in dB, if
+    // known for every stage it changes.
+    fn change(&mut self, config: &DeviceConfig) -> Option<i64> {
+        let mut change = Some(0);
+        if let Some(lna) = config.lna_gain {
+            change = change.and_then(|c| Some(c + lna as i64 - self.lna? as i64));
+            self.lna = Some(lna);
+        }
+        if let Some(vga) = config.vga_gain {
+            change = change.and_then(|c| Some(c + vga as i64 - self.vga? as i64));
+            self.vga = Some(vga);
+        }
+        if let Some(amp) = config.amp {
+            change = change.and_then(|c| Some(c + AMP_DB * (amp as i64 - self.amp? as i64)));
+            self.amp = Some(amp);
+        }
+        change
+    }
+}
+
+/// The settings to change in one `ControlHandle::update`.
+#[derive(Clone, Debug, Default)]
+pub struct ControlUpdate {
+    config: DeviceConfig,
+}
+
+impl ControlUpdate {
+    pub fn freq(&mut self, freq_hz: u64) -> &mut ControlUpdate {
+        self.config.freq_hz = Some(freq_hz);
+        self
+    }
+
+    /// Also sets the baseband filter to suit, unless `baseband_filter` is
+    /// given too.
+    pub fn sample_rate(&mut self, sample_rate: f64) -> &mut ControlUpdate {
+        self.config.sample_rate = Some(sample_rate);
+        self
+    }
+
+    pub fn baseband_filter(&mut self, bandwidth_hz: u32) -> &mut ControlUpdate {
+        self.config.baseband_filter_hz = Some(bandwidth_hz);
+        self
+    }
+
+    pub fn lna(&mut self, gain: u32) -> &mut ControlUpdate {
+        self.config.lna_gain = Some(gain);
+        self
+    }
+
+    pub fn vga(&mut self, gain: u32) -> &mut ControlUpdate {
+        self.config.vga_gain = Some(gain);
+        self
+    }
+
+    pub fn txvga(&mut self, gain: u32) -> &mut ControlUpdate {
+        self.config.txvga_gain = Some(gain);
+        self
+    }
+
+    pub fn amp(&mut self, on: bool) -> &mut ControlUpdate {
+        self.config.amp = Some(on);
+        self
+    }
+
+    pub fn antenna_power(&mut self, on: bool) -> &mut ControlUpdate {
+        self.config.antenna_power = Some(on);
+        self
+    }
+}
+
+/// Streams from or to a board while letting other threads change its
+/// settings. `update` sends a batch of changes as back-to-back control
+/// transfers, keeping the window in which samples come from a mix of old
+/// and new settings as short as possible, and reports roughly which sample
+/// the changes took effect at.
+pub struct ControlHandle {
+    device: Mutex<HackRFDevice>,
+    // Samples passed to or from the callback so far.
+    samples: Arc<AtomicU64>,
+    transmitting: bool,
+    fade: Option<(GainFadeHandle, Mutex<RxGains>)>,
+}
+
+impl ControlHandle {
+    /// Take `device` and start receiving into `callback`.
+    pub fn start_rx(
+        mut device: HackRFDevice,
+        mut callback: RxCallback,
+    ) -> Result<ControlHandle, HackRFError> {
+        let samples = Arc::new(AtomicU64::new(0));
+        let cb_samples = samples.clone();
+        start_rx_owned(
+            &mut device,
+            Box::new(move |rx: &[u8]| -> bool {
+                cb_samples.fetch_add(rx.len() as u64 / 2, Ordering::SeqCst);
+                callback(rx)
+            }),
+        )?;
+        Ok(ControlHandle {
+            device: Mutex::new(device),
+            samples,
+            transmitting: false,
+            fade: None,
+        })
+    }
+
+    /// Like `start_rx`, but cross-fade the samples over `length` across
+    /// each gain change made through `update`, with a `GainFade`. Changes
+    /// are only smoothed once the gains they touch have been set through
+    /// `update` before, so set the starting gains with it first.
+    pub fn start_rx_faded(
+        device: HackRFDevice,
+        callback: RxCallback,
+        length: RampLength,
+    ) -> Result<ControlHandle, HackRFError> {
+        let fade = GainFade::new(length, device.sample_rate_hz);
+        let handle = fade.handle();
+        let mut control = ControlHandle::start_rx(device, fade.wrap(callback))?;
+        control.fade = Some((handle, Mutex::new(RxGains::default())));
+        Ok(control)
+    }
+
+    /// Take `device` and start transmitting from `callback`.
+    pub fn start_tx(
+        mut device: HackRFDevice,
+        mut callback: TxCallback,
+    ) -> Result<ControlHandle, HackRFError> {
+        let samples = Arc::new(AtomicU64::new(0));
+        let cb_samples = samples.clone();
+        start_tx_owned(
+            &mut device,
+            Box::new(move |tx: &mut [u8]| -> bool {
+                let more = callback(tx);
+                cb_samples.fetch_add(tx.len() as u64 / 2, Ordering::SeqCst);
+                more
+            }),
+        )?;
+        Ok(ControlHandle {
+            device: Mutex::new(device),
+            samples,
+            transmitting: true,
+            fade: None,
+        })
+    }
+
+    /// Apply the settings `changes` makes, stopping at the first that
+    /// fails, and return the number of samples that had passed through the
+    /// callback when they were done. Transfers already in flight were taken
+    /// with the old settings, so the change shows up in the stream a
+    /// transfer or two after that index.
+    pub fn update<F>(&self, changes: F) -> Result<u64, HackRFError>
+    where
+        F: FnOnce(&mut ControlUpdate),
+    {
+        let mut update = ControlUpdate::default();
+        changes(&mut update);
+        let mut device = lock(&self.device);
+        if let Some((handle, gains)) = &self.fade {
+            // Announced first, as the step may arrive before `apply` returns.
+            if let Some(change) = lock(gains).change(&update.config) {
+                handle.gain_changed(change as f64);
+            }
+        }
+        update.config.apply(&mut device)?;
+        Ok(self.samples.load(Ordering::SeqCst))
+    }
+
+    /// Samples passed to or from the callback so far.
+    pub fn samples(&self) -> u64 {
+        self.samples.load(Ordering::SeqCst)
+    }
+
+    /// Stop streaming and return the device.
+    pub fn stop(self) -> Result<HackRFDevice, HackRFError> {
+        let mut device = self
+            .device
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        let result = if self.transmitting {
+            let result = stop_tx(&mut device);
+            device.tx_callback = None;
+            result
+        } else {
+            let result = stop_rx(&mut device);
+            device.rx_callback = None;
+            result
+        };
+        result.map(|_| device)
+    }
+}
@@ -0,0 +1,151 @@
+// Noticing stream callbacks that block the USB thread
+// Licensed under MIT license
+
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::events::{self, EventSender, Events, StopReason, StreamEvent};
+use super::sdr::{RxCallback, TxCallback};
+
+// Shortest time between checks on the callback.
+const MIN_POLL: Duration = Duration::from_millis(1);
+
+/// Watches a stream callback from a background thread for calls that have
+/// not returned within a deadline. A callback that blocks holds up the USB
+/// thread, so the board overruns or underruns without any error being
+/// reported; this reports each late call as a `StreamEvent::CallbackStall`
+/// while it is still running, and can end the stream once it returns. Set
+/// the deadline below a transfer's duration times the transfers libhackrf
+/// keeps queued: 6.5ms per transfer at 20Msps.
+pub struct CallbackDeadline {
+    deadline: Duration,
+    stop: bool,
+    events: Events,
+    event_tx: EventSender,
+}
+
+struct Shared {
+    origin: Instant,
+    // When the running call began, in nanoseconds after `origin` plus one,
+    // or 0 between calls.
+    entered: AtomicU64,
+    // The stream is to end once the running call returns.
+    stop: AtomicBool,
+}
+
+// The callback's side of the watch.
+struct Guard {
+    shared: Arc<Shared>,
+    events: EventSender,
+    ended: bool,
+}
+
+impl CallbackDeadline {
+    pub fn new(deadline: Duration) -> CallbackDeadline {
+        let (event_tx, events) = events::channel();
+        CallbackDeadline {
+            deadline,
+            stop: false,
+            events,
+            event_tx,
+        }
+    }
+
+    /// End the stream, by returning false from the callback, once a late
+    /// call returns, reporting `Stopped` with `StopReason::Failed`.
+    /// Otherwise late calls are only reported. Defaults to off.
+    pub fn stop_stream(mut self, stop: bool) -> CallbackDeadline {
+        self.stop = stop;
+        self
+    }
+
+    /// The late calls, as `StreamEvent`s. Take it before wrapping the
+    /// callback; only the first call returns the channel.
+    pub fn events(&self) -> Option<Receiver<StreamEvent>> {
+        self.events.take()
+    }
+
+    /// Wrap an RX callback, for use with `RxChannel::start_rx` and the
+    /// adapters built on it. Fails if the watching thread cannot be
+    /// started; it ends once the wrapped callback is dropped.
+    pub fn wrap(self, mut callback: RxCallback) -> io::Result<RxCallback> {
+        let mut guard = self.watch()?;
+        Ok(Box::new(move |rx: &[u8]| -> bool {
+            guard.call(|| callback(rx))
+        }))
+    }
+
+    /// As `wrap`, for a TX callback.
+    pub fn wrap_tx(self, mut callback: TxCallback) -> io::Result<TxCallback> {
+        let mut guard = self.watch()?;
+        Ok(Box::new(move |tx: &mut [u8]| -> bool {
+            guard.call(|| callback(tx))
+        }))
+    }
+
+    fn watch(self) -> io::Result<Guard> {
+        let shared = Arc::new(Shared {
+            origin: Instant::now(),
+            entered: AtomicU64::new(0),
+            stop: AtomicBool::new(false),
+        });
+        let weak = Arc::downgrade(&shared);
+        let (deadline, stop, events) = (self.deadline, self.stop, self.event_tx.clone());
+        thread::Builder::new()
+            .name("hackrf-deadline".to_string())
+            .spawn(move || watch(weak, deadline, stop, events))?;
+        Ok(Guard {
+            shared,
+            events: self.event_tx,
+            ended: false,
+        })
+    }
+}
+
+impl Guard {
+    fn call<F: FnOnce() -> bool>(&mut self, f: F) -> bool {
+        let entered = self.shared.origin.elapsed().as_nanos() as u64 + 1;
+        self.shared.entered.store(entered, Ordering::SeqCst);
+        let more = f();
+        self.shared.entered.store(0, Ordering::SeqCst);
+        if self.shared.stop.load(Ordering::SeqCst) {
+            // Only once, though the callback may run again.
+            if !self.ended {
+                self.ended = true;
+                self.events.send(StreamEvent::Stopped {
+                    reason: StopReason::Failed,
+                });
+            }
+            return false;
+        }
+        more
+    }
+}
+
+// Check on the callback until it is dropped.
+fn watch(shared: Weak<Shared>, deadline: Duration, stop: bool, events: EventSender) {
+    let poll = (deadline / 4).max(MIN_POLL);
+    // The call last reported, to report each only once.
+    let mut reported = 0;
+    while let Some(shared) = shared.upgrade() {
+        let entered = shared.entered.load(Ordering::SeqCst);
+        if entered != 0 && entered != reported {
+            let began = Duration::from_nanos(entered - 1);
+            let elapsed = shared.origin.elapsed().saturating_sub(began);
+            // Check the call had not returned while timing it.
+            if elapsed >= deadline && shared.entered.load(Ordering::SeqCst) == entered {
+                reported = entered;
+                if stop {
+                    shared.stop.store(true, Ordering::SeqCst);
+                }
+                events.send(StreamEvent::CallbackStall { elapsed });
+            }
+        }
+        drop(shared);
+        thread::sleep(poll);
+    }
+}
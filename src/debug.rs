@@ -0,0 +1,98 @@
+// Raw register access for debugging the RF chips
+// Licensed under MIT license
+
+use super::{ffi, hackrf_error, HackRFDevice, HackRFError};
+
+/// A chip whose registers can be accessed directly over USB.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Chip {
+    /// MAX2837 transceiver: 32 registers of 10 bits.
+    Max2837,
+    /// Si5351C clock generator: 256 registers of 8 bits.
+    Si5351C,
+    /// RFFC5071 mixer/synthesizer: 31 registers of 16 bits.
+    Rffc5071,
+}
+
+impl Chip {
+    /// Number of registers, numbered from 0.
+    pub fn register_count(self) -> u16 {
+        match self {
+            Chip::Max2837 => 32,
+            Chip::Si5351C => 256,
+            Chip::Rffc5071 => 31,
+        }
+    }
+
+    /// Largest value a register can hold.
+    pub fn max_value(self) -> u16 {
+        match self {
+            Chip::Max2837 => 0x3FF,
+            Chip::Si5351C => 0xFF,
+            Chip::Rffc5071 => 0xFFFF,
+        }
+    }
+}
+
+/// Read register `register` of `chip`.
+pub fn register_read(
+    device: &mut HackRFDevice,
+    chip: Chip,
+    register: u16,
+) -> Result<u16, HackRFError> {
+    if register >= chip.register_count() {
+        return Err(hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM));
+    }
+    let mut value = 0;
+    let r = unsafe {
+        match chip {
+            Chip::Max2837 => ffi::hackrf_max2837_read(device.ptr, register as u8, &mut value),
+            Chip::Si5351C => ffi::hackrf_si5351c_read(device.ptr, register, &mut value),
+            Chip::Rffc5071 => ffi::hackrf_rffc5071_read(device.ptr, register as u8, &mut value),
+        }
+    };
+    match r {
+        ffi::HACKRF_SUCCESS => Ok(value),
+        err => Err(device.error(
+            err,
+            "debug::register_read",
+            format_args!("chip={:?}, register={}", chip, register),
+        )),
+    }
+}
+
+/// Write `value` to register `register` of `chip`.
+/// This bypasses the firmware's own view of the chip, so later settings
+/// made through the normal API may overwrite or conflict with it.
+pub fn register_write(
+    device: &mut HackRFDevice,
+    chip: Chip,
+    register: u16,
+    value: u16,
+) -> Result<(), HackRFError> {
+    if register >= chip.register_count() || value > chip.max_value() {
+        return Err(hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM));
+    }
+    let r = unsafe {
+        match chip {
+            Chip::Max2837 => ffi::hackrf_max2837_write(device.ptr, register as u8, value),
+            Chip::Si5351C => ffi::hackrf_si5351c_write(device.ptr, register, value),
+            Chip::Rffc5071 => ffi::hackrf_rffc5071_write(device.ptr, register as u8, value),
+        }
+    };
+    match r {
+        ffi::HACKRF_SUCCESS => Ok(()),
+        err => Err(device.error(
+            err,
+            "debug::register_write",
+            format_args!("chip={:?}, register={}, value={}", chip, register, value),
+        )),
+    }
+}
+
+/// Read every register of `chip`, in order.
+pub fn register_dump(device: &mut HackRFDevice, chip: Chip) -> Result<Vec<u16>, HackRFError> {
+    (0..chip.register_count())
+        .map(|register| register_read(device, chip, register))
+        .collect()
+}
@@ -0,0 +1,137 @@
+// Receiving below the hardware's lowest sample rate
+// Licensed under MIT license
+
+use std::f64::consts::PI;
+
+use super::sdr::RxCallback;
+use super::{
+    compute_baseband_filter_bw, ffi, hackrf_error, set_baseband_filter_bandwidth, set_sample_rate,
+    start_rx_owned, HackRFDevice, HackRFError,
+};
+
+// Lowest sample rate the MAX2837 and its clocking handle cleanly.
+const MIN_HARDWARE_RATE: f64 = 2e6;
+// Filter taps per unit of decimation.
+const TAPS_PER_FACTOR: usize = 16;
+
+/// Blackman-windowed sinc lowpass filter of `taps` taps with unity gain at
+/// DC, cutting off at `cutoff` times the sample rate.
+pub(crate) fn lowpass(taps: usize, cutoff: f64) -> Vec<f32> {
+    let middle = (taps - 1) as f64 / 2.0;
+    let mut filter: Vec<f64> = (0..taps)
+        .map(|n| {
+            let x = n as f64 - middle;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * x).sin() / (PI * x)
+            };
+            let w = 2.0 * PI * n as f64 / (taps - 1).max(1) as f64;
+            sinc * (0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos())
+        })
+        .collect();
+    let sum: f64 = filter.iter().sum();
+    filter.iter_mut().for_each(|tap| *tap /= sum);
+    filter.into_iter().map(|tap| tap as f32).collect()
+}
+
+// cs8 samples as I/Q pairs of floats.
+pub(crate) fn cs8_pairs(cs8: &[u8]) -> impl Iterator<Item = [f32; 2]> + '_ {
+    cs8.chunks_exact(2)
+        .map(|iq| [iq[0] as i8 as f32, iq[1] as i8 as f32])
+}
+
+// Round to a cs8 sample, clipping.
+pub(crate) fn to_cs8(x: f32) -> u8 {
+    x.round().clamp(-128.0, 127.0) as i8 as u8
+}
+
+/// Lowpass filters and decimates cs8 samples by a whole factor, keeping
+/// its state between calls so a stream can be fed a buffer at a time.
+pub struct Decimator {
+    taps: Vec<f32>,
+    factor: usize,
+    // Samples not yet consumed, as I/Q pairs.
+    history: Vec<[f32; 2]>,
+}
+
+impl Decimator {
+    /// Decimate by `factor`, filtering out everything beyond 80% of the
+    /// output Nyquist frequency first.
+    pub fn new(factor: usize) -> Decimator {
+        assert!(factor > 0);
+        Decimator {
+            taps: lowpass(TAPS_PER_FACTOR * factor + 1, 0.4 / factor as f64),
+            factor,
+            history: Vec::new(),
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Filter and decimate `input`, appending the output samples, as cs8,
+    /// to `output`. Output lags input by half the filter length.
+    pub fn process(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        self.history.extend(cs8_pairs(input));
+        self.run(|[i, q]| {
+            output.push(to_cs8(i));
+            output.push(to_cs8(q));
+        });
+    }
+
+    /// As `process`, for I/Q pairs of floats, keeping their precision.
+    pub fn process_f32(&mut self, input: &[[f32; 2]], output: &mut Vec<[f32; 2]>) {
+        self.history.extend_from_slice(input);
+        self.run(|iq| output.push(iq));
+    }
+
+    fn run(&mut self, mut emit: impl FnMut([f32; 2])) {
+        let n = self.taps.len();
+        let mut pos = 0;
+        while pos + n <= self.history.len() {
+            let (mut i, mut q) = (0.0, 0.0);
+            for (tap, iq) in self.taps.iter().zip(&self.history[pos..pos + n]) {
+                i += tap * iq[0];
+                q += tap * iq[1];
+            }
+            emit([i, q]);
+            pos += self.factor;
+        }
+        self.history.drain(..pos);
+    }
+}
+
+/// Receive at `rate` samples per second, even below the 2Msps the board
+/// can manage cleanly: the board runs at the smallest whole multiple of
+/// `rate` it supports and a `Decimator` brings the samples down to `rate`
+/// before they reach `callback`, still as cs8. Sets the sample rate and
+/// baseband filter to suit, so `set_sample_rate` need not be called. Stop
+/// with `stop_rx`.
+pub fn start_rx_decimated(
+    device: &mut HackRFDevice,
+    rate: f64,
+    mut callback: RxCallback,
+) -> Result<(), HackRFError> {
+    if rate.is_nan() || rate <= 0.0 {
+        return Err(hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM));
+    }
+    let factor = (MIN_HARDWARE_RATE / rate).ceil().max(1.0) as usize;
+    let hardware_rate = rate * factor as f64;
+    set_sample_rate(device, hardware_rate)?;
+    set_baseband_filter_bandwidth(device, compute_baseband_filter_bw(hardware_rate as u32))?;
+    if factor == 1 {
+        return start_rx_owned(device, callback);
+    }
+    let mut decimator = Decimator::new(factor);
+    let mut decimated = Vec::new();
+    start_rx_owned(
+        device,
+        Box::new(move |rx: &[u8]| -> bool {
+            decimated.clear();
+            decimator.process(rx, &mut decimated);
+            decimated.is_empty() || callback(&decimated)
+        }),
+    )
+}
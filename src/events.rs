@@ -0,0 +1,108 @@
+// Lifecycle events reported by streams alongside their samples
+// Licensed under MIT license
+
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use super::HackRFError;
+
+// Events kept for a reader that has fallen behind, or not asked for them.
+// Further events are dropped.
+const QUEUE_LEN: usize = 256;
+
+// Longest run of lost transfers reported as one `Overrun`.
+const MAX_RUN: u64 = 64;
+
+/// Why a stream stopped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// `stop` was called.
+    Requested,
+    /// The stream was dropped, or its callback asked to stop.
+    Finished,
+    /// Streaming failed, as on a USB error or the board being unplugged.
+    Failed,
+}
+
+/// Something that happened to a stream, read from the channel its `events`
+/// returns, so that supervisory code can watch a stream's lifecycle in one
+/// place without touching its samples.
+#[derive(Debug)]
+pub enum StreamEvent {
+    /// Streaming began. Always the first event.
+    Started,
+    /// Streaming ended.
+    Stopped { reason: StopReason },
+    /// Transfers were lost because the queue was full: this many in a row,
+    /// reported once the run ends or reaches 64.
+    Overrun { lost: u64 },
+    /// The TX queue ran dry and silence is being sent, until it is next
+    /// refilled.
+    Underrun,
+    /// The error that stopped a stream, before its `Stopped`.
+    UsbError(HackRFError),
+    /// A callback watched by `CallbackDeadline` has been running this long
+    /// without returning, holding up the USB thread.
+    CallbackStall { elapsed: Duration },
+    /// A stream that failed has been restarted, after being down this long.
+    Reconnected { gap: Duration },
+}
+
+/// Sends a stream's events without ever blocking it.
+#[derive(Clone)]
+pub(crate) struct EventSender {
+    tx: SyncSender<StreamEvent>,
+}
+
+/// The receiving end, until the stream's owner takes it.
+pub(crate) struct Events {
+    rx: Mutex<Option<Receiver<StreamEvent>>>,
+}
+
+pub(crate) fn channel() -> (EventSender, Events) {
+    let (tx, rx) = mpsc::sync_channel(QUEUE_LEN);
+    (
+        EventSender { tx },
+        Events {
+            rx: Mutex::new(Some(rx)),
+        },
+    )
+}
+
+impl EventSender {
+    pub(crate) fn send(&self, event: StreamEvent) {
+        let _ = self.tx.try_send(event);
+    }
+}
+
+impl Events {
+    pub(crate) fn take(&self) -> Option<Receiver<StreamEvent>> {
+        self.rx
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+}
+
+/// Counts transfers lost in a row, to report each run as one `Overrun`.
+#[derive(Default)]
+pub(crate) struct Overruns {
+    run: u64,
+}
+
+impl Overruns {
+    pub(crate) fn lost(&mut self, events: &EventSender) {
+        self.run += 1;
+        if self.run == MAX_RUN {
+            self.delivered(events);
+        }
+    }
+
+    pub(crate) fn delivered(&mut self, events: &EventSender) {
+        if self.run > 0 {
+            events.send(StreamEvent::Overrun { lost: self.run });
+            self.run = 0;
+        }
+    }
+}
@@ -0,0 +1,235 @@
+// Fault injection for testing error handling
+// Licensed under MIT license
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use super::format::SampleFormat;
+use super::sdr::{GainStage, RxCallback, RxChannel, SdrDevice, TxCallback, TxChannel};
+use super::{hackrf_error, HackRFError};
+
+/// Calls that a `FaultInjector` can be told to fail.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    SetFrequency,
+    SetSampleRate,
+    SetRxGain,
+    SetTxGain,
+    StartRx,
+    StopRx,
+    StartTx,
+    StopTx,
+}
+
+#[derive(Default)]
+struct Faults {
+    failures: Vec<(Operation, i32)>,
+    stall: Option<Duration>,
+    short_count: usize,
+    short_len: usize,
+    delay: Option<Duration>,
+    end_stream: bool,
+}
+
+// What to do to the transfer about to be handed to a callback.
+struct Transfer {
+    pause: Duration,
+    len: usize,
+    end: bool,
+}
+
+impl Faults {
+    fn transfer(&mut self, len: usize) -> Transfer {
+        let pause = self.stall.take().unwrap_or_default() + self.delay.unwrap_or_default();
+        let len = if self.short_count > 0 {
+            self.short_count -= 1;
+            len.min(self.short_len)
+        } else {
+            len
+        };
+        let end = std::mem::replace(&mut self.end_stream, false);
+        Transfer { pause, len, end }
+    }
+}
+
+fn lock(faults: &Mutex<Faults>) -> MutexGuard<'_, Faults> {
+    faults.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Arms faults on a `FaultInjector`. Clones share the same injector and may
+/// be used from any thread, including while it is streaming.
+#[derive(Clone)]
+pub struct FaultHandle {
+    faults: Arc<Mutex<Faults>>,
+}
+
+impl FaultHandle {
+    /// Make the next call of `op` fail with the libhackrf error `errno`, one
+    /// of the `ERROR_` constants, without reaching the wrapped device.
+    /// Several failures may be queued; they are used up in order.
+    pub fn fail_next(&self, op: Operation, errno: i32) {
+        lock(&self.faults).failures.push((op, errno));
+    }
+
+    /// Hold the next transfer for `duration` before its callback runs, as
+    /// a stalled USB endpoint would.
+    pub fn stall(&self, duration: Duration) {
+        lock(&self.faults).stall = Some(duration);
+    }
+
+    /// Cut the next `count` transfers short at `len` bytes. RX callbacks
+    /// see fewer bytes; TX callbacks fill fewer, with the rest sent as
+    /// silence.
+    pub fn short_transfers(&self, count: usize, len: usize) {
+        let mut faults = lock(&self.faults);
+        faults.short_count = count;
+        faults.short_len = len;
+    }
+
+    /// Delay every callback by `delay`, or stop delaying with `None`.
+    pub fn delay_callbacks(&self, delay: Option<Duration>) {
+        lock(&self.faults).delay = delay;
+    }
+
+    /// End the stream at the next transfer, without calling the callback,
+    /// as if the board had dropped off the bus.
+    pub fn end_stream(&self) {
+        lock(&self.faults).end_stream = true;
+    }
+}
+
+/// Wraps a `HackRFDevice` or other backend, passing every call through
+/// unless a fault has been armed with its `FaultHandle`, so applications
+/// can exercise their error handling and reconnect paths on demand.
+pub struct FaultInjector<D> {
+    inner: D,
+    faults: Arc<Mutex<Faults>>,
+}
+
+impl<D> FaultInjector<D> {
+    /// Wrap `inner` with no faults armed.
+    pub fn new(inner: D) -> FaultInjector<D> {
+        FaultInjector {
+            inner,
+            faults: Arc::new(Mutex::new(Faults::default())),
+        }
+    }
+
+    /// A handle for arming faults.
+    pub fn handle(&self) -> FaultHandle {
+        FaultHandle {
+            faults: self.faults.clone(),
+        }
+    }
+
+    /// The wrapped backend.
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    /// The wrapped backend, for calls that should bypass fault injection.
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    /// Unwrap the backend.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn check(&self, op: Operation) -> Result<(), HackRFError> {
+        let mut faults = lock(&self.faults);
+        match faults.failures.iter().position(|f| f.0 == op) {
+            Some(i) => Err(hackrf_error(faults.failures.remove(i).1)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<D: SdrDevice<Error = HackRFError>> SdrDevice for FaultInjector<D> {
+    type Error = HackRFError;
+
+    fn set_frequency(&mut self, hz: u64) -> Result<(), HackRFError> {
+        self.check(Operation::SetFrequency)?;
+        self.inner.set_frequency(hz)
+    }
+
+    fn frequency(&self) -> u64 {
+        self.inner.frequency()
+    }
+
+    fn set_sample_rate(&mut self, hz: f64) -> Result<(), HackRFError> {
+        self.check(Operation::SetSampleRate)?;
+        self.inner.set_sample_rate(hz)
+    }
+
+    fn sample_rate(&self) -> f64 {
+        self.inner.sample_rate()
+    }
+
+    fn native_format(&self) -> SampleFormat {
+        self.inner.native_format()
+    }
+}
+
+impl<D: RxChannel<Error = HackRFError>> RxChannel for FaultInjector<D> {
+    fn rx_gain_stages(&self) -> &'static [GainStage] {
+        self.inner.rx_gain_stages()
+    }
+
+    fn set_rx_gain(&mut self, name: &str, db: f64) -> Result<(), HackRFError> {
+        self.check(Operation::SetRxGain)?;
+        self.inner.set_rx_gain(name, db)
+    }
+
+    fn start_rx(&mut self, mut callback: RxCallback) -> Result<(), HackRFError> {
+        self.check(Operation::StartRx)?;
+        let faults = self.faults.clone();
+        self.inner.start_rx(Box::new(move |rx: &[u8]| -> bool {
+            let transfer = lock(&faults).transfer(rx.len());
+            if transfer.end {
+                return false;
+            }
+            thread::sleep(transfer.pause);
+            callback(&rx[..transfer.len])
+        }))
+    }
+
+    fn stop_rx(&mut self) -> Result<(), HackRFError> {
+        self.check(Operation::StopRx)?;
+        self.inner.stop_rx()
+    }
+}
+
+impl<D: TxChannel<Error = HackRFError>> TxChannel for FaultInjector<D> {
+    fn tx_gain_stages(&self) -> &'static [GainStage] {
+        self.inner.tx_gain_stages()
+    }
+
+    fn set_tx_gain(&mut self, name: &str, db: f64) -> Result<(), HackRFError> {
+        self.check(Operation::SetTxGain)?;
+        self.inner.set_tx_gain(name, db)
+    }
+
+    fn start_tx(&mut self, mut callback: TxCallback) -> Result<(), HackRFError> {
+        self.check(Operation::StartTx)?;
+        let faults = self.faults.clone();
+        self.inner.start_tx(Box::new(move |tx: &mut [u8]| -> bool {
+            let transfer = lock(&faults).transfer(tx.len());
+            if transfer.end {
+                tx.fill(0);
+                return false;
+            }
+            thread::sleep(transfer.pause);
+            let (head, tail) = tx.split_at_mut(transfer.len);
+            tail.fill(0);
+            callback(head)
+        }))
+    }
+
+    fn stop_tx(&mut self) -> Result<(), HackRFError> {
+        self.check(Operation::StopTx)?;
+        self.inner.stop_tx()
+    }
+}
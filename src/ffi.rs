@@ -22,6 +22,33 @@ pub const BOARD_ID_JAWBREAKER: u8 = 1;
 pub const BOARD_ID_HACKRF_ONE: u8 = 2;
 pub const BOARD_ID_INVALID: u8 = 0xFF;
 
+pub const USB_BOARD_ID_JAWBREAKER: c_int = 0x604B;
+pub const USB_BOARD_ID_HACKRF_ONE: c_int = 0x6089;
+pub const USB_BOARD_ID_RAD1O: c_int = 0xCC15;
+pub const USB_BOARD_ID_INVALID: c_int = 0xFFFF;
+
+pub const BOARD_REV_UNDETECTED: u8 = 0xFF;
+
+pub const HACKRF_PLATFORM_JAWBREAKER: u32 = 1 << 0;
+pub const HACKRF_PLATFORM_HACKRF1_OG: u32 = 1 << 1;
+pub const HACKRF_PLATFORM_RAD1O: u32 = 1 << 2;
+pub const HACKRF_PLATFORM_HACKRF1_R9: u32 = 1 << 3;
+
+pub const BYTES_PER_BLOCK: usize = 16384;
+pub const MAX_SWEEP_RANGES: usize = 10;
+
+pub const LINEAR: c_uint = 0;
+pub const INTERLEAVED: c_uint = 1;
+
+pub const HACKRF_OPERACAKE_ADDRESS_INVALID: u8 = 0xFF;
+pub const HACKRF_OPERACAKE_MAX_BOARDS: usize = 8;
+pub const HACKRF_OPERACAKE_MAX_DWELL_TIMES: usize = 16;
+pub const HACKRF_OPERACAKE_MAX_FREQ_RANGES: usize = 8;
+
+pub const OPERACAKE_MODE_MANUAL: c_uint = 0;
+pub const OPERACAKE_MODE_FREQUENCY: c_uint = 1;
+pub const OPERACAKE_MODE_TIME: c_uint = 2;
+
 pub const RF_PATH_FILTER_BYPASS: c_uint = 0;
 pub const RF_PATH_FILTER_LOW_PASS: c_uint = 1;
 pub const RF_PATH_FILTER_HIGH_PASS: c_uint = 2;
@@ -30,6 +57,22 @@ pub const RF_PATH_FILTER_HIGH_PASS: c_uint = 2;
 #[repr(C)]
 pub struct hackrf_device;
 
+// Named as in hackrf.h, so that `sys` can check the declarations below.
+#[allow(non_camel_case_types)]
+pub type hackrf_sample_block_cb_fn = extern "C" fn(*mut hackrf_transfer) -> c_int;
+#[allow(non_camel_case_types)]
+pub type hackrf_board_id = c_uint;
+#[allow(non_camel_case_types)]
+pub type hackrf_usb_board_id = c_uint;
+#[allow(non_camel_case_types)]
+pub type board_rev = c_uint;
+#[allow(non_camel_case_types)]
+pub type rf_path_filter = c_uint;
+#[allow(non_camel_case_types)]
+pub type sweep_style = c_uint;
+#[allow(non_camel_case_types)]
+pub type operacake_switching_mode = c_uint;
+
 #[repr(C)]
 pub struct hackrf_transfer {
     pub device: *mut hackrf_device,
@@ -46,27 +89,228 @@ pub struct read_partid_serialno_t {
     pub serial_no: [u32; 4],
 }
 
-#[link(name = "hackrf")]
-extern "C" {
+#[repr(C)]
+pub struct hackrf_device_list_t {
+    pub serial_numbers: *mut *mut c_char,
+    pub usb_board_ids: *mut c_int,
+    pub usb_device_index: *mut c_int,
+    pub devicecount: c_int,
+    pub usb_devices: *mut *mut c_void,
+    pub usb_devicecount: c_int,
+}
+
+#[repr(C)]
+pub struct hackrf_operacake_dwell_time {
+    pub dwell: u32,
+    pub port: u8,
+}
+
+#[repr(C)]
+pub struct hackrf_operacake_freq_range {
+    pub freq_min: u16,
+    pub freq_max: u16,
+    pub port: u8,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct hackrf_m0_state {
+    pub requested_mode: u16,
+    pub request_flag: u16,
+    pub active_mode: u32,
+    pub m0_count: u32,
+    pub m4_count: u32,
+    pub num_shortfalls: u32,
+    pub longest_shortfall: u32,
+    pub shortfall_limit: u32,
+    pub threshold: u32,
+    pub next_mode: u32,
+    pub error: u32,
+}
+
+// Declares the libhackrf functions: linked by build.rs, which locates
+// libhackrf, or with the dlopen feature looked up when the library is first
+// loaded and called through wrappers of the same signature.
+macro_rules! functions {
+    ($($(#[$attr:meta])* pub fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;)*) => {
+        // `sys` declares the same functions over its own struct types.
+        #[cfg(not(feature = "dlopen"))]
+        #[cfg_attr(feature = "bindgen", allow(clashing_extern_declarations))]
+        extern "C" {
+            $($(#[$attr])* pub fn $name($($arg: $ty),*) $(-> $ret)?;)*
+        }
+
+        #[cfg(feature = "dlopen")]
+        struct Functions {
+            $($(#[$attr])* $name: unsafe extern "C" fn($($ty),*) $(-> $ret)?,)*
+        }
+
+        #[cfg(feature = "dlopen")]
+        impl Functions {
+            unsafe fn load(lib: &libloading::Library) -> Result<Functions, libloading::Error> {
+                Ok(Functions {
+                    $(
+                        $(#[$attr])*
+                        $name: *lib.get(concat!(stringify!($name), "\0").as_bytes())?,
+                    )*
+                })
+            }
+        }
+
+        $(
+            #[cfg(feature = "dlopen")]
+            $(#[$attr])*
+            pub unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                (functions().$name)($($arg),*)
+            }
+        )*
+
+        // With the bindgen feature, fail the build if a declaration has
+        // drifted from the installed hackrf.h: the same signatures, naming
+        // types as the header does, are resolved here against `sys`, and
+        // must match the generated bindings exactly.
+        #[cfg(feature = "bindgen")]
+        #[allow(dead_code, unused_imports)]
+        mod signatures {
+            use super::super::sys::*;
+            use std::ffi::{c_char, c_double, c_int, c_uint, c_void};
+
+            fn check() {
+                $(
+                    $(#[$attr])*
+                    let _: unsafe extern "C" fn($($ty),*) $(-> $ret)? = $name;
+                )*
+            }
+        }
+    };
+}
+
+#[cfg(feature = "dlopen")]
+struct Loaded {
+    // Kept open for as long as `functions` point into it.
+    _library: libloading::Library,
+    functions: Functions,
+}
+
+#[cfg(feature = "dlopen")]
+static LOADED: std::sync::OnceLock<Result<Loaded, String>> = std::sync::OnceLock::new();
+
+// File names tried in turn when HACKRF_LIBRARY is not set.
+#[cfg(all(feature = "dlopen", target_os = "windows"))]
+const LIBRARY_NAMES: [&str; 1] = ["hackrf.dll"];
+#[cfg(all(feature = "dlopen", target_os = "macos"))]
+const LIBRARY_NAMES: [&str; 2] = ["libhackrf.0.dylib", "libhackrf.dylib"];
+#[cfg(all(
+    feature = "dlopen",
+    not(any(target_os = "windows", target_os = "macos"))
+))]
+const LIBRARY_NAMES: [&str; 2] = ["libhackrf.so.0", "libhackrf.so"];
+
+/// Load libhackrf, once, from `HACKRF_LIBRARY` if set or else by its usual
+/// file names, returning why it could not be if not.
+#[cfg(feature = "dlopen")]
+pub fn load() -> Result<(), String> {
+    library().map(|_| ()).map_err(str::to_string)
+}
+
+#[cfg(feature = "dlopen")]
+fn library() -> Result<&'static Loaded, &'static str> {
+    let loaded = LOADED.get_or_init(|| {
+        let names: Vec<std::ffi::OsString> = match std::env::var_os("HACKRF_LIBRARY") {
+            Some(path) => vec![path],
+            None => LIBRARY_NAMES.iter().map(|name| name.into()).collect(),
+        };
+        let mut errors = Vec::new();
+        for name in &names {
+            // Loading runs the library's initialisers, which libhackrf has
+            // none of, and the symbols are checked against `Functions`.
+            match unsafe { libloading::Library::new(name) } {
+                Ok(library) => {
+                    return match unsafe { Functions::load(&library) } {
+                        Ok(functions) => Ok(Loaded {
+                            _library: library,
+                            functions,
+                        }),
+                        Err(err) => Err(format!("{}: {}", name.to_string_lossy(), err)),
+                    };
+                }
+                Err(err) => errors.push(err.to_string()),
+            }
+        }
+        Err(errors.join("; "))
+    });
+    loaded.as_ref().map_err(String::as_str)
+}
+
+/// Whether libhackrf has been loaded.
+#[cfg(feature = "dlopen")]
+pub fn loaded() -> bool {
+    LOADED.get().is_some_and(|loaded| loaded.is_ok())
+}
+
+#[cfg(feature = "dlopen")]
+fn functions() -> &'static Functions {
+    match library() {
+        Ok(loaded) => &loaded.functions,
+        Err(err) => panic!("libhackrf could not be loaded: {}", err),
+    }
+}
+
+functions! {
     pub fn hackrf_init() -> c_int;
     pub fn hackrf_exit() -> c_int;
 
+    pub fn hackrf_library_version() -> *const c_char;
+    pub fn hackrf_library_release() -> *const c_char;
+
+    pub fn hackrf_device_list() -> *mut hackrf_device_list_t;
+    pub fn hackrf_device_list_open(
+        list: *mut hackrf_device_list_t,
+        idx: c_int,
+        device: *mut *mut hackrf_device,
+    ) -> c_int;
+    pub fn hackrf_device_list_free(list: *mut hackrf_device_list_t);
+
     pub fn hackrf_open(device: *mut *mut hackrf_device) -> c_int;
+    pub fn hackrf_open_by_serial(
+        desired_serial_number: *const c_char,
+        device: *mut *mut hackrf_device,
+    ) -> c_int;
     pub fn hackrf_close(device: *mut hackrf_device) -> c_int;
+    // Only in libhackrf builds for Android, where the device node cannot be
+    // opened directly: wraps a descriptor from UsbManager with libusb.
+    #[cfg(target_os = "android")]
+    pub fn hackrf_open_by_fd(fd: c_int, device: *mut *mut hackrf_device) -> c_int;
 
     pub fn hackrf_start_rx(
         device: *mut hackrf_device,
-        callback: extern "C" fn(*mut hackrf_transfer) -> c_int,
+        callback: hackrf_sample_block_cb_fn,
         rx_ctx: *mut c_void,
     ) -> c_int;
     pub fn hackrf_stop_rx(device: *mut hackrf_device) -> c_int;
     pub fn hackrf_start_tx(
         device: *mut hackrf_device,
-        callback: extern "C" fn(*mut hackrf_transfer) -> c_int,
+        callback: hackrf_sample_block_cb_fn,
         tx_ctx: *mut c_void,
     ) -> c_int;
     pub fn hackrf_stop_tx(device: *mut hackrf_device) -> c_int;
 
+    // Frequency list is pairs of start/stop frequencies in MHz
+    pub fn hackrf_init_sweep(
+        device: *mut hackrf_device,
+        frequency_list: *const u16,
+        num_ranges: c_int,
+        num_bytes: u32,
+        step_width: u32,
+        offset: u32,
+        style: sweep_style,
+    ) -> c_int;
+    pub fn hackrf_start_rx_sweep(
+        device: *mut hackrf_device,
+        callback: hackrf_sample_block_cb_fn,
+        rx_ctx: *mut c_void,
+    ) -> c_int;
+
     // Returns HACKRF_TRUE=1 if success
     pub fn hackrf_is_streaming(device: *mut hackrf_device) -> c_int;
 
@@ -81,6 +325,8 @@ extern "C" {
         version: *mut c_char,
         length: u8,
     ) -> c_int;
+    pub fn hackrf_board_rev_read(device: *mut hackrf_device, value: *mut u8) -> c_int;
+    pub fn hackrf_supported_platform_read(device: *mut hackrf_device, value: *mut u32) -> c_int;
     pub fn hackrf_board_partid_serialno_read(
         device: *mut hackrf_device,
         read_partid_serialno: *mut read_partid_serialno_t,
@@ -91,7 +337,7 @@ extern "C" {
         device: *mut hackrf_device,
         if_freq_hz: u64,
         lo_freq_hz: u64,
-        path: c_uint,
+        path: rf_path_filter,
     ) -> c_int;
 
     // Currently 8-20MHz, either as a fraction:
@@ -118,9 +364,107 @@ extern "C" {
     // Antenna port power control
     pub fn hackrf_set_antenna_enable(device: *mut hackrf_device, value: u8) -> c_int;
 
+    // SPI flash holding the firmware image
+    pub fn hackrf_spiflash_erase(device: *mut hackrf_device) -> c_int;
+    pub fn hackrf_spiflash_write(
+        device: *mut hackrf_device,
+        address: u32,
+        length: u16,
+        // Declared `unsigned char* const` in hackrf.h, though only read.
+        data: *mut u8,
+    ) -> c_int;
+    pub fn hackrf_spiflash_read(
+        device: *mut hackrf_device,
+        address: u32,
+        length: u16,
+        data: *mut u8,
+    ) -> c_int;
+    pub fn hackrf_spiflash_status(device: *mut hackrf_device, data: *mut u8) -> c_int;
+    pub fn hackrf_spiflash_clear_status(device: *mut hackrf_device) -> c_int;
+
+    pub fn hackrf_reset(device: *mut hackrf_device) -> c_int;
+
+    // Opera Cake antenna switch add-on boards
+    pub fn hackrf_get_operacake_boards(device: *mut hackrf_device, boards: *mut u8) -> c_int;
+    pub fn hackrf_set_operacake_mode(
+        device: *mut hackrf_device,
+        address: u8,
+        mode: operacake_switching_mode,
+    ) -> c_int;
+    pub fn hackrf_get_operacake_mode(
+        device: *mut hackrf_device,
+        address: u8,
+        mode: *mut operacake_switching_mode,
+    ) -> c_int;
+    pub fn hackrf_set_operacake_ports(
+        device: *mut hackrf_device,
+        address: u8,
+        port_a: u8,
+        port_b: u8,
+    ) -> c_int;
+    pub fn hackrf_set_operacake_dwell_times(
+        device: *mut hackrf_device,
+        dwell_times: *mut hackrf_operacake_dwell_time,
+        count: u8,
+    ) -> c_int;
+    pub fn hackrf_set_operacake_freq_ranges(
+        device: *mut hackrf_device,
+        freq_ranges: *mut hackrf_operacake_freq_range,
+        count: u8,
+    ) -> c_int;
+    pub fn hackrf_operacake_gpio_test(
+        device: *mut hackrf_device,
+        address: u8,
+        test_result: *mut u16,
+    ) -> c_int;
+
+    // Direct register access to the transceiver, clock generator and mixer
+    pub fn hackrf_max2837_read(
+        device: *mut hackrf_device,
+        register_number: u8,
+        value: *mut u16,
+    ) -> c_int;
+    pub fn hackrf_max2837_write(
+        device: *mut hackrf_device,
+        register_number: u8,
+        value: u16,
+    ) -> c_int;
+    pub fn hackrf_si5351c_read(
+        device: *mut hackrf_device,
+        register_number: u16,
+        value: *mut u16,
+    ) -> c_int;
+    pub fn hackrf_si5351c_write(
+        device: *mut hackrf_device,
+        register_number: u16,
+        value: u16,
+    ) -> c_int;
+    pub fn hackrf_rffc5071_read(
+        device: *mut hackrf_device,
+        register_number: u8,
+        value: *mut u16,
+    ) -> c_int;
+    pub fn hackrf_rffc5071_write(
+        device: *mut hackrf_device,
+        register_number: u8,
+        value: u16,
+    ) -> c_int;
+
+    // 10MHz reference clock input and output
+    pub fn hackrf_set_clkout_enable(device: *mut hackrf_device, value: u8) -> c_int;
+    pub fn hackrf_get_clkin_status(device: *mut hackrf_device, status: *mut u8) -> c_int;
+
+    // State of the M0 coprocessor moving samples between USB and the CPLD
+    pub fn hackrf_get_m0_state(device: *mut hackrf_device, value: *mut hackrf_m0_state) -> c_int;
+
+    // Hold streaming until a pulse arrives on the trigger input
+    pub fn hackrf_set_hw_sync_mode(device: *mut hackrf_device, value: u8) -> c_int;
+
     pub fn hackrf_error_name(errcode: c_int) -> *const c_char;
-    pub fn hackrf_board_id_name(hackrf_board_id: u8) -> *const c_char;
-    pub fn hackrf_filter_path_name(path: c_uint) -> *const c_char;
+    pub fn hackrf_board_id_name(board_id: hackrf_board_id) -> *const c_char;
+    pub fn hackrf_filter_path_name(path: rf_path_filter) -> *const c_char;
+    pub fn hackrf_usb_board_id_name(usb_board_id: hackrf_usb_board_id) -> *const c_char;
+    pub fn hackrf_board_rev_name(rev: board_rev) -> *const c_char;
 
     // Compute nearest freq for bw filter (manual filter)
     pub fn hackrf_compute_baseband_filter_bw_round_down_lt(bandwidth_hz: u32) -> u32;
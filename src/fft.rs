@@ -0,0 +1,213 @@
+// FFT power spectra of cs8 samples
+// Licensed under MIT license
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustfft::num_complex::Complex32;
+use rustfft::{Fft, FftPlanner};
+
+use super::stream::RxStream;
+use super::{ffi, hackrf_error, HackRFDevice, HackRFError};
+
+const RECV_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Window applied to each block before its FFT.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    /// 4-term Blackman-Harris, for low leakage from strong signals.
+    BlackmanHarris,
+    /// Flat top, for accurate levels of tones between bins.
+    FlatTop,
+}
+
+impl Window {
+    /// The window's `size` coefficients.
+    pub fn coefficients(self, size: usize) -> Vec<f32> {
+        let terms: &[f32] = match self {
+            Window::Rectangular => &[1.0],
+            Window::Hann => &[0.5, 0.5],
+            Window::Hamming => &[0.54, 0.46],
+            Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            Window::FlatTop => &[
+                0.215_578_95,
+                0.416_631_6,
+                0.277_263_16,
+                0.083_578_95,
+                0.006_947_368,
+            ],
+        };
+        (0..size)
+            .map(|i| {
+                let x = 2.0 * PI * i as f32 / (size - 1) as f32;
+                terms
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * (k as f32 * x).cos()
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// Computes windowed power spectra of blocks of cs8 samples, reusing its
+/// FFT plan and buffers between calls. Any FFT size is supported.
+pub struct PowerSpectrum {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex32>,
+    scratch: Vec<Complex32>,
+    power: Vec<f32>,
+}
+
+impl PowerSpectrum {
+    /// Prepare for FFTs of `size` samples, using a Hann window.
+    pub fn new(size: usize) -> PowerSpectrum {
+        PowerSpectrum::with_window(size, Window::Hann)
+    }
+
+    /// Prepare for FFTs of `size` samples, using `window`.
+    pub fn with_window(size: usize, window: Window) -> PowerSpectrum {
+        assert!(size > 1);
+        let fft = FftPlanner::new().plan_fft_forward(size);
+        let scratch = vec![Complex32::default(); fft.get_inplace_scratch_len()];
+        let window = window.coefficients(size);
+        PowerSpectrum {
+            fft,
+            window,
+            buffer: vec![Complex32::default(); size],
+            scratch,
+            power: vec![0.0; size],
+        }
+    }
+
+    /// Number of samples (and bins) in each FFT.
+    pub fn size(&self) -> usize {
+        self.window.len()
+    }
+
+    /// Compute the power in each bin, in dB relative to a full-scale tone
+    /// at the centre of a bin (before windowing loss), for `size()` cs8
+    /// samples. Bins are in FFT order: DC first, negative frequencies in the
+    /// second half. Matches `hackrf_sweep`'s scaling.
+    pub fn compute(&mut self, cs8: &[u8]) -> &[f32] {
+        assert_eq!(cs8.len(), self.size() * 2);
+        for ((iq, w), out) in cs8
+            .chunks_exact(2)
+            .zip(self.window.iter())
+            .zip(self.buffer.iter_mut())
+        {
+            *out = Complex32::new(
+                iq[0] as i8 as f32 / 128.0 * w,
+                iq[1] as i8 as f32 / 128.0 * w,
+            );
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+        let scale = 1.0 / self.size() as f32;
+        for (bin, p) in self.buffer.iter().zip(self.power.iter_mut()) {
+            *p = 10.0 * (bin * scale).norm_sqr().log10();
+        }
+        &self.power
+    }
+
+    // Correction in dB that makes a full-scale tone at the centre of a bin
+    // read 0dB, undoing the window's loss in amplitude.
+    pub(crate) fn window_correction_db(&self) -> f64 {
+        let gain = self.window.iter().sum::<f32>() / self.size() as f32;
+        -20.0 * (gain as f64).log10()
+    }
+}
+
+// Average of `count` spectra whose linear powers are summed in `linear`, in
+// FFT order, as dB from the lowest frequency up.
+pub(crate) fn average_db(linear: &[f64], count: usize, correction_db: f64) -> Vec<f32> {
+    let mut power: Vec<f32> = linear
+        .iter()
+        .map(|p| (10.0 * (p / count as f64).log10() + correction_db) as f32)
+        .collect();
+    power.rotate_left(linear.len().div_ceil(2));
+    power
+}
+
+/// A power spectrum of the band around the current tuning.
+#[derive(Clone, Debug)]
+pub struct Spectrum {
+    /// Centre frequency the board was tuned to, 0 if never tuned.
+    pub center_hz: u64,
+    pub bin_width_hz: f64,
+    /// Power in each bin in dBFS, from the lowest frequency up, with the
+    /// centre frequency in bin `power_dbfs.len() / 2`. A full-scale tone at
+    /// the centre of a bin reads 0dBFS whatever the window.
+    pub power_dbfs: Vec<f32>,
+}
+
+impl Spectrum {
+    /// Frequency at the centre of bin `bin`.
+    pub fn bin_hz(&self, bin: usize) -> f64 {
+        let offset = bin as f64 - (self.power_dbfs.len() / 2) as f64;
+        self.center_hz as f64 + offset * self.bin_width_hz
+    }
+}
+
+/// Receive briefly and return the average of `averages` power spectra of
+/// `fft_size` samples each, using `window`, at the current frequency,
+/// sample rate and gains. The board's DC spike shows at the centre bin.
+/// Streaming must be stopped, and is stopped again on return.
+pub fn spectrum(
+    device: &mut HackRFDevice,
+    fft_size: usize,
+    averages: usize,
+    window: Window,
+) -> Result<Spectrum, HackRFError> {
+    assert!(averages > 0);
+    let mut fft = PowerSpectrum::with_window(fft_size, window);
+    let stream = RxStream::start(device, 16)?;
+    let mut linear = vec![0.0f64; fft_size];
+    let mut pending = Vec::with_capacity(fft_size * 2);
+    let mut averaged = 0;
+    let mut result = Ok(());
+    // The first transfer may predate the last retune.
+    let mut skip = true;
+    while averaged < averages {
+        let buf = match stream.recv_timeout(RECV_TIMEOUT) {
+            Ok(buf) => buf,
+            Err(_) => {
+                result = Err(hackrf_error(ffi::HACKRF_ERROR_STREAMING_STOPPED));
+                break;
+            }
+        };
+        if std::mem::replace(&mut skip, false) {
+            stream.recycle(buf);
+            continue;
+        }
+        let mut rest = &buf[..];
+        while !rest.is_empty() && averaged < averages {
+            let n = std::cmp::min(fft_size * 2 - pending.len(), rest.len());
+            pending.extend_from_slice(&rest[..n]);
+            rest = &rest[n..];
+            if pending.len() == fft_size * 2 {
+                for (sum, p) in linear.iter_mut().zip(fft.compute(&pending)) {
+                    *sum += 10f64.powf(*p as f64 / 10.0);
+                }
+                pending.clear();
+                averaged += 1;
+            }
+        }
+        stream.recycle(buf);
+    }
+    stream.stop(device)?;
+    result?;
+    Ok(Spectrum {
+        center_hz: device.freq_hz,
+        bin_width_hz: device.sample_rate_hz / fft_size as f64,
+        power_dbfs: average_db(&linear, averages, fft.window_correction_db()),
+    })
+}
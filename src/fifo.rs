@@ -0,0 +1,164 @@
+// Named pipes for exchanging samples with GNU Radio
+// Licensed under MIT license
+
+use std::ffi::CString;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::os::raw::{c_char, c_int};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::time::Duration;
+
+use super::file::FileSource;
+use super::format::SampleFormat;
+use super::recorder::Recorder;
+use super::stream::TxStream;
+use super::{HackRFDevice, HackRFError, StopHandle};
+
+// Bytes of cs8 read from the pipe per queued TX buffer: one USB transfer.
+const CHUNK_LEN: usize = 262_144;
+// Buffers queued between the pipe and the TX callback.
+const QUEUE_LEN: usize = 16;
+// How long to wait for queued samples to go out once the writer closes.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+// Permissions for new pipes, less the umask, as the mkfifo tool uses.
+const FIFO_MODE: ModeT = 0o666;
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+type ModeT = u16;
+#[cfg(not(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+)))]
+type ModeT = u32;
+
+extern "C" {
+    fn mkfifo(path: *const c_char, mode: ModeT) -> c_int;
+}
+
+/// Create a named pipe at `path`, or do nothing if one is already there.
+pub fn create_fifo<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    match fs::metadata(path) {
+        Ok(meta) if meta.file_type().is_fifo() => return Ok(()),
+        Ok(_) => {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} exists and is not a FIFO", path.display()),
+            ))
+        }
+        Err(ref err) if err.kind() == ErrorKind::NotFound => (),
+        Err(err) => return Err(err),
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { mkfifo(c_path.as_ptr(), FIFO_MODE) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Receive into the named pipe at `path`, creating it if needed, in
+/// `format`. Blocks until a reader such as GNU Radio's File Source opens
+/// the pipe. Stop with `Recorder::stop`; if the reader goes away first,
+/// receiving ends and the broken pipe is reported by `Recorder::errors`.
+pub fn rx_fifo<P: AsRef<Path>>(
+    device: &mut HackRFDevice,
+    path: P,
+    format: SampleFormat,
+) -> Result<Recorder, HackRFError> {
+    create_fifo(&path)?;
+    Recorder::builder(path).format(format).start(device)
+}
+
+/// Transmit samples in `format` written to the named pipe at `path`,
+/// creating it if needed, until the writer closes the pipe or `stop` is
+/// triggered. Blocks until a writer such as GNU Radio's File Sink (with
+/// "unbuffered" on) opens the pipe; `stop` is checked between reads.
+/// Silence is sent whenever the writer falls behind.
+pub fn transmit_fifo<P: AsRef<Path>>(
+    device: &mut HackRFDevice,
+    path: P,
+    format: SampleFormat,
+    stop: &StopHandle,
+) -> Result<(), HackRFError> {
+    create_fifo(&path)?;
+    let mut source = FileSource::open(path, format)?;
+    let stream = TxStream::start(device, QUEUE_LEN)?;
+    let mut result = Ok(());
+    while !stop.is_stopped() {
+        let mut buf = vec![0; CHUNK_LEN];
+        let n = match source.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) => {
+                result = Err(HackRFError::from(err));
+                break;
+            }
+        };
+        buf.truncate(n);
+        if stream.send(buf).is_err() {
+            break;
+        }
+    }
+    if !stop.is_stopped() {
+        stream.flush(FLUSH_TIMEOUT);
+    }
+    stream.stop(device)?;
+    result
+}
+
+/// Describe a GNU Radio block connected to the named pipe at `path`, in
+/// the YAML of a `.grc` file's `blocks:` list: `samp_rate` and
+/// `center_freq` variables taken from `device`, and a File Source (for
+/// `rx_fifo`, when `rx` is true) or File Sink (for `transmit_fifo`) of the
+/// matching item type. Cs8 and cs16 travel as interleaved bytes or shorts,
+/// so pair them with GNU Radio's IChar/IShort conversion blocks.
+pub fn grc_blocks<P: AsRef<Path>>(
+    device: &HackRFDevice,
+    path: P,
+    format: SampleFormat,
+    rx: bool,
+) -> String {
+    let item_type = match format {
+        SampleFormat::Cs8 => "byte",
+        SampleFormat::Cs16 => "short",
+        SampleFormat::Cf32 => "complex",
+    };
+    let mut grc = String::new();
+    for &(name, value) in &[
+        ("samp_rate", device.sample_rate_hz),
+        ("center_freq", device.freq_hz as f64),
+    ] {
+        let _ = write!(
+            grc,
+            "- name: {}\n  id: variable\n  parameters:\n    value: '{}'\n  \
+             states:\n    state: enabled\n",
+            name, value
+        );
+    }
+    let (name, id, option) = if rx {
+        ("hackrf_rx", "blocks_file_source", "repeat: 'False'")
+    } else {
+        ("hackrf_tx", "blocks_file_sink", "unbuffered: 'True'")
+    };
+    let _ = write!(
+        grc,
+        "- name: {}\n  id: {}\n  parameters:\n    file: '{}'\n    type: {}\n    \
+         vlen: '1'\n    {}\n  states:\n    state: enabled\n",
+        name,
+        id,
+        path.as_ref().display(),
+        item_type,
+        option
+    );
+    grc
+}
@@ -0,0 +1,168 @@
+// Transmitting I/Q samples from files
+// Licensed under MIT license
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::format::SampleFormat;
+use super::tx::wait_tx_finished;
+use super::{start_tx, HackRFDevice, HackRFError, StopHandle};
+
+/// A file of I/Q samples to transmit with `transmit_file`, the equivalent of
+/// `hackrf_transfer -t`. Samples are read as the TX stream asks for them, so
+/// they go out at whatever sample rate the device is configured for.
+pub struct FileSource {
+    reader: BufReader<File>,
+    format: SampleFormat,
+    looping: bool,
+    progress: Option<Box<dyn FnMut(u64) + Send>>,
+    scratch: Vec<u8>,
+    sent: u64,
+    // Samples per second to read at, when the first read happened, and
+    // samples read since.
+    throttle: Option<(f64, Option<Instant>, u64)>,
+}
+
+impl FileSource {
+    /// Open `path`, which holds samples in `format`.
+    pub fn open<P: AsRef<Path>>(path: P, format: SampleFormat) -> io::Result<FileSource> {
+        Ok(FileSource {
+            reader: BufReader::new(File::open(path)?),
+            format,
+            looping: false,
+            progress: None,
+            scratch: Vec::new(),
+            sent: 0,
+            throttle: None,
+        })
+    }
+
+    /// Restart from the beginning of the file when the end is reached,
+    /// instead of finishing the transmission.
+    pub fn looping(mut self, looping: bool) -> FileSource {
+        self.looping = looping;
+        self
+    }
+
+    /// Call `progress` after each USB transfer with the total number of
+    /// samples sent so far.
+    pub fn on_progress<F: FnMut(u64) + Send + 'static>(mut self, progress: F) -> FileSource {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Pace reads through `Read` to the real time the samples span at
+    /// `sample_rate`, sped up or slowed down by `speed`, so a recording can
+    /// stand in for a live stream when feeding demodulators, a network
+    /// server or anything else that expects samples to arrive as the board
+    /// would deliver them. Each read blocks until its samples would have
+    /// been received, counting from the first read. Does not affect
+    /// `transmit_file`, which the device paces.
+    pub fn throttle(mut self, sample_rate: f64, speed: f64) -> FileSource {
+        assert!(sample_rate > 0.0 && speed > 0.0);
+        self.throttle = Some((sample_rate * speed, None, 0));
+        self
+    }
+
+    /// Fill `tx` with cs8 samples from the file, rewinding if looping.
+    /// Returns the number of bytes written, which is less than `tx.len()`
+    /// only once the file is exhausted.
+    fn fill(&mut self, tx: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        let mut rewound = false;
+        while filled < tx.len() {
+            let n = self.read_cs8(&mut tx[filled..])?;
+            if n > 0 {
+                filled += n;
+                rewound = false;
+            } else if self.looping && !rewound {
+                self.reader.seek(SeekFrom::Start(0))?;
+                rewound = true;
+            } else {
+                break;
+            }
+        }
+        Ok(filled)
+    }
+
+    fn read_cs8(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.format == SampleFormat::Cs8 {
+            return read_full(&mut self.reader, out);
+        }
+        self.scratch.resize(out.len() / 2 * self.format.bytes_per_sample(), 0);
+        let n = read_full(&mut self.reader, &mut self.scratch)?;
+        // Any trailing partial sample is dropped.
+        Ok(self.format.to_cs8(&self.scratch[..n], out))
+    }
+}
+
+/// Reading a `FileSource` yields cs8 samples, converted and looped as
+/// configured, so the same source can feed offline processing.
+impl Read for FileSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill(buf)?;
+        if let Some((rate, ref mut start, ref mut read)) = self.throttle {
+            let start = *start.get_or_insert_with(Instant::now);
+            *read += n as u64 / 2;
+            let due = start + Duration::from_secs_f64(*read as f64 / rate);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Read until `buf` is full or the reader reaches end of file.
+pub(crate) fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match reader.read(&mut buf[total..]) {
+            Ok(0) => break,
+            Ok(n) => total += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
+}
+
+/// Transmit the contents of `source`, blocking until the file has been sent
+/// (never, if it is looping) or `stop` is triggered.
+pub fn transmit_file(
+    device: &mut HackRFDevice,
+    source: &mut FileSource,
+    stop: &StopHandle,
+) -> Result<(), HackRFError> {
+    let mut error = None;
+    let mut finished = false;
+    let mut callback = |tx: &mut [u8]| -> bool {
+        if finished {
+            tx.fill(0);
+            return false;
+        }
+        let n = match source.fill(tx) {
+            Ok(n) => n,
+            Err(err) => {
+                error = Some(err);
+                0
+            }
+        };
+        tx[n..].fill(0);
+        finished = n < tx.len();
+        source.sent += n as u64 / 2;
+        if let Some(ref mut progress) = source.progress {
+            progress(source.sent);
+        }
+        true
+    };
+    start_tx(device, &mut callback)?;
+    wait_tx_finished(device, stop)?;
+    match error {
+        Some(err) => Err(HackRFError::from(err)),
+        None => Ok(()),
+    }
+}
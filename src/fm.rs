@@ -0,0 +1,127 @@
+// FM demodulation to audio
+// Licensed under MIT license
+
+use super::decimate::{cs8_pairs, Decimator};
+use super::resample::Resampler;
+use super::sdr::RxCallback;
+
+/// Which kind of FM a `FmDemodulator` receives.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FmMode {
+    /// Broadcast FM: 75kHz deviation, 50us de-emphasis.
+    Wide,
+    /// Two-way radio: 5kHz deviation, no de-emphasis.
+    Narrow,
+}
+
+impl FmMode {
+    // Lowest rate the discriminator runs at.
+    fn quadrature_rate(self) -> u32 {
+        match self {
+            FmMode::Wide => 240_000,
+            FmMode::Narrow => 48_000,
+        }
+    }
+
+    // Peak deviation, which demodulates to full scale.
+    fn deviation_hz(self) -> f32 {
+        match self {
+            FmMode::Wide => 75e3,
+            FmMode::Narrow => 5e3,
+        }
+    }
+}
+
+/// Turns cs8 samples of an FM signal at the centre of the band into audio:
+/// decimates to a rate just wide enough for the signal, takes the change in
+/// phase between samples, applies de-emphasis and resamples to the audio
+/// rate. Mono only.
+pub struct FmDemodulator {
+    decimator: Decimator,
+    resampler: Resampler,
+    // Inverse of the phase step, in radians, at full deviation.
+    gain: f32,
+    previous: [f32; 2],
+    // De-emphasis filter coefficient (0 for none) and state.
+    alpha: f32,
+    emphasised: f32,
+    quadrature_rate: f64,
+    iq: Vec<[f32; 2]>,
+    demodulated: Vec<[f32; 2]>,
+    audio: Vec<[f32; 2]>,
+}
+
+impl FmDemodulator {
+    /// Demodulate samples at `sample_rate` into audio at `audio_rate`.
+    pub fn new(mode: FmMode, sample_rate: u32, audio_rate: u32) -> FmDemodulator {
+        let factor = (sample_rate / mode.quadrature_rate()).max(1);
+        let quadrature_rate = sample_rate as f64 / factor as f64;
+        let gain = quadrature_rate as f32 / (2.0 * std::f32::consts::PI * mode.deviation_hz());
+        let demodulator = FmDemodulator {
+            decimator: Decimator::new(factor as usize),
+            resampler: Resampler::new(audio_rate as usize * factor as usize, sample_rate as usize),
+            gain,
+            previous: [1.0, 0.0],
+            alpha: 0.0,
+            emphasised: 0.0,
+            quadrature_rate,
+            iq: Vec::new(),
+            demodulated: Vec::new(),
+            audio: Vec::new(),
+        };
+        match mode {
+            FmMode::Wide => demodulator.deemphasis_us(50.0),
+            FmMode::Narrow => demodulator,
+        }
+    }
+
+    /// Set the de-emphasis time constant, such as 75us for broadcast FM in
+    /// the Americas, or 0 to turn de-emphasis off.
+    pub fn deemphasis_us(mut self, tau_us: f64) -> FmDemodulator {
+        self.alpha = if tau_us > 0.0 {
+            (1.0 - (-1.0 / (tau_us * 1e-6 * self.quadrature_rate)).exp()) as f32
+        } else {
+            0.0
+        };
+        self
+    }
+
+    /// Demodulate `cs8`, appending audio samples to `audio`, full deviation
+    /// giving +-1.0.
+    pub fn process(&mut self, cs8: &[u8], audio: &mut Vec<f32>) {
+        self.iq.clear();
+        self.iq.extend(cs8_pairs(cs8));
+        self.demodulated.clear();
+        self.decimator.process_f32(&self.iq, &mut self.demodulated);
+        for sample in self.demodulated.iter_mut() {
+            let [i, q] = *sample;
+            let [pi, pq] = self.previous;
+            // Phase of this sample relative to the last.
+            let mut x = (q * pi - i * pq).atan2(i * pi + q * pq) * self.gain;
+            self.previous = [i, q];
+            if self.alpha > 0.0 {
+                self.emphasised += self.alpha * (x - self.emphasised);
+                x = self.emphasised;
+            }
+            *sample = [x, 0.0];
+        }
+        self.audio.clear();
+        self.resampler
+            .process_f32(&self.demodulated, &mut self.audio);
+        audio.extend(self.audio.iter().map(|s| s[0]));
+    }
+
+    /// Wrap `callback` into an RX callback that receives audio, for use
+    /// with `RxChannel::start_rx` and the adapters built on it.
+    pub fn wrap<F>(mut self, mut callback: F) -> RxCallback
+    where
+        F: FnMut(&[f32]) -> bool + Send + 'static,
+    {
+        let mut audio = Vec::new();
+        Box::new(move |rx: &[u8]| -> bool {
+            audio.clear();
+            self.process(rx, &mut audio);
+            audio.is_empty() || callback(&audio)
+        })
+    }
+}
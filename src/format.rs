@@ -0,0 +1,107 @@
+// I/Q sample formats and conversions
+// Licensed under MIT license
+
+/// On-disk/on-wire representations of complex baseband samples.
+/// The HackRF itself always produces and consumes `Cs8`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Interleaved signed 8-bit I/Q, as used by `hackrf_transfer`.
+    Cs8,
+    /// Interleaved little-endian signed 16-bit I/Q, full scale at +-32767.
+    Cs16,
+    /// Interleaved little-endian 32-bit float I/Q, scaled to +-1.0.
+    Cf32,
+}
+
+impl SampleFormat {
+    /// Number of bytes used to store one complex sample.
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            SampleFormat::Cs8 => 2,
+            SampleFormat::Cs16 => 4,
+            SampleFormat::Cf32 => 8,
+        }
+    }
+
+    /// Convert cs8 bytes to this format, appending them to `output`.
+    pub fn from_cs8(self, input: &[u8], output: &mut Vec<u8>) {
+        match self {
+            SampleFormat::Cs8 => output.extend_from_slice(input),
+            SampleFormat::Cs16 => {
+                let start = output.len();
+                output.resize(start + input.len() * 2, 0);
+                cs8_to_cs16(input, &mut output[start..]);
+            }
+            SampleFormat::Cf32 => {
+                let start = output.len();
+                output.resize(start + input.len() * 4, 0);
+                cs8_to_cf32(input, &mut output[start..]);
+            }
+        }
+    }
+
+    /// Convert bytes in this format to cs8, filling `output` from as many
+    /// whole samples of `input` as fit. Returns the number of cs8 bytes
+    /// written.
+    pub fn to_cs8(self, input: &[u8], output: &mut [u8]) -> usize {
+        let scale = self.bytes_per_sample() / 2;
+        let n = std::cmp::min(input.len() / self.bytes_per_sample() * 2, output.len() & !1);
+        let input = &input[..n * scale];
+        match self {
+            SampleFormat::Cs8 => output[..n].copy_from_slice(input),
+            SampleFormat::Cs16 => cs16_to_cs8(input, &mut output[..n]),
+            SampleFormat::Cf32 => cf32_to_cs8(input, &mut output[..n]),
+        }
+        n
+    }
+}
+
+/// Convert little-endian cf32 bytes to cs8, writing `input.len() / 4` bytes
+/// to `output`. Scaled by 128, the inverse of `cs8_to_cf32`, so converting
+/// back and forth is lossless; values outside -1.0 to 127/128 are clipped.
+pub fn cf32_to_cs8(input: &[u8], output: &mut [u8]) {
+    for (bytes, out) in input.chunks_exact(4).zip(output.iter_mut()) {
+        let v = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        *out = f32_to_cs8(v);
+    }
+}
+
+// One value of `cf32_to_cs8`, for adapters working in complex samples.
+pub(crate) fn f32_to_cs8(v: f32) -> u8 {
+    (v * 128.0).round().clamp(-128.0, 127.0) as i8 as u8
+}
+
+/// Convert cs8 bytes to little-endian cf32, writing `input.len() * 4` bytes
+/// to `output`. Divided by 128, so -128 maps to -1.0, as libhackrf's tools
+/// and the rest of this crate read cs8.
+pub fn cs8_to_cf32(input: &[u8], output: &mut [u8]) {
+    for (&b, out) in input.iter().zip(output.chunks_exact_mut(4)) {
+        out.copy_from_slice(&(b as i8 as f32 / 128.0).to_le_bytes());
+    }
+}
+
+/// Convert little-endian cs16 bytes to cs8, writing `input.len() / 2` bytes
+/// to `output`. The low byte of each value is discarded.
+pub fn cs16_to_cs8(input: &[u8], output: &mut [u8]) {
+    for (bytes, out) in input.chunks_exact(2).zip(output.iter_mut()) {
+        *out = bytes[1];
+    }
+}
+
+/// Convert cs8 bytes to little-endian cs16, writing `input.len() * 2` bytes
+/// to `output`. Each value is shifted into the high byte.
+pub fn cs8_to_cs16(input: &[u8], output: &mut [u8]) {
+    for (&b, out) in input.iter().zip(output.chunks_exact_mut(2)) {
+        out.copy_from_slice(&((b as i8 as i16) << 8).to_le_bytes());
+    }
+}
+
+/// Invert the spectrum of cs8 samples in place, mirroring it about the
+/// centre frequency, by swapping I and Q. This is the same as taking the
+/// complex conjugate, up to a fixed 90 degree phase shift, but is lossless:
+/// negating Q would have to clip -128.
+pub fn invert_spectrum(samples: &mut [u8]) {
+    for iq in samples.chunks_exact_mut(2) {
+        iq.swap(0, 1);
+    }
+}
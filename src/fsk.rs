@@ -0,0 +1,158 @@
+// 2-FSK and GFSK modulation and demodulation
+// Licensed under MIT license
+
+use std::f64::consts::PI;
+
+// Symbols either side of the centre of the Gaussian filter.
+const GAUSSIAN_SPAN: f64 = 2.0;
+// How far each zero crossing pulls the demodulator's symbol clock.
+const TIMING_GAIN: f64 = 0.1;
+
+/// Modulates bits onto a carrier at the centre frequency as 2-FSK, a 1 at
+/// `+deviation_hz` and a 0 at `-deviation_hz`, or GFSK once a Gaussian
+/// filter is set, producing cs8 ready for `transmit_repeated` or a
+/// `TxStream`. The phase is continuous between calls.
+#[derive(Clone, Debug)]
+pub struct FskModulator {
+    sample_rate: f64,
+    baud: f64,
+    deviation_hz: f64,
+    // Pulse shaping taps, or none for plain FSK.
+    gaussian: Vec<f64>,
+    amplitude: f64,
+    phase: f64,
+}
+
+impl FskModulator {
+    /// Modulate `baud` symbols per second for transmitting at `sample_rate`.
+    pub fn new(sample_rate: f64, baud: f64, deviation_hz: f64) -> FskModulator {
+        assert!(baud > 0.0 && baud <= sample_rate / 2.0);
+        assert!(deviation_hz.abs() < sample_rate / 2.0);
+        FskModulator {
+            sample_rate,
+            baud,
+            deviation_hz,
+            gaussian: Vec::new(),
+            amplitude: 1.0,
+            phase: 0.0,
+        }
+    }
+
+    /// Shape the frequency changes with a Gaussian filter of bandwidth-time
+    /// product `bt`, giving GFSK: 0.5 for Bluetooth LE, 0.3 for GSM.
+    pub fn gaussian(mut self, bt: f64) -> FskModulator {
+        assert!(bt > 0.0);
+        let per_symbol = self.sample_rate / self.baud;
+        let half = (GAUSSIAN_SPAN * per_symbol).round() as i64;
+        let scale = 2.0 * PI * PI * bt * bt / 2f64.ln();
+        let taps: Vec<f64> = (-half..=half)
+            .map(|n| {
+                let t = n as f64 / per_symbol;
+                (-scale * t * t).exp()
+            })
+            .collect();
+        let sum: f64 = taps.iter().sum();
+        self.gaussian = taps.iter().map(|tap| tap / sum).collect();
+        self
+    }
+
+    /// Amplitude relative to full scale, 0.0-1.0. Defaults to 1.0.
+    pub fn amplitude(mut self, amplitude: f64) -> FskModulator {
+        assert!((0.0..=1.0).contains(&amplitude));
+        self.amplitude = amplitude;
+        self
+    }
+
+    /// Modulate `bits`, appending cs8 samples to `output`.
+    pub fn modulate(&mut self, bits: &[bool], output: &mut Vec<u8>) {
+        let count = (bits.len() as f64 * self.sample_rate / self.baud).round() as usize;
+        let nrz: Vec<f64> = (0..count)
+            .map(|n| {
+                let symbol = (n as f64 * self.baud / self.sample_rate) as usize;
+                if bits[symbol.min(bits.len() - 1)] {
+                    1.0
+                } else {
+                    -1.0
+                }
+            })
+            .collect();
+        let half = self.gaussian.len() / 2;
+        let scale = 127.0 * self.amplitude;
+        for (n, &symbol) in nrz.iter().enumerate() {
+            let frequency = if self.gaussian.is_empty() {
+                symbol
+            } else {
+                // Hold the first and last symbols beyond the ends.
+                self.gaussian
+                    .iter()
+                    .enumerate()
+                    .map(|(k, tap)| {
+                        let m = (n + k).saturating_sub(half).min(count - 1);
+                        tap * nrz[m]
+                    })
+                    .sum()
+            };
+            self.phase += 2.0 * PI * self.deviation_hz * frequency / self.sample_rate;
+            self.phase %= 2.0 * PI;
+            output.push((scale * self.phase.cos()).round() as i8 as u8);
+            output.push((scale * self.phase.sin()).round() as i8 as u8);
+        }
+    }
+}
+
+/// Recovers bits from 2-FSK or GFSK centred on the band, taking I/Q pairs,
+/// such as from `Decimator::process_f32`, filtered to little more than
+/// the signal's bandwidth. Frequencies above the centre are 1s. A
+/// discriminator feeds an integrate-and-dump detector whose symbol clock
+/// follows the zero crossings between symbols, so it tolerates some
+/// mismatch in baud rate.
+pub struct FskDemodulator {
+    // Fraction of a symbol per sample.
+    step: f64,
+    previous: [f32; 2],
+    // Position within the current symbol, 0.0-1.0.
+    clock: f64,
+    sum: f32,
+    last: f32,
+}
+
+impl FskDemodulator {
+    /// Demodulate `baud` symbols per second from samples at `sample_rate`.
+    pub fn new(sample_rate: f64, baud: f64) -> FskDemodulator {
+        assert!(baud > 0.0 && baud <= sample_rate / 2.0);
+        FskDemodulator {
+            step: baud / sample_rate,
+            previous: [1.0, 0.0],
+            clock: 0.0,
+            sum: 0.0,
+            last: 0.0,
+        }
+    }
+
+    /// Demodulate `iq`, appending the bits found to `bits`.
+    pub fn process(&mut self, iq: &[[f32; 2]], bits: &mut Vec<bool>) {
+        for &[i, q] in iq {
+            let [pi, pq] = self.previous;
+            self.previous = [i, q];
+            let frequency = (q * pi - i * pq).atan2(i * pi + q * pq);
+            // Crossings should fall on symbol boundaries; nudge the clock
+            // towards the nearest one.
+            if (frequency > 0.0) != (self.last > 0.0) {
+                let error = if self.clock < 0.5 {
+                    self.clock
+                } else {
+                    self.clock - 1.0
+                };
+                self.clock -= TIMING_GAIN * error;
+            }
+            self.last = frequency;
+            self.sum += frequency;
+            self.clock += self.step;
+            if self.clock >= 1.0 {
+                self.clock -= 1.0;
+                bits.push(self.sum > 0.0);
+                self.sum = 0.0;
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// FutureSDR source and sink blocks
+// Licensed under MIT license
+
+use std::time::Duration;
+
+use libfuturesdr::anyhow::{anyhow, Result};
+use libfuturesdr::macros::async_trait;
+use libfuturesdr::num_complex::Complex32;
+use libfuturesdr::runtime::{
+    Block, BlockMeta, BlockMetaBuilder, Kernel, MessageIo, MessageIoBuilder, StreamIo,
+    StreamIoBuilder, WorkIo,
+};
+
+use super::format::f32_to_cs8;
+use super::stream::{RxStream, TxStream};
+use super::{HackRFDevice, HackRFError};
+
+// USB transfers queued between the device and the flowgraph.
+const QUEUE_LEN: usize = 64;
+// How long the sink waits for queued samples to go out before stopping.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn hackrf_err(err: HackRFError) -> libfuturesdr::anyhow::Error {
+    anyhow!("{:?}", err)
+}
+
+/// FutureSDR source block streaming `Complex32` samples, scaled to +-1.0,
+/// from a configured `HackRFDevice` on its `out` port. Receiving starts
+/// when the flowgraph starts and stops when it terminates.
+pub struct HackRfSource {
+    device: HackRFDevice,
+    stream: Option<RxStream>,
+    current: Vec<u8>,
+    pos: usize,
+}
+
+impl HackRfSource {
+    /// Wrap `device`, which should already be tuned and have its sample
+    /// rate and gains set.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(device: HackRFDevice) -> Block {
+        Block::new(
+            BlockMetaBuilder::new("HackRfSource").blocking().build(),
+            StreamIoBuilder::new()
+                .add_output::<Complex32>("out")
+                .build(),
+            MessageIoBuilder::new().build(),
+            HackRfSource {
+                device,
+                stream: None,
+                current: Vec::new(),
+                pos: 0,
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl Kernel for HackRfSource {
+    async fn init(
+        &mut self,
+        _sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        self.stream = Some(RxStream::start(&mut self.device, QUEUE_LEN).map_err(hackrf_err)?);
+        Ok(())
+    }
+
+    async fn work(
+        &mut self,
+        io: &mut WorkIo,
+        sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or_else(|| anyhow!("HackRF RX not started"))?;
+        let out = sio.output(0).slice::<Complex32>();
+        let mut n = 0;
+        while n < out.len() {
+            if self.pos == self.current.len() {
+                // Wait for data only if there is nothing to hand on yet.
+                let buf = if n == 0 {
+                    stream.recv()
+                } else {
+                    stream.recv_timeout(Duration::ZERO).ok()
+                };
+                match buf {
+                    Some(buf) => {
+                        stream.recycle(std::mem::replace(&mut self.current, buf));
+                        self.pos = 0;
+                        continue;
+                    }
+                    None if n == 0 => {
+                        io.finished = true;
+                        return Ok(());
+                    }
+                    None => break,
+                }
+            }
+            let iq = &self.current[self.pos..];
+            let k = (out.len() - n).min(iq.len() / 2);
+            for (o, s) in out[n..n + k].iter_mut().zip(iq.chunks_exact(2)) {
+                *o = Complex32::new(s[0] as i8 as f32 / 128.0, s[1] as i8 as f32 / 128.0);
+            }
+            n += k;
+            self.pos += k * 2;
+        }
+        sio.output(0).produce(n);
+        // With room left in the output, go straight back for more samples;
+        // otherwise the runtime calls again once downstream has consumed.
+        io.call_again = n < out.len();
+        Ok(())
+    }
+
+    async fn deinit(
+        &mut self,
+        _sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        if let Some(stream) = self.stream.take() {
+            stream.stop(&mut self.device).map_err(hackrf_err)?;
+        }
+        Ok(())
+    }
+}
+
+/// FutureSDR sink block transmitting the `Complex32` samples, scaled to
+/// +-1.0, arriving on its `in` port through a configured `HackRFDevice`.
+/// Silence is sent whenever the flowgraph falls behind. The block finishes
+/// once its input has finished and every sample has been queued.
+pub struct HackRfSink {
+    device: HackRFDevice,
+    stream: Option<TxStream>,
+}
+
+impl HackRfSink {
+    /// Wrap `device`, which should already be tuned and have its sample
+    /// rate and gains set.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(device: HackRFDevice) -> Block {
+        Block::new(
+            BlockMetaBuilder::new("HackRfSink").blocking().build(),
+            StreamIoBuilder::new().add_input::<Complex32>("in").build(),
+            MessageIoBuilder::new().build(),
+            HackRfSink {
+                device,
+                stream: None,
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl Kernel for HackRfSink {
+    async fn init(
+        &mut self,
+        _sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        self.stream = Some(TxStream::start(&mut self.device, QUEUE_LEN).map_err(hackrf_err)?);
+        Ok(())
+    }
+
+    async fn work(
+        &mut self,
+        io: &mut WorkIo,
+        sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or_else(|| anyhow!("HackRF TX not started"))?;
+        // Read this first: samples arriving afterwards belong to a later call.
+        let finished = sio.input(0).finished();
+        let input = sio.input(0).slice::<Complex32>();
+        if !input.is_empty() {
+            let mut cs8 = Vec::with_capacity(input.len() * 2);
+            for s in input {
+                cs8.push(f32_to_cs8(s.re));
+                cs8.push(f32_to_cs8(s.im));
+            }
+            stream.send(cs8).map_err(|_| anyhow!("HackRF TX stopped"))?;
+            sio.input(0).consume(input.len());
+        }
+        if finished {
+            io.finished = true;
+        }
+        Ok(())
+    }
+
+    async fn deinit(
+        &mut self,
+        _sio: &mut StreamIo,
+        _mio: &mut MessageIo<Self>,
+        _meta: &mut BlockMeta,
+    ) -> Result<()> {
+        if let Some(stream) = self.stream.take() {
+            stream.flush(FLUSH_TIMEOUT);
+            stream.stop(&mut self.device).map_err(hackrf_err)?;
+        }
+        Ok(())
+    }
+}
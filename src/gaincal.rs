@@ -0,0 +1,257 @@
+// Receive gain calibration against a reference tone
+// Licensed under MIT license
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use super::strength::capture;
+use super::{
+    compute_baseband_filter_bw, ffi, hackrf_error, set_amp_enable, set_baseband_filter_bandwidth,
+    set_freq, set_lna_gain, set_sample_rate, set_vga_gain, HackRFDevice, HackRFError,
+};
+
+// A sample this close to full scale means the receiver may be clipping.
+const CLIP_LEVEL: i8 = 126;
+
+/// One gain setting measured by a `GainSweep`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GainPoint {
+    pub lna_db: u32,
+    pub vga_db: u32,
+    /// Level of the reference tone as received, relative to full scale.
+    pub level_dbfs: f64,
+    /// Gain from the antenna port to the samples: dBFS per dBm.
+    pub gain_db: f64,
+    /// Whether samples reached full scale, making the level unreliable.
+    pub clipped: bool,
+}
+
+/// The measured receive gain of one board at one frequency, for turning
+/// sample levels into absolute power. Can be saved and loaded as CSV lines
+/// of `lna_db, vga_db, gain_db`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GainTable {
+    pub freq_hz: u64,
+    pub amp: bool,
+    pub points: Vec<GainPoint>,
+}
+
+impl GainTable {
+    /// The measured gain at a setting, if it was measured without clipping.
+    pub fn gain_db(&self, lna_db: u32, vga_db: u32) -> Option<f64> {
+        self.points
+            .iter()
+            .find(|p| p.lna_db == lna_db && p.vga_db == vga_db && !p.clipped)
+            .map(|p| p.gain_db)
+    }
+
+    /// Convert a level in dBFS received at a gain setting to dBm at the
+    /// antenna port.
+    pub fn dbm(&self, lna_db: u32, vga_db: u32, dbfs: f64) -> Option<f64> {
+        Some(dbfs - self.gain_db(lna_db, vga_db)?)
+    }
+
+    /// Difference between each setting's measured gain and what its nominal
+    /// gain predicts, relative to the lowest unclipped setting: the error
+    /// in assuming the gain steps are exact.
+    pub fn linearity_error_db(&self) -> Vec<(u32, u32, f64)> {
+        let usable: Vec<&GainPoint> = self.points.iter().filter(|p| !p.clipped).collect();
+        let base = match usable.iter().min_by_key(|p| p.lna_db + p.vga_db) {
+            Some(base) => **base,
+            None => return Vec::new(),
+        };
+        usable
+            .iter()
+            .map(|p| {
+                let nominal = (p.lna_db + p.vga_db) as f64 - (base.lna_db + base.vga_db) as f64;
+                (p.lna_db, p.vga_db, p.gain_db - base.gain_db - nominal)
+            })
+            .collect()
+    }
+
+    /// Write the table to `path`: a header line of `freq_hz, amp`, then one
+    /// line per unclipped point.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{}, {}", self.freq_hz, self.amp)?;
+        for p in self.points.iter().filter(|p| !p.clipped) {
+            writeln!(out, "{}, {}, {:.2}", p.lna_db, p.vga_db, p.gain_db)?;
+        }
+        out.flush()
+    }
+
+    /// Read a table written by `save`. Levels are not saved, so are zero.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<GainTable> {
+        fn invalid() -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, "malformed gain table")
+        }
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header = lines.next().ok_or_else(invalid)??;
+        let mut fields = header.split(',').map(str::trim);
+        let freq_hz = fields
+            .next()
+            .and_then(|f| f.parse().ok())
+            .ok_or_else(invalid)?;
+        let amp = fields
+            .next()
+            .and_then(|f| f.parse().ok())
+            .ok_or_else(invalid)?;
+        let mut points = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut fields = line.split(',').map(str::trim);
+            let mut next = || fields.next().ok_or_else(invalid);
+            let lna_db = next()?.parse().map_err(|_| invalid())?;
+            let vga_db = next()?.parse().map_err(|_| invalid())?;
+            let gain_db = next()?.parse().map_err(|_| invalid())?;
+            points.push(GainPoint {
+                lna_db,
+                vga_db,
+                level_dbfs: 0.0,
+                gain_db,
+                clipped: false,
+            });
+        }
+        Ok(GainTable {
+            freq_hz,
+            amp,
+            points,
+        })
+    }
+}
+
+/// Calibrates a board's receive gain with an external reference tone of
+/// known level, such as from a signal generator through an attenuator. Each
+/// LNA and VGA setting in turn is measured by correlating against the tone,
+/// which rejects the noise and the DC spike, giving a `GainTable` for that
+/// board. Gains vary between boards and with frequency, so calibrate near
+/// the frequencies of interest. Keep the tone weak enough that the higher
+/// settings do not clip; clipped points are marked and left out of lookups.
+#[derive(Clone, Debug)]
+pub struct GainSweep {
+    freq_hz: u64,
+    tone_offset_hz: f64,
+    reference_dbm: f64,
+    sample_rate: f64,
+    lna_gains: Vec<u32>,
+    vga_gains: Vec<u32>,
+    amp: bool,
+    samples: usize,
+    settle: Duration,
+}
+
+impl GainSweep {
+    /// Tune to `freq_hz` and measure a tone of `reference_dbm` at the
+    /// antenna port, `tone_offset_hz` away. Keep the offset clear of DC.
+    pub fn new(freq_hz: u64, tone_offset_hz: f64, reference_dbm: f64) -> GainSweep {
+        GainSweep {
+            freq_hz,
+            tone_offset_hz,
+            reference_dbm,
+            sample_rate: 2e6,
+            lna_gains: (0..=40).step_by(8).collect(),
+            vga_gains: (0..=62).step_by(2).collect(),
+            amp: false,
+            samples: 65536,
+            settle: Duration::from_millis(20),
+        }
+    }
+
+    /// Defaults to 2Msps.
+    pub fn sample_rate(mut self, sample_rate: f64) -> GainSweep {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// LNA settings to measure. Defaults to all of them, 0-40dB.
+    pub fn lna_gains(mut self, lna_gains: Vec<u32>) -> GainSweep {
+        self.lna_gains = lna_gains;
+        self
+    }
+
+    /// VGA settings to measure. Defaults to all of them, 0-62dB.
+    pub fn vga_gains(mut self, vga_gains: Vec<u32>) -> GainSweep {
+        self.vga_gains = vga_gains;
+        self
+    }
+
+    /// Whether to measure with the RF amp on. Defaults to off.
+    pub fn amp(mut self, amp: bool) -> GainSweep {
+        self.amp = amp;
+        self
+    }
+
+    /// Number of samples measured at each setting. Defaults to 65536.
+    pub fn samples(mut self, samples: usize) -> GainSweep {
+        assert!(samples > 0);
+        self.samples = samples;
+        self
+    }
+
+    /// Time to wait after changing gain before measuring. Defaults to 20ms.
+    pub fn settle(mut self, settle: Duration) -> GainSweep {
+        self.settle = settle;
+        self
+    }
+
+    /// Measure every combination of LNA and VGA setting. The device is left
+    /// tuned, at the last setting, with streaming stopped.
+    pub fn run(&self, device: &mut HackRFDevice) -> Result<GainTable, HackRFError> {
+        if self.tone_offset_hz.abs() >= self.sample_rate / 2.0 {
+            return Err(hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM));
+        }
+        set_sample_rate(device, self.sample_rate)?;
+        let bw = compute_baseband_filter_bw((self.sample_rate * 0.75) as u32);
+        set_baseband_filter_bandwidth(device, bw)?;
+        set_freq(device, self.freq_hz)?;
+        set_amp_enable(device, self.amp)?;
+        let mut points = Vec::new();
+        for &lna_db in &self.lna_gains {
+            set_lna_gain(device, lna_db)?;
+            for &vga_db in &self.vga_gains {
+                set_vga_gain(device, vga_db)?;
+                thread::sleep(self.settle);
+                let samples = capture(device, self.samples)?;
+                let (level_dbfs, clipped) = self.tone_level(&samples);
+                points.push(GainPoint {
+                    lna_db,
+                    vga_db,
+                    level_dbfs,
+                    gain_db: level_dbfs - self.reference_dbm,
+                    clipped,
+                });
+            }
+        }
+        Ok(GainTable {
+            freq_hz: self.freq_hz,
+            amp: self.amp,
+            points,
+        })
+    }
+
+    // Level of the tone in cs8 `samples` in dBFS, from a single DFT bin at
+    // its offset, and whether any sample was at full scale.
+    fn tone_level(&self, samples: &[u8]) -> (f64, bool) {
+        let step = -2.0 * PI * self.tone_offset_hz / self.sample_rate;
+        let (mut sum_i, mut sum_q) = (0.0, 0.0);
+        let mut clipped = false;
+        for (n, iq) in samples.chunks_exact(2).enumerate() {
+            let (i, q) = (iq[0] as i8, iq[1] as i8);
+            clipped |= i.unsigned_abs() >= CLIP_LEVEL as u8 || q.unsigned_abs() >= CLIP_LEVEL as u8;
+            let (sin, cos) = (step * n as f64).sin_cos();
+            let (i, q) = (i as f64, q as f64);
+            sum_i += i * cos - q * sin;
+            sum_q += i * sin + q * cos;
+        }
+        let n = (samples.len() / 2) as f64;
+        let power = (sum_i * sum_i + sum_q * sum_q) / (n * n);
+        (10.0 * (power / (128.0 * 128.0)).log10(), clipped)
+    }
+}
@@ -0,0 +1,137 @@
+// Smoothing the step in received samples when gains change
+// Licensed under MIT license
+
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::ramp::RampLength;
+use super::sdr::RxCallback;
+
+// Samples per power measurement while looking for the step.
+const WINDOW: usize = 256;
+// How many samples after a gain change to look for its step, in transfers'
+// worth of samples, covering those already in flight.
+const SEARCH_TRANSFERS: u64 = 4;
+// Weight of each new window in the running power average.
+const AVERAGING: f64 = 0.1;
+
+/// Cross-fades received samples across a gain change, so that the sudden
+/// step in level does not upset AGCs and demodulators downstream. Told the
+/// size of a change through its `GainFadeHandle`, it looks in the samples
+/// that follow for the jump in power it causes, which arrives a few
+/// transfers later since some were in flight, then scales the samples
+/// from there back to the old level and ramps them up or down to the new
+/// one with a raised cosine. Steps under 3dB, and steps it cannot find,
+/// as when the signal itself changes level at the same time, are passed
+/// through.
+pub struct GainFade {
+    length: u64,
+    // The change announced through the handle, as f64 bits, or NaN bits.
+    pending: Arc<AtomicU64>,
+    // Amplitude ratio of the step being looked for, and samples left to
+    // look in.
+    expected: Option<(f64, u64)>,
+    // Running average power per sample.
+    reference: f64,
+    // Gain the current fade started at, and how far through it is.
+    from: f64,
+    position: u64,
+}
+
+/// Tells a `GainFade` on the streaming thread about gain changes.
+#[derive(Clone)]
+pub struct GainFadeHandle {
+    pending: Arc<AtomicU64>,
+}
+
+impl GainFade {
+    /// Fade over `length` for samples at `sample_rate`.
+    pub fn new(length: RampLength, sample_rate: f64) -> GainFade {
+        let length = length.samples(sample_rate);
+        GainFade {
+            length,
+            pending: Arc::new(AtomicU64::new(f64::NAN.to_bits())),
+            expected: None,
+            reference: 0.0,
+            from: 1.0,
+            position: length,
+        }
+    }
+
+    pub fn handle(&self) -> GainFadeHandle {
+        GainFadeHandle {
+            pending: self.pending.clone(),
+        }
+    }
+
+    /// Look for and smooth out gain steps in cs8 samples, in place.
+    pub fn process(&mut self, buf: &mut [u8]) {
+        let change_db = f64::from_bits(self.pending.swap(f64::NAN.to_bits(), Ordering::SeqCst));
+        if change_db.abs() >= 3.0 {
+            let search = SEARCH_TRANSFERS * buf.len() as u64 / 2;
+            self.expected = Some((10f64.powf(change_db / 20.0), search));
+        }
+        for window in buf.chunks_mut(WINDOW * 2) {
+            let power = window
+                .iter()
+                .map(|&x| (x as i8 as f64) * (x as i8 as f64))
+                .sum::<f64>()
+                / (window.len() / 2).max(1) as f64;
+            if let Some((ratio, left)) = self.expected {
+                let step_db = 10.0 * (power / self.reference).log10();
+                let expected_db = 20.0 * ratio.log10();
+                if self.reference > 0.0 && (step_db - expected_db).abs() < expected_db.abs() / 2.0 {
+                    // Found it: start from the old level.
+                    self.from = self.gain() / ratio;
+                    self.position = 0;
+                    self.expected = None;
+                } else {
+                    let left = left.saturating_sub(window.len() as u64 / 2);
+                    self.expected = if left > 0 { Some((ratio, left)) } else { None };
+                }
+            }
+            if self.expected.is_none() || self.reference == 0.0 {
+                self.reference += AVERAGING * (power - self.reference);
+            }
+            if self.position < self.length {
+                for iq in window.chunks_exact_mut(2) {
+                    let gain = self.gain();
+                    for x in iq {
+                        *x = ((*x as i8 as f64) * gain).round().clamp(-128.0, 127.0) as i8 as u8;
+                    }
+                    self.position += 1;
+                }
+            }
+        }
+    }
+
+    /// Wrap `callback` into an RX callback that receives smoothed samples,
+    /// for use with `RxChannel::start_rx` and the adapters built on it.
+    pub fn wrap(mut self, mut callback: RxCallback) -> RxCallback {
+        let mut smoothed = Vec::new();
+        Box::new(move |rx: &[u8]| -> bool {
+            smoothed.clear();
+            smoothed.extend_from_slice(rx);
+            self.process(&mut smoothed);
+            callback(&smoothed)
+        })
+    }
+
+    // Gain at the current point of the fade, from `from` to 1.
+    fn gain(&self) -> f64 {
+        if self.position >= self.length {
+            return 1.0;
+        }
+        let x = 0.5 - 0.5 * (PI * self.position as f64 / self.length as f64).cos();
+        self.from + (1.0 - self.from) * x
+    }
+}
+
+impl GainFadeHandle {
+    /// Announce a change in receive gain of `change_db`, just made or about
+    /// to be.
+    pub fn gain_changed(&self, change_db: f64) {
+        self.pending.store(change_db.to_bits(), Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,359 @@
+// Frequency hopping while streaming
+// Licensed under MIT license
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::ramp::{Ramp, RampHandle, RampLength};
+use super::{
+    is_streaming, set_freq, start_rx, start_tx, stop_rx, stop_tx, HackRFDevice, HackRFError,
+    StopHandle,
+};
+
+// How often the dwell is checked.
+const POLL: Duration = Duration::from_millis(1);
+// Longest to wait for a ramp down before retuning or stopping anyway.
+const RAMP_DOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// How long a `Hopper` stays on each frequency.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Dwell {
+    Time(Duration),
+    /// A number of samples passed to or from the callback.
+    Samples(u64),
+}
+
+/// Where in the stream from `Hopper::receive_marked` the board retuned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetuneMarker {
+    /// Index of the transfer, counting from 0 at the start of streaming.
+    pub transfer: u64,
+    /// Sample within the transfer from which samples are at `freq_hz`.
+    pub offset: usize,
+    pub freq_hz: u64,
+}
+
+/// Retunes through a list of frequencies in turn while receiving or
+/// transmitting, passing the callback the frequency each buffer belongs to,
+/// for scanning receivers and frequency hopping experiments.
+///
+/// Retuning happens between USB transfers, from the calling thread, so
+/// hops land on transfer boundaries rather than exact samples. Transfers
+/// already in flight when the board retunes hold samples from either side
+/// of the hop, so by default the first transfer after each hop is skipped:
+/// not passed to the RX callback, or sent as silence on TX. On TX, a ramp
+/// can also be set to fade out before each hop and back in after it.
+/// `receive_marked` instead passes every transfer with markers where each
+/// hop lands, optionally blanking the samples around it.
+#[derive(Clone, Debug)]
+pub struct Hopper {
+    freqs_hz: Vec<u64>,
+    dwell: Dwell,
+    skip: usize,
+    ramp: Option<RampLength>,
+    blank: Option<RampLength>,
+}
+
+struct Shared {
+    freq_hz: AtomicU64,
+    // Set while the board is retuning.
+    retuning: AtomicBool,
+    // Incremented at each hop.
+    hop: AtomicU64,
+    // Samples handled by the callback since the last hop.
+    dwelt: AtomicU64,
+    // Whether hops are to be marked, as for `receive_marked`.
+    marking: bool,
+    // Samples delivered so far, and when the last transfer was.
+    delivered: Mutex<(u64, Instant)>,
+    // Hops not yet marked in the stream: the sample indices at which
+    // retuning began and ended, and the new frequency.
+    hops: Mutex<Vec<(u64, u64, u64)>>,
+}
+
+impl Shared {
+    // Estimate the index of the sample being taken now, from the samples
+    // delivered and the time since the last transfer, which is the one the
+    // board has been filling since.
+    fn sample_now(&self, rate: f64) -> u64 {
+        let (delivered, at) = *lock(&self.delivered);
+        delivered + (at.elapsed().as_secs_f64() * rate) as u64
+    }
+}
+
+// Per-stream state on the streaming thread.
+struct Tracker {
+    hop: u64,
+    skip: usize,
+}
+
+impl Tracker {
+    // Whether the transfer about to be handled should be skipped.
+    fn skip(&mut self, shared: &Shared, skip: usize) -> bool {
+        if shared.retuning.load(Ordering::SeqCst) {
+            return true;
+        }
+        let hop = shared.hop.load(Ordering::SeqCst);
+        if hop != self.hop {
+            self.hop = hop;
+            self.skip = skip;
+        }
+        if self.skip > 0 {
+            self.skip -= 1;
+            return true;
+        }
+        false
+    }
+}
+
+impl Hopper {
+    /// Hop through `freqs_hz` in order, starting again at the first after
+    /// the last, staying on each for `dwell`.
+    pub fn new(freqs_hz: Vec<u64>, dwell: Dwell) -> Hopper {
+        assert!(!freqs_hz.is_empty());
+        Hopper {
+            freqs_hz,
+            dwell,
+            skip: 1,
+            ramp: None,
+            blank: None,
+        }
+    }
+
+    /// Number of transfers to skip after each hop, while the board settles
+    /// and in-flight transfers drain. Defaults to 1.
+    pub fn skip_transfers(mut self, skip: usize) -> Hopper {
+        self.skip = skip;
+        self
+    }
+
+    /// When transmitting, ramp the amplitude up at the start, down and up
+    /// again around each hop, and down at the end, over `length`, to avoid
+    /// splatter. Hops wait for the ramp down. Defaults to none.
+    pub fn ramp(mut self, length: RampLength) -> Hopper {
+        self.ramp = Some(length);
+        self
+    }
+
+    /// In `receive_marked`, zero the samples for `length` after each retune
+    /// marker, as well as those taken while retuning, so that none from the
+    /// synthesiser settling reach the callback. Defaults to none.
+    pub fn blank(mut self, length: RampLength) -> Hopper {
+        self.blank = Some(length);
+        self
+    }
+
+    /// Receive while hopping, calling `callback` with the frequency in Hz
+    /// and the samples of each transfer. Blocks until the callback returns
+    /// false or `stop` is triggered.
+    pub fn receive<F>(
+        &self,
+        device: &mut HackRFDevice,
+        stop: &StopHandle,
+        mut callback: F,
+    ) -> Result<(), HackRFError>
+    where
+        F: FnMut(u64, &[u8]) -> bool,
+    {
+        let shared = self.tune_first(device)?;
+        let mut tracker = Tracker { hop: 0, skip: 0 };
+        let mut rx = |buf: &[u8]| -> bool {
+            if tracker.skip(&shared, self.skip) {
+                return true;
+            }
+            shared
+                .dwelt
+                .fetch_add(buf.len() as u64 / 2, Ordering::SeqCst);
+            callback(shared.freq_hz.load(Ordering::SeqCst), buf)
+        };
+        start_rx(device, &mut rx)?;
+        let result = self.hop(device, &shared, stop, None);
+        let stopped = stop_rx(device);
+        result.and(stopped)
+    }
+
+    /// Receive while hopping without skipping transfers, calling
+    /// `callback` with the frequency in Hz at the start of each transfer,
+    /// its samples, and a marker for each retune within it, so that scanners
+    /// can split the stream by frequency themselves.
+    ///
+    /// The sample a retune lands on is estimated from the host clock and
+    /// the samples delivered so far, and marked at the earliest sample it
+    /// could have taken effect: samples still buffered on the board make
+    /// the real change up to a few milliseconds later. Use `blank` to cover
+    /// that and the settling time. Blocks until the callback returns false
+    /// or `stop` is triggered.
+    pub fn receive_marked<F>(
+        &self,
+        device: &mut HackRFDevice,
+        stop: &StopHandle,
+        mut callback: F,
+    ) -> Result<(), HackRFError>
+    where
+        F: FnMut(u64, &[u8], &[RetuneMarker]) -> bool,
+    {
+        let mut shared = self.tune_first(device)?;
+        shared.marking = true;
+        let blank = self
+            .blank
+            .map_or(0, |length| length.samples(device.sample_rate_hz));
+        let mut freq_hz = self.freqs_hz[0];
+        let mut transfer = 0;
+        let mut start = 0;
+        // First sample after the end of blanking.
+        let mut blank_until = 0;
+        let mut blanked = Vec::new();
+        let mut markers = Vec::new();
+        let mut rx = |buf: &[u8]| -> bool {
+            let end = start + buf.len() as u64 / 2;
+            *lock(&shared.delivered) = (end, Instant::now());
+            shared
+                .dwelt
+                .fetch_add(buf.len() as u64 / 2, Ordering::SeqCst);
+            let first_freq_hz = freq_hz;
+            // Blanking carried over from a hop in an earlier transfer.
+            let carried = blank_until.clamp(start, end);
+            markers.clear();
+            lock(&shared.hops).retain(|&(began, ended, freq)| {
+                if began >= end {
+                    return true;
+                }
+                markers.push(RetuneMarker {
+                    transfer,
+                    offset: (began.max(start) - start) as usize,
+                    freq_hz: freq,
+                });
+                freq_hz = freq;
+                blank_until = blank_until.max(ended + blank);
+                false
+            });
+            let more = if blank_until > start {
+                blanked.clear();
+                blanked.extend_from_slice(buf);
+                blanked[..(carried - start) as usize * 2].fill(0);
+                let to = (blank_until.min(end) - start) as usize * 2;
+                for marker in &markers {
+                    blanked[marker.offset * 2..to].fill(0);
+                }
+                callback(first_freq_hz, &blanked, &markers)
+            } else {
+                callback(first_freq_hz, buf, &markers)
+            };
+            transfer += 1;
+            start = end;
+            more
+        };
+        start_rx(device, &mut rx)?;
+        let result = self.hop(device, &shared, stop, None);
+        let stopped = stop_rx(device);
+        result.and(stopped)
+    }
+
+    /// Transmit while hopping, calling `callback` with the frequency in Hz
+    /// to fill each transfer. Blocks until the callback returns false or
+    /// `stop` is triggered.
+    pub fn transmit<F>(
+        &self,
+        device: &mut HackRFDevice,
+        stop: &StopHandle,
+        mut callback: F,
+    ) -> Result<(), HackRFError>
+    where
+        F: FnMut(u64, &mut [u8]) -> bool,
+    {
+        let shared = self.tune_first(device)?;
+        let mut tracker = Tracker { hop: 0, skip: 0 };
+        let mut ramp = self
+            .ramp
+            .map(|length| Ramp::new(length, device.sample_rate_hz));
+        let handle = ramp.as_ref().map(Ramp::handle);
+        let mut tx = |buf: &mut [u8]| -> bool {
+            if tracker.skip(&shared, self.skip) {
+                buf.fill(0);
+                return true;
+            }
+            shared
+                .dwelt
+                .fetch_add(buf.len() as u64 / 2, Ordering::SeqCst);
+            let more = callback(shared.freq_hz.load(Ordering::SeqCst), buf);
+            if let Some(ref mut ramp) = ramp {
+                ramp.process(buf);
+            }
+            more
+        };
+        start_tx(device, &mut tx)?;
+        let result = self.hop(device, &shared, stop, handle.as_ref());
+        // Nothing is left to fade if the callback has ended streaming.
+        if let Some(ref handle) = handle.filter(|_| is_streaming(device).unwrap_or(false)) {
+            handle.ramp_down_wait(RAMP_DOWN_TIMEOUT);
+        }
+        let stopped = stop_tx(device);
+        result.and(stopped)
+    }
+
+    fn tune_first(&self, device: &mut HackRFDevice) -> Result<Shared, HackRFError> {
+        set_freq(device, self.freqs_hz[0])?;
+        Ok(Shared {
+            freq_hz: AtomicU64::new(self.freqs_hz[0]),
+            retuning: AtomicBool::new(false),
+            hop: AtomicU64::new(0),
+            dwelt: AtomicU64::new(0),
+            marking: false,
+            delivered: Mutex::new((0, Instant::now())),
+            hops: Mutex::new(Vec::new()),
+        })
+    }
+
+    // Retune at the end of each dwell until streaming ends.
+    fn hop(
+        &self,
+        device: &mut HackRFDevice,
+        shared: &Shared,
+        stop: &StopHandle,
+        ramp: Option<&RampHandle>,
+    ) -> Result<(), HackRFError> {
+        let mut index = 0;
+        loop {
+            let arrived = Instant::now();
+            loop {
+                if stop.is_stopped() || !is_streaming(device)? {
+                    return Ok(());
+                }
+                let dwelt = match self.dwell {
+                    Dwell::Time(time) => arrived.elapsed() >= time,
+                    Dwell::Samples(n) => shared.dwelt.load(Ordering::SeqCst) >= n,
+                };
+                if dwelt {
+                    break;
+                }
+                thread::sleep(POLL);
+            }
+            index = (index + 1) % self.freqs_hz.len();
+            if let Some(ramp) = ramp {
+                ramp.ramp_down_wait(RAMP_DOWN_TIMEOUT);
+            }
+            shared.retuning.store(true, Ordering::SeqCst);
+            let rate = device.sample_rate_hz;
+            let began = shared.sample_now(rate);
+            let result = set_freq(device, self.freqs_hz[index]);
+            if shared.marking && result.is_ok() {
+                let ended = shared.sample_now(rate);
+                lock(&shared.hops).push((began, ended, self.freqs_hz[index]));
+            }
+            shared.freq_hz.store(self.freqs_hz[index], Ordering::SeqCst);
+            shared.dwelt.store(0, Ordering::SeqCst);
+            shared.hop.fetch_add(1, Ordering::SeqCst);
+            shared.retuning.store(false, Ordering::SeqCst);
+            if let Some(ramp) = ramp {
+                ramp.ramp_up();
+            }
+            result?;
+        }
+    }
+}
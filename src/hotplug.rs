@@ -0,0 +1,112 @@
+// Watching for boards being plugged in and removed
+// Licensed under MIT license
+
+use std::collections::BTreeSet;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{device_list, HackRFError};
+
+/// A change reported by `HotplugWatcher`, naming the board by its serial
+/// number as listed by `device_list`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HotplugEvent {
+    DeviceArrived(String),
+    DeviceLeft(String),
+}
+
+/// Reports HackRFs as they are plugged in and removed, so long-running
+/// services can open new boards and notice lost ones. libhackrf has no
+/// hotplug notifications, so the watcher lists the connected boards on a
+/// thread every `interval` and reports the differences; a board that comes
+/// and goes within one interval is missed. Boards are told apart by serial
+/// number, so boards whose serial cannot be read count as one.
+pub struct HotplugWatcher {
+    events: Receiver<HotplugEvent>,
+    stop: Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HotplugWatcher {
+    /// Start watching, after `init`. Boards already connected are reported
+    /// as arriving before any change.
+    pub fn start(interval: Duration) -> Result<HotplugWatcher, HackRFError> {
+        let (events_tx, events) = mpsc::channel();
+        let (stop, stop_rx) = mpsc::channel();
+        let mut known = BTreeSet::new();
+        // List once here, so that a failure is returned to the caller.
+        let present = serials()?;
+        report(&mut known, present, &events_tx);
+        let thread = thread::Builder::new()
+            .name("hackrf-hotplug".to_string())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                    // Listing fails transiently while a board is enumerating;
+                    // try again next time.
+                    if let Ok(present) = serials() {
+                        if !report(&mut known, present, &events_tx) {
+                            break;
+                        }
+                    }
+                }
+            })?;
+        Ok(HotplugWatcher {
+            events,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Wait for the next event.
+    pub fn recv(&self) -> Option<HotplugEvent> {
+        self.events.recv().ok()
+    }
+
+    /// As `recv`, giving up after `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<HotplugEvent, RecvTimeoutError> {
+        self.events.recv_timeout(timeout)
+    }
+
+    /// The next event if one is waiting.
+    pub fn try_recv(&self) -> Result<HotplugEvent, TryRecvError> {
+        self.events.try_recv()
+    }
+}
+
+impl Drop for HotplugWatcher {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serials() -> Result<BTreeSet<String>, HackRFError> {
+    Ok(device_list()?.into_iter().map(|d| d.serial).collect())
+}
+
+// Send the differences between `known` and `present`, then remember
+// `present`. Returns false if nobody is listening any more.
+fn report(
+    known: &mut BTreeSet<String>,
+    present: BTreeSet<String>,
+    events: &Sender<HotplugEvent>,
+) -> bool {
+    let left = known
+        .difference(&present)
+        .cloned()
+        .map(HotplugEvent::DeviceLeft);
+    let arrived = present
+        .difference(known)
+        .cloned()
+        .map(HotplugEvent::DeviceArrived);
+    for event in left.chain(arrived) {
+        if events.send(event).is_err() {
+            return false;
+        }
+    }
+    *known = present;
+    true
+}
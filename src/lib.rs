@@ -4,14 +4,253 @@
 
 #![allow(dead_code)]
 
-use std::ffi::{c_int, c_uint, c_void};
+use std::ffi::{c_char, c_int, c_uint, c_void, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
+#[cfg(feature = "ctrlc")]
+extern crate ctrlc as libctrlc;
+#[cfg(feature = "usb")]
+extern crate futures_lite;
+#[cfg(feature = "futuresdr")]
+extern crate futuresdr as libfuturesdr;
+#[cfg(feature = "dlopen")]
+extern crate libloading;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "metrics")]
+extern crate metrics as libmetrics;
+#[cfg(feature = "seify")]
+extern crate num_complex;
+#[cfg(feature = "usb")]
+extern crate nusb;
+#[cfg(feature = "fft")]
+extern crate rustfft;
+#[cfg(feature = "seify")]
+extern crate seify as libseify;
+#[cfg(feature = "sigmf")]
+extern crate serde_json;
+#[cfg(feature = "zmq")]
+extern crate zmq as libzmq;
+
+mod am;
+mod bandwidth;
+mod batch;
+mod coherent;
+mod config;
+mod control;
+mod deadline;
+#[cfg(feature = "debug-registers")]
+pub mod debug;
+mod decimate;
+mod events;
+pub mod fault;
 mod ffi;
+#[cfg(feature = "fft")]
+pub mod fft;
+#[cfg(unix)]
+pub mod fifo;
+mod file;
+mod fm;
+pub mod format;
+mod fsk;
+#[cfg(feature = "futuresdr")]
+pub mod futuresdr;
+mod gaincal;
+mod gainfade;
+mod hopper;
+mod hotplug;
+#[cfg(feature = "log")]
+mod logging;
+mod metadata;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod mixer;
+pub mod mock;
+mod monitor;
+#[cfg(feature = "net")]
+pub mod net;
+#[cfg(feature = "fft")]
+mod occupancy;
+mod ook;
+pub mod operacake;
+mod pool;
+pub mod presets;
+mod psk;
+mod ramp;
+mod rate;
+mod recorder;
+pub mod replay;
+mod resample;
+mod scan;
+pub mod sdr;
+#[cfg(feature = "seify")]
+pub mod seify;
+#[cfg(feature = "fft")]
+mod selftest;
+#[cfg(feature = "ctrlc")]
+mod shutdown;
+#[cfg(feature = "sigmf")]
+pub mod sigmf;
+pub mod sim;
+mod source;
+pub mod spiflash;
+pub mod stream;
+mod strength;
+mod supervisor;
+pub mod sweep;
+#[cfg(feature = "bindgen")]
+pub mod sys;
+mod tdd;
+mod tee;
+mod threads;
+mod throughput;
+mod time;
+mod timestamp;
+mod transceiver;
+mod trigger;
+mod tuning;
+mod turnaround;
+mod tx;
+#[cfg(feature = "usb")]
+pub mod usb;
+mod wake;
+mod watchdog;
+#[cfg(feature = "fft")]
+mod waterfall;
+mod wav;
+pub mod waveforms;
+#[cfg(feature = "zmq")]
+pub mod zmq;
+
+pub use am::{AmDemodulator, Sideband, SsbDemodulator};
+pub use bandwidth::{BasebandFilterBandwidth, RoundPolicy};
+pub use batch::{start_rx_batched, Batcher};
+pub use coherent::CoherentPair;
+pub use config::DeviceConfig;
+pub use control::{ControlHandle, ControlUpdate};
+pub use deadline::CallbackDeadline;
+pub use decimate::{start_rx_decimated, Decimator};
+pub use events::{StopReason, StreamEvent};
+pub use fault::{FaultHandle, FaultInjector};
+#[cfg(feature = "fft")]
+pub use fft::{spectrum, Spectrum, Window};
+pub use file::{transmit_file, FileSource};
+pub use fm::{FmDemodulator, FmMode};
+pub use format::SampleFormat;
+pub use fsk::{FskDemodulator, FskModulator};
+#[cfg(feature = "futuresdr")]
+pub use futuresdr::{HackRfSink, HackRfSource};
+pub use gaincal::{GainPoint, GainSweep, GainTable};
+pub use gainfade::{GainFade, GainFadeHandle};
+pub use hopper::{Dwell, Hopper, RetuneMarker};
+pub use hotplug::{HotplugEvent, HotplugWatcher};
+pub use metadata::Acquisition;
+pub use mixer::{start_rx_offset, Mixer};
+pub use mock::MockDevice;
+pub use monitor::{Monitor, MonitorTap};
+#[cfg(feature = "fft")]
+pub use occupancy::{ChannelOccupancy, OccupancyScanner};
+pub use ook::OokBurst;
+pub use psk::{Psk, PskDemodulator, PskModulator};
+pub use ramp::{Ramp, RampHandle, RampLength};
+pub use rate::SampleRate;
+pub use recorder::{Recorder, RecorderBuilder, Rotate};
+pub use replay::ReplayBackend;
+pub use resample::Resampler;
+pub use scan::{capture_segments, segment_scan, Segment, SegmentScan};
+pub use sdr::{Backend, RxChannel, SdrDevice, TxChannel};
+use sdr::{RxCallback, TxCallback};
+#[cfg(feature = "seify")]
+pub use seify::SeifyDevice;
+#[cfg(feature = "fft")]
+pub use selftest::{self_test, SelfTest, SelfTestReport};
+#[cfg(feature = "ctrlc")]
+pub use shutdown::stop_on_ctrlc;
+#[cfg(feature = "sigmf")]
+pub use sigmf::SigMF;
+pub use sim::{Signal, SimBackend};
+pub use source::{NoiseSource, PrbsOrder, PrbsSource};
+pub use stream::{RxStream, RxStreamBuilder, TxStream, TxStreamBuilder};
+pub use strength::{LogFormat, StrengthLogger};
+pub use supervisor::{Supervised, Supervisor};
+pub use tdd::{Slot, TddScheduler};
+pub use tee::{Overflow, Tee, TeeConsumer};
+pub use threads::{set_thread_affinity, set_thread_priority, Priority};
+pub use throughput::{throughput_test, ThroughputReport};
+pub use timestamp::{SampleClock, Stamp, Timestamper};
+pub use transceiver::Transceiver;
+pub use trigger::{capture_burst, capture_on_trigger, Burst};
+pub use tuning::{explain_tuning, TuningPlan};
+pub use turnaround::{Direction, HalfDuplex, Turnaround};
+pub use tx::{
+    transmit_at, transmit_repeated, transmit_sweep, transmit_tone, transmit_when_clear, Repeat,
+};
+#[cfg(feature = "usb")]
+pub use usb::UsbBackend;
+pub use wake::Wake;
+pub use watchdog::{Stall, Watchdog};
+#[cfg(feature = "fft")]
+pub use waterfall::Waterfall;
+pub use wav::WavWriter;
+#[cfg(feature = "zmq")]
+pub use zmq::{transmit_zmq, ZmqPublisher};
 
 pub struct HackRFDevice {
     ptr: *mut ffi::hackrf_device,
+    // Last sample rate set through this handle, used by helpers that need to
+    // synthesise or pace samples. The firmware powers up at 10Msps.
+    sample_rate_hz: f64,
+    // Last RF centre frequency tuned through this handle, 0 if never tuned.
+    freq_hz: u64,
+    // Gains, filter and switches last set through this handle, for
+    // `cached_config`. Frequency and sample rate are kept above.
+    settings: DeviceConfig,
+    // Serial number read when opened, to say which board failed in errors.
+    serial: Option<String>,
+    // RX callback owned by the device for helpers that outlive the call that
+    // started streaming, such as `Recorder`. Dropped after the device closes.
+    rx_callback: Option<RxCallback>,
+    // As `rx_callback`, for TX.
+    tx_callback: Option<TxCallback>,
+}
+
+impl HackRFDevice {
+    fn unopened() -> HackRFDevice {
+        HackRFDevice {
+            ptr: std::ptr::null_mut(),
+            sample_rate_hz: 10e6,
+            freq_hz: 0,
+            settings: DeviceConfig::default(),
+            serial: None,
+            rx_callback: None,
+            tx_callback: None,
+        }
+    }
+
+    // The board has just been opened.
+    fn opened(mut self) -> HackRFDevice {
+        self.serial = read_serial(&mut self);
+        self
+    }
+
+    // An error from libhackrf's `err` in `operation`, called with `args`.
+    pub(crate) fn error(
+        &self,
+        err: c_int,
+        operation: &'static str,
+        args: std::fmt::Arguments,
+    ) -> HackRFError {
+        returned_error(err).context(operation, args, self.serial.clone())
+    }
 }
 
+// Every call into libhackrf goes through `&mut self` or a free function
+// taking `&mut HackRFDevice`, so the handle is only ever used from one
+// thread at a time, and the callbacks the device owns are `Send`; moving it
+// to another thread is sound. It is not `Sync`.
+unsafe impl Send for HackRFDevice {}
+
 impl Drop for HackRFDevice {
     #[inline(never)]
     fn drop(&mut self) {
@@ -26,30 +265,241 @@ impl Drop for HackRFDevice {
 pub struct HackRFError {
     errno: c_int,
     errstr: String,
+    context: Option<Box<ErrorContext>>,
+}
+
+// What was being done when an error happened.
+struct ErrorContext {
+    operation: &'static str,
+    args: String,
+    serial: Option<String>,
 }
 
 impl std::fmt::Debug for HackRFError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "HackRF error: {} ({})", self.errstr, self.errno)
+        write!(f, "HackRF error: {} ({})", self, self.errno)
     }
 }
 
+impl HackRFError {
+    /// The libhackrf error code, one of the `ERROR_` constants.
+    pub fn errno(&self) -> i32 {
+        self.errno
+    }
+
+    /// The function that failed, such as `set_freq`, if known.
+    pub fn operation(&self) -> Option<&'static str> {
+        self.context.as_ref().map(|context| context.operation)
+    }
+
+    /// The arguments the failed function was called with, as `name=value`
+    /// pairs separated by commas, if known.
+    pub fn args(&self) -> Option<&str> {
+        self.context.as_ref().map(|context| &*context.args)
+    }
+
+    /// Serial number of the board the error happened on, if known. Tells
+    /// apart failures from different boards in the same process.
+    pub fn serial(&self) -> Option<&str> {
+        self.context.as_ref()?.serial.as_deref()
+    }
+
+    // Record that the error happened in `operation`, called with `args`,
+    // on the board with `serial`.
+    pub(crate) fn context(
+        mut self,
+        operation: &'static str,
+        args: std::fmt::Arguments,
+        serial: Option<String>,
+    ) -> HackRFError {
+        self.context = Some(Box::new(ErrorContext {
+            operation,
+            args: args.to_string(),
+            serial,
+        }));
+        self
+    }
+
+    /// libusb failed, as when the board is unplugged mid-transfer. Usually
+    /// cured by closing and reopening the device.
+    pub fn is_usb(&self) -> bool {
+        self.errno == ffi::HACKRF_ERROR_LIBUSB
+    }
+
+    /// The device, or its stream, is in use. Worth retrying once the other
+    /// user is done with it.
+    pub fn is_busy(&self) -> bool {
+        self.errno == ffi::HACKRF_ERROR_BUSY
+    }
+
+    /// No matching board is connected.
+    pub fn is_not_found(&self) -> bool {
+        self.errno == ffi::HACKRF_ERROR_NOT_FOUND
+    }
+
+    /// The stream has ended, either on its own or because it was stopped.
+    pub fn is_streaming_stopped(&self) -> bool {
+        matches!(
+            self.errno,
+            ffi::HACKRF_ERROR_STREAMING_STOPPED | ffi::HACKRF_ERROR_STREAMING_EXIT_CALLED
+        )
+    }
+
+    /// The streaming thread failed, or could not be started.
+    pub fn is_thread(&self) -> bool {
+        matches!(
+            self.errno,
+            ffi::HACKRF_ERROR_THREAD | ffi::HACKRF_ERROR_STREAMING_THREAD_ERR
+        )
+    }
+
+    /// An argument was out of range for the board.
+    pub fn is_invalid_param(&self) -> bool {
+        self.errno == ffi::HACKRF_ERROR_INVALID_PARAM
+    }
+}
+
+/// libhackrf error codes, as returned by `HackRFError::errno`.
+pub const ERROR_INVALID_PARAM: i32 = ffi::HACKRF_ERROR_INVALID_PARAM;
+pub const ERROR_NOT_FOUND: i32 = ffi::HACKRF_ERROR_NOT_FOUND;
+pub const ERROR_BUSY: i32 = ffi::HACKRF_ERROR_BUSY;
+pub const ERROR_NO_MEM: i32 = ffi::HACKRF_ERROR_NO_MEM;
+pub const ERROR_LIBUSB: i32 = ffi::HACKRF_ERROR_LIBUSB;
+pub const ERROR_THREAD: i32 = ffi::HACKRF_ERROR_THREAD;
+pub const ERROR_STREAMING_THREAD_ERR: i32 = ffi::HACKRF_ERROR_STREAMING_THREAD_ERR;
+pub const ERROR_STREAMING_STOPPED: i32 = ffi::HACKRF_ERROR_STREAMING_STOPPED;
+pub const ERROR_STREAMING_EXIT_CALLED: i32 = ffi::HACKRF_ERROR_STREAMING_EXIT_CALLED;
+pub const ERROR_OTHER: i32 = ffi::HACKRF_ERROR_OTHER;
+
+impl std::fmt::Display for HackRFError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // Such as "set_freq(freq_hz=2400000000) on <serial>: USB error".
+        if let Some(ref context) = self.context {
+            write!(f, "{}({})", context.operation, context.args)?;
+            if let Some(ref serial) = context.serial {
+                write!(f, " on {}", serial)?;
+            }
+            f.write_str(": ")?;
+        }
+        f.write_str(&self.errstr)
+    }
+}
+
+impl std::error::Error for HackRFError {}
+
+impl From<std::io::Error> for HackRFError {
+    fn from(err: std::io::Error) -> HackRFError {
+        // Unwrap device errors that were passed through I/O interfaces.
+        let err = match err.downcast::<HackRFError>() {
+            Ok(inner) => return inner,
+            Err(err) => err,
+        };
+        HackRFError {
+            errno: ffi::HACKRF_ERROR_OTHER,
+            errstr: err.to_string(),
+            context: None,
+        }
+    }
+}
+
+/// Carries the device error inside the `io::Error`, recoverable with
+/// `get_ref` and `downcast_ref`, or by converting back to `HackRFError`.
+impl From<HackRFError> for std::io::Error {
+    fn from(err: HackRFError) -> std::io::Error {
+        use std::io::ErrorKind;
+        let kind = match err.errno {
+            ffi::HACKRF_ERROR_INVALID_PARAM => ErrorKind::InvalidInput,
+            ffi::HACKRF_ERROR_NOT_FOUND => ErrorKind::NotFound,
+            ffi::HACKRF_ERROR_BUSY => ErrorKind::ResourceBusy,
+            ffi::HACKRF_ERROR_NO_MEM => ErrorKind::OutOfMemory,
+            // Most often the board being unplugged mid-transfer.
+            ffi::HACKRF_ERROR_LIBUSB => ErrorKind::ConnectionAborted,
+            ffi::HACKRF_ERROR_STREAMING_STOPPED | ffi::HACKRF_ERROR_STREAMING_EXIT_CALLED => {
+                ErrorKind::UnexpectedEof
+            }
+            _ => ErrorKind::Other,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+// An error libhackrf returned, as opposed to one made up on this side.
+fn returned_error(err: c_int) -> HackRFError {
+    let err = hackrf_error(err);
+    #[cfg(feature = "metrics")]
+    metrics::error(&err.errstr);
+    err
+}
+
 fn hackrf_error(err: c_int) -> HackRFError {
+    #[cfg(feature = "dlopen")]
+    let errstr = if ffi::loaded() {
+        error_name(err)
+    } else {
+        unloaded_error_name(err).to_string()
+    };
+    #[cfg(not(feature = "dlopen"))]
+    let errstr = error_name(err);
+    HackRFError {
+        errno: err as c_int,
+        errstr,
+        context: None,
+    }
+}
+
+fn error_name(err: c_int) -> String {
     let s = unsafe {
         let ptr = ffi::hackrf_error_name(err);
         std::ffi::CStr::from_ptr(ptr)
     };
-    HackRFError {
-        errno: err as c_int,
-        errstr: s.to_str().unwrap().to_string(),
+    s.to_str().unwrap().to_string()
+}
+
+// libhackrf's names for its errors, for errors raised by the backends that
+// do not need it while it is not loaded.
+#[cfg(feature = "dlopen")]
+fn unloaded_error_name(err: c_int) -> &'static str {
+    match err {
+        ffi::HACKRF_SUCCESS => "HACKRF_SUCCESS",
+        ffi::HACKRF_TRUE => "HACKRF_TRUE",
+        ffi::HACKRF_ERROR_INVALID_PARAM => "invalid parameter(s)",
+        ffi::HACKRF_ERROR_NOT_FOUND => "HackRF not found",
+        ffi::HACKRF_ERROR_BUSY => "HackRF busy",
+        ffi::HACKRF_ERROR_NO_MEM => "insufficient memory",
+        ffi::HACKRF_ERROR_LIBUSB => "USB error",
+        ffi::HACKRF_ERROR_THREAD => "transfer thread error",
+        ffi::HACKRF_ERROR_STREAMING_THREAD_ERR => "streaming thread encountered an error",
+        ffi::HACKRF_ERROR_STREAMING_STOPPED => "streaming stopped",
+        ffi::HACKRF_ERROR_STREAMING_EXIT_CALLED => "streaming terminated",
+        _ => "unspecified error",
     }
 }
 
+/// Load libhackrf if it has not been already, or return why it cannot be,
+/// so an application can carry on without it. `init` does this first, and
+/// any other call into libhackrf panics if it is missing. `HACKRF_LIBRARY`
+/// may be set to the library's path.
+#[cfg(feature = "dlopen")]
+pub fn load_library() -> Result<(), HackRFError> {
+    ffi::load().map_err(|err| HackRFError {
+        errno: ffi::HACKRF_ERROR_OTHER,
+        errstr: format!("libhackrf could not be loaded: {}", err),
+        context: None,
+    })
+}
+
+/// Copy a C string owned by libhackrf into a String.
+unsafe fn c_string(ptr: *const c_char) -> String {
+    std::ffi::CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
 /// Initialise the HackRF library. Call this once at application startup.
 pub fn init() -> Result<(), HackRFError> {
+    #[cfg(feature = "dlopen")]
+    load_library()?;
     match unsafe { ffi::hackrf_init() } {
         ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        err => Err(returned_error(err)),
     }
 }
 
@@ -58,24 +508,137 @@ pub fn init() -> Result<(), HackRFError> {
 pub fn exit() -> Result<(), HackRFError> {
     match unsafe { ffi::hackrf_exit() } {
         ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        err => Err(returned_error(err)),
     }
 }
 
 /// Attempt to open a connected HackRF device.
 pub fn open() -> Result<HackRFDevice, HackRFError> {
-    let mut device: HackRFDevice = unsafe { std::mem::zeroed() };
+    let mut device = HackRFDevice::unopened();
     match unsafe { ffi::hackrf_open(&mut device.ptr) } {
-        ffi::HACKRF_SUCCESS => Ok(device),
-        err => Err(hackrf_error(err)),
+        ffi::HACKRF_SUCCESS => Ok(device.opened()),
+        err => Err(returned_error(err).context("open", format_args!(""), None)),
+    }
+}
+
+/// As `open`, giving up after `timeout`. Opening can block for a long time
+/// when another process has claimed the board, so this opens on a helper
+/// thread and fails with `ERROR_BUSY` if it has not finished in time,
+/// keeping GUIs responsive, or `ERROR_THREAD` if the thread failed. A
+/// board the helper opens after that is closed again.
+pub fn open_with_timeout(timeout: std::time::Duration) -> Result<HackRFDevice, HackRFError> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        // If the caller gave up, this drops and so closes the device.
+        let _ = tx.send(open());
+    });
+    let err = match rx.recv_timeout(timeout) {
+        Ok(result) => return result,
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => ffi::HACKRF_ERROR_BUSY,
+        // The helper panicked without sending.
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => ffi::HACKRF_ERROR_THREAD,
+    };
+    Err(hackrf_error(err).context(
+        "open_with_timeout",
+        format_args!("timeout={:?}", timeout),
+        None,
+    ))
+}
+
+/// Open the connected HackRF with the given serial number, as listed by
+/// `device_list`.
+pub fn open_by_serial(serial: &str) -> Result<HackRFDevice, HackRFError> {
+    let serial = CString::new(serial).map_err(|_| hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM))?;
+    let mut device = HackRFDevice::unopened();
+    match unsafe { ffi::hackrf_open_by_serial(serial.as_ptr(), &mut device.ptr) } {
+        ffi::HACKRF_SUCCESS => Ok(device.opened()),
+        err => Err(returned_error(err).context(
+            "open_by_serial",
+            format_args!(""),
+            serial.into_string().ok(),
+        )),
+    }
+}
+
+/// Open a HackRF through a file descriptor for its USB device, as returned
+/// by `UsbDeviceConnection.getFileDescriptor()` on Android, where apps cannot
+/// open device nodes themselves. The descriptor stays owned by the caller
+/// and must remain open until the device is closed. Needs a libhackrf built
+/// for Android, which provides `hackrf_open_by_fd`, and `init` must have
+/// been called as usual.
+#[cfg(target_os = "android")]
+pub fn open_by_fd(fd: std::os::unix::io::RawFd) -> Result<HackRFDevice, HackRFError> {
+    let mut device = HackRFDevice::unopened();
+    match unsafe { ffi::hackrf_open_by_fd(fd, &mut device.ptr) } {
+        ffi::HACKRF_SUCCESS => Ok(device.opened()),
+        err => Err(returned_error(err).context("open_by_fd", format_args!("fd={}", fd), None)),
+    }
+}
+
+/// A HackRF found by `device_list`.
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    /// Serial number, usable with `open_by_serial`. Empty if unreadable.
+    pub serial: String,
+    /// USB product ID, which identifies the kind of board.
+    pub usb_board_id: u16,
+    /// Name of the kind of board, e.g. "HackRF One".
+    pub usb_board_name: String,
+}
+
+/// List the HackRF devices currently connected.
+pub fn device_list() -> Result<Vec<DeviceInfo>, HackRFError> {
+    let list = unsafe { ffi::hackrf_device_list() };
+    if list.is_null() {
+        return Err(hackrf_error(ffi::HACKRF_ERROR_NO_MEM));
+    }
+    let mut devices = Vec::new();
+    unsafe {
+        let l = &*list;
+        for i in 0..l.devicecount as usize {
+            let serial = *l.serial_numbers.add(i);
+            let usb_board_id = *l.usb_board_ids.add(i);
+            devices.push(DeviceInfo {
+                serial: if serial.is_null() {
+                    String::new()
+                } else {
+                    c_string(serial)
+                },
+                usb_board_id: usb_board_id as u16,
+                usb_board_name: c_string(ffi::hackrf_usb_board_id_name(usb_board_id as c_uint)),
+            });
+        }
+        ffi::hackrf_device_list_free(list);
+    }
+    Ok(devices)
+}
+
+/// Return the libhackrf version and release strings.
+pub fn library_version() -> (String, String) {
+    unsafe {
+        (
+            c_string(ffi::hackrf_library_version()),
+            c_string(ffi::hackrf_library_release()),
+        )
     }
 }
 
 /// Close a connected HackRF device.
-pub fn close(device: HackRFDevice) -> Result<(), HackRFError> {
-    match unsafe { ffi::hackrf_close(device.ptr) } {
+pub fn close(mut device: HackRFDevice) -> Result<(), HackRFError> {
+    // Null the pointer so Drop doesn't close the device a second time.
+    let ptr = std::mem::replace(&mut device.ptr, std::ptr::null_mut());
+    match unsafe { ffi::hackrf_close(ptr) } {
         ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        err => Err(device.error(err, "close", format_args!(""))),
+    }
+}
+
+/// Reset the board, restarting its firmware. The board drops off the USB
+/// bus, so close `device` afterwards and open it again once it reappears.
+pub fn reset(device: &mut HackRFDevice) -> Result<(), HackRFError> {
+    match unsafe { ffi::hackrf_reset(device.ptr) } {
+        ffi::HACKRF_SUCCESS => Ok(()),
+        err => Err(device.error(err, "reset", format_args!(""))),
     }
 }
 
@@ -86,7 +649,11 @@ extern "C" fn rx_cb(transfer: *mut ffi::hackrf_transfer) -> c_int {
     unsafe {
         let data = &*transfer;
         let buffer: &[u8] = std::slice::from_raw_parts(data.buffer, data.buffer_length as usize);
-        let cb: &mut &mut dyn FnMut(&[u8]) -> bool = std::mem::transmute(data.rx_ctx);
+        let cb = &mut *(data.rx_ctx as *mut &mut dyn FnMut(&[u8]) -> bool);
+        #[cfg(feature = "metrics")]
+        metrics::rx_transfer(buffer.len());
+        #[cfg(feature = "log")]
+        logging::rx_transfer();
 
         match (**cb)(buffer) {
             true => 0 as c_int,
@@ -101,11 +668,15 @@ extern "C" fn rx_cb(transfer: *mut ffi::hackrf_transfer) -> c_int {
 extern "C" fn tx_cb(transfer: *mut ffi::hackrf_transfer) -> c_int {
     unsafe {
         let data = &*transfer;
-        let buffer: &mut [u8] = std::slice::from_mut(&mut *data.buffer);
-
-        let cb: *mut &mut dyn FnMut(&mut [u8]) -> bool = std::mem::transmute(data.rx_ctx);
+        let buffer: &mut [u8] =
+            std::slice::from_raw_parts_mut(data.buffer, data.buffer_length as usize);
+        let cb = &mut *(data.tx_ctx as *mut &mut dyn FnMut(&mut [u8]) -> bool);
+        #[cfg(feature = "metrics")]
+        metrics::tx_transfer(buffer.len());
+        #[cfg(feature = "log")]
+        logging::tx_transfer();
 
-        match (*cb)(buffer) {
+        match (**cb)(buffer) {
             true => 0 as c_int,
             false => 1 as c_int,
         }
@@ -127,15 +698,29 @@ pub fn start_rx(
     let ctx = unsafe { std::mem::transmute(reference as *mut &mut dyn FnMut(&[u8]) -> bool) };
     match unsafe { ffi::hackrf_start_rx(device.ptr, rx_cb, ctx) } {
         ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        err => Err(device.error(err, "start_rx", format_args!(""))),
     }
 }
 
+/// Begin an RX stream whose callback is owned by `device`, so it stays alive
+/// for as long as streaming might use it. Any previous owned callback is
+/// replaced, so only call this when not already receiving.
+pub(crate) fn start_rx_owned(
+    device: &mut HackRFDevice,
+    callback: RxCallback,
+) -> Result<(), HackRFError> {
+    let callback = device.rx_callback.insert(callback);
+    let callback = &mut **callback as *mut (dyn FnMut(&[u8]) -> bool + Send);
+    start_rx(device, unsafe { &mut *callback })
+}
+
 /// Stop RX stream
 pub fn stop_rx(device: &mut HackRFDevice) -> Result<(), HackRFError> {
+    #[cfg(feature = "metrics")]
+    metrics::rx_stopped();
     match unsafe { ffi::hackrf_stop_rx(device.ptr) } {
         ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        err => Err(device.error(err, "stop_rx", format_args!(""))),
     }
 }
 
@@ -150,18 +735,40 @@ pub fn start_tx(
     device: &mut HackRFDevice,
     callback: &mut impl FnMut(&mut [u8]) -> bool,
 ) -> Result<(), HackRFError> {
-    let ctx = (callback as *mut _) as *mut std::ffi::c_void;
+    start_tx_dyn(device, callback)
+}
+
+fn start_tx_dyn(
+    device: &mut HackRFDevice,
+    callback: &mut dyn FnMut(&mut [u8]) -> bool,
+) -> Result<(), HackRFError> {
+    let boxed = Box::new(callback);
+    let reference = Box::leak(boxed);
+    let ctx = reference as *mut &mut dyn FnMut(&mut [u8]) -> bool as *mut c_void;
     match unsafe { ffi::hackrf_start_tx(device.ptr, tx_cb, ctx) } {
         ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        err => Err(device.error(err, "start_tx", format_args!(""))),
     }
 }
 
+/// Begin a TX stream whose callback is owned by `device`, as for
+/// `start_rx_owned`.
+pub(crate) fn start_tx_owned(
+    device: &mut HackRFDevice,
+    callback: TxCallback,
+) -> Result<(), HackRFError> {
+    let callback = device.tx_callback.insert(callback);
+    let callback = &mut **callback as *mut (dyn FnMut(&mut [u8]) -> bool + Send);
+    start_tx_dyn(device, unsafe { &mut *callback })
+}
+
 /// Stop TX stream
 pub fn stop_tx(device: &mut HackRFDevice) -> Result<(), HackRFError> {
+    #[cfg(feature = "metrics")]
+    metrics::tx_stopped();
     match unsafe { ffi::hackrf_stop_tx(device.ptr) } {
         ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        err => Err(device.error(err, "stop_tx", format_args!(""))),
     }
 }
 
@@ -172,20 +779,51 @@ pub fn is_streaming(device: &mut HackRFDevice) -> Result<bool, HackRFError> {
     match unsafe { ffi::hackrf_is_streaming(device.ptr) } {
         ffi::HACKRF_TRUE => Ok(true),
         ffi::HACKRF_ERROR_STREAMING_STOPPED | ffi::HACKRF_ERROR_STREAMING_EXIT_CALLED => Ok(false),
-        err => Err(hackrf_error(err)),
+        err => Err(device.error(err, "is_streaming", format_args!(""))),
+    }
+}
+
+/// A cloneable handle used to stop a blocking stream helper such as
+/// `transmit_repeated`, either from another thread or from inside a callback.
+#[derive(Clone, Default)]
+pub struct StopHandle {
+    stopped: Arc<AtomicBool>,
+}
+
+impl StopHandle {
+    pub fn new() -> StopHandle {
+        StopHandle::default()
+    }
+
+    /// Ask the stream using this handle to stop at the next opportunity.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true once `stop` has been called on any clone of this handle.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
     }
 }
 
 /// Set the HackRF baseband filter bandwidth, in Hz.
 /// See also `compute_baseband_filter_bw` and
-/// `compute_baseband_filter_bw_round_down_lt`.
+/// `compute_baseband_filter_bw_round_down_lt`, or `BasebandFilterBandwidth`
+/// for the bandwidths the filter supports.
 pub fn set_baseband_filter_bandwidth(
     device: &mut HackRFDevice,
     bandwidth_hz: c_uint,
 ) -> Result<(), HackRFError> {
     match unsafe { ffi::hackrf_set_baseband_filter_bandwidth(device.ptr, bandwidth_hz as u32) } {
-        ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        ffi::HACKRF_SUCCESS => {
+            device.settings.baseband_filter_hz = Some(bandwidth_hz);
+            Ok(())
+        }
+        err => Err(device.error(
+            err,
+            "set_baseband_filter_bandwidth",
+            format_args!("bandwidth_hz={}", bandwidth_hz),
+        )),
     }
 }
 
@@ -196,24 +834,50 @@ pub fn board_id_read(device: &mut HackRFDevice) -> Result<(c_int, String), HackR
     match unsafe { ffi::hackrf_board_id_read(device.ptr, &mut id) } {
         ffi::HACKRF_SUCCESS => {
             let s = unsafe {
-                let ptr = ffi::hackrf_board_id_name(id as u8);
+                let ptr = ffi::hackrf_board_id_name(id as c_uint);
                 std::ffi::CStr::from_ptr(ptr)
             };
             Ok((id as c_int, s.to_str().unwrap().to_string()))
         }
-        err => Err(hackrf_error(err)),
+        err => Err(device.error(err, "board_id_read", format_args!(""))),
+    }
+}
+
+/// Read the board revision. Returns a tuple of the numeric revision and a
+/// corresponding String. Only HackRF One r6 and later can report this.
+pub fn board_rev_read(device: &mut HackRFDevice) -> Result<(u8, String), HackRFError> {
+    let mut rev: u8 = ffi::BOARD_REV_UNDETECTED;
+    match unsafe { ffi::hackrf_board_rev_read(device.ptr, &mut rev) } {
+        ffi::HACKRF_SUCCESS => Ok((rev, unsafe {
+            c_string(ffi::hackrf_board_rev_name(rev as c_uint))
+        })),
+        err => Err(device.error(err, "board_rev_read", format_args!(""))),
+    }
+}
+
+/// Bits returned by `supported_platform_read`.
+pub const PLATFORM_JAWBREAKER: u32 = ffi::HACKRF_PLATFORM_JAWBREAKER;
+pub const PLATFORM_HACKRF1_OG: u32 = ffi::HACKRF_PLATFORM_HACKRF1_OG;
+pub const PLATFORM_RAD1O: u32 = ffi::HACKRF_PLATFORM_RAD1O;
+pub const PLATFORM_HACKRF1_R9: u32 = ffi::HACKRF_PLATFORM_HACKRF1_R9;
+
+/// Read the set of hardware platforms the installed firmware supports, as a
+/// bitmask of the `PLATFORM_` constants.
+pub fn supported_platform_read(device: &mut HackRFDevice) -> Result<u32, HackRFError> {
+    let mut platforms = 0;
+    match unsafe { ffi::hackrf_supported_platform_read(device.ptr, &mut platforms) } {
+        ffi::HACKRF_SUCCESS => Ok(platforms),
+        err => Err(device.error(err, "supported_platform_read", format_args!(""))),
     }
 }
 
 /// Read the board's firmware version string.
 pub fn version_string_read(device: &mut HackRFDevice) -> Result<String, HackRFError> {
-    let mut buf = [0; 127];
+    // libhackrf NUL-terminates the string after up to `length` bytes.
+    let mut buf = [0; 128];
     match unsafe { ffi::hackrf_version_string_read(device.ptr, buf.as_mut_ptr(), 127) } {
-        ffi::HACKRF_SUCCESS => {
-            let s = unsafe { std::str::from_utf8(std::mem::transmute(buf.as_slice())) };
-            Ok(String::from(s.unwrap()))
-        }
-        err => Err(hackrf_error(err)),
+        ffi::HACKRF_SUCCESS => Ok(unsafe { c_string(buf.as_ptr()) }),
+        err => Err(device.error(err, "version_string_read", format_args!(""))),
     }
 }
 
@@ -224,19 +888,32 @@ pub fn board_partid_serialno_read(
     let mut serial: ffi::read_partid_serialno_t = unsafe { std::mem::zeroed() };
     match unsafe { ffi::hackrf_board_partid_serialno_read(device.ptr, &mut serial) } {
         ffi::HACKRF_SUCCESS => Ok((serial.part_id, serial.serial_no)),
-        err => Err(hackrf_error(err)),
+        err => Err(device.error(err, "board_partid_serialno_read", format_args!(""))),
     }
 }
 
+// The board's serial number as 32 hex digits, the form `open_by_serial`
+// takes, or `None` if it cannot be read.
+pub(crate) fn read_serial(device: &mut HackRFDevice) -> Option<String> {
+    board_partid_serialno_read(device)
+        .ok()
+        .map(|(_, serial)| serial.iter().map(|word| format!("{:08x}", word)).collect())
+}
+
 /// Set HackRF frequency
 pub fn set_freq(device: &mut HackRFDevice, freq_hz: u64) -> Result<(), HackRFError> {
     match unsafe { ffi::hackrf_set_freq(device.ptr, freq_hz) } {
-        ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        ffi::HACKRF_SUCCESS => {
+            device.freq_hz = freq_hz;
+            #[cfg(feature = "metrics")]
+            metrics::retune();
+            Ok(())
+        }
+        err => Err(device.error(err, "set_freq", format_args!("freq_hz={}", freq_hz))),
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum RFPathFilter {
     Bypass,
     LowPass,
@@ -245,6 +922,7 @@ pub enum RFPathFilter {
 
 /// Set HackRF frequency, specifying IF and LO and filters separately.
 /// `path` may be `RFPathFilter::Bypass`, `LowPass` or `HighPass`.
+/// `explain_tuning` shows what `set_freq` would choose.
 pub fn set_freq_explicit(
     device: &mut HackRFDevice,
     if_freq_hz: u64,
@@ -257,30 +935,92 @@ pub fn set_freq_explicit(
         RFPathFilter::HighPass => ffi::RF_PATH_FILTER_HIGH_PASS,
     };
     match unsafe { ffi::hackrf_set_freq_explicit(device.ptr, if_freq_hz, lo_freq_hz, c_path) } {
-        ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        ffi::HACKRF_SUCCESS => {
+            device.freq_hz = match path {
+                RFPathFilter::Bypass => if_freq_hz,
+                RFPathFilter::LowPass => lo_freq_hz.abs_diff(if_freq_hz),
+                RFPathFilter::HighPass => if_freq_hz + lo_freq_hz,
+            };
+            #[cfg(feature = "metrics")]
+            metrics::retune();
+            Ok(())
+        }
+        err => Err(device.error(
+            err,
+            "set_freq_explicit",
+            format_args!(
+                "if_freq_hz={}, lo_freq_hz={}, path={:?}",
+                if_freq_hz, lo_freq_hz, path
+            ),
+        )),
     }
 }
 
 /// Set HackRF sample rate, specifying c_integer frequency and divider
 /// Preferred rates are 8, 10, 12.5, 16 and 20MHz
+/// Returns the rate actually produced and the baseband filter libhackrf
+/// set to suit it.
 pub fn set_sample_rate_manual(
     device: &mut HackRFDevice,
     freq_hz: u32,
     divider: u32,
-) -> Result<(), HackRFError> {
+) -> Result<SampleRate, HackRFError> {
     match unsafe { ffi::hackrf_set_sample_rate_manual(device.ptr, freq_hz, divider) } {
-        ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        ffi::HACKRF_SUCCESS => {
+            let actual_hz = rate::achieved(freq_hz, divider);
+            device.sample_rate_hz = actual_hz;
+            Ok(SampleRate {
+                requested_hz: freq_hz as f64 / divider as f64,
+                actual_hz,
+                freq_hz,
+                divider,
+                baseband_filter_hz: compute_baseband_filter_bw(
+                    (0.75 * freq_hz as f64 / divider as f64) as u32,
+                ),
+            })
+        }
+        err => Err(device.error(
+            err,
+            "set_sample_rate_manual",
+            format_args!("freq_hz={}, divider={}", freq_hz, divider),
+        )),
     }
 }
 
 /// Set HackRF sample rate, specifying frequency as a double float
 /// Preferred rates are 8, 10, 12.5, 16 and 20MHz
-pub fn set_sample_rate(device: &mut HackRFDevice, freq_hz: f64) -> Result<(), HackRFError> {
+/// Returns the rate actually produced, which downstream resamplers should
+/// use in place of the request, and the baseband filter libhackrf set to
+/// suit it.
+pub fn set_sample_rate(device: &mut HackRFDevice, freq_hz: f64) -> Result<SampleRate, HackRFError> {
     match unsafe { ffi::hackrf_set_sample_rate(device.ptr, freq_hz) } {
-        ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        ffi::HACKRF_SUCCESS => {
+            let (fraction_hz, divider) = rate::fraction(freq_hz);
+            let actual_hz = rate::achieved(fraction_hz, divider);
+            device.sample_rate_hz = actual_hz;
+            Ok(SampleRate {
+                requested_hz: freq_hz,
+                actual_hz,
+                freq_hz: fraction_hz,
+                divider,
+                baseband_filter_hz: compute_baseband_filter_bw(
+                    (0.75 * fraction_hz as f64 / divider as f64) as u32,
+                ),
+            })
+        }
+        err => Err(device.error(err, "set_sample_rate", format_args!("freq_hz={}", freq_hz))),
+    }
+}
+
+/// The settings last made through `device`'s handle, without asking the
+/// board: `None` for any never set, and the power-on sample rate if that was
+/// never set. Changes made by other tools or firmware defaults are not
+/// reflected.
+pub fn cached_config(device: &HackRFDevice) -> DeviceConfig {
+    DeviceConfig {
+        freq_hz: Some(device.freq_hz).filter(|&freq| freq != 0),
+        sample_rate: Some(device.sample_rate_hz),
+        ..device.settings.clone()
     }
 }
 
@@ -291,8 +1031,11 @@ pub fn set_amp_enable(device: &mut HackRFDevice, on: bool) -> Result<(), HackRFE
         true => 1,
     };
     match unsafe { ffi::hackrf_set_amp_enable(device.ptr, value) } {
-        ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        ffi::HACKRF_SUCCESS => {
+            device.settings.amp = Some(on);
+            Ok(())
+        }
+        err => Err(device.error(err, "set_amp_enable", format_args!("on={}", on))),
     }
 }
 
@@ -300,8 +1043,11 @@ pub fn set_amp_enable(device: &mut HackRFDevice, on: bool) -> Result<(), HackRFE
 pub fn set_lna_gain(device: &mut HackRFDevice, gain: u32) -> Result<(), HackRFError> {
     assert!(gain <= 40);
     match unsafe { ffi::hackrf_set_lna_gain(device.ptr, gain) } {
-        ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        ffi::HACKRF_SUCCESS => {
+            device.settings.lna_gain = Some(gain);
+            Ok(())
+        }
+        err => Err(device.error(err, "set_lna_gain", format_args!("gain={}", gain))),
     }
 }
 
@@ -309,8 +1055,11 @@ pub fn set_lna_gain(device: &mut HackRFDevice, gain: u32) -> Result<(), HackRFEr
 pub fn set_vga_gain(device: &mut HackRFDevice, gain: u32) -> Result<(), HackRFError> {
     assert!(gain <= 62);
     match unsafe { ffi::hackrf_set_vga_gain(device.ptr, gain) } {
-        ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        ffi::HACKRF_SUCCESS => {
+            device.settings.vga_gain = Some(gain);
+            Ok(())
+        }
+        err => Err(device.error(err, "set_vga_gain", format_args!("gain={}", gain))),
     }
 }
 
@@ -318,8 +1067,11 @@ pub fn set_vga_gain(device: &mut HackRFDevice, gain: u32) -> Result<(), HackRFEr
 pub fn set_txvga_gain(device: &mut HackRFDevice, gain: u32) -> Result<(), HackRFError> {
     assert!(gain <= 47);
     match unsafe { ffi::hackrf_set_txvga_gain(device.ptr, gain) } {
-        ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        ffi::HACKRF_SUCCESS => {
+            device.settings.txvga_gain = Some(gain);
+            Ok(())
+        }
+        err => Err(device.error(err, "set_txvga_gain", format_args!("gain={}", gain))),
     }
 }
 
@@ -330,8 +1082,70 @@ pub fn set_antenna_enable(device: &mut HackRFDevice, on: bool) -> Result<(), Hac
         true => 1,
     };
     match unsafe { ffi::hackrf_set_antenna_enable(device.ptr, value) } {
+        ffi::HACKRF_SUCCESS => {
+            device.settings.antenna_power = Some(on);
+            Ok(())
+        }
+        err => Err(device.error(err, "set_antenna_enable", format_args!("on={}", on))),
+    }
+}
+
+/// Enable or disable the 10MHz reference output on the CLKOUT connector, to
+/// drive the CLKIN of other boards.
+pub fn set_clkout_enable(device: &mut HackRFDevice, on: bool) -> Result<(), HackRFError> {
+    match unsafe { ffi::hackrf_set_clkout_enable(device.ptr, on as u8) } {
+        ffi::HACKRF_SUCCESS => Ok(()),
+        err => Err(device.error(err, "set_clkout_enable", format_args!("on={}", on))),
+    }
+}
+
+/// Whether a reference clock is detected on the CLKIN connector. The board
+/// uses it in place of its own crystal whenever one is present.
+pub fn clkin_status(device: &mut HackRFDevice) -> Result<bool, HackRFError> {
+    let mut status = 0;
+    match unsafe { ffi::hackrf_get_clkin_status(device.ptr, &mut status) } {
+        ffi::HACKRF_SUCCESS => Ok(status != 0),
+        err => Err(device.error(err, "clkin_status", format_args!(""))),
+    }
+}
+
+/// Counters kept by the M0 coprocessor, which moves samples between the
+/// USB buffers and the radio, as read by `m0_state`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct M0State {
+    /// Bytes moved since streaming started, wrapping at 2^32.
+    pub m0_count: u32,
+    /// Bytes the host side has handled, wrapping at 2^32.
+    pub m4_count: u32,
+    /// Times the M0 ran out of data to send or room to receive, when the
+    /// host did not keep up and samples were lost or padded.
+    pub num_shortfalls: u32,
+    /// Length of the longest shortfall, in bytes.
+    pub longest_shortfall: u32,
+}
+
+/// Read the M0 coprocessor's counters, which reveal samples lost at the
+/// board because the host fell behind. Needs firmware 2022.09.1 or later.
+pub fn m0_state(device: &mut HackRFDevice) -> Result<M0State, HackRFError> {
+    let mut state = ffi::hackrf_m0_state::default();
+    match unsafe { ffi::hackrf_get_m0_state(device.ptr, &mut state) } {
+        ffi::HACKRF_SUCCESS => Ok(M0State {
+            m0_count: state.m0_count,
+            m4_count: state.m4_count,
+            num_shortfalls: state.num_shortfalls,
+            longest_shortfall: state.longest_shortfall,
+        }),
+        err => Err(device.error(err, "m0_state", format_args!(""))),
+    }
+}
+
+/// Enable or disable hardware sync mode. While enabled, streaming started
+/// with `start_rx` or `start_tx` only begins once a pulse arrives on the
+/// trigger input, so several boards can start on the same sample.
+pub fn set_hw_sync_mode(device: &mut HackRFDevice, on: bool) -> Result<(), HackRFError> {
+    match unsafe { ffi::hackrf_set_hw_sync_mode(device.ptr, on as u8) } {
         ffi::HACKRF_SUCCESS => Ok(()),
-        err => Err(hackrf_error(err)),
+        err => Err(device.error(err, "set_hw_sync_mode", format_args!("on={}", on))),
     }
 }
 
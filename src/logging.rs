@@ -0,0 +1,92 @@
+// Rate-limited warnings of lost samples for the `log` crate
+// Licensed under MIT license
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+// Shortest time between warnings of the same kind. The first event is
+// warned of at once; the rest are counted and reported together.
+const INTERVAL: Duration = Duration::from_secs(5);
+
+struct Events {
+    what: &'static str,
+    // Events not yet warned of.
+    pending: AtomicU64,
+    state: Mutex<State>,
+}
+
+struct State {
+    // When the last warning was logged.
+    last: Option<Instant>,
+    total: u64,
+}
+
+static OVERRUNS: Events = Events::new("RX overrun: transfers dropped because a queue was full");
+static UNDERRUNS: Events =
+    Events::new("TX underrun: transfers padded with silence because no samples were queued");
+
+impl Events {
+    const fn new(what: &'static str) -> Events {
+        Events {
+            what,
+            pending: AtomicU64::new(0),
+            state: Mutex::new(State {
+                last: None,
+                total: 0,
+            }),
+        }
+    }
+
+    fn record(&self) {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.flush();
+    }
+
+    // Warn of pending events unless a warning was logged too recently.
+    fn flush(&self) {
+        if self.pending.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        let since = state.last.map(|last| now - last);
+        if since.is_some_and(|since| since < INTERVAL) {
+            return;
+        }
+        let count = self.pending.swap(0, Ordering::Relaxed);
+        if count == 0 {
+            return;
+        }
+        state.total += count;
+        state.last = Some(now);
+        match since {
+            Some(since) => log::warn!(
+                "{}: {} in the last {:.1}s, {} in total",
+                self.what,
+                count,
+                since.as_secs_f64(),
+                state.total
+            ),
+            None => log::warn!("{}: {}", self.what, count),
+        }
+    }
+}
+
+pub(crate) fn overrun() {
+    OVERRUNS.record();
+}
+
+pub(crate) fn underrun() {
+    UNDERRUNS.record();
+}
+
+// Called for every transfer, so that events are reported once the interval
+// is up even if no more follow.
+pub(crate) fn rx_transfer() {
+    OVERRUNS.flush();
+}
+
+pub(crate) fn tx_transfer() {
+    UNDERRUNS.flush();
+}
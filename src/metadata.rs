@@ -0,0 +1,159 @@
+// Acquisition metadata written alongside recordings
+// Licensed under MIT license
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use super::config::DeviceConfig;
+use super::format::SampleFormat;
+use super::time::UtcTime;
+use super::{cached_config, read_serial, version_string_read, HackRFDevice};
+
+/// What a recording was made with: the settings made through the device
+/// handle, as `cached_config` reports them, and the board's firmware
+/// version and serial number. `Recorder` gathers this when it starts and
+/// writes it with every file, so that no capture's provenance is lost.
+#[derive(Clone, Debug)]
+pub struct Acquisition {
+    pub config: DeviceConfig,
+    /// `None` if it could not be read.
+    pub firmware: Option<String>,
+    /// As 32 hex digits, the form `open_by_serial` takes, or `None` if it
+    /// could not be read.
+    pub serial: Option<String>,
+}
+
+impl Acquisition {
+    /// Gather the metadata for `device`. Reads the firmware version and
+    /// serial number from the board, so call it before streaming starts.
+    pub fn read(device: &mut HackRFDevice) -> Acquisition {
+        Acquisition {
+            config: cached_config(device),
+            firmware: version_string_read(device).ok(),
+            serial: read_serial(device),
+        }
+    }
+
+    /// A SigMF metadata document describing a recording in `format` that
+    /// started at `started`, with the gains and switches in `hackrf:`
+    /// fields. Written next to a raw recording as its `.sigmf-meta` file,
+    /// this makes the pair a SigMF recording: pass the recording's file
+    /// name as `dataset` unless it is the matching `.sigmf-data` file.
+    pub fn sigmf_meta(
+        &self,
+        format: SampleFormat,
+        started: SystemTime,
+        dataset: Option<&str>,
+    ) -> String {
+        let datatype = match format {
+            SampleFormat::Cs8 => "ci8",
+            SampleFormat::Cs16 => "ci16_le",
+            SampleFormat::Cf32 => "cf32_le",
+        };
+        let mut global = vec![
+            ("core:datatype".to_string(), json_string(datatype)),
+            ("core:version".to_string(), json_string("1.0.0")),
+            ("core:hw".to_string(), json_string("HackRF")),
+            ("core:recorder".to_string(), json_string("hackrf-rs")),
+        ];
+        if let Some(dataset) = dataset {
+            global.push(("core:dataset".to_string(), json_string(dataset)));
+        }
+        if let Some(rate) = self.config.sample_rate {
+            global.push(("core:sample_rate".to_string(), rate.to_string()));
+        }
+        for (key, value) in self.fields() {
+            global.push((format!("hackrf:{}", key), value));
+        }
+        let mut capture = vec![
+            ("core:sample_start".to_string(), "0".to_string()),
+            ("core:datetime".to_string(), json_string(&iso8601(started))),
+        ];
+        if let Some(freq_hz) = self.config.freq_hz {
+            capture.push(("core:frequency".to_string(), freq_hz.to_string()));
+        }
+        format!(
+            "{{\n  \"global\": {},\n  \"captures\": [{}],\n  \"annotations\": []\n}}\n",
+            json_object(&global, "    "),
+            json_object(&capture, "    ")
+        )
+    }
+
+    /// A one-line summary, as `key=value` pairs, for formats with room for
+    /// a comment.
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        if let Some(freq_hz) = self.config.freq_hz {
+            let _ = write!(summary, "freq_hz={} ", freq_hz);
+        }
+        if let Some(rate) = self.config.sample_rate {
+            let _ = write!(summary, "sample_rate={} ", rate);
+        }
+        for (key, value) in self.fields() {
+            let _ = write!(summary, "{}={} ", key, value.trim_matches('"'));
+        }
+        summary.trim_end().to_string()
+    }
+
+    // The `hackrf:` fields that are known, with JSON values.
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let config = &self.config;
+        let mut fields = Vec::new();
+        let numbers = [
+            ("baseband_filter_hz", config.baseband_filter_hz),
+            ("lna_gain", config.lna_gain),
+            ("vga_gain", config.vga_gain),
+            ("txvga_gain", config.txvga_gain),
+        ];
+        for &(key, value) in &numbers {
+            if let Some(value) = value {
+                fields.push((key, value.to_string()));
+            }
+        }
+        for &(key, value) in &[("amp", config.amp), ("antenna_power", config.antenna_power)] {
+            if let Some(value) = value {
+                fields.push((key, value.to_string()));
+            }
+        }
+        if let Some(ref firmware) = self.firmware {
+            fields.push(("firmware", json_string(firmware)));
+        }
+        if let Some(ref serial) = self.serial {
+            fields.push(("serial", json_string(serial)));
+        }
+        fields
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn json_object(fields: &[(String, String)], indent: &str) -> String {
+    let body: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}{}: {}", indent, json_string(key), value))
+        .collect();
+    format!("{{\n{}\n{}}}", body.join(",\n"), &indent[2..])
+}
+
+fn iso8601(time: SystemTime) -> String {
+    let t = UtcTime::new(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        t.year, t.month, t.day, t.hour, t.minute, t.second, t.micros
+    )
+}
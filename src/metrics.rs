@@ -0,0 +1,124 @@
+// Counters and gauges for the `metrics` crate
+// Licensed under MIT license
+
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use libmetrics::{counter, describe_counter, describe_gauge, gauge, Unit};
+
+// How often the samples/sec gauges are updated.
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Rate {
+    since: Option<Instant>,
+    samples: u64,
+}
+
+static RX_RATE: Mutex<Rate> = Mutex::new(Rate {
+    since: None,
+    samples: 0,
+});
+static TX_RATE: Mutex<Rate> = Mutex::new(Rate {
+    since: None,
+    samples: 0,
+});
+
+/// Register descriptions of everything this crate reports. Call after
+/// installing a recorder, such as a Prometheus exporter. The metrics are:
+///
+/// * `hackrf_samples_total{direction}`: samples streamed, `rx` or `tx`
+/// * `hackrf_samples_per_second{direction}`: recent streaming rate, 0 once
+///   the stream stops or a `Watchdog` or `Supervisor` finds it stalled
+/// * `hackrf_dropped_buffers_total`: RX transfers dropped by a full queue
+/// * `hackrf_tx_underruns_total`: TX transfers padded with silence
+/// * `hackrf_errors_total{error}`: errors returned, by libhackrf name
+/// * `hackrf_retunes_total`: successful `set_freq` calls
+///
+/// Values are summed over all open devices.
+pub fn describe() {
+    describe_counter!(
+        "hackrf_samples_total",
+        Unit::Count,
+        "Complex samples streamed"
+    );
+    describe_gauge!(
+        "hackrf_samples_per_second",
+        "Complex samples streamed per second"
+    );
+    describe_counter!(
+        "hackrf_dropped_buffers_total",
+        Unit::Count,
+        "RX transfers dropped because a queue was full"
+    );
+    describe_counter!(
+        "hackrf_tx_underruns_total",
+        Unit::Count,
+        "TX transfers padded with silence because no samples were queued"
+    );
+    describe_counter!(
+        "hackrf_errors_total",
+        Unit::Count,
+        "Errors returned by libhackrf"
+    );
+    describe_counter!(
+        "hackrf_retunes_total",
+        Unit::Count,
+        "Successful changes of centre frequency"
+    );
+}
+
+fn transfer(direction: &'static str, rate: &Mutex<Rate>, bytes: usize) {
+    let samples = bytes as u64 / 2;
+    counter!("hackrf_samples_total", "direction" => direction).increment(samples);
+    let mut rate = rate.lock().unwrap_or_else(PoisonError::into_inner);
+    let now = Instant::now();
+    let since = *rate.since.get_or_insert(now);
+    rate.samples += samples;
+    let elapsed = now - since;
+    if elapsed >= RATE_INTERVAL {
+        gauge!("hackrf_samples_per_second", "direction" => direction)
+            .set(rate.samples as f64 / elapsed.as_secs_f64());
+        rate.since = Some(now);
+        rate.samples = 0;
+    }
+}
+
+pub(crate) fn rx_transfer(bytes: usize) {
+    transfer("rx", &RX_RATE, bytes);
+}
+
+pub(crate) fn tx_transfer(bytes: usize) {
+    transfer("tx", &TX_RATE, bytes);
+}
+
+// The stream has stopped or stalled, so its rate is no longer current.
+fn stopped(direction: &'static str, rate: &Mutex<Rate>) {
+    let mut rate = rate.lock().unwrap_or_else(PoisonError::into_inner);
+    rate.since = None;
+    rate.samples = 0;
+    gauge!("hackrf_samples_per_second", "direction" => direction).set(0.0);
+}
+
+pub(crate) fn rx_stopped() {
+    stopped("rx", &RX_RATE);
+}
+
+pub(crate) fn tx_stopped() {
+    stopped("tx", &TX_RATE);
+}
+
+pub(crate) fn dropped_buffer() {
+    counter!("hackrf_dropped_buffers_total").increment(1);
+}
+
+pub(crate) fn tx_underrun() {
+    counter!("hackrf_tx_underruns_total").increment(1);
+}
+
+pub(crate) fn error(name: &str) {
+    counter!("hackrf_errors_total", "error" => name.to_string()).increment(1);
+}
+
+pub(crate) fn retune() {
+    counter!("hackrf_retunes_total").increment(1);
+}
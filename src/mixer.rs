@@ -0,0 +1,76 @@
+// Shifting received signals in frequency
+// Licensed under MIT license
+
+use std::f64::consts::PI;
+
+use super::decimate::to_cs8;
+use super::sdr::RxCallback;
+use super::{set_freq, start_rx_owned, HackRFDevice, HackRFError};
+
+/// Numerically controlled oscillator and mixer, shifting cs8 samples in
+/// frequency, keeping its phase between calls so a stream can be fed a
+/// buffer at a time.
+pub struct Mixer {
+    // Current oscillator value and per-sample rotation, as (re, im).
+    phase: (f64, f64),
+    step: (f64, f64),
+}
+
+impl Mixer {
+    /// Shift signals up by `shift_hz`, or down if negative, at `sample_rate`
+    /// samples per second.
+    pub fn new(shift_hz: f64, sample_rate: f64) -> Mixer {
+        let angle = 2.0 * PI * shift_hz / sample_rate;
+        Mixer {
+            phase: (1.0, 0.0),
+            step: (angle.cos(), angle.sin()),
+        }
+    }
+
+    /// Shift `samples` in place.
+    pub fn process(&mut self, samples: &mut [u8]) {
+        let (mut re, mut im) = self.phase;
+        let (step_re, step_im) = self.step;
+        for iq in samples.chunks_exact_mut(2) {
+            let i = iq[0] as i8 as f64;
+            let q = iq[1] as i8 as f64;
+            iq[0] = to_cs8((i * re - q * im) as f32);
+            iq[1] = to_cs8((i * im + q * re) as f32);
+            let next = re * step_re - im * step_im;
+            im = re * step_im + im * step_re;
+            re = next;
+        }
+        // Stop rounding errors building up in the oscillator's amplitude.
+        let norm = (re * re + im * im).sqrt();
+        self.phase = (re / norm, im / norm);
+    }
+
+    /// Wrap `callback` into an RX callback that receives shifted samples,
+    /// for use with `RxChannel::start_rx` and the adapters built on it.
+    pub fn wrap(mut self, mut callback: RxCallback) -> RxCallback {
+        let mut shifted = Vec::new();
+        Box::new(move |rx: &[u8]| -> bool {
+            shifted.clear();
+            shifted.extend_from_slice(rx);
+            self.process(&mut shifted);
+            callback(&shifted)
+        })
+    }
+}
+
+/// Receive `freq_hz` with the board tuned `offset_hz` away from it, then
+/// shift it back to the centre in software, so the signal is delivered at
+/// baseband without sitting on the DC spike from the board's own LO. Keep
+/// the offset and the signal's bandwidth within the sample rate last set on
+/// `device`. Stop with `stop_rx`.
+pub fn start_rx_offset(
+    device: &mut HackRFDevice,
+    freq_hz: u64,
+    offset_hz: i64,
+    callback: RxCallback,
+) -> Result<(), HackRFError> {
+    set_freq(device, freq_hz.saturating_add_signed(offset_hz))?;
+    // The signal sits `offset_hz` below the LO; bring it back up.
+    let mixer = Mixer::new(offset_hz as f64, device.sample_rate_hz);
+    start_rx_owned(device, mixer.wrap(callback))
+}
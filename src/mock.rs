@@ -0,0 +1,226 @@
+// Mock device for testing applications without hardware
+// Licensed under MIT license
+
+use super::format::SampleFormat;
+use super::sdr::{
+    find_stage, GainStage, RxCallback, RxChannel, SdrDevice, TxCallback, TxChannel, RX_GAIN_STAGES,
+    TX_GAIN_STAGES,
+};
+use super::{ffi, hackrf_error, HackRFError};
+
+/// A call made on a `MockDevice`, as recorded by `MockDevice::calls`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Call {
+    SetFrequency(u64),
+    SetSampleRate(f64),
+    SetRxGain(String, f64),
+    SetTxGain(String, f64),
+    StartRx,
+    StopRx,
+    StartTx,
+    StopTx,
+}
+
+/// Stands in for a `HackRFDevice` through the `SdrDevice`, `RxChannel` and
+/// `TxChannel` traits, so code written against them can be unit tested
+/// without a board. Every call is recorded, gains are validated against
+/// the HackRF's gain stages, and streaming is driven by the test: RX
+/// callbacks are fed a repeating byte pattern by `deliver_rx`, or exact
+/// bytes by `feed_rx`, and TX callbacks are drained by `pull_tx`.
+pub struct MockDevice {
+    freq_hz: u64,
+    sample_rate_hz: f64,
+    calls: Vec<Call>,
+    rx_callback: Option<RxCallback>,
+    tx_callback: Option<TxCallback>,
+    rx_pattern: Vec<u8>,
+    rx_pos: usize,
+    transfer_len: usize,
+}
+
+impl Default for MockDevice {
+    fn default() -> MockDevice {
+        MockDevice::new()
+    }
+}
+
+impl MockDevice {
+    /// A mock at the HackRF's power-on sample rate of 10Msps, delivering
+    /// silence in 256KiB transfers.
+    pub fn new() -> MockDevice {
+        MockDevice {
+            freq_hz: 0,
+            sample_rate_hz: 10e6,
+            calls: Vec::new(),
+            rx_callback: None,
+            tx_callback: None,
+            rx_pattern: vec![0; 2],
+            rx_pos: 0,
+            transfer_len: 262_144,
+        }
+    }
+
+    /// Bytes that `deliver_rx` repeats, continuing across transfers.
+    pub fn rx_pattern(mut self, pattern: Vec<u8>) -> MockDevice {
+        assert!(!pattern.is_empty());
+        self.rx_pattern = pattern;
+        self.rx_pos = 0;
+        self
+    }
+
+    /// Size in bytes of each transfer from `deliver_rx`.
+    pub fn transfer_len(mut self, len: usize) -> MockDevice {
+        assert!(len > 0);
+        self.transfer_len = len;
+        self
+    }
+
+    /// Every call made so far, oldest first.
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    /// Forget the calls recorded so far.
+    pub fn clear_calls(&mut self) {
+        self.calls.clear();
+    }
+
+    /// Whether an RX callback is installed.
+    pub fn is_receiving(&self) -> bool {
+        self.rx_callback.is_some()
+    }
+
+    /// Whether a TX callback is installed.
+    pub fn is_transmitting(&self) -> bool {
+        self.tx_callback.is_some()
+    }
+
+    /// Pass up to `transfers` transfers of the RX pattern to the RX
+    /// callback, returning how many it accepted before returning `false`.
+    /// A callback that returns `false` is dropped, as streaming would end.
+    pub fn deliver_rx(&mut self, transfers: usize) -> usize {
+        let mut buf = vec![0; self.transfer_len];
+        for delivered in 0..transfers {
+            for b in buf.iter_mut() {
+                *b = self.rx_pattern[self.rx_pos];
+                self.rx_pos = (self.rx_pos + 1) % self.rx_pattern.len();
+            }
+            if !self.feed_rx(&buf) {
+                return delivered;
+            }
+        }
+        transfers
+    }
+
+    /// Pass `data` to the RX callback as a single transfer. Returns what
+    /// the callback returned, or `false` if not receiving.
+    pub fn feed_rx(&mut self, data: &[u8]) -> bool {
+        let keep = match self.rx_callback {
+            Some(ref mut callback) => callback(data),
+            None => return false,
+        };
+        if !keep {
+            self.rx_callback = None;
+        }
+        keep
+    }
+
+    /// Ask the TX callback to fill a transfer of `len` bytes and return it,
+    /// or `None` if not transmitting. A callback that returns `false` is
+    /// dropped after its buffer is returned.
+    pub fn pull_tx(&mut self, len: usize) -> Option<Vec<u8>> {
+        let mut buf = vec![0; len];
+        let keep = match self.tx_callback {
+            Some(ref mut callback) => callback(&mut buf),
+            None => return None,
+        };
+        if !keep {
+            self.tx_callback = None;
+        }
+        Some(buf)
+    }
+}
+
+impl SdrDevice for MockDevice {
+    type Error = HackRFError;
+
+    fn set_frequency(&mut self, hz: u64) -> Result<(), HackRFError> {
+        self.calls.push(Call::SetFrequency(hz));
+        self.freq_hz = hz;
+        Ok(())
+    }
+
+    fn frequency(&self) -> u64 {
+        self.freq_hz
+    }
+
+    fn set_sample_rate(&mut self, hz: f64) -> Result<(), HackRFError> {
+        self.calls.push(Call::SetSampleRate(hz));
+        self.sample_rate_hz = hz;
+        Ok(())
+    }
+
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate_hz
+    }
+
+    fn native_format(&self) -> SampleFormat {
+        SampleFormat::Cs8
+    }
+}
+
+impl RxChannel for MockDevice {
+    fn rx_gain_stages(&self) -> &'static [GainStage] {
+        &RX_GAIN_STAGES
+    }
+
+    fn set_rx_gain(&mut self, name: &str, db: f64) -> Result<(), HackRFError> {
+        self.calls.push(Call::SetRxGain(name.to_string(), db));
+        find_stage(&RX_GAIN_STAGES, name, db).map(|_| ())
+    }
+
+    /// Fails with `HACKRF_ERROR_BUSY` while already streaming in either
+    /// direction, as the HackRF is half duplex.
+    fn start_rx(&mut self, callback: RxCallback) -> Result<(), HackRFError> {
+        self.calls.push(Call::StartRx);
+        if self.is_receiving() || self.is_transmitting() {
+            return Err(hackrf_error(ffi::HACKRF_ERROR_BUSY));
+        }
+        self.rx_callback = Some(callback);
+        Ok(())
+    }
+
+    fn stop_rx(&mut self) -> Result<(), HackRFError> {
+        self.calls.push(Call::StopRx);
+        self.rx_callback = None;
+        Ok(())
+    }
+}
+
+impl TxChannel for MockDevice {
+    fn tx_gain_stages(&self) -> &'static [GainStage] {
+        &TX_GAIN_STAGES
+    }
+
+    fn set_tx_gain(&mut self, name: &str, db: f64) -> Result<(), HackRFError> {
+        self.calls.push(Call::SetTxGain(name.to_string(), db));
+        find_stage(&TX_GAIN_STAGES, name, db).map(|_| ())
+    }
+
+    /// Fails with `HACKRF_ERROR_BUSY` while already streaming in either
+    /// direction, as the HackRF is half duplex.
+    fn start_tx(&mut self, callback: TxCallback) -> Result<(), HackRFError> {
+        self.calls.push(Call::StartTx);
+        if self.is_receiving() || self.is_transmitting() {
+            return Err(hackrf_error(ffi::HACKRF_ERROR_BUSY));
+        }
+        self.tx_callback = Some(callback);
+        Ok(())
+    }
+
+    fn stop_tx(&mut self) -> Result<(), HackRFError> {
+        self.calls.push(Call::StopTx);
+        self.tx_callback = None;
+        Ok(())
+    }
+}
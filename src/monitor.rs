@@ -0,0 +1,102 @@
+// A low-rate copy of the RX stream for meters and displays
+// Licensed under MIT license
+
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::pool::BufferPool;
+use super::sdr::RxCallback;
+use super::wake::{self, Consumer, Producer, Wake};
+
+// Copies kept for the monitor; older ones are replaced by newer.
+const MONITOR_BUFFERS: usize = 4;
+
+/// Taps a copy of one transfer in every `every` off an RX callback, for UI
+/// meters and waterfalls that only need to see the signal now and then.
+/// The full-rate path pays for one copy per `every` transfers and nothing
+/// else: the tapped transfers keep the full bandwidth, so a spectrum of one
+/// shows the whole band, and anything heavier, such as a `Decimator` for a
+/// narrow view, runs on the monitor's side. Tapping 1 in 64 at 20Msps
+/// gives a fresh 131072 samples about twice a second.
+pub struct MonitorTap {
+    every: usize,
+    queue: Producer<Vec<u8>>,
+    free: Arc<BufferPool>,
+}
+
+/// The receiving end of a `MonitorTap`. If it is not read often enough,
+/// the oldest copies are replaced with newer ones.
+pub struct Monitor {
+    queue: Consumer<Vec<u8>>,
+    free: Arc<BufferPool>,
+}
+
+impl MonitorTap {
+    pub fn new(every: usize) -> (MonitorTap, Monitor) {
+        assert!(every > 0);
+        let (producer, consumer) = wake::channel(MONITOR_BUFFERS, Wake::default());
+        let free = Arc::new(BufferPool::new(MONITOR_BUFFERS + 2, false));
+        (
+            MonitorTap {
+                every,
+                queue: producer,
+                free: free.clone(),
+            },
+            Monitor {
+                queue: consumer,
+                free,
+            },
+        )
+    }
+
+    /// Wrap `callback`, which still sees every transfer, into an RX
+    /// callback that also feeds the monitor, for use with
+    /// `RxChannel::start_rx` and the adapters built on it. Dropping the
+    /// `Monitor` only stops the copies.
+    pub fn wrap(self, mut callback: RxCallback) -> RxCallback {
+        let mut count = 0;
+        Box::new(move |rx: &[u8]| -> bool {
+            if count == 0 {
+                let mut copy = self.free.take().unwrap_or_default();
+                copy.clear();
+                copy.extend_from_slice(rx);
+                if let Ok(Some(evicted)) = self.queue.send_evicting(copy) {
+                    self.free.give(evicted);
+                }
+            }
+            count = (count + 1) % self.every;
+            callback(rx)
+        })
+    }
+}
+
+impl Monitor {
+    /// Wait for the next copy. Returns `None` once the stream has stopped
+    /// and no copies are left.
+    pub fn recv(&self) -> Option<Vec<u8>> {
+        self.queue.recv_deadline(None).ok()
+    }
+
+    /// As `recv`, giving up after `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Vec<u8>, RecvTimeoutError> {
+        self.queue.recv_deadline(Some(Instant::now() + timeout))
+    }
+
+    /// Hand a copy back for reuse, avoiding an allocation per copy.
+    pub fn recycle(&self, buf: Vec<u8>) {
+        self.free.give(buf);
+    }
+
+    /// The newest copy, discarding any older ones, or `None` if there is no
+    /// new copy since the last call. Suits redrawing once a frame.
+    pub fn latest(&self) -> Option<Vec<u8>> {
+        let mut latest = None;
+        while let Ok(buf) = self.queue.try_recv() {
+            if let Some(older) = latest.replace(buf) {
+                self.free.give(older);
+            }
+        }
+        latest
+    }
+}
@@ -0,0 +1,192 @@
+// rtl_tcp compatible network server
+// Licensed under MIT license
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::Arc;
+
+use super::pool::BufferPool;
+use super::threads::{self, Priority};
+use super::{
+    compute_baseband_filter_bw, set_antenna_enable, set_baseband_filter_bandwidth, set_freq,
+    set_lna_gain, set_sample_rate, set_vga_gain, start_rx_owned, stop_rx, HackRFDevice,
+    HackRFError,
+};
+
+// rtl_tcp command bytes, each followed by a big-endian u32 parameter.
+const CMD_SET_FREQ: u8 = 0x01;
+const CMD_SET_SAMPLE_RATE: u8 = 0x02;
+const CMD_SET_GAIN: u8 = 0x04;
+const CMD_SET_GAIN_BY_INDEX: u8 = 0x0d;
+const CMD_SET_BIAS_TEE: u8 = 0x0e;
+
+// Clients expect a tuner they know the gain table of; the R820T's 29 steps
+// are the most widely supported.
+const TUNER_R820T: u32 = 5;
+const R820T_GAINS: [u32; 29] = [
+    0, 9, 14, 27, 37, 77, 87, 125, 144, 157, 166, 197, 207, 229, 254, 280, 297, 328, 338, 364, 372,
+    386, 402, 421, 434, 439, 445, 480, 496,
+];
+
+// USB transfers queued for the network before samples are dropped.
+const QUEUE_LEN: usize = 64;
+
+/// Scheduling for the thread that sends samples to the client, for
+/// `serve_with` and `serve_client_with`. Falls back to normal scheduling on
+/// any core if the OS refuses.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SenderThread {
+    pub priority: Priority,
+    /// Cores to run on, numbered as the OS numbers them, or empty for any.
+    pub cores: Vec<usize>,
+}
+
+/// Listen on `addr` and serve one rtl_tcp client at a time, forever.
+/// See `serve_client`. An error serving a client, or from a command it
+/// sends, ends only that client or command, and is logged with the `log`
+/// feature; only failing to listen is returned.
+pub fn serve<A: ToSocketAddrs>(device: &mut HackRFDevice, addr: A) -> Result<(), HackRFError> {
+    serve_with(device, addr, &SenderThread::default())
+}
+
+/// As `serve`, scheduling the sender thread as `sender` says.
+pub fn serve_with<A: ToSocketAddrs>(
+    device: &mut HackRFDevice,
+    addr: A,
+    sender: &SenderThread,
+) -> Result<(), HackRFError> {
+    let listener = TcpListener::bind(addr)?;
+    for stream in listener.incoming() {
+        let result = match stream {
+            Ok(stream) => serve_client_with(device, stream, sender),
+            Err(err) => Err(err.into()),
+        };
+        if let Err(err) = result {
+            warn("client failed", err);
+        }
+    }
+    Ok(())
+}
+
+/// Stream RX samples to a connected rtl_tcp client until it disconnects,
+/// applying its frequency, sample rate, gain and bias tee commands.
+/// Samples are sent as unsigned 8-bit I/Q, like an RTL-SDR. Gains in tenths
+/// of a dB are split between the LNA and VGA; other commands are ignored.
+/// If the network can't keep up, whole transfers are dropped. A command
+/// the device rejects, such as a sample rate of 0, is ignored.
+pub fn serve_client(device: &mut HackRFDevice, stream: TcpStream) -> Result<(), HackRFError> {
+    serve_client_with(device, stream, &SenderThread::default())
+}
+
+/// As `serve_client`, scheduling the sender thread as `sender` says.
+pub fn serve_client_with(
+    device: &mut HackRFDevice,
+    mut stream: TcpStream,
+    sender: &SenderThread,
+) -> Result<(), HackRFError> {
+    stream.set_nodelay(true)?;
+    let mut header = Vec::with_capacity(12);
+    header.extend_from_slice(b"RTL0");
+    header.extend_from_slice(&TUNER_R820T.to_be_bytes());
+    header.extend_from_slice(&(R820T_GAINS.len() as u32).to_be_bytes());
+    stream.write_all(&header)?;
+
+    let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(QUEUE_LEN);
+    // Room for a full queue, one buffer being filled and one sent.
+    let free = Arc::new(BufferPool::new(QUEUE_LEN + 2, false));
+    let writer_free = free.clone();
+    let mut out = stream.try_clone()?;
+    let send = move || {
+        for buf in rx.iter() {
+            let sent = out.write_all(&buf).is_ok();
+            writer_free.give(buf);
+            if !sent {
+                break;
+            }
+        }
+        // Wake the command reader if the client has gone.
+        let _ = out.shutdown(Shutdown::Both);
+    };
+    let cores = sender.cores.clone();
+    let writer = threads::spawn("hackrf-net", sender.priority, cores, send)?.thread;
+    let callback = move |samples: &[u8]| -> bool {
+        let mut buf = free.take().unwrap_or_default();
+        buf.clear();
+        buf.extend(samples.iter().map(|b| b ^ 0x80));
+        match tx.try_send(buf) {
+            Ok(()) => true,
+            Err(TrySendError::Full(buf)) => {
+                #[cfg(feature = "metrics")]
+                super::metrics::dropped_buffer();
+                #[cfg(feature = "log")]
+                super::logging::overrun();
+                free.give(buf);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    };
+    let result = match start_rx_owned(device, Box::new(callback)) {
+        Ok(()) => {
+            read_commands(device, &mut stream);
+            stop_rx(device)
+        }
+        Err(err) => Err(err),
+    };
+    // Dropping the callback closes the queue, ending the writer.
+    device.rx_callback = None;
+    let _ = stream.shutdown(Shutdown::Both);
+    writer.join().expect("network writer thread panicked");
+    result
+}
+
+fn read_commands(device: &mut HackRFDevice, stream: &mut TcpStream) {
+    let mut cmd = [0; 5];
+    loop {
+        // The client disconnecting, or the writer failing to reach it, is
+        // the normal way to finish.
+        if stream.read_exact(&mut cmd).is_err() {
+            return;
+        }
+        let param = u32::from_be_bytes([cmd[1], cmd[2], cmd[3], cmd[4]]);
+        if let Err(err) = command(device, cmd[0], param) {
+            warn("command failed", err);
+        }
+    }
+}
+
+fn command(device: &mut HackRFDevice, cmd: u8, param: u32) -> Result<(), HackRFError> {
+    match cmd {
+        CMD_SET_FREQ => set_freq(device, param as u64),
+        CMD_SET_SAMPLE_RATE => {
+            set_sample_rate(device, param as f64)?;
+            let bw = compute_baseband_filter_bw((param as f64 * 0.75) as u32);
+            set_baseband_filter_bandwidth(device, bw)
+        }
+        CMD_SET_GAIN => set_gain(device, param),
+        CMD_SET_GAIN_BY_INDEX => {
+            let index = (param as usize).min(R820T_GAINS.len() - 1);
+            set_gain(device, R820T_GAINS[index])
+        }
+        CMD_SET_BIAS_TEE => set_antenna_enable(device, param != 0),
+        _ => Ok(()),
+    }
+}
+
+// Report an error that ends a client or a command, but not the server.
+fn warn(what: &str, err: HackRFError) {
+    #[cfg(feature = "log")]
+    log::warn!("rtl_tcp {}: {}", what, err);
+    #[cfg(not(feature = "log"))]
+    let _ = (what, err);
+}
+
+/// Set a total gain in tenths of a dB, favouring the LNA for noise figure.
+fn set_gain(device: &mut HackRFDevice, tenths_db: u32) -> Result<(), HackRFError> {
+    let db = (tenths_db / 10).min(40 + 62);
+    let lna = (db / 8 * 8).min(40);
+    let vga = ((db - lna) / 2 * 2).min(62);
+    set_lna_gain(device, lna)?;
+    set_vga_gain(device, vga)
+}
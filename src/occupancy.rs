@@ -0,0 +1,194 @@
+// Channel occupancy statistics from repeated sweeps
+// Licensed under MIT license
+
+use std::time::{Duration, Instant, SystemTime};
+
+use super::sweep::{self, SweepProcessor, SweepStyle};
+use super::{
+    is_streaming, set_baseband_filter_bandwidth, set_sample_rate_manual, stop_rx, HackRFDevice,
+    HackRFError, StopHandle,
+};
+
+// hackrf_sweep's tuning plan: 20Msps, retuning in 20MHz steps with the
+// wanted spectrum offset 7.5MHz from the LO.
+const SAMPLE_RATE_HZ: u32 = 20_000_000;
+const BASEBAND_FILTER_HZ: u32 = 15_000_000;
+const TUNE_STEP_MHZ: u16 = 20;
+const OFFSET_HZ: u32 = 7_500_000;
+
+/// How busy one channel was over an `OccupancyScanner` measurement.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChannelOccupancy {
+    pub low_hz: u64,
+    pub high_hz: u64,
+    /// Fraction of sweeps in which the channel was above the threshold.
+    pub duty_cycle: f64,
+    /// Strongest bin seen in the channel, in dB as `hackrf_sweep` reports,
+    /// or minus infinity if no sweep covered it.
+    pub max_power_db: f32,
+    /// Strongest bin in each sweep, averaged over the sweeps.
+    pub mean_power_db: f32,
+    /// Number of sweeps that covered the channel.
+    pub sweeps: u64,
+}
+
+#[derive(Copy, Clone, Default)]
+struct Stats {
+    above: u64,
+    sweeps: u64,
+    max: f32,
+    // Sum of each sweep's peak, in linear power.
+    sum: f64,
+}
+
+/// Sweeps a frequency range repeatedly for a measurement period and
+/// reports how often each channel in it was occupied: the fraction of
+/// sweeps in which its strongest bin crossed a threshold, along with its
+/// peak and average levels, as for a site survey. Uses the firmware's sweep
+/// mode with the same tuning plan as `hackrf_sweep`, so levels are in the
+/// same dB, and leaves gains as set on the device.
+#[derive(Clone, Debug)]
+pub struct OccupancyScanner {
+    freq_min_mhz: u16,
+    freq_max_mhz: u16,
+    channel_hz: u64,
+    threshold_db: f32,
+    bin_width_hz: u32,
+}
+
+impl OccupancyScanner {
+    /// Measure channels `channel_hz` wide from `freq_min_mhz` to
+    /// `freq_max_mhz`, counting a channel as occupied above `threshold_db`.
+    pub fn new(
+        freq_min_mhz: u16,
+        freq_max_mhz: u16,
+        channel_hz: u64,
+        threshold_db: f32,
+    ) -> OccupancyScanner {
+        assert!(freq_min_mhz < freq_max_mhz && freq_max_mhz <= 7250);
+        assert!(channel_hz > 0);
+        OccupancyScanner {
+            freq_min_mhz,
+            freq_max_mhz,
+            channel_hz,
+            threshold_db,
+            bin_width_hz: 100_000,
+        }
+    }
+
+    /// FFT bin width, which should be well under the channel width.
+    /// Defaults to 100kHz.
+    pub fn bin_width_hz(mut self, bin_width_hz: u32) -> OccupancyScanner {
+        assert!((2445..=5_000_000).contains(&bin_width_hz));
+        self.bin_width_hz = bin_width_hz;
+        self
+    }
+
+    /// Sweep for `period`, or until `stop` is triggered, and return the
+    /// statistics for each channel from the bottom of the range up. Sets
+    /// the sample rate and baseband filter for sweeping.
+    pub fn run(
+        &self,
+        device: &mut HackRFDevice,
+        period: Duration,
+        stop: &StopHandle,
+    ) -> Result<Vec<ChannelOccupancy>, HackRFError> {
+        set_sample_rate_manual(device, SAMPLE_RATE_HZ, 1)?;
+        set_baseband_filter_bandwidth(device, BASEBAND_FILTER_HZ)?;
+        // Round the range up to a whole number of tuning steps.
+        let steps = 1 + (self.freq_max_mhz - self.freq_min_mhz - 1) / TUNE_STEP_MHZ;
+        sweep::init_sweep(
+            device,
+            &[(self.freq_min_mhz, self.freq_min_mhz + steps * TUNE_STEP_MHZ)],
+            sweep::BYTES_PER_BLOCK as u32,
+            TUNE_STEP_MHZ as u32 * 1_000_000,
+            OFFSET_HZ,
+            SweepStyle::Interleaved,
+        )?;
+
+        let low_hz = self.freq_min_mhz as u64 * 1_000_000;
+        let high_hz = self.freq_max_mhz as u64 * 1_000_000;
+        let channels = high_hz.saturating_sub(low_hz).div_ceil(self.channel_hz) as usize;
+        let mut stats = vec![
+            Stats {
+                max: f32::NEG_INFINITY,
+                ..Stats::default()
+            };
+            channels
+        ];
+        // Each channel's strongest bin in the sweep under way.
+        let mut peaks = vec![f32::NEG_INFINITY; channels];
+        let mut processor = SweepProcessor::new(SAMPLE_RATE_HZ, self.bin_width_hz);
+        let started = Instant::now();
+        let mut sweeping = false;
+        let cb_stop = stop.clone();
+        let mut callback = |buffer: &[u8]| -> bool {
+            let now = SystemTime::now();
+            for block in sweep::sweep_blocks(buffer) {
+                // Only count whole sweeps, starting from the bottom of the
+                // range.
+                if block.frequency_hz == low_hz {
+                    if sweeping {
+                        for (stat, peak) in stats.iter_mut().zip(peaks.iter_mut()) {
+                            if *peak > f32::NEG_INFINITY {
+                                stat.sweeps += 1;
+                                stat.above += (*peak >= self.threshold_db) as u64;
+                                stat.max = stat.max.max(*peak);
+                                stat.sum += 10f64.powf(*peak as f64 / 10.0);
+                            }
+                            *peak = f32::NEG_INFINITY;
+                        }
+                    }
+                    if cb_stop.is_stopped() || started.elapsed() >= period {
+                        return false;
+                    }
+                    sweeping = true;
+                }
+                if !sweeping {
+                    continue;
+                }
+                for row in processor.process(&block, now).iter() {
+                    for (i, &power) in row.power_db.iter().enumerate() {
+                        let hz = row.hz_low as f64 + (i as f64 + 0.5) * row.bin_width_hz;
+                        if hz < low_hz as f64 || hz >= high_hz as f64 {
+                            continue;
+                        }
+                        let channel = ((hz - low_hz as f64) / self.channel_hz as f64) as usize;
+                        let peak = &mut peaks[channel.min(channels - 1)];
+                        *peak = peak.max(power);
+                    }
+                }
+            }
+            true
+        };
+        sweep::start_rx_sweep(device, &mut callback)?;
+        while !stop.is_stopped() {
+            match is_streaming(device) {
+                Ok(true) => std::thread::sleep(Duration::from_millis(50)),
+                Ok(false) => break,
+                Err(err) => {
+                    let _ = stop_rx(device);
+                    return Err(err);
+                }
+            }
+        }
+        stop_rx(device)?;
+
+        Ok(stats
+            .iter()
+            .enumerate()
+            .map(|(i, stat)| {
+                let channel_low = low_hz + i as u64 * self.channel_hz;
+                let sweeps = stat.sweeps.max(1) as f64;
+                ChannelOccupancy {
+                    low_hz: channel_low,
+                    high_hz: (channel_low + self.channel_hz).min(high_hz),
+                    duty_cycle: stat.above as f64 / sweeps,
+                    max_power_db: stat.max,
+                    mean_power_db: (10.0 * (stat.sum / sweeps).log10()) as f32,
+                    sweeps: stat.sweeps,
+                }
+            })
+            .collect())
+    }
+}
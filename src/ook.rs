@@ -0,0 +1,137 @@
+// Building OOK/ASK bursts to transmit
+// Licensed under MIT license
+
+use std::f64::consts::PI;
+use std::time::Duration;
+
+/// Builds on-off keyed or amplitude-shift keyed bursts as cs8 buffers ready
+/// for `transmit_repeated` or a `TxStream`, as sent by 433MHz and 315MHz
+/// remote controls. Each bit is one symbol: a 1 at the high level, a 0 at
+/// the low level, which is silence for OOK. Line codes such as PWM or
+/// Manchester are expressed as the bits of their chips.
+#[derive(Clone, Debug)]
+pub struct OokBurst {
+    sample_rate: f64,
+    symbol_rate: f64,
+    high: f64,
+    low: f64,
+    ramp: Duration,
+    offset_hz: f64,
+    repeats: usize,
+    gap: Duration,
+}
+
+impl OokBurst {
+    /// Bursts at `symbol_rate` symbols per second, for transmitting at
+    /// `sample_rate`. Defaults to OOK at full scale, with no ramping, sent
+    /// once, on the centre frequency.
+    pub fn new(sample_rate: f64, symbol_rate: f64) -> OokBurst {
+        assert!(symbol_rate > 0.0 && symbol_rate <= sample_rate);
+        OokBurst {
+            sample_rate,
+            symbol_rate,
+            high: 1.0,
+            low: 0.0,
+            ramp: Duration::from_secs(0),
+            offset_hz: 0.0,
+            repeats: 1,
+            gap: Duration::from_secs(0),
+        }
+    }
+
+    /// Amplitudes of 1s and 0s relative to full scale, 0.0-1.0. A `low`
+    /// above 0 gives ASK.
+    pub fn levels(mut self, high: f64, low: f64) -> OokBurst {
+        assert!((0.0..=1.0).contains(&high) && (0.0..=1.0).contains(&low));
+        self.high = high;
+        self.low = low;
+        self
+    }
+
+    /// Shape each change of level with a raised cosine lasting `ramp`,
+    /// narrowing the burst's spectrum and avoiding key clicks.
+    pub fn ramp(mut self, ramp: Duration) -> OokBurst {
+        self.ramp = ramp;
+        self
+    }
+
+    /// Put the carrier `offset_hz` from the centre frequency, away from the
+    /// board's LO leakage, which otherwise fills in the silent symbols.
+    pub fn offset_hz(mut self, offset_hz: f64) -> OokBurst {
+        assert!(offset_hz.abs() < self.sample_rate / 2.0);
+        self.offset_hz = offset_hz;
+        self
+    }
+
+    /// Send the pattern `repeats` times, with `gap` of silence after each,
+    /// as remote controls do.
+    pub fn repeat(mut self, repeats: usize, gap: Duration) -> OokBurst {
+        self.repeats = repeats;
+        self.gap = gap;
+        self
+    }
+
+    /// The burst for `bits`, as cs8.
+    pub fn build(&self, bits: &[bool]) -> Vec<u8> {
+        let per_symbol = self.sample_rate / self.symbol_rate;
+        let ramp = (self.ramp.as_secs_f64() * self.sample_rate).round() as usize;
+        let gap = (self.gap.as_secs_f64() * self.sample_rate).round() as usize;
+        let symbols = (bits.len() as f64 * per_symbol).round() as usize;
+        let step = 2.0 * PI * self.offset_hz / self.sample_rate;
+
+        // Envelope of one repetition, ramping from and back to silence.
+        // Ramps start from wherever the last one got to, should it not have
+        // finished within a symbol.
+        let mut envelope: Vec<f64> = Vec::with_capacity(symbols + ramp);
+        for (k, &bit) in bits.iter().enumerate() {
+            let level = if bit { self.high } else { self.low };
+            let end = ((k + 1) as f64 * per_symbol).round() as usize;
+            let count = end - envelope.len();
+            let previous = envelope.last().copied().unwrap_or(0.0);
+            push_level(&mut envelope, previous, level, count, ramp);
+        }
+        let previous = envelope.last().copied().unwrap_or(0.0);
+        push_level(&mut envelope, previous, 0.0, ramp, ramp);
+
+        let mut burst = Vec::with_capacity((envelope.len() + gap) * 2 * self.repeats);
+        let mut n = 0u64;
+        for _ in 0..self.repeats {
+            for &amplitude in &envelope {
+                let phase = step * n as f64;
+                burst.push((127.0 * amplitude * phase.cos()).round() as i8 as u8);
+                burst.push((127.0 * amplitude * phase.sin()).round() as i8 as u8);
+                n += 1;
+            }
+            burst.resize(burst.len() + gap * 2, 0);
+            n += gap as u64;
+        }
+        burst
+    }
+
+    /// As `build`, for bits written as a string of `0`s and `1`s. Any other
+    /// characters, such as spaces, are ignored.
+    pub fn build_str(&self, bits: &str) -> Vec<u8> {
+        let bits: Vec<bool> = bits
+            .chars()
+            .filter_map(|c| match c {
+                '0' => Some(false),
+                '1' => Some(true),
+                _ => None,
+            })
+            .collect();
+        self.build(&bits)
+    }
+}
+
+// Append `count` samples at `level`, the first `ramp` rising or falling to it
+// from `previous` along a raised cosine.
+fn push_level(envelope: &mut Vec<f64>, previous: f64, level: f64, count: usize, ramp: usize) {
+    for t in 0..count {
+        envelope.push(if t < ramp && previous != level {
+            let x = 0.5 - 0.5 * (PI * t as f64 / ramp as f64).cos();
+            previous + (level - previous) * x
+        } else {
+            level
+        });
+    }
+}
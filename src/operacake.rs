@@ -0,0 +1,201 @@
+// Opera Cake antenna switching boards
+// Licensed under MIT license
+
+use super::{ffi, hackrf_error, HackRFDevice, HackRFError};
+
+/// Maximum number of entries for `set_dwell_times`.
+pub const MAX_DWELL_TIMES: usize = ffi::HACKRF_OPERACAKE_MAX_DWELL_TIMES;
+/// Maximum number of entries for `set_freq_ranges`.
+pub const MAX_FREQ_RANGES: usize = ffi::HACKRF_OPERACAKE_MAX_FREQ_RANGES;
+
+/// One of the eight antenna ports on an Opera Cake.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Port {
+    A1,
+    A2,
+    A3,
+    A4,
+    B1,
+    B2,
+    B3,
+    B4,
+}
+
+impl Port {
+    /// Parse a port name such as `"A1"` or `"b3"`.
+    pub fn from_name(name: &str) -> Option<Port> {
+        match name.to_ascii_uppercase().as_str() {
+            "A1" => Some(Port::A1),
+            "A2" => Some(Port::A2),
+            "A3" => Some(Port::A3),
+            "A4" => Some(Port::A4),
+            "B1" => Some(Port::B1),
+            "B2" => Some(Port::B2),
+            "B3" => Some(Port::B3),
+            "B4" => Some(Port::B4),
+            _ => None,
+        }
+    }
+
+    /// Whether this is one of the A side ports, A1-A4.
+    pub fn is_a_side(self) -> bool {
+        (self as u8) < 4
+    }
+}
+
+/// How an Opera Cake chooses which ports to connect.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Ports are set explicitly with `set_ports`.
+    Manual,
+    /// Ports follow the tuned frequency, using `set_freq_ranges`.
+    Frequency,
+    /// Ports cycle on a schedule, using `set_dwell_times`.
+    Time,
+}
+
+/// List the addresses of attached Opera Cake boards.
+pub fn boards(device: &mut HackRFDevice) -> Result<Vec<u8>, HackRFError> {
+    let mut boards = [ffi::HACKRF_OPERACAKE_ADDRESS_INVALID; ffi::HACKRF_OPERACAKE_MAX_BOARDS];
+    match unsafe { ffi::hackrf_get_operacake_boards(device.ptr, boards.as_mut_ptr()) } {
+        ffi::HACKRF_SUCCESS => Ok(boards
+            .iter()
+            .cloned()
+            .take_while(|&a| a != ffi::HACKRF_OPERACAKE_ADDRESS_INVALID)
+            .collect()),
+        err => Err(device.error(err, "operacake::boards", format_args!(""))),
+    }
+}
+
+/// Set the switching mode of the board at `address`.
+pub fn set_mode(device: &mut HackRFDevice, address: u8, mode: Mode) -> Result<(), HackRFError> {
+    let mode = match mode {
+        Mode::Manual => ffi::OPERACAKE_MODE_MANUAL,
+        Mode::Frequency => ffi::OPERACAKE_MODE_FREQUENCY,
+        Mode::Time => ffi::OPERACAKE_MODE_TIME,
+    };
+    match unsafe { ffi::hackrf_set_operacake_mode(device.ptr, address, mode) } {
+        ffi::HACKRF_SUCCESS => Ok(()),
+        err => Err(device.error(
+            err,
+            "operacake::set_mode",
+            format_args!("address={}, mode={:?}", address, mode),
+        )),
+    }
+}
+
+/// Read the switching mode of the board at `address`.
+pub fn mode(device: &mut HackRFDevice, address: u8) -> Result<Mode, HackRFError> {
+    let mut mode = 0;
+    match unsafe { ffi::hackrf_get_operacake_mode(device.ptr, address, &mut mode) } {
+        ffi::HACKRF_SUCCESS => match mode {
+            ffi::OPERACAKE_MODE_MANUAL => Ok(Mode::Manual),
+            ffi::OPERACAKE_MODE_FREQUENCY => Ok(Mode::Frequency),
+            ffi::OPERACAKE_MODE_TIME => Ok(Mode::Time),
+            _ => Err(hackrf_error(ffi::HACKRF_ERROR_OTHER)),
+        },
+        err => Err(device.error(err, "operacake::mode", format_args!("address={}", address))),
+    }
+}
+
+/// In manual mode, connect `port_a` to the board's A common port and
+/// `port_b` to its B common port. One must be an A side port and the other
+/// a B side port.
+pub fn set_ports(
+    device: &mut HackRFDevice,
+    address: u8,
+    port_a: Port,
+    port_b: Port,
+) -> Result<(), HackRFError> {
+    if port_a.is_a_side() == port_b.is_a_side() {
+        return Err(hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM));
+    }
+    match unsafe {
+        ffi::hackrf_set_operacake_ports(device.ptr, address, port_a as u8, port_b as u8)
+    } {
+        ffi::HACKRF_SUCCESS => Ok(()),
+        err => Err(device.error(
+            err,
+            "operacake::set_ports",
+            format_args!(
+                "address={}, port_a={:?}, port_b={:?}",
+                address, port_a, port_b
+            ),
+        )),
+    }
+}
+
+/// Set the schedule for time mode: each entry connects a port to the A
+/// common port for a number of samples, in order, then repeats.
+pub fn set_dwell_times(
+    device: &mut HackRFDevice,
+    dwell_times: &[(u32, Port)],
+) -> Result<(), HackRFError> {
+    if dwell_times.len() > MAX_DWELL_TIMES {
+        return Err(hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM));
+    }
+    let mut list: Vec<ffi::hackrf_operacake_dwell_time> = dwell_times
+        .iter()
+        .map(|&(dwell, port)| ffi::hackrf_operacake_dwell_time {
+            dwell,
+            port: port as u8,
+        })
+        .collect();
+    match unsafe {
+        ffi::hackrf_set_operacake_dwell_times(device.ptr, list.as_mut_ptr(), list.len() as u8)
+    } {
+        ffi::HACKRF_SUCCESS => Ok(()),
+        err => Err(device.error(
+            err,
+            "operacake::set_dwell_times",
+            format_args!("dwell_times={:?}", dwell_times),
+        )),
+    }
+}
+
+/// Set the ranges for frequency mode: each entry connects a port to the A
+/// common port while tuned between its minimum and maximum, in MHz. Earlier
+/// entries take priority where ranges overlap.
+pub fn set_freq_ranges(
+    device: &mut HackRFDevice,
+    ranges_mhz: &[(u16, u16, Port)],
+) -> Result<(), HackRFError> {
+    if ranges_mhz.len() > MAX_FREQ_RANGES || ranges_mhz.iter().any(|r| r.0 > r.1) {
+        return Err(hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM));
+    }
+    let mut list: Vec<ffi::hackrf_operacake_freq_range> = ranges_mhz
+        .iter()
+        .map(
+            |&(freq_min, freq_max, port)| ffi::hackrf_operacake_freq_range {
+                freq_min,
+                freq_max,
+                port: port as u8,
+            },
+        )
+        .collect();
+    match unsafe {
+        ffi::hackrf_set_operacake_freq_ranges(device.ptr, list.as_mut_ptr(), list.len() as u8)
+    } {
+        ffi::HACKRF_SUCCESS => Ok(()),
+        err => Err(device.error(
+            err,
+            "operacake::set_freq_ranges",
+            format_args!("ranges_mhz={:?}", ranges_mhz),
+        )),
+    }
+}
+
+/// Run the GPIO self test on the board at `address`. Returns 0 if it
+/// passed, otherwise a bitmask of failing pins; 0xFFFF means the test
+/// couldn't run.
+pub fn gpio_test(device: &mut HackRFDevice, address: u8) -> Result<u16, HackRFError> {
+    let mut result = 0;
+    match unsafe { ffi::hackrf_operacake_gpio_test(device.ptr, address, &mut result) } {
+        ffi::HACKRF_SUCCESS => Ok(result),
+        err => Err(device.error(
+            err,
+            "operacake::gpio_test",
+            format_args!("address={}", address),
+        )),
+    }
+}
@@ -0,0 +1,90 @@
+// Reusing sample buffers so streaming does not allocate
+// Licensed under MIT license
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+// Bytes per USB transfer, as libhackrf uses.
+pub(crate) const TRANSFER_LEN: usize = 262_144;
+
+/// Buffers handed back by a consumer for the producer to fill again. Its
+/// storage is set aside up front, so once streaming has settled, taking and
+/// giving buffers never touches the allocator, which on some platforms can
+/// stall a USB callback long enough to drop samples.
+pub(crate) struct BufferPool {
+    free: Mutex<Vec<Vec<u8>>>,
+    limit: usize,
+}
+
+impl BufferPool {
+    /// A pool holding up to `limit` buffers, or `limit` buffers of
+    /// `TRANSFER_LEN` bytes ready allocated if `preallocate` is set.
+    pub(crate) fn new(limit: usize, preallocate: bool) -> BufferPool {
+        let mut free = Vec::with_capacity(limit);
+        if preallocate {
+            free.resize_with(limit, || Vec::with_capacity(TRANSFER_LEN));
+        }
+        BufferPool {
+            free: Mutex::new(free),
+            limit,
+        }
+    }
+
+    pub(crate) fn take(&self) -> Option<Vec<u8>> {
+        self.free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+    }
+
+    /// Keep `buf` for reuse, unless the pool is full.
+    pub(crate) fn give(&self, buf: Vec<u8>) {
+        let mut free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+        if free.len() < self.limit {
+            free.push(buf);
+        }
+    }
+}
+
+/// Shared, read-only copies of transfers, reused once every holder has
+/// dropped them. Owned by the one thread that fills them.
+pub(crate) struct SharedPool {
+    buffers: Vec<Arc<[u8]>>,
+    limit: usize,
+}
+
+impl SharedPool {
+    /// A pool reusing up to `limit` copies, with that many of
+    /// `TRANSFER_LEN` bytes ready allocated if `preallocate` is set.
+    pub(crate) fn new(limit: usize, preallocate: bool) -> SharedPool {
+        let mut buffers = Vec::with_capacity(limit);
+        if preallocate {
+            buffers.resize_with(limit, || Arc::from(vec![0; TRANSFER_LEN]));
+        }
+        SharedPool { buffers, limit }
+    }
+
+    /// A shared copy of `data`, in a buffer no one else holds if one of
+    /// the right length is free.
+    pub(crate) fn copy(&mut self, data: &[u8]) -> Arc<[u8]> {
+        for buf in self.buffers.iter_mut() {
+            if buf.len() == data.len() {
+                if let Some(unique) = Arc::get_mut(buf) {
+                    unique.copy_from_slice(data);
+                    return buf.clone();
+                }
+            }
+        }
+        let copy: Arc<[u8]> = Arc::from(data);
+        if self.buffers.len() < self.limit {
+            self.buffers.push(copy.clone());
+        } else if let Some(stale) = self
+            .buffers
+            .iter_mut()
+            .find(|buf| buf.len() != data.len() && Arc::strong_count(buf) == 1)
+        {
+            // Transfers changed size; stop keeping the old one.
+            *stale = copy.clone();
+        }
+        copy
+    }
+}
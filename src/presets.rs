@@ -0,0 +1,68 @@
+// Known-good receive settings for common signals
+// Licensed under MIT license
+
+//! Ready-made `DeviceConfig`s for common signals, with a suitable sample
+//! rate and baseband filter and moderate gains that will not overload the
+//! receiver near typical transmitters. They are starting points: raise the
+//! gains for weak signals. All are for receiving, and all leave the RF amp
+//! and antenna port power off.
+
+use super::DeviceConfig;
+
+fn receive(freq_hz: u64, sample_rate: f64, lna_gain: u32, vga_gain: u32) -> DeviceConfig {
+    DeviceConfig {
+        freq_hz: Some(freq_hz),
+        sample_rate: Some(sample_rate),
+        lna_gain: Some(lna_gain),
+        vga_gain: Some(vga_gain),
+        amp: Some(false),
+        antenna_power: Some(false),
+        ..DeviceConfig::default()
+    }
+}
+
+/// ADS-B aircraft transponders on 1090MHz, at the 2Msps decoders expect.
+pub fn adsb() -> DeviceConfig {
+    DeviceConfig {
+        baseband_filter_hz: Some(1_750_000),
+        ..receive(1_090_000_000, 2e6, 32, 40)
+    }
+}
+
+/// A broadcast FM station at `freq_hz`, at 2.4Msps, which `FmDemodulator`
+/// decimates exactly to its 240ksps discriminator rate.
+pub fn broadcast_fm(freq_hz: u64) -> DeviceConfig {
+    receive(freq_hz, 2.4e6, 16, 16)
+}
+
+/// AM airband voice at `freq_hz`, 118-137MHz.
+pub fn airband(freq_hz: u64) -> DeviceConfig {
+    receive(freq_hz, 2e6, 32, 30)
+}
+
+/// Remote controls, weather stations and other devices in the 433MHz ISM
+/// band, centred on 433.92MHz.
+pub fn ism_433() -> DeviceConfig {
+    receive(433_920_000, 2e6, 24, 20)
+}
+
+/// Remote controls and key fobs on 315MHz, common in North America.
+pub fn ism_315() -> DeviceConfig {
+    receive(315_000_000, 2e6, 24, 20)
+}
+
+/// The European 868MHz SRD band, centred on 868.3MHz.
+pub fn ism_868() -> DeviceConfig {
+    receive(868_300_000, 2e6, 24, 20)
+}
+
+/// GPS L1 C/A on 1575.42MHz, at 4Msps to cover its 2MHz main lobe. The
+/// signal is below the noise floor, so gains are high. Active antennas
+/// need `antenna_power` turning on, which should only be done with an
+/// antenna or LNA that expects the bias voltage.
+pub fn gps_l1() -> DeviceConfig {
+    DeviceConfig {
+        baseband_filter_hz: Some(2_500_000),
+        ..receive(1_575_420_000, 4e6, 40, 40)
+    }
+}
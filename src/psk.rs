@@ -0,0 +1,214 @@
+// BPSK and QPSK modulation and demodulation
+// Licensed under MIT license
+
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+// Symbols either side of the centre of the root raised cosine pulse.
+const RRC_SPAN: i64 = 4;
+// Loop gains for symbol timing and carrier tracking.
+const TIMING_GAIN: f64 = 0.05;
+const PHASE_GAIN: f64 = 0.1;
+const FREQUENCY_GAIN: f64 = 0.002;
+
+/// Which phase-shift keying a modulator or demodulator uses. A 1 bit is a
+/// positive I or Q; QPSK carries two bits per symbol, the first on I.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Psk {
+    Bpsk,
+    Qpsk,
+}
+
+impl Psk {
+    fn bits_per_symbol(self) -> usize {
+        match self {
+            Psk::Bpsk => 1,
+            Psk::Qpsk => 2,
+        }
+    }
+}
+
+/// Modulates bits onto a carrier at the centre frequency as BPSK or QPSK,
+/// with rectangular pulses or, once set, root raised cosine ones, producing
+/// cs8 ready for `transmit_repeated` or a `TxStream`.
+#[derive(Clone, Debug)]
+pub struct PskModulator {
+    psk: Psk,
+    // Samples per symbol.
+    per_symbol: f64,
+    // Root raised cosine roll-off, or none for rectangular pulses.
+    rolloff: Option<f64>,
+    amplitude: f64,
+}
+
+impl PskModulator {
+    /// Modulate `baud` symbols per second for transmitting at `sample_rate`.
+    pub fn new(psk: Psk, sample_rate: f64, baud: f64) -> PskModulator {
+        assert!(baud > 0.0 && baud <= sample_rate / 2.0);
+        PskModulator {
+            psk,
+            per_symbol: sample_rate / baud,
+            rolloff: None,
+            amplitude: 1.0,
+        }
+    }
+
+    /// Shape symbols with a root raised cosine pulse of roll-off `rolloff`,
+    /// such as 0.35, confining the signal to `baud * (1 + rolloff)` Hz.
+    /// Shaped signals peak above their amplitude, so lower it to about 0.7
+    /// to avoid clipping.
+    pub fn root_raised_cosine(mut self, rolloff: f64) -> PskModulator {
+        assert!(rolloff > 0.0 && rolloff <= 1.0);
+        self.rolloff = Some(rolloff);
+        self
+    }
+
+    /// Amplitude relative to full scale, 0.0-1.0. Defaults to 1.0.
+    pub fn amplitude(mut self, amplitude: f64) -> PskModulator {
+        assert!((0.0..=1.0).contains(&amplitude));
+        self.amplitude = amplitude;
+        self
+    }
+
+    /// Modulate `bits`, appending cs8 samples to `output`. For QPSK, an odd
+    /// final bit is paired with a 0.
+    pub fn modulate(&mut self, bits: &[bool], output: &mut Vec<u8>) {
+        let level = |bit: Option<&bool>| if bit == Some(&true) { 1.0 } else { -1.0 };
+        let symbols: Vec<(f64, f64)> = bits
+            .chunks(self.psk.bits_per_symbol())
+            .map(|chunk| match self.psk {
+                Psk::Bpsk => (level(chunk.first()), 0.0),
+                Psk::Qpsk => (
+                    level(chunk.first()) * FRAC_1_SQRT_2,
+                    level(chunk.get(1)) * FRAC_1_SQRT_2,
+                ),
+            })
+            .collect();
+        let count = (symbols.len() as f64 * self.per_symbol).round() as usize;
+        let scale = 127.0 * self.amplitude;
+        for n in 0..count {
+            // Time in symbols, with symbol k centred on k + 0.5.
+            let t = n as f64 / self.per_symbol;
+            let (i, q) = match self.rolloff {
+                None => symbols[(t as usize).min(symbols.len() - 1)],
+                Some(rolloff) => {
+                    let nearest = t as i64;
+                    (nearest - RRC_SPAN..=nearest + RRC_SPAN)
+                        .filter(|&k| k >= 0 && (k as usize) < symbols.len())
+                        .fold((0.0, 0.0), |(i, q), k| {
+                            let (si, sq) = symbols[k as usize];
+                            let p = rrc(t - k as f64 - 0.5, rolloff) / rrc(0.0, rolloff);
+                            (i + si * p, q + sq * p)
+                        })
+                }
+            };
+            output.push((scale * i).round().clamp(-128.0, 127.0) as i8 as u8);
+            output.push((scale * q).round().clamp(-128.0, 127.0) as i8 as u8);
+        }
+    }
+}
+
+// Root raised cosine pulse at `t` symbols from its centre.
+fn rrc(t: f64, rolloff: f64) -> f64 {
+    let b = rolloff;
+    if t == 0.0 {
+        1.0 - b + 4.0 * b / PI
+    } else if (4.0 * b * t).abs() == 1.0 {
+        b / 2f64.sqrt()
+            * ((1.0 + 2.0 / PI) * (PI / (4.0 * b)).sin()
+                + (1.0 - 2.0 / PI) * (PI / (4.0 * b)).cos())
+    } else {
+        ((PI * t * (1.0 - b)).sin() + 4.0 * b * t * (PI * t * (1.0 + b)).cos())
+            / (PI * t * (1.0 - (4.0 * b * t).powi(2)))
+    }
+}
+
+/// Recovers bits from BPSK or QPSK centred on the band, taking I/Q pairs,
+/// such as from `Decimator::process_f32`, filtered to little more than the
+/// signal's bandwidth, at two or more samples per symbol. A Gardner
+/// detector recovers symbol timing and a Costas loop then locks to the
+/// carrier, tracking small frequency offsets. As with any PSK receiver
+/// the carrier phase is ambiguous, by 180 degrees for BPSK and 90 for QPSK,
+/// so the bits may come out inverted or rotated: resolve this with a known
+/// sync word or differential coding.
+pub struct PskDemodulator {
+    psk: Psk,
+    per_symbol: f64,
+    // Samples not yet consumed, and where the next symbol is due in them.
+    history: Vec<[f32; 2]>,
+    next: f64,
+    previous: [f32; 2],
+    phase: f64,
+    frequency: f64,
+}
+
+impl PskDemodulator {
+    /// Demodulate `baud` symbols per second from samples at `sample_rate`.
+    pub fn new(psk: Psk, sample_rate: f64, baud: f64) -> PskDemodulator {
+        let per_symbol = sample_rate / baud;
+        assert!(per_symbol >= 2.0);
+        PskDemodulator {
+            psk,
+            per_symbol,
+            history: Vec::new(),
+            next: per_symbol,
+            previous: [0.0; 2],
+            phase: 0.0,
+            frequency: 0.0,
+        }
+    }
+
+    /// Demodulate `iq`, appending the bits found to `bits`.
+    pub fn process(&mut self, iq: &[[f32; 2]], bits: &mut Vec<bool>) {
+        self.history.extend_from_slice(iq);
+        while self.next.ceil() < self.history.len() as f64 {
+            let current = interpolate(&self.history, self.next);
+            let middle = interpolate(&self.history, self.next - self.per_symbol / 2.0);
+            // Gardner: across a transition the midpoint should be zero, and
+            // its sign shows whether the symbols are being sampled early or
+            // late.
+            let [pi, pq] = self.previous;
+            let error = (pi - current[0]) * middle[0] + (pq - current[1]) * middle[1];
+            let power = pi * pi + pq * pq + current[0] * current[0] + current[1] * current[1];
+            let error = if power > 0.0 { error / power } else { 0.0 };
+            self.previous = current;
+            self.next += self.per_symbol + TIMING_GAIN * self.per_symbol * error as f64;
+            self.costas(current, bits);
+        }
+        let consumed = (self.next - self.per_symbol).floor().max(0.0) as usize;
+        let consumed = consumed.min(self.history.len());
+        self.history.drain(..consumed);
+        self.next -= consumed as f64;
+    }
+
+    // Derotate a symbol, decide it and update the carrier loop.
+    fn costas(&mut self, symbol: [f32; 2], bits: &mut Vec<bool>) {
+        let (sin, cos) = self.phase.sin_cos();
+        let i = symbol[0] as f64 * cos + symbol[1] as f64 * sin;
+        let q = symbol[1] as f64 * cos - symbol[0] as f64 * sin;
+        bits.push(i > 0.0);
+        if self.psk == Psk::Qpsk {
+            bits.push(q > 0.0);
+        }
+        // Errors are normalised to the signal level.
+        let power = i * i + q * q;
+        if power == 0.0 {
+            return;
+        }
+        let error = match self.psk {
+            Psk::Bpsk => i * q / power,
+            Psk::Qpsk => (i.signum() * q - q.signum() * i) / power.sqrt(),
+        };
+        self.frequency += FREQUENCY_GAIN * error;
+        self.phase = (self.phase + self.frequency + PHASE_GAIN * error) % (2.0 * PI);
+    }
+}
+
+// Sample at fractional position `t`, linearly interpolated.
+fn interpolate(samples: &[[f32; 2]], t: f64) -> [f32; 2] {
+    let t = t.max(0.0);
+    let n = t as usize;
+    let x = (t - n as f64) as f32;
+    let a = samples[n];
+    let b = samples[(n + 1).min(samples.len() - 1)];
+    [a[0] + (b[0] - a[0]) * x, a[1] + (b[1] - a[1]) * x]
+}
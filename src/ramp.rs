@@ -0,0 +1,148 @@
+// Amplitude ramps for soft-starting and stopping transmission
+// Licensed under MIT license
+
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::sdr::TxCallback;
+
+// Transfers libhackrf keeps submitted. A transfer is only refilled once it
+// has been sent, so after this many more silent buffers the fade has gone
+// out and only silence is still queued.
+const IN_FLIGHT: u64 = 4;
+
+/// How long a `Ramp` takes to go between silence and full amplitude.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RampLength {
+    Time(Duration),
+    Samples(u64),
+}
+
+impl RampLength {
+    pub(crate) fn samples(self, sample_rate: f64) -> u64 {
+        match self {
+            RampLength::Time(time) => (time.as_secs_f64() * sample_rate).round() as u64,
+            RampLength::Samples(n) => n,
+        }
+    }
+}
+
+struct Control {
+    // Whether to ramp towards full amplitude, or towards silence.
+    up: AtomicBool,
+    // Buffers in a row that have ended in silence while ramping down.
+    silent: AtomicU64,
+}
+
+/// Shapes the amplitude of transmitted samples with a raised cosine
+/// envelope, so that keying on and off, and pauses around retunes, do not
+/// cause key clicks and splatter into neighbouring channels. Starts silent
+/// and ramps up from the first samples it processes; a `RampHandle` ramps
+/// it down and up again from another thread.
+pub struct Ramp {
+    length: u64,
+    // Samples into the ramp, 0 for silence and `length` for full amplitude.
+    position: u64,
+    control: Arc<Control>,
+}
+
+/// Controls a `Ramp` that is processing samples on the streaming thread.
+#[derive(Clone)]
+pub struct RampHandle {
+    control: Arc<Control>,
+}
+
+impl Ramp {
+    /// Ramp over `length` for samples at `sample_rate`.
+    pub fn new(length: RampLength, sample_rate: f64) -> Ramp {
+        Ramp {
+            length: length.samples(sample_rate),
+            position: 0,
+            control: Arc::new(Control {
+                up: AtomicBool::new(true),
+                silent: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn handle(&self) -> RampHandle {
+        RampHandle {
+            control: self.control.clone(),
+        }
+    }
+
+    /// Apply the envelope to cs8 samples in place.
+    pub fn process(&mut self, buf: &mut [u8]) {
+        let up = self.control.up.load(Ordering::SeqCst);
+        for iq in buf.chunks_exact_mut(2) {
+            if up && self.position < self.length {
+                self.position += 1;
+            } else if !up && self.position > 0 {
+                self.position -= 1;
+            } else if up {
+                // At full amplitude for the rest of the buffer.
+                break;
+            }
+            let gain = if self.length == 0 {
+                up as u8 as f64
+            } else {
+                0.5 - 0.5 * (PI * self.position as f64 / self.length as f64).cos()
+            };
+            for x in iq {
+                *x = ((*x as i8 as f64) * gain).round() as i8 as u8;
+            }
+        }
+        if !up && self.position == 0 {
+            self.control.silent.fetch_add(1, Ordering::SeqCst);
+        } else {
+            self.control.silent.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// Wrap `callback` into a TX callback whose samples are ramped, for use
+    /// with `TxChannel::start_tx` and the adapters built on it.
+    pub fn wrap(mut self, mut callback: TxCallback) -> TxCallback {
+        Box::new(move |tx: &mut [u8]| -> bool {
+            let more = callback(tx);
+            self.process(tx);
+            more
+        })
+    }
+}
+
+impl RampHandle {
+    /// Ramp up to full amplitude.
+    pub fn ramp_up(&self) {
+        self.control.silent.store(0, Ordering::SeqCst);
+        self.control.up.store(true, Ordering::SeqCst);
+    }
+
+    /// Ramp down to silence, which then continues until `ramp_up`.
+    pub fn ramp_down(&self) {
+        self.control.up.store(false, Ordering::SeqCst);
+    }
+
+    /// Whether the ramp has reached silence after `ramp_down`, and the
+    /// transfers filled before then have been sent, so that only silence
+    /// is left queued and stopping or retuning cannot cut the fade short.
+    pub fn is_silent(&self) -> bool {
+        self.control.silent.load(Ordering::SeqCst) > IN_FLIGHT
+    }
+
+    /// Ramp down and wait up to `timeout` for silence. Returns `false` on
+    /// timeout, as when streaming has stopped.
+    pub fn ramp_down_wait(&self, timeout: Duration) -> bool {
+        self.ramp_down();
+        let deadline = Instant::now() + timeout;
+        while !self.is_silent() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        true
+    }
+}
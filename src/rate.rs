@@ -0,0 +1,75 @@
+// The sample rate the board actually runs at for a requested rate
+// Licensed under MIT license
+
+// The Si5351's PLL runs at this frequency, and its multisynth divides it
+// down to twice the sample rate.
+const VCO_HZ: u64 = 800_000_000;
+// Largest denominator of the multisynth's fractional divider.
+const MAX_DENOMINATOR: u64 = (1 << 20) - 1;
+
+/// The sample rate set by `set_sample_rate` or `set_sample_rate_manual`,
+/// as the clock generator actually produces it, and the baseband filter
+/// libhackrf chose to go with it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SampleRate {
+    pub requested_hz: f64,
+    /// The rate the board runs at, which differs slightly from the request
+    /// when the clock generator's fractional divider cannot hit it exactly.
+    pub actual_hz: f64,
+    /// The rate as the fraction of `freq_hz` over `divider` sent to the
+    /// firmware.
+    pub freq_hz: u32,
+    pub divider: u32,
+    pub baseband_filter_hz: u32,
+}
+
+// libhackrf's conversion of a rate to a whole number of Hz over a divider
+// for `hackrf_set_sample_rate`: the smallest divider under 32 that makes
+// the rate whole to within the precision of the double, otherwise 1.
+pub(crate) fn fraction(freq_hz: f64) -> (u32, u32) {
+    let e = (freq_hz.to_bits() >> 52) as i64 - 1023;
+    let frac = (1.0 + freq_hz - freq_hz.trunc()).to_bits() & ((1 << 52) - 1);
+    let mask = ((1u64 << 52) - 1) & !((1u64 << (e + 4).clamp(0, 63)) - 1);
+    let mut sum = 0u64;
+    let divider = (1..32)
+        .find(|_| {
+            sum += frac;
+            sum & mask == 0 || !sum & mask == 0
+        })
+        .unwrap_or(1);
+    ((freq_hz * divider as f64 + 0.5) as u32, divider)
+}
+
+// The rate the firmware's Si5351 plan produces for `freq_hz / divider`:
+// an exact fractional divider where the denominator fits, otherwise the
+// nearest below with the largest denominator.
+pub(crate) fn achieved(freq_hz: u32, divider: u32) -> f64 {
+    let num = 2 * freq_hz as u64;
+    let target = VCO_HZ * divider as u64;
+    if num == 0 {
+        return 0.0;
+    }
+    let a = target / num;
+    let rem = target - a * num;
+    let (b, c) = if rem == 0 {
+        (0, 1)
+    } else {
+        let g = gcd(rem, num);
+        let (rem, num) = (rem / g, num / g);
+        if num <= MAX_DENOMINATOR {
+            (rem, num)
+        } else {
+            (MAX_DENOMINATOR * rem / num, MAX_DENOMINATOR)
+        }
+    };
+    VCO_HZ as f64 / (a as f64 + b as f64 / c as f64) / 2.0
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
@@ -0,0 +1,1029 @@
+// Recording the RX stream to disk
+// Licensed under MIT license
+
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use super::format::SampleFormat;
+use super::metadata::Acquisition;
+use super::pool::BufferPool;
+use super::stream::Budget;
+use super::threads::{self, Priority};
+use super::time::UtcTime;
+use super::wav::WavWriter;
+use super::{start_rx_owned, stop_rx, HackRFDevice, HackRFError};
+
+/// When a `Recorder` moves on to a new file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rotate {
+    /// After this many bytes of samples.
+    Size(u64),
+    /// After this much of the stream, counted in samples recorded.
+    Time(Duration),
+}
+
+/// Configures and starts a `Recorder`.
+pub struct RecorderBuilder {
+    path: PathBuf,
+    buffers: usize,
+    format: SampleFormat,
+    wav: bool,
+    #[cfg(feature = "zstd")]
+    zstd: Option<(i32, u32)>,
+    squelch: Option<(f64, Duration)>,
+    ring: Option<Duration>,
+    trigger: Option<(f64, Duration)>,
+    rotate: Option<Rotate>,
+    manifest: bool,
+    priority: Priority,
+    cores: Vec<usize>,
+    preallocate: bool,
+    max_duration: Option<Duration>,
+}
+
+impl RecorderBuilder {
+    /// Number of USB transfers that may be queued for the writer thread
+    /// before incoming data is dropped. Defaults to 64 (16MiB).
+    pub fn buffers(mut self, buffers: usize) -> RecorderBuilder {
+        assert!(buffers > 0);
+        self.buffers = buffers;
+        self
+    }
+
+    /// Sample format to write. Conversion from the HackRF's native cs8
+    /// happens on the writer thread. Defaults to `SampleFormat::Cs8`.
+    pub fn format(mut self, format: SampleFormat) -> RecorderBuilder {
+        self.format = format;
+        self
+    }
+
+    /// Write a 2-channel WAV (RF64 beyond 4GiB) with an SDR#-style `auxi`
+    /// chunk holding the centre frequency, instead of raw samples.
+    pub fn wav(mut self, wav: bool) -> RecorderBuilder {
+        self.wav = wav;
+        self
+    }
+
+    /// Compress raw recordings with zstd at `level`, 1-22, as they are
+    /// written, using `workers` threads besides the writer, or none to
+    /// compress on the writer thread. Level 1 with a couple of workers keeps
+    /// up with 20Msps on most machines and roughly halves cs8 from a quiet
+    /// band. Has no effect on WAV files.
+    #[cfg(feature = "zstd")]
+    pub fn zstd(mut self, level: i32, workers: u32) -> RecorderBuilder {
+        self.zstd = Some((level, workers));
+        self
+    }
+
+    /// Only record while the signal is above `threshold_db` dBFS, and for
+    /// `hang` after it drops below, skipping the quiet stretches between.
+    /// Each recorded segment is listed in an index file named after the
+    /// recording with `.idx` appended, as CSV lines of
+    /// `sample, samples, date, time`: where the segment starts in the
+    /// recording, in samples, its length and when it began, in UTC.
+    pub fn squelch(mut self, threshold_db: f64, hang: Duration) -> RecorderBuilder {
+        self.squelch = Some((threshold_db, hang));
+        self
+    }
+
+    /// Write a manifest alongside the recording, named after it with
+    /// `.manifest` appended, so that archived captures can be checked for
+    /// completeness and corruption later. It holds CSV lines of
+    /// `chunk, offset, bytes, crc32` for each 16MiB of sample data as
+    /// written, before compression and without WAV headers, running across
+    /// files if rotated; `gap, sample, samples` where transfers were dropped
+    /// and roughly how many samples went missing; and a final
+    /// `total, bytes, samples`. Not written in ring mode.
+    pub fn manifest(mut self, manifest: bool) -> RecorderBuilder {
+        self.manifest = manifest;
+        self
+    }
+
+    /// Scheduling for the writer thread, which also converts formats and
+    /// compresses. `Priority::RealTime` keeps other load on the machine
+    /// from stalling it into drops; if the OS refuses, it runs as normal
+    /// and `Recorder::priority` says so. Defaults to `Priority::Normal`.
+    pub fn priority(mut self, priority: Priority) -> RecorderBuilder {
+        self.priority = priority;
+        self
+    }
+
+    /// Run the writer thread only on `cores`, numbered as the OS numbers
+    /// them, to keep it clear of other busy threads on a loaded machine. If
+    /// pinning fails, as for a core that does not exist, it runs anywhere
+    /// and `Recorder::pinned` says so. Defaults to any core.
+    pub fn cores(mut self, cores: &[usize]) -> RecorderBuilder {
+        self.cores = cores.to_vec();
+        self
+    }
+
+    /// Allocate all `buffers` transfers' worth of queue before starting, so
+    /// that receiving never allocates, rather than as the queue first
+    /// fills. Defaults to off.
+    pub fn preallocate(mut self, preallocate: bool) -> RecorderBuilder {
+        self.preallocate = preallocate;
+        self
+    }
+
+    /// Split the recording into numbered files, as `capture-1.cs8`,
+    /// `capture-2.cs8` and so on, starting a new one whenever `rotate` says,
+    /// on a sample boundary, so that long captures stay within filesystem
+    /// and tool limits. WAV files each get their own header. Each file is
+    /// listed in an index named after the recording with `.files` appended,
+    /// as CSV lines of `file, sample, samples, date, time, freq_hz, rate`:
+    /// where it starts in the recording, in samples, its length, when the
+    /// transfer it starts in arrived, in UTC, and the tuning.
+    pub fn rotate(mut self, rotate: Rotate) -> RecorderBuilder {
+        self.rotate = Some(rotate);
+        self
+    }
+
+    /// Keep only the last `keep` of the stream in memory instead of writing
+    /// it out, and write that to a new file whenever `Recorder::snapshot`
+    /// is called or the trigger set with `ring_trigger` fires, so the moments
+    /// before an event are never missed. Snapshots are named after the
+    /// recording with a count before the extension, as `capture-1.cs8`, and
+    /// written in the chosen format and container. `squelch` does not apply.
+    pub fn ring(mut self, keep: Duration) -> RecorderBuilder {
+        self.ring = Some(keep);
+        self
+    }
+
+    /// In ring mode, take a snapshot `post` after the signal reaches
+    /// `threshold_db` dBFS, so that it holds the event as well as what led
+    /// up to it. The signal crossing again before then does not trigger
+    /// another.
+    pub fn ring_trigger(mut self, threshold_db: f64, post: Duration) -> RecorderBuilder {
+        self.trigger = Some((threshold_db, post));
+        self
+    }
+
+    /// Stop receiving after this long's worth of samples at the device's
+    /// sample rate, counting any dropped, so that a scripted capture ends
+    /// even if nothing stops it. The last transfer is cut short to record
+    /// exactly that many samples; `stop` still has to be called to finish
+    /// the file. Defaults to no limit.
+    pub fn max_duration(mut self, max_duration: Duration) -> RecorderBuilder {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Create the output file, unless in ring mode, and begin receiving.
+    /// The device's settings, firmware version and serial number are read
+    /// first and written with every file: as a SigMF `.sigmf-meta` file
+    /// next to raw recordings, named after the recording with its extension
+    /// replaced, or as the comment of WAV files. Compressed recordings get
+    /// no metadata file.
+    pub fn start(self, device: &mut HackRFDevice) -> Result<Recorder, HackRFError> {
+        let (full_tx, full_rx) = mpsc::sync_channel::<(Vec<u8>, SystemTime)>(self.buffers);
+        // Room for a full queue, one buffer being filled and one written.
+        let limit = self.buffers + 2;
+        let free = Arc::new(BufferPool::new(limit, self.preallocate));
+        let writer_free = free.clone();
+        let (err_tx, err_rx) = mpsc::channel();
+        let (snapshot_tx, snapshot_rx) = mpsc::channel();
+        let (written_tx, written_rx) = mpsc::channel();
+        let dropped = Arc::new(AtomicU64::new(0));
+        let cores = self.cores.clone();
+        let writer = match self.ring {
+            Some(keep) => {
+                let ring = Ring::new(&self, keep, self.settings(device));
+                threads::spawn("hackrf-recorder", self.priority, cores, move || {
+                    ring.run(full_rx, &writer_free, snapshot_rx, written_tx, err_tx)
+                })?
+            }
+            None => {
+                let settings = self.settings(device);
+                let output = match self.rotate {
+                    Some(rotate) => Files::Rotating(Rotation::new(&self.path, rotate, settings)?),
+                    None => Files::Single(settings.open(&self.path)?),
+                };
+                let squelch = self.open_squelch(device.sample_rate_hz)?;
+                let manifest = match self.manifest {
+                    true => Some(Manifest::new(&self.path, self.format, dropped.clone())?),
+                    false => None,
+                };
+                let format = self.format;
+                threads::spawn("hackrf-recorder", self.priority, cores, move || {
+                    write_loop(
+                        output,
+                        format,
+                        squelch,
+                        manifest,
+                        full_rx,
+                        &writer_free,
+                        err_tx,
+                    )
+                })?
+            }
+        };
+
+        // The callback runs on the USB thread, so it never touches the file:
+        // it copies each transfer into a recycled buffer and hands it over.
+        let cb_dropped = dropped.clone();
+        let mut allocated = if self.preallocate { limit } else { 0 };
+        let mut budget = Budget::new(self.max_duration, device.sample_rate_hz, SampleFormat::Cs8);
+        let callback = move |rx: &[u8]| -> bool {
+            let n = budget.take(rx.len());
+            if n == 0 {
+                return false;
+            }
+            let rx = &rx[..n];
+            let more = !budget.is_spent();
+            let mut buf = match free.take() {
+                Some(buf) => buf,
+                None if allocated < limit => {
+                    allocated += 1;
+                    Vec::with_capacity(rx.len())
+                }
+                None => {
+                    cb_dropped.fetch_add(1, Ordering::Relaxed);
+                    #[cfg(feature = "metrics")]
+                    super::metrics::dropped_buffer();
+                    #[cfg(feature = "log")]
+                    super::logging::overrun();
+                    return more;
+                }
+            };
+            buf.clear();
+            buf.extend_from_slice(rx);
+            match full_tx.try_send((buf, SystemTime::now())) {
+                Ok(()) => more,
+                Err(TrySendError::Full((buf, _))) => {
+                    cb_dropped.fetch_add(1, Ordering::Relaxed);
+                    #[cfg(feature = "metrics")]
+                    super::metrics::dropped_buffer();
+                    #[cfg(feature = "log")]
+                    super::logging::overrun();
+                    free.give(buf);
+                    more
+                }
+                // The writer has given up after an I/O error.
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        };
+        start_rx_owned(device, Box::new(callback))?;
+        Ok(Recorder {
+            writer: writer.thread,
+            priority: writer.priority,
+            pinned: writer.pinned,
+            errors: err_rx,
+            dropped,
+            snapshot: snapshot_tx,
+            snapshots: written_rx,
+        })
+    }
+
+    fn settings(&self, device: &mut HackRFDevice) -> OutputSettings {
+        OutputSettings {
+            acquisition: Arc::new(Acquisition::read(device)),
+            wav: self.wav,
+            #[cfg(feature = "zstd")]
+            zstd: self.zstd,
+            format: self.format,
+            rate: device.sample_rate_hz,
+            freq_hz: device.freq_hz,
+        }
+    }
+
+    fn open_squelch(&self, rate: f64) -> io::Result<Option<Squelch>> {
+        Ok(match self.squelch {
+            Some((threshold_db, hang)) => {
+                let mut index = OsString::from(self.path.as_os_str());
+                index.push(".idx");
+                let index = BufWriter::new(File::create(index)?);
+                Some(Squelch::new(index, threshold_db, hang, rate))
+            }
+            None => None,
+        })
+    }
+}
+
+/// Records the RX stream to a file of raw samples, or a WAV file, in cs8
+/// unless another `SampleFormat` is chosen with `RecorderBuilder::format`.
+/// File I/O happens on a dedicated writer thread fed through a queue of
+/// recycled buffers, so a slow disk shows up as counted drops rather than
+/// stalling the USB callback. In ring mode, set with `RecorderBuilder::ring`,
+/// it keeps the last few seconds in memory and writes them out on demand;
+/// writing a large snapshot holds up the writer, so transfers may be dropped
+/// meanwhile unless `buffers` covers it.
+pub struct Recorder {
+    writer: JoinHandle<u64>,
+    priority: Priority,
+    pinned: bool,
+    errors: Receiver<io::Error>,
+    dropped: Arc<AtomicU64>,
+    snapshot: Sender<()>,
+    snapshots: Receiver<PathBuf>,
+}
+
+impl Recorder {
+    /// Configure a recording to `path`; finish with `RecorderBuilder::start`.
+    pub fn builder<P: AsRef<Path>>(path: P) -> RecorderBuilder {
+        RecorderBuilder {
+            path: path.as_ref().to_path_buf(),
+            buffers: 64,
+            format: SampleFormat::Cs8,
+            wav: false,
+            #[cfg(feature = "zstd")]
+            zstd: None,
+            squelch: None,
+            ring: None,
+            trigger: None,
+            rotate: None,
+            manifest: false,
+            priority: Priority::Normal,
+            cores: Vec::new(),
+            preallocate: false,
+            max_duration: None,
+        }
+    }
+
+    /// Start recording to `path` with default settings.
+    pub fn start<P: AsRef<Path>>(
+        device: &mut HackRFDevice,
+        path: P,
+    ) -> Result<Recorder, HackRFError> {
+        Recorder::builder(path).start(device)
+    }
+
+    /// Number of USB transfers dropped so far because the writer fell behind.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The scheduling the writer thread got: as asked for with
+    /// `RecorderBuilder::priority`, or `Priority::Normal` if that was
+    /// refused.
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Whether the writer thread is pinned to the cores given with
+    /// `RecorderBuilder::cores`.
+    pub fn pinned(&self) -> bool {
+        self.pinned
+    }
+
+    /// Errors from the writer thread. After an error the writer stops and
+    /// receiving ends.
+    pub fn errors(&self) -> &Receiver<io::Error> {
+        &self.errors
+    }
+
+    /// In ring mode, write the samples held in memory to a new file. Has no
+    /// effect otherwise.
+    pub fn snapshot(&self) {
+        let _ = self.snapshot.send(());
+    }
+
+    /// Paths of the snapshots written so far in ring mode, as each is
+    /// completed.
+    pub fn snapshots(&self) -> &Receiver<PathBuf> {
+        &self.snapshots
+    }
+
+    /// Stop receiving, wait for queued data to reach the file and return the
+    /// number of bytes written, across all snapshots in ring mode. Returns
+    /// any writer error not yet taken from `errors`.
+    pub fn stop(self, device: &mut HackRFDevice) -> Result<u64, HackRFError> {
+        let result = stop_rx(device);
+        // Dropping the callback closes the queue, letting the writer finish.
+        device.rx_callback = None;
+        let written = self.writer.join().expect("recorder writer thread panicked");
+        result?;
+        match self.errors.try_recv() {
+            Ok(err) => Err(HackRFError::from(err)),
+            Err(_) => Ok(written),
+        }
+    }
+}
+
+// `path` with `-count` before its extension.
+fn numbered(path: &Path, count: usize) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("-{}", count));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+// How each file of a recording is written.
+#[derive(Clone)]
+struct OutputSettings {
+    acquisition: Arc<Acquisition>,
+    wav: bool,
+    #[cfg(feature = "zstd")]
+    zstd: Option<(i32, u32)>,
+    format: SampleFormat,
+    rate: f64,
+    freq_hz: u64,
+}
+
+impl OutputSettings {
+    // Create `path` and the writer for it.
+    fn open(&self, path: &Path) -> io::Result<Output> {
+        let file = File::create(path)?;
+        if self.wav {
+            let rate = self.rate.round() as u32;
+            let wav = WavWriter::new(file, self.format, rate, self.freq_hz)?;
+            return Ok(Output::Wav(wav.comment(self.acquisition.summary())));
+        }
+        // No sidecar for pipes and devices, nor for compressed files, which
+        // SigMF cannot describe.
+        #[cfg(feature = "zstd")]
+        let sidecar = self.zstd.is_none();
+        #[cfg(not(feature = "zstd"))]
+        let sidecar = true;
+        if sidecar && file.metadata()?.is_file() {
+            // Data files not named `.sigmf-data` are named in the metadata.
+            let dataset = match path.extension() {
+                Some(ext) if ext == "sigmf-data" => None,
+                _ => path.file_name().and_then(|name| name.to_str()),
+            };
+            let meta = self
+                .acquisition
+                .sigmf_meta(self.format, SystemTime::now(), dataset);
+            std::fs::write(path.with_extension("sigmf-meta"), meta)?;
+        }
+        #[cfg(feature = "zstd")]
+        if let Some((level, workers)) = self.zstd {
+            let mut encoder = zstd::stream::write::Encoder::new(file, level)?;
+            if workers > 0 {
+                encoder.multithread(workers)?;
+            }
+            return Ok(Output::Zstd(encoder));
+        }
+        Ok(Output::Raw(file))
+    }
+}
+
+enum Output {
+    Raw(File),
+    Wav(WavWriter<File>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, File>),
+}
+
+impl Output {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match *self {
+            Output::Raw(ref mut file) => file.write_all(buf),
+            Output::Wav(ref mut wav) => wav.write_all(buf),
+            #[cfg(feature = "zstd")]
+            Output::Zstd(ref mut encoder) => encoder.write_all(buf),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            // Pipes and devices cannot be synced.
+            Output::Raw(file) if !file.metadata()?.is_file() => Ok(()),
+            Output::Raw(file) => file.sync_all(),
+            Output::Wav(wav) => wav.finish()?.sync_all(),
+            #[cfg(feature = "zstd")]
+            Output::Zstd(encoder) => Output::Raw(encoder.finish()?).finish(),
+        }
+    }
+}
+
+// Where `write_loop` writes.
+enum Files {
+    Single(Output),
+    Rotating(Rotation),
+}
+
+impl Files {
+    fn write_all(&mut self, buf: &[u8], at: SystemTime) -> io::Result<()> {
+        match *self {
+            Files::Single(ref mut output) => output.write_all(buf),
+            Files::Rotating(ref mut rotation) => rotation.write_all(buf, at),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Files::Single(output) => output.finish(),
+            Files::Rotating(rotation) => rotation.finish(),
+        }
+    }
+}
+
+// The sequence of files for `RecorderBuilder::rotate`.
+struct Rotation {
+    path: PathBuf,
+    settings: OutputSettings,
+    index: BufWriter<File>,
+    // Bytes per file.
+    limit: u64,
+    output: Output,
+    // Number of the current file, bytes in it so far, when it began, and
+    // the bytes in the files before it.
+    count: usize,
+    len: u64,
+    started: Option<SystemTime>,
+    before: u64,
+}
+
+impl Rotation {
+    fn new(path: &Path, rotate: Rotate, settings: OutputSettings) -> io::Result<Rotation> {
+        let sample = settings.format.bytes_per_sample() as u64;
+        let limit = match rotate {
+            Rotate::Size(bytes) => bytes / sample * sample,
+            Rotate::Time(time) => (time.as_secs_f64() * settings.rate) as u64 * sample,
+        };
+        let mut index = OsString::from(path.as_os_str());
+        index.push(".files");
+        Ok(Rotation {
+            output: settings.open(&numbered(path, 1))?,
+            path: path.to_path_buf(),
+            settings,
+            index: BufWriter::new(File::create(index)?),
+            limit: limit.max(sample),
+            count: 1,
+            len: 0,
+            started: None,
+            before: 0,
+        })
+    }
+
+    fn write_all(&mut self, mut buf: &[u8], at: SystemTime) -> io::Result<()> {
+        while !buf.is_empty() {
+            if self.len == self.limit {
+                self.close()?;
+                self.count += 1;
+                let output = self.settings.open(&numbered(&self.path, self.count))?;
+                std::mem::replace(&mut self.output, output).finish()?;
+                self.before += self.len;
+                self.len = 0;
+                self.started = None;
+            }
+            let n = std::cmp::min(buf.len() as u64, self.limit - self.len) as usize;
+            self.started.get_or_insert(at);
+            self.output.write_all(&buf[..n])?;
+            self.len += n as u64;
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+
+    // List the current file in the index.
+    fn close(&mut self) -> io::Result<()> {
+        let sample = self.settings.format.bytes_per_sample() as u64;
+        let t = UtcTime::new(self.started.unwrap_or_else(SystemTime::now));
+        let path = numbered(&self.path, self.count);
+        writeln!(
+            self.index,
+            "{}, {}, {}, {:04}-{:02}-{:02}, {:02}:{:02}:{:02}.{:06}, {}, {}",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            self.before / sample,
+            self.len / sample,
+            t.year,
+            t.month,
+            t.day,
+            t.hour,
+            t.minute,
+            t.second,
+            t.micros,
+            self.settings.freq_hz,
+            self.settings.rate
+        )
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.close()?;
+        self.output.finish()?;
+        self.index.into_inner()?.sync_all()
+    }
+}
+
+// Bytes of sample data covered by each checksum in a manifest.
+const MANIFEST_CHUNK: u64 = 16 << 20;
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+// CRC-32 as used by zip and gzip.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+// Writes the manifest for `RecorderBuilder::manifest`.
+struct Manifest {
+    out: BufWriter<File>,
+    bytes_per_sample: u64,
+    // Bytes of sample data so far, and the running CRC of the current chunk.
+    len: u64,
+    crc: u32,
+    // Transfers dropped by the callback, and how many have been listed.
+    dropped: Arc<AtomicU64>,
+    gaps: u64,
+}
+
+impl Manifest {
+    fn new(path: &Path, format: SampleFormat, dropped: Arc<AtomicU64>) -> io::Result<Manifest> {
+        let mut name = OsString::from(path.as_os_str());
+        name.push(".manifest");
+        Ok(Manifest {
+            out: BufWriter::new(File::create(name)?),
+            bytes_per_sample: format.bytes_per_sample() as u64,
+            len: 0,
+            crc: !0,
+            dropped,
+            gaps: 0,
+        })
+    }
+
+    // List any transfers dropped before the one of `transfer_samples` just
+    // received.
+    fn check_gaps(&mut self, transfer_samples: u64) -> io::Result<()> {
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > self.gaps {
+            writeln!(
+                self.out,
+                "gap, {}, {}",
+                self.len / self.bytes_per_sample,
+                (dropped - self.gaps) * transfer_samples
+            )?;
+            self.gaps = dropped;
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let left = MANIFEST_CHUNK - self.len % MANIFEST_CHUNK;
+            let n = std::cmp::min(buf.len() as u64, left) as usize;
+            for &byte in &buf[..n] {
+                self.crc =
+                    CRC32_TABLE[((self.crc ^ byte as u32) & 0xff) as usize] ^ (self.crc >> 8);
+            }
+            self.len += n as u64;
+            if self.len.is_multiple_of(MANIFEST_CHUNK) {
+                self.end_chunk()?;
+            }
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+
+    fn end_chunk(&mut self) -> io::Result<()> {
+        let size = match self.len % MANIFEST_CHUNK {
+            0 => MANIFEST_CHUNK,
+            size => size,
+        };
+        writeln!(
+            self.out,
+            "chunk, {}, {}, {:08x}",
+            self.len - size,
+            size,
+            !self.crc
+        )?;
+        self.crc = !0;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        if !self.len.is_multiple_of(MANIFEST_CHUNK) {
+            self.end_chunk()?;
+        }
+        writeln!(
+            self.out,
+            "total, {}, {}",
+            self.len,
+            self.len / self.bytes_per_sample
+        )?;
+        self.out.into_inner()?.sync_all()
+    }
+}
+
+// Samples averaged for each squelch or trigger power measurement.
+const WINDOW: usize = 64;
+// Longest the ring writer waits for a transfer before checking for
+// snapshot requests.
+const RING_POLL: Duration = Duration::from_millis(100);
+
+// Whether any window of `buf` reaches `threshold`, the sum of I^2 + Q^2
+// over a whole window.
+fn above(buf: &[u8], threshold: f64) -> bool {
+    buf.chunks(WINDOW * 2).any(|window| {
+        let power: f64 = window
+            .iter()
+            .map(|&x| (x as i8 as f64) * (x as i8 as f64))
+            .sum();
+        power >= threshold * window.len() as f64 / (WINDOW * 2) as f64
+    })
+}
+
+// Gates the stream on power for `RecorderBuilder::squelch`.
+struct Squelch {
+    index: BufWriter<File>,
+    // Sum of I^2 + Q^2 over a window at the threshold.
+    threshold: f64,
+    hang: u64,
+    rate: f64,
+    // Samples written to the recording so far.
+    recorded: u64,
+    // Start sample and time of the open segment.
+    segment: Option<(u64, SystemTime)>,
+    // Samples since the signal was last above the threshold.
+    quiet: u64,
+}
+
+impl Squelch {
+    fn new(index: BufWriter<File>, threshold_db: f64, hang: Duration, rate: f64) -> Squelch {
+        Squelch {
+            index,
+            threshold: 10f64.powf(threshold_db / 10.0) * 128.0 * 128.0 * WINDOW as f64,
+            hang: (hang.as_secs_f64() * rate) as u64,
+            rate,
+            recorded: 0,
+            segment: None,
+            quiet: 0,
+        }
+    }
+
+    // Append the parts of `buf`, which arrived at `at`, that pass the
+    // squelch to `out`.
+    fn filter(&mut self, buf: &[u8], at: SystemTime, out: &mut Vec<u8>) -> io::Result<()> {
+        let mut offset = 0;
+        for window in buf.chunks(WINDOW * 2) {
+            let samples = window.len() as u64 / 2;
+            let power: f64 = window
+                .iter()
+                .map(|&x| (x as i8 as f64) * (x as i8 as f64))
+                .sum();
+            if power >= self.threshold * window.len() as f64 / (WINDOW * 2) as f64 {
+                if self.segment.is_none() {
+                    // The buffer arrived just after its last sample.
+                    let before = (buf.len() - offset) as f64 / 2.0 / self.rate;
+                    let start = at - Duration::from_secs_f64(before);
+                    self.segment = Some((self.recorded, start));
+                }
+                self.quiet = 0;
+            } else if self.segment.is_some() {
+                self.quiet += samples;
+                if self.quiet > self.hang {
+                    self.close()?;
+                }
+            }
+            if self.segment.is_some() {
+                out.extend_from_slice(window);
+                self.recorded += samples;
+            }
+            offset += window.len();
+        }
+        Ok(())
+    }
+
+    // End the open segment, if any, and list it in the index.
+    fn close(&mut self) -> io::Result<()> {
+        if let Some((start, time)) = self.segment.take() {
+            let t = UtcTime::new(time);
+            writeln!(
+                self.index,
+                "{}, {}, {:04}-{:02}-{:02}, {:02}:{:02}:{:02}.{:06}",
+                start,
+                self.recorded - start,
+                t.year,
+                t.month,
+                t.day,
+                t.hour,
+                t.minute,
+                t.second,
+                t.micros
+            )?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.close()?;
+        self.index.into_inner()?.sync_all()
+    }
+}
+
+// Holds the last stretch of the stream for `RecorderBuilder::ring`.
+struct Ring {
+    path: PathBuf,
+    settings: OutputSettings,
+    // Bytes of cs8 to keep.
+    capacity: usize,
+    chunks: VecDeque<Vec<u8>>,
+    len: usize,
+    // Sum of I^2 + Q^2 over a window at the trigger threshold, and the
+    // samples to wait after it fires.
+    trigger: Option<(f64, u64)>,
+    // Samples left until a triggered snapshot is due.
+    pending: Option<u64>,
+    // Snapshots written so far.
+    count: usize,
+}
+
+impl Ring {
+    fn new(builder: &RecorderBuilder, keep: Duration, settings: OutputSettings) -> Ring {
+        let rate = settings.rate;
+        Ring {
+            path: builder.path.clone(),
+            settings,
+            capacity: (keep.as_secs_f64() * rate) as usize * 2,
+            chunks: VecDeque::new(),
+            len: 0,
+            trigger: builder.trigger.map(|(threshold_db, post)| {
+                (
+                    10f64.powf(threshold_db / 10.0) * 128.0 * 128.0 * WINDOW as f64,
+                    (post.as_secs_f64() * rate) as u64,
+                )
+            }),
+            pending: None,
+            count: 0,
+        }
+    }
+
+    fn run(
+        mut self,
+        full: Receiver<(Vec<u8>, SystemTime)>,
+        free: &BufferPool,
+        requests: Receiver<()>,
+        snapshots: Sender<PathBuf>,
+        errors: Sender<io::Error>,
+    ) -> u64 {
+        let mut written = 0;
+        loop {
+            let mut due = match full.recv_timeout(RING_POLL) {
+                Ok((buf, _)) => {
+                    let due = self.push(&buf);
+                    free.give(buf);
+                    due
+                }
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => return written,
+            };
+            // Requests made while one is being written are served by it.
+            due |= requests.try_iter().count() > 0;
+            if due {
+                match self.snapshot() {
+                    Ok((path, len)) => {
+                        written += len;
+                        let _ = snapshots.send(path);
+                    }
+                    Err(err) => {
+                        let _ = errors.send(err);
+                        return written;
+                    }
+                }
+            }
+        }
+    }
+
+    // Keep `buf`, dropping what has fallen out of the ring, and return
+    // whether a triggered snapshot is now due.
+    fn push(&mut self, buf: &[u8]) -> bool {
+        let samples = buf.len() as u64 / 2;
+        if let Some((threshold, post)) = self.trigger {
+            if self.pending.is_none() && above(buf, threshold) {
+                self.pending = Some(post);
+            }
+        }
+        let mut chunk = match self.chunks.front() {
+            Some(front) if self.len - front.len() >= self.capacity => {
+                let front = self.chunks.pop_front().unwrap_or_default();
+                self.len -= front.len();
+                front
+            }
+            _ => Vec::with_capacity(buf.len()),
+        };
+        chunk.clear();
+        chunk.extend_from_slice(buf);
+        self.len += chunk.len();
+        self.chunks.push_back(chunk);
+        while self
+            .chunks
+            .front()
+            .is_some_and(|front| self.len - front.len() >= self.capacity)
+        {
+            let front = self.chunks.pop_front().unwrap_or_default();
+            self.len -= front.len();
+        }
+        match self.pending {
+            Some(left) if left <= samples => {
+                self.pending = None;
+                true
+            }
+            Some(left) => {
+                self.pending = Some(left - samples);
+                false
+            }
+            None => false,
+        }
+    }
+
+    // Write the last `capacity` bytes held to the next snapshot file, and
+    // return its path and the bytes written.
+    fn snapshot(&mut self) -> io::Result<(PathBuf, u64)> {
+        self.count += 1;
+        let path = numbered(&self.path, self.count);
+        let mut output = self.settings.open(&path)?;
+        let format = self.settings.format;
+        let mut skip = self.len.saturating_sub(self.capacity);
+        let mut written = 0;
+        let mut converted = Vec::new();
+        for chunk in &self.chunks {
+            let n = std::cmp::min(skip, chunk.len());
+            skip -= n;
+            let out = match format {
+                SampleFormat::Cs8 => &chunk[n..],
+                _ => {
+                    converted.clear();
+                    format.from_cs8(&chunk[n..], &mut converted);
+                    &converted
+                }
+            };
+            output.write_all(out)?;
+            written += out.len() as u64;
+        }
+        output.finish()?;
+        Ok((path, written))
+    }
+}
+
+fn write_loop(
+    mut output: Files,
+    format: SampleFormat,
+    mut squelch: Option<Squelch>,
+    mut manifest: Option<Manifest>,
+    full: Receiver<(Vec<u8>, SystemTime)>,
+    free: &BufferPool,
+    errors: Sender<io::Error>,
+) -> u64 {
+    let mut written = 0;
+    let mut gated = Vec::new();
+    let mut converted = Vec::new();
+    for (buf, at) in full.iter() {
+        let mut out = &buf;
+        if let Some(ref mut squelch) = squelch {
+            gated.clear();
+            if let Err(err) = squelch.filter(&buf, at, &mut gated) {
+                let _ = errors.send(err);
+                return written;
+            }
+            out = &gated;
+        }
+        let out = match format {
+            SampleFormat::Cs8 => out,
+            _ => {
+                converted.clear();
+                format.from_cs8(out, &mut converted);
+                &converted
+            }
+        };
+        let recorded = match manifest {
+            Some(ref mut manifest) => manifest
+                .check_gaps(buf.len() as u64 / 2)
+                .and_then(|_| manifest.update(out)),
+            None => Ok(()),
+        };
+        if let Err(err) = recorded.and_then(|_| output.write_all(out, at)) {
+            let _ = errors.send(err);
+            return written;
+        }
+        written += out.len() as u64;
+        free.give(buf);
+    }
+    let mut finished = match squelch {
+        Some(squelch) => squelch.finish().and_then(|_| output.finish()),
+        None => output.finish(),
+    };
+    if let Some(manifest) = manifest {
+        finished = finished.and_then(|_| manifest.finish());
+    }
+    if let Err(err) = finished {
+        let _ = errors.send(err);
+    }
+    written
+}
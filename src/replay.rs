@@ -0,0 +1,274 @@
+// Replaying recordings as if they were live
+// Licensed under MIT license
+
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::file::FileSource;
+use super::format::SampleFormat;
+use super::sdr::{find_stage, GainStage, RxCallback, RxChannel, SdrDevice, RX_GAIN_STAGES};
+#[cfg(feature = "sigmf")]
+use super::sigmf::SigMF;
+use super::{ffi, hackrf_error, HackRFError, StopHandle};
+
+// Largest transfer handed to the RX callback, as libhackrf uses.
+const TRANSFER_LEN: usize = 262_144;
+
+/// A stand-in for a receiving `HackRFDevice` that plays back a recording,
+/// for deterministic regression tests of DSP code. Samples are delivered
+/// as cs8 at the recorded sample rate, or as fast as the callback takes
+/// them if pacing is off. Where the recording was retuned, as marked by a
+/// SigMF capture segment, transfers end at the boundary and `frequency`
+/// changes to the new segment's before its samples arrive.
+///
+/// The recording fixes the frequency and sample rate: `set_frequency` and
+/// `set_sample_rate` are accepted but ignored. Gains are validated only.
+pub struct ReplayBackend {
+    path: PathBuf,
+    format: SampleFormat,
+    sample_rate_hz: f64,
+    // (first sample, centre frequency in Hz) per segment, in order.
+    segments: Arc<Vec<(u64, u64)>>,
+    paced: bool,
+    speed: f64,
+    looping: bool,
+    freq_hz: Arc<AtomicU64>,
+    finished: Arc<AtomicBool>,
+    rx: Option<(StopHandle, JoinHandle<()>)>,
+}
+
+impl ReplayBackend {
+    /// Replay a raw file of samples in `format`, recorded at `sample_rate`
+    /// samples per second tuned to `freq_hz`.
+    pub fn open_raw<P: AsRef<Path>>(
+        path: P,
+        format: SampleFormat,
+        sample_rate: f64,
+        freq_hz: u64,
+    ) -> io::Result<ReplayBackend> {
+        // Fail now rather than on the RX thread if the file is unreadable.
+        FileSource::open(&path, format)?;
+        Ok(ReplayBackend::new(
+            path.as_ref().to_path_buf(),
+            format,
+            sample_rate,
+            vec![(0, freq_hz)],
+        ))
+    }
+
+    /// Replay a SigMF recording, retuning at each capture segment that
+    /// records a frequency.
+    #[cfg(feature = "sigmf")]
+    pub fn open_sigmf(sigmf: &SigMF) -> ReplayBackend {
+        let mut segments: Vec<(u64, u64)> = Vec::new();
+        for capture in &sigmf.captures {
+            let freq_hz = match capture.frequency {
+                Some(freq) => freq.round() as u64,
+                None => segments.last().map_or(0, |s| s.1),
+            };
+            segments.push((capture.sample_start, freq_hz));
+        }
+        ReplayBackend::new(
+            sigmf.data_path.clone(),
+            sigmf.format,
+            sigmf.sample_rate,
+            segments,
+        )
+    }
+
+    fn new(
+        path: PathBuf,
+        format: SampleFormat,
+        sample_rate_hz: f64,
+        segments: Vec<(u64, u64)>,
+    ) -> ReplayBackend {
+        let freq_hz = segments[0].1;
+        ReplayBackend {
+            path,
+            format,
+            sample_rate_hz,
+            segments: Arc::new(segments),
+            paced: true,
+            speed: 1.0,
+            looping: false,
+            freq_hz: Arc::new(AtomicU64::new(freq_hz)),
+            finished: Arc::new(AtomicBool::new(false)),
+            rx: None,
+        }
+    }
+
+    /// Deliver samples at the recorded sample rate, the default, or as
+    /// fast as the RX callback accepts them.
+    pub fn paced(mut self, paced: bool) -> ReplayBackend {
+        self.paced = paced;
+        self
+    }
+
+    /// When paced, deliver samples `speed` times faster than they were
+    /// recorded, or slower if under 1, to skim through long recordings or
+    /// give slow processing more time. `sample_rate` still reports the
+    /// recorded rate. Defaults to 1.
+    pub fn speed(mut self, speed: f64) -> ReplayBackend {
+        assert!(speed > 0.0);
+        self.speed = speed;
+        self
+    }
+
+    /// Start again from the beginning at the end of the recording, instead
+    /// of ending the stream.
+    pub fn looping(mut self, looping: bool) -> ReplayBackend {
+        self.looping = looping;
+        self
+    }
+
+    /// Whether the last replay reached the end of the recording, or failed
+    /// to read it.
+    pub fn finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for ReplayBackend {
+    fn drop(&mut self) {
+        let _ = self.stop_rx();
+    }
+}
+
+struct Player {
+    path: PathBuf,
+    format: SampleFormat,
+    sample_rate_hz: f64,
+    segments: Arc<Vec<(u64, u64)>>,
+    paced: bool,
+    speed: f64,
+    looping: bool,
+    freq_hz: Arc<AtomicU64>,
+}
+
+impl Player {
+    // Returns true if the end of the recording was reached.
+    fn run(&self, stop: &StopHandle, callback: &mut RxCallback) -> io::Result<bool> {
+        let mut buf = vec![0; TRANSFER_LEN];
+        let start = Instant::now();
+        let mut played = 0u64;
+        loop {
+            let mut source = FileSource::open(&self.path, self.format)?;
+            let mut sample = 0u64;
+            let mut segment = 0;
+            self.freq_hz.store(self.segments[0].1, Ordering::SeqCst);
+            loop {
+                if stop.is_stopped() {
+                    return Ok(false);
+                }
+                // Retune between transfers, never within one.
+                while segment + 1 < self.segments.len() && self.segments[segment + 1].0 <= sample {
+                    segment += 1;
+                    self.freq_hz
+                        .store(self.segments[segment].1, Ordering::SeqCst);
+                }
+                let mut len = TRANSFER_LEN;
+                if let Some(next) = self.segments.get(segment + 1) {
+                    len = len.min(((next.0 - sample) * 2) as usize);
+                }
+                let n = source.read(&mut buf[..len])?;
+                if n == 0 {
+                    break;
+                }
+                sample += n as u64 / 2;
+                played += n as u64 / 2;
+                if self.paced {
+                    let rate = self.sample_rate_hz * self.speed;
+                    let due = start + Duration::from_secs_f64(played as f64 / rate);
+                    if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                        thread::sleep(wait);
+                    }
+                }
+                if !callback(&buf[..n]) {
+                    return Ok(false);
+                }
+            }
+            // An empty recording would loop forever.
+            if !self.looping || sample == 0 {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl SdrDevice for ReplayBackend {
+    type Error = HackRFError;
+
+    fn set_frequency(&mut self, _hz: u64) -> Result<(), HackRFError> {
+        Ok(())
+    }
+
+    fn frequency(&self) -> u64 {
+        self.freq_hz.load(Ordering::SeqCst)
+    }
+
+    fn set_sample_rate(&mut self, _hz: f64) -> Result<(), HackRFError> {
+        Ok(())
+    }
+
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate_hz
+    }
+
+    fn native_format(&self) -> SampleFormat {
+        SampleFormat::Cs8
+    }
+}
+
+impl RxChannel for ReplayBackend {
+    fn rx_gain_stages(&self) -> &'static [GainStage] {
+        &RX_GAIN_STAGES
+    }
+
+    fn set_rx_gain(&mut self, name: &str, db: f64) -> Result<(), HackRFError> {
+        find_stage(&RX_GAIN_STAGES, name, db).map(|_| ())
+    }
+
+    /// Replays from the start of the recording each time.
+    fn start_rx(&mut self, mut callback: RxCallback) -> Result<(), HackRFError> {
+        if self.rx.as_ref().is_some_and(|rx| rx.1.is_finished()) {
+            self.stop_rx()?;
+        }
+        if self.rx.is_some() {
+            return Err(hackrf_error(ffi::HACKRF_ERROR_BUSY));
+        }
+        let player = Player {
+            path: self.path.clone(),
+            format: self.format,
+            sample_rate_hz: self.sample_rate_hz,
+            segments: self.segments.clone(),
+            paced: self.paced,
+            speed: self.speed,
+            looping: self.looping,
+            freq_hz: self.freq_hz.clone(),
+        };
+        let stop = StopHandle::new();
+        let thread_stop = stop.clone();
+        let finished = self.finished.clone();
+        finished.store(false, Ordering::SeqCst);
+        let thread = thread::Builder::new()
+            .name("hackrf-replay".to_string())
+            .spawn(move || {
+                let ended = player.run(&thread_stop, &mut callback).unwrap_or(true);
+                finished.store(ended, Ordering::SeqCst);
+            })?;
+        self.rx = Some((stop, thread));
+        Ok(())
+    }
+
+    fn stop_rx(&mut self) -> Result<(), HackRFError> {
+        if let Some((stop, thread)) = self.rx.take() {
+            stop.stop();
+            thread.join().expect("replay thread panicked");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+// Rational sample rate conversion
+// Licensed under MIT license
+
+use super::decimate::{cs8_pairs, lowpass, to_cs8};
+use super::sdr::RxCallback;
+
+// Filter taps per polyphase branch.
+const TAPS_PER_PHASE: usize = 16;
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Polyphase resampler changing the rate of cs8 samples by a ratio of
+/// whole numbers, such as 10Msps to 48ksps for audio, keeping its state
+/// between calls so a stream can be fed a buffer at a time. The filter
+/// grows with the larger side of the ratio, so for large reductions it is
+/// cheaper to bring the rate down with a `Decimator` first.
+pub struct Resampler {
+    // Filter taps for each phase, in the order of the samples they weight.
+    phases: Vec<Vec<f32>>,
+    interpolation: usize,
+    decimation: usize,
+    // Samples not yet consumed, as I/Q pairs.
+    history: Vec<[f32; 2]>,
+    // Position of the next output sample, in input samples times
+    // `interpolation`, from the start of `history`.
+    position: usize,
+}
+
+impl Resampler {
+    /// Multiply the sample rate by `interpolation / decimation`.
+    pub fn new(interpolation: usize, decimation: usize) -> Resampler {
+        assert!(interpolation > 0 && decimation > 0);
+        let divisor = gcd(interpolation, decimation);
+        let (up, down) = (interpolation / divisor, decimation / divisor);
+        let taps = TAPS_PER_PHASE * up.max(down) / up + 1;
+        // Cut off at 80% of the lower Nyquist frequency, at the upsampled
+        // rate, with gain `up` to make up for the inserted zeros.
+        let filter = lowpass(taps * up, 0.4 / up.max(down) as f64);
+        let phases = (0..up)
+            .map(|p| {
+                (0..taps)
+                    .rev()
+                    .map(|k| filter[p + up * k] * up as f32)
+                    .collect()
+            })
+            .collect();
+        Resampler {
+            phases,
+            interpolation: up,
+            decimation: down,
+            history: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Convert from `input_rate` to `output_rate` samples per second.
+    pub fn for_rates(input_rate: u32, output_rate: u32) -> Resampler {
+        Resampler::new(output_rate as usize, input_rate as usize)
+    }
+
+    /// The conversion ratio in lowest terms, as (interpolation, decimation).
+    pub fn ratio(&self) -> (usize, usize) {
+        (self.interpolation, self.decimation)
+    }
+
+    /// Resample `input`, appending the output samples, as cs8, to `output`.
+    pub fn process(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        self.history.extend(cs8_pairs(input));
+        self.run(|[i, q]| {
+            output.push(to_cs8(i));
+            output.push(to_cs8(q));
+        });
+    }
+
+    /// As `process`, for I/Q pairs of floats, keeping their precision. Real
+    /// signals such as audio can be passed with Q set to 0.
+    pub fn process_f32(&mut self, input: &[[f32; 2]], output: &mut Vec<[f32; 2]>) {
+        self.history.extend_from_slice(input);
+        self.run(|iq| output.push(iq));
+    }
+
+    fn run(&mut self, mut emit: impl FnMut([f32; 2])) {
+        let taps = self.phases[0].len();
+        loop {
+            let start = self.position / self.interpolation;
+            if start + taps > self.history.len() {
+                break;
+            }
+            let phase = &self.phases[self.position % self.interpolation];
+            let (mut i, mut q) = (0.0, 0.0);
+            for (tap, iq) in phase.iter().zip(&self.history[start..start + taps]) {
+                i += tap * iq[0];
+                q += tap * iq[1];
+            }
+            emit([i, q]);
+            self.position += self.decimation;
+        }
+        let consumed = std::cmp::min(self.position / self.interpolation, self.history.len());
+        self.history.drain(..consumed);
+        self.position -= consumed * self.interpolation;
+    }
+
+    /// Wrap `callback` into an RX callback that receives resampled
+    /// samples, for use with `RxChannel::start_rx` and the adapters built
+    /// on it.
+    pub fn wrap(mut self, mut callback: RxCallback) -> RxCallback {
+        let mut resampled = Vec::new();
+        Box::new(move |rx: &[u8]| -> bool {
+            resampled.clear();
+            self.process(rx, &mut resampled);
+            resampled.is_empty() || callback(&resampled)
+        })
+    }
+}
@@ -0,0 +1,139 @@
+// Scanning frequency segments by retuning from the host
+// Licensed under MIT license
+
+use std::thread;
+use std::time::Duration;
+
+use super::strength::capture;
+use super::trigger::Burst;
+use super::{set_freq, HackRFDevice, HackRFError};
+
+/// Samples captured at one step of a `segment_scan`.
+#[derive(Clone, Debug)]
+pub struct Segment {
+    /// Centre frequency the board was tuned to, in Hz.
+    pub freq_hz: u64,
+    /// cs8 samples.
+    pub samples: Vec<u8>,
+}
+
+/// Iterator over the segments of a scan started with `segment_scan`.
+pub struct SegmentScan<'a> {
+    device: &'a mut HackRFDevice,
+    ranges_hz: Vec<(u64, u64)>,
+    dwell: Duration,
+    settle: Duration,
+    step_hz: u64,
+    // Range being scanned, and the low edge of the next segment in it.
+    range: usize,
+    next_hz: u64,
+    failed: bool,
+}
+
+/// Scan `ranges_hz`, pairs of start and stop frequencies in Hz, once,
+/// segment by segment: retune the board from the host, wait for it to
+/// settle, and capture `dwell` worth of samples at the current sample rate,
+/// yielding each segment's centre frequency and samples. Unlike the
+/// firmware sweep behind `init_sweep`, the dwell can be as long as wanted,
+/// and the time spent processing each segment before asking for the next
+/// loses nothing but time, at the cost of a few milliseconds per retune.
+/// Stops after the first error.
+pub fn segment_scan<'a>(
+    device: &'a mut HackRFDevice,
+    ranges_hz: &[(u64, u64)],
+    dwell: Duration,
+) -> SegmentScan<'a> {
+    assert!(ranges_hz.iter().all(|&(start, stop)| start < stop));
+    // Keep clear of the baseband filter's skirts at the band edges.
+    let step_hz = (device.sample_rate_hz * 0.75) as u64;
+    SegmentScan {
+        device,
+        ranges_hz: ranges_hz.to_vec(),
+        dwell,
+        settle: Duration::from_millis(1),
+        step_hz,
+        range: 0,
+        next_hz: ranges_hz.first().map_or(0, |&(start, _)| start),
+        failed: false,
+    }
+}
+
+impl<'a> SegmentScan<'a> {
+    /// Time to wait after each retune before capturing. Defaults to 1ms,
+    /// on top of the first transfer after starting, which is discarded.
+    pub fn settle(mut self, settle: Duration) -> SegmentScan<'a> {
+        self.settle = settle;
+        self
+    }
+
+    /// Width of each segment. Defaults to three quarters of the sample
+    /// rate.
+    pub fn step_hz(mut self, step_hz: u64) -> SegmentScan<'a> {
+        assert!(step_hz > 0);
+        self.step_hz = step_hz;
+        self
+    }
+
+    fn scan(&mut self, freq_hz: u64) -> Result<Segment, HackRFError> {
+        let samples = capture_at(self.device, freq_hz, self.settle, self.dwell)?;
+        Ok(Segment { freq_hz, samples })
+    }
+}
+
+impl<'a> Iterator for SegmentScan<'a> {
+    type Item = Result<Segment, HackRFError>;
+
+    fn next(&mut self) -> Option<Result<Segment, HackRFError>> {
+        if self.failed {
+            return None;
+        }
+        let &(_, stop) = self.ranges_hz.get(self.range)?;
+        let freq_hz = self.next_hz + self.step_hz / 2;
+        self.next_hz += self.step_hz;
+        if self.next_hz >= stop {
+            self.range += 1;
+            if let Some(&(start, _)) = self.ranges_hz.get(self.range) {
+                self.next_hz = start;
+            }
+        }
+        let segment = self.scan(freq_hz);
+        self.failed = segment.is_err();
+        Some(segment)
+    }
+}
+
+/// Capture each of `segments`, pairs of frequency in Hz and duration, in
+/// turn: retune, wait `settle`, and receive for the duration at the current
+/// sample rate, discarding the first transfer after each retune. Each
+/// capture is returned as a `Burst` carrying its frequency and sample
+/// rate, ready to keep with `Burst::save_wav` or replay, for survey-style
+/// data collection.
+pub fn capture_segments(
+    device: &mut HackRFDevice,
+    segments: &[(u64, Duration)],
+    settle: Duration,
+) -> Result<Vec<Burst>, HackRFError> {
+    segments
+        .iter()
+        .map(|&(freq_hz, duration)| {
+            Ok(Burst {
+                samples: capture_at(device, freq_hz, settle, duration)?,
+                sample_rate: device.sample_rate_hz,
+                freq_hz,
+            })
+        })
+        .collect()
+}
+
+// Tune to `freq_hz`, wait `settle` and capture `duration` of samples.
+fn capture_at(
+    device: &mut HackRFDevice,
+    freq_hz: u64,
+    settle: Duration,
+    duration: Duration,
+) -> Result<Vec<u8>, HackRFError> {
+    set_freq(device, freq_hz)?;
+    thread::sleep(settle);
+    let samples = (duration.as_secs_f64() * device.sample_rate_hz).ceil() as usize;
+    capture(device, samples)
+}
@@ -0,0 +1,201 @@
+// Hardware-agnostic SDR traits
+// Licensed under MIT license
+
+use std::fmt::Debug;
+
+use super::format::SampleFormat;
+use super::{
+    ffi, hackrf_error, set_amp_enable, set_freq, set_lna_gain, set_sample_rate, set_txvga_gain,
+    set_vga_gain, start_rx_owned, start_tx_owned, stop_rx, stop_tx, HackRFDevice, HackRFError,
+};
+
+/// Owned callback for `RxChannel::start_rx`.
+pub type RxCallback = Box<dyn FnMut(&[u8]) -> bool + Send>;
+/// Owned callback for `TxChannel::start_tx`.
+pub type TxCallback = Box<dyn FnMut(&mut [u8]) -> bool + Send>;
+
+/// A named, independently adjustable gain stage.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GainStage {
+    pub name: &'static str,
+    pub min_db: f64,
+    pub max_db: f64,
+    /// Resolution of the stage; requested gains are rounded down to a step.
+    pub step_db: f64,
+}
+
+impl GainStage {
+    pub(crate) fn quantize(&self, db: f64) -> Option<f64> {
+        if db < self.min_db || db > self.max_db {
+            return None;
+        }
+        Some(self.min_db + ((db - self.min_db) / self.step_db).floor() * self.step_db)
+    }
+}
+
+/// Tuning and sample rate control common to any SDR. Write applications
+/// against these traits to run on other hardware, or on a mock in tests.
+pub trait SdrDevice {
+    type Error: Debug;
+
+    /// Tune to a centre frequency in Hz.
+    fn set_frequency(&mut self, hz: u64) -> Result<(), Self::Error>;
+    /// Last frequency set, in Hz.
+    fn frequency(&self) -> u64;
+    /// Set the sample rate in samples per second.
+    fn set_sample_rate(&mut self, hz: f64) -> Result<(), Self::Error>;
+    /// Last sample rate set, in samples per second.
+    fn sample_rate(&self) -> f64;
+    /// Format of the samples passed to stream callbacks.
+    fn native_format(&self) -> SampleFormat;
+}
+
+/// A device that can receive.
+pub trait RxChannel: SdrDevice {
+    /// Gain stages in the receive path, in signal order.
+    fn rx_gain_stages(&self) -> &'static [GainStage];
+    /// Set the receive gain of the stage called `name`.
+    fn set_rx_gain(&mut self, name: &str, db: f64) -> Result<(), Self::Error>;
+    /// Begin receiving, calling `callback` with each buffer of samples in
+    /// `native_format` until it returns `false` or `stop_rx` is called.
+    fn start_rx(&mut self, callback: RxCallback) -> Result<(), Self::Error>;
+    /// Stop receiving and drop the callback.
+    fn stop_rx(&mut self) -> Result<(), Self::Error>;
+}
+
+/// A device that can transmit.
+pub trait TxChannel: SdrDevice {
+    /// Gain stages in the transmit path, in signal order.
+    fn tx_gain_stages(&self) -> &'static [GainStage];
+    /// Set the transmit gain of the stage called `name`.
+    fn set_tx_gain(&mut self, name: &str, db: f64) -> Result<(), Self::Error>;
+    /// Begin transmitting, calling `callback` to fill each buffer with
+    /// samples in `native_format` until it returns `false` or `stop_tx` is
+    /// called.
+    fn start_tx(&mut self, callback: TxCallback) -> Result<(), Self::Error>;
+    /// Stop transmitting and drop the callback.
+    fn stop_tx(&mut self) -> Result<(), Self::Error>;
+}
+
+/// A device that can both receive and transmit: a `HackRFDevice`, or a
+/// stand-in such as `MockDevice`.
+pub trait Backend: RxChannel + TxChannel {}
+
+impl<T: RxChannel + TxChannel + ?Sized> Backend for T {}
+
+pub(crate) const RX_GAIN_STAGES: [GainStage; 3] = [
+    GainStage {
+        name: "AMP",
+        min_db: 0.0,
+        max_db: 14.0,
+        step_db: 14.0,
+    },
+    GainStage {
+        name: "LNA",
+        min_db: 0.0,
+        max_db: 40.0,
+        step_db: 8.0,
+    },
+    GainStage {
+        name: "VGA",
+        min_db: 0.0,
+        max_db: 62.0,
+        step_db: 2.0,
+    },
+];
+
+pub(crate) const TX_GAIN_STAGES: [GainStage; 2] = [
+    GainStage {
+        name: "VGA",
+        min_db: 0.0,
+        max_db: 47.0,
+        step_db: 1.0,
+    },
+    GainStage {
+        name: "AMP",
+        min_db: 0.0,
+        max_db: 14.0,
+        step_db: 14.0,
+    },
+];
+
+pub(crate) fn find_stage(stages: &[GainStage], name: &str, db: f64) -> Result<f64, HackRFError> {
+    stages
+        .iter()
+        .find(|s| s.name.eq_ignore_ascii_case(name))
+        .and_then(|s| s.quantize(db))
+        .ok_or_else(|| hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM))
+}
+
+impl SdrDevice for HackRFDevice {
+    type Error = HackRFError;
+
+    fn set_frequency(&mut self, hz: u64) -> Result<(), HackRFError> {
+        set_freq(self, hz)
+    }
+
+    fn frequency(&self) -> u64 {
+        self.freq_hz
+    }
+
+    fn set_sample_rate(&mut self, hz: f64) -> Result<(), HackRFError> {
+        set_sample_rate(self, hz).map(|_| ())
+    }
+
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate_hz
+    }
+
+    fn native_format(&self) -> SampleFormat {
+        SampleFormat::Cs8
+    }
+}
+
+impl RxChannel for HackRFDevice {
+    fn rx_gain_stages(&self) -> &'static [GainStage] {
+        &RX_GAIN_STAGES
+    }
+
+    fn set_rx_gain(&mut self, name: &str, db: f64) -> Result<(), HackRFError> {
+        let db = find_stage(&RX_GAIN_STAGES, name, db)?;
+        match name.to_ascii_uppercase().as_str() {
+            "AMP" => set_amp_enable(self, db > 0.0),
+            "LNA" => set_lna_gain(self, db as u32),
+            _ => set_vga_gain(self, db as u32),
+        }
+    }
+
+    fn start_rx(&mut self, callback: RxCallback) -> Result<(), HackRFError> {
+        start_rx_owned(self, callback)
+    }
+
+    fn stop_rx(&mut self) -> Result<(), HackRFError> {
+        let result = stop_rx(self);
+        self.rx_callback = None;
+        result
+    }
+}
+
+impl TxChannel for HackRFDevice {
+    fn tx_gain_stages(&self) -> &'static [GainStage] {
+        &TX_GAIN_STAGES
+    }
+
+    fn set_tx_gain(&mut self, name: &str, db: f64) -> Result<(), HackRFError> {
+        let db = find_stage(&TX_GAIN_STAGES, name, db)?;
+        match name.to_ascii_uppercase().as_str() {
+            "AMP" => set_amp_enable(self, db > 0.0),
+            _ => set_txvga_gain(self, db as u32),
+        }
+    }
+
+    fn start_tx(&mut self, callback: TxCallback) -> Result<(), HackRFError> {
+        start_tx_owned(self, callback)
+    }
+
+    fn stop_tx(&mut self) -> Result<(), HackRFError> {
+        let result = stop_tx(self);
+        self.tx_callback = None;
+        result
+    }
+}
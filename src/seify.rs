@@ -0,0 +1,571 @@
+// seify backend, for FutureSDR and other seify-based applications
+// Licensed under MIT license
+
+use std::any::Any;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use libseify::{
+    Args, DeviceTrait, Direction, Driver, Error, Range, RangeItem, RxStreamer, TxStreamer,
+};
+use num_complex::Complex32;
+
+use super::format::f32_to_cs8;
+use super::sdr::{GainStage, RxChannel, SdrDevice, TxChannel};
+use super::stream::{RxStream, TxStream};
+use super::{
+    board_partid_serialno_read, compute_baseband_filter_bw, set_baseband_filter_bandwidth,
+    HackRFDevice, HackRFError,
+};
+
+// Samples in each libhackrf USB transfer.
+const TRANSFER_SAMPLES: usize = 131_072;
+// Transfers queued between the USB thread and the streamers.
+const QUEUE_LEN: usize = 64;
+
+impl From<HackRFError> for Error {
+    fn from(err: HackRFError) -> Error {
+        Error::Misc(format!("{:?}", err))
+    }
+}
+
+struct Shared {
+    device: HackRFDevice,
+    // Last gain set on each stage of `rx_gain_stages`/`tx_gain_stages`.
+    rx_gains: Vec<f64>,
+    tx_gains: Vec<f64>,
+    bandwidth_hz: f64,
+}
+
+/// A `HackRFDevice` implementing seify's `DeviceTrait`, with a single RX and
+/// a single TX channel sharing one tuner. Clones share the same device.
+/// Wrap it with `seify::Device::from_impl` to use it as a generic device.
+#[derive(Clone)]
+pub struct SeifyDevice {
+    shared: Arc<Mutex<Shared>>,
+    id: String,
+}
+
+impl SeifyDevice {
+    /// Take ownership of an open device.
+    pub fn new(mut device: HackRFDevice) -> Result<SeifyDevice, HackRFError> {
+        let (_, serial) = board_partid_serialno_read(&mut device)?;
+        let id = serial.iter().map(|w| format!("{:08x}", w)).collect();
+        let rx_gains = vec![0.0; device.rx_gain_stages().len()];
+        let tx_gains = vec![0.0; device.tx_gain_stages().len()];
+        Ok(SeifyDevice {
+            shared: Arc::new(Mutex::new(Shared {
+                device,
+                rx_gains,
+                tx_gains,
+                bandwidth_hz: 0.0,
+            })),
+            id,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().expect("seify device mutex poisoned")
+    }
+}
+
+fn check_channel(channel: usize) -> Result<(), Error> {
+    match channel {
+        0 => Ok(()),
+        _ => Err(Error::ValueError),
+    }
+}
+
+fn stages(device: &HackRFDevice, direction: Direction) -> &'static [GainStage] {
+    match direction {
+        Direction::Rx => device.rx_gain_stages(),
+        Direction::Tx => device.tx_gain_stages(),
+    }
+}
+
+fn set_stage(shared: &mut Shared, direction: Direction, name: &str, db: f64) -> Result<(), Error> {
+    let stages = stages(&shared.device, direction);
+    let index = stages
+        .iter()
+        .position(|s| s.name.eq_ignore_ascii_case(name))
+        .ok_or(Error::ValueError)?;
+    let db = stages[index].quantize(db).ok_or(Error::ValueError)?;
+    match direction {
+        Direction::Rx => {
+            shared.device.set_rx_gain(name, db)?;
+            shared.rx_gains[index] = db;
+        }
+        Direction::Tx => {
+            shared.device.set_tx_gain(name, db)?;
+            shared.tx_gains[index] = db;
+        }
+    }
+    Ok(())
+}
+
+fn stage_range(stage: &GainStage) -> Range {
+    Range::new(vec![RangeItem::Step(
+        stage.min_db,
+        stage.max_db,
+        stage.step_db,
+    )])
+}
+
+impl DeviceTrait for SeifyDevice {
+    type RxStreamer = SeifyRxStreamer;
+    type TxStreamer = SeifyTxStreamer;
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn driver(&self) -> Driver {
+        Driver::HackRf
+    }
+
+    fn id(&self) -> Result<String, Error> {
+        Ok(self.id.clone())
+    }
+
+    fn info(&self) -> Result<Args, Error> {
+        let mut args = Args::new();
+        args.set("driver", "hackrf");
+        args.set("serial", self.id.clone());
+        Ok(args)
+    }
+
+    fn num_channels(&self, _direction: Direction) -> Result<usize, Error> {
+        Ok(1)
+    }
+
+    fn full_duplex(&self, _direction: Direction, channel: usize) -> Result<bool, Error> {
+        check_channel(channel)?;
+        Ok(false)
+    }
+
+    fn rx_streamer(&self, channels: &[usize], _args: Args) -> Result<SeifyRxStreamer, Error> {
+        if channels != [0] {
+            return Err(Error::ValueError);
+        }
+        Ok(SeifyRxStreamer {
+            device: self.clone(),
+            stream: None,
+            current: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    fn tx_streamer(&self, channels: &[usize], _args: Args) -> Result<SeifyTxStreamer, Error> {
+        if channels != [0] {
+            return Err(Error::ValueError);
+        }
+        Ok(SeifyTxStreamer {
+            device: self.clone(),
+            stream: None,
+        })
+    }
+
+    fn antennas(&self, direction: Direction, channel: usize) -> Result<Vec<String>, Error> {
+        self.antenna(direction, channel).map(|a| vec![a])
+    }
+
+    fn antenna(&self, _direction: Direction, channel: usize) -> Result<String, Error> {
+        check_channel(channel)?;
+        Ok("TX/RX".to_string())
+    }
+
+    fn set_antenna(&self, direction: Direction, channel: usize, name: &str) -> Result<(), Error> {
+        if self.antenna(direction, channel)? == name {
+            Ok(())
+        } else {
+            Err(Error::ValueError)
+        }
+    }
+
+    fn supports_agc(&self, _direction: Direction, channel: usize) -> Result<bool, Error> {
+        check_channel(channel)?;
+        Ok(false)
+    }
+
+    fn enable_agc(&self, _direction: Direction, _channel: usize, _agc: bool) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    fn agc(&self, _direction: Direction, _channel: usize) -> Result<bool, Error> {
+        Err(Error::NotSupported)
+    }
+
+    fn gain_elements(&self, direction: Direction, channel: usize) -> Result<Vec<String>, Error> {
+        check_channel(channel)?;
+        let shared = self.lock();
+        Ok(stages(&shared.device, direction)
+            .iter()
+            .map(|s| s.name.to_string())
+            .collect())
+    }
+
+    /// Overall gain excludes the RF amplifier, which must be switched on
+    /// explicitly as the `AMP` element. It is spread over the LNA then VGA.
+    fn set_gain(&self, direction: Direction, channel: usize, gain: f64) -> Result<(), Error> {
+        check_channel(channel)?;
+        let mut shared = self.lock();
+        match direction {
+            Direction::Rx => {
+                if !(0.0..=102.0).contains(&gain) {
+                    return Err(Error::ValueError);
+                }
+                let lna = (gain / 8.0).floor().min(5.0) * 8.0;
+                set_stage(&mut shared, direction, "LNA", lna)?;
+                set_stage(&mut shared, direction, "VGA", (gain - lna).min(62.0))
+            }
+            Direction::Tx => set_stage(&mut shared, direction, "VGA", gain),
+        }
+    }
+
+    fn gain(&self, direction: Direction, channel: usize) -> Result<Option<f64>, Error> {
+        check_channel(channel)?;
+        let shared = self.lock();
+        let gains = match direction {
+            Direction::Rx => &shared.rx_gains,
+            Direction::Tx => &shared.tx_gains,
+        };
+        let amp = stages(&shared.device, direction)
+            .iter()
+            .position(|s| s.name == "AMP");
+        Ok(Some(
+            gains
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| Some(i) != amp)
+                .map(|(_, g)| g)
+                .sum(),
+        ))
+    }
+
+    fn gain_range(&self, direction: Direction, channel: usize) -> Result<Range, Error> {
+        check_channel(channel)?;
+        let max = match direction {
+            Direction::Rx => 102.0,
+            Direction::Tx => 47.0,
+        };
+        Ok(Range::new(vec![RangeItem::Interval(0.0, max)]))
+    }
+
+    fn set_gain_element(
+        &self,
+        direction: Direction,
+        channel: usize,
+        name: &str,
+        gain: f64,
+    ) -> Result<(), Error> {
+        check_channel(channel)?;
+        set_stage(&mut self.lock(), direction, name, gain)
+    }
+
+    fn gain_element(
+        &self,
+        direction: Direction,
+        channel: usize,
+        name: &str,
+    ) -> Result<Option<f64>, Error> {
+        check_channel(channel)?;
+        let shared = self.lock();
+        let index = stages(&shared.device, direction)
+            .iter()
+            .position(|s| s.name.eq_ignore_ascii_case(name))
+            .ok_or(Error::ValueError)?;
+        Ok(Some(match direction {
+            Direction::Rx => shared.rx_gains[index],
+            Direction::Tx => shared.tx_gains[index],
+        }))
+    }
+
+    fn gain_element_range(
+        &self,
+        direction: Direction,
+        channel: usize,
+        name: &str,
+    ) -> Result<Range, Error> {
+        check_channel(channel)?;
+        let shared = self.lock();
+        stages(&shared.device, direction)
+            .iter()
+            .find(|s| s.name.eq_ignore_ascii_case(name))
+            .map(stage_range)
+            .ok_or(Error::ValueError)
+    }
+
+    fn frequency_range(&self, _direction: Direction, channel: usize) -> Result<Range, Error> {
+        check_channel(channel)?;
+        Ok(Range::new(vec![RangeItem::Interval(0.0, 7_250_000_000.0)]))
+    }
+
+    fn frequency(&self, _direction: Direction, channel: usize) -> Result<f64, Error> {
+        check_channel(channel)?;
+        Ok(self.lock().device.frequency() as f64)
+    }
+
+    fn set_frequency(
+        &self,
+        _direction: Direction,
+        channel: usize,
+        frequency: f64,
+        _args: Args,
+    ) -> Result<(), Error> {
+        check_channel(channel)?;
+        if !(0.0..=7_250_000_000.0).contains(&frequency) {
+            return Err(Error::ValueError);
+        }
+        Ok(self.lock().device.set_frequency(frequency as u64)?)
+    }
+
+    fn frequency_components(
+        &self,
+        _direction: Direction,
+        channel: usize,
+    ) -> Result<Vec<String>, Error> {
+        check_channel(channel)?;
+        Ok(vec!["TUNER".to_string()])
+    }
+
+    fn component_frequency_range(
+        &self,
+        direction: Direction,
+        channel: usize,
+        name: &str,
+    ) -> Result<Range, Error> {
+        match name {
+            "TUNER" => self.frequency_range(direction, channel),
+            _ => Err(Error::ValueError),
+        }
+    }
+
+    fn component_frequency(
+        &self,
+        direction: Direction,
+        channel: usize,
+        name: &str,
+    ) -> Result<f64, Error> {
+        match name {
+            "TUNER" => self.frequency(direction, channel),
+            _ => Err(Error::ValueError),
+        }
+    }
+
+    fn set_component_frequency(
+        &self,
+        direction: Direction,
+        channel: usize,
+        name: &str,
+        frequency: f64,
+    ) -> Result<(), Error> {
+        match name {
+            "TUNER" => self.set_frequency(direction, channel, frequency, Args::new()),
+            _ => Err(Error::ValueError),
+        }
+    }
+
+    fn sample_rate(&self, _direction: Direction, channel: usize) -> Result<f64, Error> {
+        check_channel(channel)?;
+        Ok(self.lock().device.sample_rate())
+    }
+
+    /// Also selects the widest baseband filter suited to the new rate,
+    /// unless a bandwidth has been set explicitly.
+    fn set_sample_rate(
+        &self,
+        _direction: Direction,
+        channel: usize,
+        rate: f64,
+    ) -> Result<(), Error> {
+        check_channel(channel)?;
+        if !(2e6..=20e6).contains(&rate) {
+            return Err(Error::ValueError);
+        }
+        let mut shared = self.lock();
+        shared.device.set_sample_rate(rate)?;
+        if shared.bandwidth_hz == 0.0 {
+            let bw = compute_baseband_filter_bw((rate * 0.75) as u32);
+            set_baseband_filter_bandwidth(&mut shared.device, bw)?;
+        }
+        Ok(())
+    }
+
+    fn get_sample_rate_range(&self, _direction: Direction, channel: usize) -> Result<Range, Error> {
+        check_channel(channel)?;
+        Ok(Range::new(vec![RangeItem::Interval(2e6, 20e6)]))
+    }
+
+    fn bandwidth(&self, _direction: Direction, channel: usize) -> Result<f64, Error> {
+        check_channel(channel)?;
+        Ok(self.lock().bandwidth_hz)
+    }
+
+    fn set_bandwidth(&self, _direction: Direction, channel: usize, bw: f64) -> Result<(), Error> {
+        check_channel(channel)?;
+        let bw = compute_baseband_filter_bw(bw as u32);
+        let mut shared = self.lock();
+        set_baseband_filter_bandwidth(&mut shared.device, bw)?;
+        shared.bandwidth_hz = bw as f64;
+        Ok(())
+    }
+
+    fn get_bandwidth_range(&self, _direction: Direction, channel: usize) -> Result<Range, Error> {
+        check_channel(channel)?;
+        Ok(Range::new(vec![RangeItem::Interval(1.75e6, 28e6)]))
+    }
+
+    fn has_dc_offset_mode(&self, _direction: Direction, channel: usize) -> Result<bool, Error> {
+        check_channel(channel)?;
+        Ok(false)
+    }
+
+    fn set_dc_offset_mode(
+        &self,
+        _direction: Direction,
+        _channel: usize,
+        _automatic: bool,
+    ) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    fn dc_offset_mode(&self, _direction: Direction, _channel: usize) -> Result<bool, Error> {
+        Err(Error::NotSupported)
+    }
+}
+
+/// seify RX streamer for a `SeifyDevice`, delivering samples as
+/// `Complex32` scaled to +-1.0.
+pub struct SeifyRxStreamer {
+    device: SeifyDevice,
+    stream: Option<RxStream>,
+    current: Vec<u8>,
+    pos: usize,
+}
+
+impl RxStreamer for SeifyRxStreamer {
+    fn mtu(&self) -> Result<usize, Error> {
+        Ok(TRANSFER_SAMPLES)
+    }
+
+    fn activate_at(&mut self, time_ns: Option<i64>) -> Result<(), Error> {
+        if time_ns.is_some() {
+            return Err(Error::NotSupported);
+        }
+        if self.stream.is_none() {
+            let mut shared = self.device.lock();
+            self.stream = Some(RxStream::start(&mut shared.device, QUEUE_LEN)?);
+        }
+        Ok(())
+    }
+
+    fn deactivate_at(&mut self, time_ns: Option<i64>) -> Result<(), Error> {
+        if time_ns.is_some() {
+            return Err(Error::NotSupported);
+        }
+        if let Some(stream) = self.stream.take() {
+            stream.stop(&mut self.device.lock().device)?;
+        }
+        self.current.clear();
+        self.pos = 0;
+        Ok(())
+    }
+
+    /// Returns 0 samples if none arrive within `timeout_us`.
+    fn read(&mut self, buffers: &mut [&mut [Complex32]], timeout_us: i64) -> Result<usize, Error> {
+        if buffers.len() != 1 {
+            return Err(Error::ValueError);
+        }
+        let stream = self.stream.as_ref().ok_or(Error::Inactive)?;
+        if self.pos == self.current.len() {
+            let timeout = Duration::from_micros(timeout_us.max(0) as u64);
+            let buf = match stream.recv_timeout(timeout) {
+                Ok(buf) => buf,
+                Err(_) => return Ok(0),
+            };
+            stream.recycle(std::mem::replace(&mut self.current, buf));
+            self.pos = 0;
+        }
+        let iq = &self.current[self.pos..];
+        let n = buffers[0].len().min(iq.len() / 2);
+        for (out, s) in buffers[0].iter_mut().zip(iq.chunks_exact(2)).take(n) {
+            *out = Complex32::new(s[0] as i8 as f32 / 128.0, s[1] as i8 as f32 / 128.0);
+        }
+        self.pos += n * 2;
+        Ok(n)
+    }
+}
+
+/// seify TX streamer for a `SeifyDevice`, taking `Complex32` samples
+/// scaled to +-1.0. Silence is sent whenever no samples are queued.
+pub struct SeifyTxStreamer {
+    device: SeifyDevice,
+    stream: Option<TxStream>,
+}
+
+impl TxStreamer for SeifyTxStreamer {
+    fn mtu(&self) -> Result<usize, Error> {
+        Ok(TRANSFER_SAMPLES)
+    }
+
+    fn activate_at(&mut self, time_ns: Option<i64>) -> Result<(), Error> {
+        if time_ns.is_some() {
+            return Err(Error::NotSupported);
+        }
+        if self.stream.is_none() {
+            let mut shared = self.device.lock();
+            self.stream = Some(TxStream::start(&mut shared.device, QUEUE_LEN)?);
+        }
+        Ok(())
+    }
+
+    fn deactivate_at(&mut self, time_ns: Option<i64>) -> Result<(), Error> {
+        if time_ns.is_some() {
+            return Err(Error::NotSupported);
+        }
+        if let Some(stream) = self.stream.take() {
+            stream.stop(&mut self.device.lock().device)?;
+        }
+        Ok(())
+    }
+
+    /// Queues all of `buffers[0]`, waiting for room if necessary; timed
+    /// transmission is not supported.
+    fn write(
+        &mut self,
+        buffers: &[&[Complex32]],
+        at_ns: Option<i64>,
+        _end_burst: bool,
+        _timeout_us: i64,
+    ) -> Result<usize, Error> {
+        if buffers.len() != 1 {
+            return Err(Error::ValueError);
+        }
+        if at_ns.is_some() {
+            return Err(Error::NotSupported);
+        }
+        let stream = self.stream.as_ref().ok_or(Error::Inactive)?;
+        let mut cs8 = Vec::with_capacity(buffers[0].len() * 2);
+        for s in buffers[0] {
+            cs8.push(f32_to_cs8(s.re));
+            cs8.push(f32_to_cs8(s.im));
+        }
+        stream.send(cs8).map_err(|_| Error::Inactive)?;
+        Ok(buffers[0].len())
+    }
+
+    fn write_all(
+        &mut self,
+        buffers: &[&[Complex32]],
+        at_ns: Option<i64>,
+        end_burst: bool,
+        timeout_us: i64,
+    ) -> Result<(), Error> {
+        self.write(buffers, at_ns, end_burst, timeout_us)
+            .map(|_| ())
+    }
+}
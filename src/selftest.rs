@@ -0,0 +1,209 @@
+// Loopback self-test between two boards
+// Licensed under MIT license
+
+use std::thread;
+use std::time::Duration;
+
+use super::fft::PowerSpectrum;
+use super::stream::RxStream;
+use super::waveforms;
+use super::{
+    compute_baseband_filter_bw, ffi, hackrf_error, set_amp_enable, set_baseband_filter_bandwidth,
+    set_freq, set_lna_gain, set_sample_rate, set_txvga_gain, set_vga_gain, start_tx_owned, stop_tx,
+    HackRFDevice, HackRFError,
+};
+
+// FFT size for the measurement, about 500Hz bins at the default rate.
+const FFT_SIZE: usize = 4096;
+// Spectra averaged for the measurement.
+const AVERAGES: usize = 16;
+// Bins either side of DC ignored when looking for the tone, to skip the
+// receiver's DC spike.
+const DC_BINS: usize = 4;
+// Time for the transmitter and AGC-free receiver to settle.
+const SETTLE: Duration = Duration::from_millis(200);
+const RECV_TIMEOUT: Duration = Duration::from_secs(2);
+// Above this the receiver is close to clipping.
+const MAX_TONE_DBFS: f32 = -6.0;
+
+/// Settings for `self_test`. The defaults transmit at minimum power, with
+/// the TX amplifier off, on 433.92MHz.
+#[derive(Copy, Clone, Debug)]
+pub struct SelfTest {
+    pub freq_hz: u64,
+    pub sample_rate: f64,
+    /// Offset of the test tone from `freq_hz`.
+    pub tone_offset_hz: f64,
+    pub tx_vga_db: u32,
+    pub rx_lna_db: u32,
+    pub rx_vga_db: u32,
+    /// How far the detected tone may be from where it was sent.
+    pub offset_tolerance_hz: f64,
+    /// Lowest acceptable tone level above the median noise floor.
+    pub min_snr_db: f32,
+}
+
+impl Default for SelfTest {
+    fn default() -> SelfTest {
+        SelfTest {
+            freq_hz: 433_920_000,
+            sample_rate: 2e6,
+            tone_offset_hz: 250e3,
+            tx_vga_db: 0,
+            rx_lna_db: 16,
+            rx_vga_db: 20,
+            offset_tolerance_hz: 2e3,
+            min_snr_db: 20.0,
+        }
+    }
+}
+
+/// The outcome of `self_test`.
+#[derive(Clone, Debug)]
+pub struct SelfTestReport {
+    pub expected_offset_hz: f64,
+    /// Offset of the strongest signal received, away from DC.
+    pub measured_offset_hz: f64,
+    /// Level of that signal, relative to full scale.
+    pub tone_dbfs: f32,
+    /// Median level across the band, relative to full scale.
+    pub noise_dbfs: f32,
+    pub snr_db: f32,
+    /// Problems with the setup that did not necessarily fail the test,
+    /// such as a receiver close to clipping.
+    pub warnings: Vec<String>,
+    /// Whether the tone was found within tolerance at the required SNR.
+    pub passed: bool,
+}
+
+/// Transmit a test tone from `tx` while receiving on `rx`, and check the
+/// tone arrives at the right offset and level. The HackRF is half duplex,
+/// so a board cannot hear itself: this needs two, with `tx`'s antenna port
+/// cabled to `rx`'s through an attenuator. Use at least 30dB, and never
+/// connect the ports directly or enable the TX amplifier, as that risks
+/// damaging the receiver. Warnings in the report flag a receiver that is
+/// close to clipping. Both boards are left tuned with streaming stopped.
+pub fn self_test(
+    tx: &mut HackRFDevice,
+    rx: &mut HackRFDevice,
+    test: &SelfTest,
+) -> Result<SelfTestReport, HackRFError> {
+    if test.tone_offset_hz.abs() >= test.sample_rate / 2.0 {
+        return Err(hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM));
+    }
+    let bw = compute_baseband_filter_bw((test.sample_rate * 0.75) as u32);
+    configure(tx, test, bw)?;
+    configure(rx, test, bw)?;
+    set_txvga_gain(tx, test.tx_vga_db)?;
+    set_lna_gain(rx, test.rx_lna_db)?;
+    set_vga_gain(rx, test.rx_vga_db)?;
+
+    // One transfer of tone, repeating without a phase jump.
+    let samples = waveforms::to_cs8(&waveforms::tone(
+        test.sample_rate,
+        test.tone_offset_hz,
+        63.0 / 127.0,
+        waveforms::TRANSFER_SAMPLES,
+    ));
+    let mut pos = 0;
+    let tone = move |buf: &mut [u8]| -> bool {
+        for x in buf.iter_mut() {
+            *x = samples[pos];
+            pos = (pos + 1) % samples.len();
+        }
+        true
+    };
+    start_tx_owned(tx, Box::new(tone))?;
+    let measured = measure(rx);
+    let stopped = stop_tx(tx);
+    tx.tx_callback = None;
+    let power = measured?;
+    stopped?;
+
+    // Search for the tone away from the DC spike.
+    let bin_hz = test.sample_rate / FFT_SIZE as f64;
+    let (peak, tone_dbfs) = power
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i > DC_BINS && i < FFT_SIZE - DC_BINS)
+        .fold(
+            (0, f32::MIN),
+            |best, (i, &p)| if p > best.1 { (i, p) } else { best },
+        );
+    let signed = if peak < FFT_SIZE / 2 {
+        peak as f64
+    } else {
+        peak as f64 - FFT_SIZE as f64
+    };
+    let measured_offset_hz = signed * bin_hz;
+    let mut sorted = power.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let noise_dbfs = sorted[FFT_SIZE / 2];
+    let snr_db = tone_dbfs - noise_dbfs;
+
+    let mut warnings = Vec::new();
+    if tone_dbfs > MAX_TONE_DBFS {
+        warnings.push(format!(
+            "tone at {:.1}dBFS is close to clipping the receiver: add attenuation",
+            tone_dbfs
+        ));
+    }
+    let passed = (measured_offset_hz - test.tone_offset_hz).abs() <= test.offset_tolerance_hz
+        && snr_db >= test.min_snr_db;
+    Ok(SelfTestReport {
+        expected_offset_hz: test.tone_offset_hz,
+        measured_offset_hz,
+        tone_dbfs,
+        noise_dbfs,
+        snr_db,
+        warnings,
+        passed,
+    })
+}
+
+fn configure(device: &mut HackRFDevice, test: &SelfTest, bw: u32) -> Result<(), HackRFError> {
+    set_freq(device, test.freq_hz)?;
+    set_sample_rate(device, test.sample_rate)?;
+    set_baseband_filter_bandwidth(device, bw)?;
+    set_amp_enable(device, false)
+}
+
+// Average AVERAGES power spectra received on `rx`, in dBFS per bin,
+// corrected for the window as `spectrum` does.
+fn measure(rx: &mut HackRFDevice) -> Result<Vec<f32>, HackRFError> {
+    thread::sleep(SETTLE);
+    let stream = RxStream::start(rx, 16)?;
+    let mut spectrum = PowerSpectrum::new(FFT_SIZE);
+    let mut linear = vec![0.0f64; FFT_SIZE];
+    let mut averaged = 0;
+    let mut result = Ok(());
+    // The first transfer may predate the settled receiver.
+    let mut skip = true;
+    while averaged < AVERAGES {
+        let buf = match stream.recv_timeout(RECV_TIMEOUT) {
+            Ok(buf) => buf,
+            Err(_) => {
+                result = Err(hackrf_error(ffi::HACKRF_ERROR_STREAMING_STOPPED));
+                break;
+            }
+        };
+        if std::mem::replace(&mut skip, false) {
+            stream.recycle(buf);
+            continue;
+        }
+        for block in buf.chunks_exact(FFT_SIZE * 2).take(AVERAGES - averaged) {
+            for (sum, p) in linear.iter_mut().zip(spectrum.compute(block)) {
+                *sum += 10f64.powf(*p as f64 / 10.0);
+            }
+            averaged += 1;
+        }
+        stream.recycle(buf);
+    }
+    stream.stop(rx)?;
+    result?;
+    let correction_db = spectrum.window_correction_db();
+    Ok(linear
+        .iter()
+        .map(|p| (10.0 * (p / AVERAGES as f64).log10() + correction_db) as f32)
+        .collect())
+}
@@ -0,0 +1,35 @@
+// Stopping cleanly on Ctrl-C
+// Licensed under MIT license
+
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::{ffi, HackRFError, StopHandle};
+
+// Exit status for a second signal, as shells report death by SIGINT.
+const FORCED_EXIT: i32 = 130;
+
+/// Trigger the returned `StopHandle` on SIGINT or SIGTERM, or Ctrl-C and
+/// Ctrl-Break on Windows, instead of the process being killed mid-transfer
+/// with its last buffers unsent or a recording unfinished. Pass the handle
+/// to `transmit_file` and the like, poll it in receive loops, or hand it to
+/// `TxStream::stop_on` to let queued samples go out first. A second signal
+/// exits at once, in case shutting down hangs. Only one handler can be set
+/// in a process, so this fails if called twice.
+pub fn stop_on_ctrlc() -> Result<StopHandle, HackRFError> {
+    let stop = StopHandle::new();
+    let handler_stop = stop.clone();
+    let signalled = AtomicBool::new(false);
+    libctrlc::set_handler(move || {
+        if signalled.swap(true, Ordering::SeqCst) {
+            process::exit(FORCED_EXIT);
+        }
+        handler_stop.stop();
+    })
+    .map_err(|err| HackRFError {
+        errno: ffi::HACKRF_ERROR_OTHER,
+        errstr: format!("could not handle Ctrl-C: {}", err),
+        context: None,
+    })?;
+    Ok(stop)
+}
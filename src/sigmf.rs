@@ -0,0 +1,139 @@
+// Reading SigMF recordings
+// Licensed under MIT license
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use super::file::{transmit_file, FileSource};
+use super::format::SampleFormat;
+use super::{set_freq, set_sample_rate, HackRFDevice, HackRFError, StopHandle};
+
+/// One entry of a SigMF `captures` array.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Capture {
+    /// Index of the first sample this capture segment applies to.
+    pub sample_start: u64,
+    /// Centre frequency in Hz, if recorded.
+    pub frequency: Option<f64>,
+}
+
+/// A validated SigMF recording: a `.sigmf-meta` JSON file describing a
+/// `.sigmf-data` file of samples, or the file its `core:dataset` names.
+pub struct SigMF {
+    /// Sample format of the data file.
+    pub format: SampleFormat,
+    /// Sample rate in Hz.
+    pub sample_rate: f64,
+    /// Capture segments, sorted by `sample_start`. Always at least one.
+    pub captures: Vec<Capture>,
+    /// Path to the data file.
+    pub data_path: PathBuf,
+    /// The full metadata document, for fields not interpreted here.
+    pub metadata: Value,
+}
+
+impl SigMF {
+    /// Open a recording given the path to its `.sigmf-meta` or `.sigmf-data`
+    /// file, or the common base name of the pair, and validate the metadata.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SigMF, HackRFError> {
+        let path = path.as_ref();
+        let meta_path = path.with_extension("sigmf-meta");
+        let metadata: Value = serde_json::from_reader(io::BufReader::new(File::open(&meta_path)?))
+            .map_err(|e| invalid(&format!("{}: {}", meta_path.display(), e)))?;
+
+        let global = metadata
+            .get("global")
+            .ok_or_else(|| invalid("missing global object"))?;
+        let datatype = global
+            .get("core:datatype")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid("missing core:datatype"))?;
+        let format = match datatype {
+            "ci8" => SampleFormat::Cs8,
+            "ci16_le" => SampleFormat::Cs16,
+            "cf32_le" => SampleFormat::Cf32,
+            other => return Err(invalid(&format!("unsupported core:datatype {}", other))),
+        };
+        // A data file not named after the metadata is named in it.
+        let data_path = match global.get("core:dataset").and_then(Value::as_str) {
+            Some(dataset) => meta_path.with_file_name(dataset),
+            None => path.with_extension("sigmf-data"),
+        };
+        let sample_rate = global
+            .get("core:sample_rate")
+            .and_then(Value::as_f64)
+            .filter(|&rate| rate > 0.0)
+            .ok_or_else(|| invalid("missing or invalid core:sample_rate"))?;
+
+        let mut captures = Vec::new();
+        if let Some(list) = metadata.get("captures").and_then(Value::as_array) {
+            for capture in list {
+                let sample_start = capture
+                    .get("core:sample_start")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| invalid("capture missing core:sample_start"))?;
+                let frequency = capture.get("core:frequency").and_then(Value::as_f64);
+                captures.push(Capture {
+                    sample_start,
+                    frequency,
+                });
+            }
+        }
+        if captures.is_empty() {
+            return Err(invalid("no captures"));
+        }
+        captures.sort_by_key(|c| c.sample_start);
+
+        let data_len = std::fs::metadata(&data_path)?.len();
+        if data_len % format.bytes_per_sample() as u64 != 0 {
+            return Err(invalid("data file length is not a whole number of samples"));
+        }
+
+        Ok(SigMF {
+            format,
+            sample_rate,
+            captures,
+            data_path,
+            metadata,
+        })
+    }
+
+    /// Number of samples in the data file.
+    pub fn samples(&self) -> io::Result<u64> {
+        let len = std::fs::metadata(&self.data_path)?.len();
+        Ok(len / self.format.bytes_per_sample() as u64)
+    }
+
+    /// Set `device`'s sample rate, and centre frequency if the first capture
+    /// records one, to match the recording.
+    pub fn configure(&self, device: &mut HackRFDevice) -> Result<(), HackRFError> {
+        set_sample_rate(device, self.sample_rate)?;
+        if let Some(freq) = self.captures[0].frequency {
+            set_freq(device, freq.round() as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Open the data file as a `FileSource`, which reads as cs8 whatever the
+    /// recorded format, for offline processing or TX.
+    pub fn source(&self) -> io::Result<FileSource> {
+        FileSource::open(&self.data_path, self.format)
+    }
+
+    /// Configure `device` to match the recording and transmit it, blocking
+    /// until it has been sent or `stop` is triggered.
+    pub fn replay(&self, device: &mut HackRFDevice, stop: &StopHandle) -> Result<(), HackRFError> {
+        self.configure(device)?;
+        transmit_file(device, &mut self.source()?, stop)
+    }
+}
+
+fn invalid(msg: &str) -> HackRFError {
+    HackRFError::from(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid SigMF metadata: {}", msg),
+    ))
+}
@@ -0,0 +1,346 @@
+// Simulated device generating synthetic signals
+// Licensed under MIT license
+
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::format::SampleFormat;
+use super::sdr::{
+    find_stage, GainStage, RxCallback, RxChannel, SdrDevice, TxCallback, TxChannel, RX_GAIN_STAGES,
+    TX_GAIN_STAGES,
+};
+use super::{ffi, hackrf_error, HackRFError, StopHandle};
+
+// Bytes per simulated USB transfer, as libhackrf uses.
+const TRANSFER_LEN: usize = 262_144;
+
+/// A signal present on the air around a `SimBackend`. Frequencies are
+/// absolute RF frequencies in Hz and amplitudes are relative to full scale.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Signal {
+    /// An unmodulated carrier.
+    Tone { freq_hz: f64, amplitude: f64 },
+    /// A carrier amplitude modulated by a sine wave of `mod_hz`, with
+    /// `depth` between 0 and 1.
+    Am {
+        freq_hz: f64,
+        amplitude: f64,
+        mod_hz: f64,
+        depth: f64,
+    },
+    /// A carrier frequency modulated by a sine wave of `mod_hz`, peaking
+    /// at `deviation_hz` from the carrier.
+    Fm {
+        freq_hz: f64,
+        amplitude: f64,
+        mod_hz: f64,
+        deviation_hz: f64,
+    },
+    /// White Gaussian noise across the whole band, with `amplitude` RMS.
+    Noise { amplitude: f64 },
+}
+
+#[derive(Clone)]
+struct Shared {
+    freq_hz: u64,
+    sample_rate_hz: f64,
+    signals: Vec<Signal>,
+}
+
+type Locked<'a> = MutexGuard<'a, Shared>;
+
+/// A stand-in for a `HackRFDevice` that synthesises `Signal`s in real time
+/// instead of receiving them, so receivers can be developed and tested end
+/// to end without a board. RX callbacks run on a thread at the configured
+/// sample rate, and each signal appears at its offset from the current
+/// centre frequency, moving on retune and vanishing outside the band.
+/// Transmitted samples are consumed at the sample rate and discarded.
+/// Gains are validated but do not change the signal level.
+pub struct SimBackend {
+    shared: Arc<Mutex<Shared>>,
+    seed: u64,
+    rx: Option<(StopHandle, JoinHandle<()>)>,
+    tx: Option<(StopHandle, JoinHandle<()>)>,
+    tx_samples: Arc<AtomicU64>,
+}
+
+impl Default for SimBackend {
+    fn default() -> SimBackend {
+        SimBackend::new()
+    }
+}
+
+impl SimBackend {
+    /// A simulated device at the HackRF's power-on sample rate of 10Msps,
+    /// with nothing on the air.
+    pub fn new() -> SimBackend {
+        SimBackend {
+            shared: Arc::new(Mutex::new(Shared {
+                freq_hz: 0,
+                sample_rate_hz: 10e6,
+                signals: Vec::new(),
+            })),
+            seed: 0x2545_f491_4f6c_dd1d,
+            rx: None,
+            tx: None,
+            tx_samples: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Add `signal` to the air, taking effect from the next transfer.
+    pub fn add_signal(&mut self, signal: Signal) {
+        self.lock().signals.push(signal);
+    }
+
+    /// Remove every signal.
+    pub fn clear_signals(&mut self) {
+        self.lock().signals.clear();
+    }
+
+    /// Seed for the noise generator, so runs can be reproduced.
+    pub fn seed(mut self, seed: u64) -> SimBackend {
+        // Xorshift never leaves zero.
+        self.seed = seed.max(1);
+        self
+    }
+
+    /// Number of samples consumed from TX callbacks so far.
+    pub fn tx_samples(&self) -> u64 {
+        self.tx_samples.load(Ordering::Relaxed)
+    }
+
+    fn lock(&self) -> Locked<'_> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn check_idle(&mut self) -> Result<(), HackRFError> {
+        // Threads whose callbacks returned false have finished.
+        if self.rx.as_ref().is_some_and(|rx| rx.1.is_finished()) {
+            let _ = self.stop_rx();
+        }
+        if self.tx.as_ref().is_some_and(|tx| tx.1.is_finished()) {
+            let _ = self.stop_tx();
+        }
+        if self.rx.is_some() || self.tx.is_some() {
+            return Err(hackrf_error(ffi::HACKRF_ERROR_BUSY));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SimBackend {
+    fn drop(&mut self) {
+        let _ = self.stop_rx();
+        let _ = self.stop_tx();
+    }
+}
+
+// Xorshift64, enough for simulated noise without pulling in a crate.
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn uniform(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Box-Muller, one pair of independent unit Gaussians.
+    pub(crate) fn gaussian(&mut self) -> (f64, f64) {
+        let r = (-2.0 * (1.0 - self.uniform()).ln()).sqrt();
+        let theta = 2.0 * PI * self.uniform();
+        (r * theta.cos(), r * theta.sin())
+    }
+}
+
+/// Continuous state for generating a list of signals across transfers.
+struct Generator {
+    rng: Rng,
+    // Carrier and modulation phase per signal, in radians.
+    phases: Vec<(f64, f64)>,
+}
+
+impl Generator {
+    fn fill(&mut self, shared: &Shared, buf: &mut [u8]) {
+        let rate = shared.sample_rate_hz;
+        let centre = shared.freq_hz as f64;
+        self.phases.resize(shared.signals.len(), (0.0, 0.0));
+        for iq in buf.chunks_exact_mut(2) {
+            let (mut i, mut q) = (0.0, 0.0);
+            for (signal, phase) in shared.signals.iter().zip(self.phases.iter_mut()) {
+                let (amplitude, freq_hz, offset_hz) = match *signal {
+                    Signal::Tone { freq_hz, amplitude } => (amplitude, freq_hz, 0.0),
+                    Signal::Am {
+                        freq_hz,
+                        amplitude,
+                        mod_hz,
+                        depth,
+                    } => {
+                        phase.1 += 2.0 * PI * mod_hz / rate;
+                        (
+                            amplitude * (1.0 + depth * phase.1.sin()) / (1.0 + depth),
+                            freq_hz,
+                            0.0,
+                        )
+                    }
+                    Signal::Fm {
+                        freq_hz,
+                        amplitude,
+                        mod_hz,
+                        deviation_hz,
+                    } => {
+                        phase.1 += 2.0 * PI * mod_hz / rate;
+                        (amplitude, freq_hz, deviation_hz * phase.1.sin())
+                    }
+                    Signal::Noise { amplitude } => {
+                        let (ni, nq) = self.rng.gaussian();
+                        i += ni * amplitude / 2f64.sqrt();
+                        q += nq * amplitude / 2f64.sqrt();
+                        continue;
+                    }
+                };
+                phase.1 %= 2.0 * PI;
+                let baseband_hz = freq_hz - centre;
+                if baseband_hz.abs() >= rate / 2.0 {
+                    continue;
+                }
+                phase.0 = (phase.0 + 2.0 * PI * (baseband_hz + offset_hz) / rate) % (2.0 * PI);
+                i += amplitude * phase.0.cos();
+                q += amplitude * phase.0.sin();
+            }
+            iq[0] = (i * 127.0).round().clamp(-127.0, 127.0) as i8 as u8;
+            iq[1] = (q * 127.0).round().clamp(-127.0, 127.0) as i8 as u8;
+        }
+    }
+}
+
+// Run `transfer` once per TRANSFER_LEN bytes of samples, paced to the
+// sample rate, until it returns false or `stop` is triggered. Each call
+// sees the settings as they were when it began.
+fn paced<F: FnMut(&Shared) -> bool>(shared: &Mutex<Shared>, stop: &StopHandle, mut transfer: F) {
+    let mut due = Instant::now();
+    while !stop.is_stopped() {
+        let settings = shared
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if !transfer(&settings) {
+            break;
+        }
+        due += Duration::from_secs_f64((TRANSFER_LEN / 2) as f64 / settings.sample_rate_hz);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+impl SdrDevice for SimBackend {
+    type Error = HackRFError;
+
+    fn set_frequency(&mut self, hz: u64) -> Result<(), HackRFError> {
+        self.lock().freq_hz = hz;
+        Ok(())
+    }
+
+    fn frequency(&self) -> u64 {
+        self.lock().freq_hz
+    }
+
+    fn set_sample_rate(&mut self, hz: f64) -> Result<(), HackRFError> {
+        if hz.is_nan() || hz <= 0.0 {
+            return Err(hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM));
+        }
+        self.lock().sample_rate_hz = hz;
+        Ok(())
+    }
+
+    fn sample_rate(&self) -> f64 {
+        self.lock().sample_rate_hz
+    }
+
+    fn native_format(&self) -> SampleFormat {
+        SampleFormat::Cs8
+    }
+}
+
+impl RxChannel for SimBackend {
+    fn rx_gain_stages(&self) -> &'static [GainStage] {
+        &RX_GAIN_STAGES
+    }
+
+    fn set_rx_gain(&mut self, name: &str, db: f64) -> Result<(), HackRFError> {
+        find_stage(&RX_GAIN_STAGES, name, db).map(|_| ())
+    }
+
+    fn start_rx(&mut self, mut callback: RxCallback) -> Result<(), HackRFError> {
+        self.check_idle()?;
+        let shared = self.shared.clone();
+        let stop = StopHandle::new();
+        let thread_stop = stop.clone();
+        let mut generator = Generator {
+            rng: Rng(self.seed),
+            phases: Vec::new(),
+        };
+        let thread = thread::Builder::new()
+            .name("hackrf-sim-rx".to_string())
+            .spawn(move || {
+                let mut buf = vec![0; TRANSFER_LEN];
+                paced(&shared, &thread_stop, |shared| {
+                    generator.fill(shared, &mut buf);
+                    callback(&buf)
+                });
+            })?;
+        self.rx = Some((stop, thread));
+        Ok(())
+    }
+
+    fn stop_rx(&mut self) -> Result<(), HackRFError> {
+        if let Some((stop, thread)) = self.rx.take() {
+            stop.stop();
+            thread.join().expect("simulated RX thread panicked");
+        }
+        Ok(())
+    }
+}
+
+impl TxChannel for SimBackend {
+    fn tx_gain_stages(&self) -> &'static [GainStage] {
+        &TX_GAIN_STAGES
+    }
+
+    fn set_tx_gain(&mut self, name: &str, db: f64) -> Result<(), HackRFError> {
+        find_stage(&TX_GAIN_STAGES, name, db).map(|_| ())
+    }
+
+    fn start_tx(&mut self, mut callback: TxCallback) -> Result<(), HackRFError> {
+        self.check_idle()?;
+        let shared = self.shared.clone();
+        let stop = StopHandle::new();
+        let thread_stop = stop.clone();
+        let tx_samples = self.tx_samples.clone();
+        let thread = thread::Builder::new()
+            .name("hackrf-sim-tx".to_string())
+            .spawn(move || {
+                let mut buf = vec![0; TRANSFER_LEN];
+                paced(&shared, &thread_stop, |_| {
+                    let more = callback(&mut buf);
+                    tx_samples.fetch_add(TRANSFER_LEN as u64 / 2, Ordering::Relaxed);
+                    more
+                });
+            })?;
+        self.tx = Some((stop, thread));
+        Ok(())
+    }
+
+    fn stop_tx(&mut self) -> Result<(), HackRFError> {
+        if let Some((stop, thread)) = self.tx.take() {
+            stop.stop();
+            thread.join().expect("simulated TX thread panicked");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+// Noise and PRBS transmit sources for receiver testing
+// Licensed under MIT license
+
+use super::decimate::{lowpass, to_cs8};
+use super::sdr::TxCallback;
+use super::sim::Rng;
+
+// Taps in the filter limiting the noise bandwidth.
+const NOISE_TAPS: usize = 63;
+
+/// Length of the sequence a `PrbsSource` sends, as the ITU-T O.150 test
+/// patterns: PRBS7 is x^7 + x^6 + 1, and so on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PrbsOrder {
+    Prbs7,
+    Prbs9,
+    Prbs15,
+    Prbs23,
+    Prbs31,
+}
+
+impl PrbsOrder {
+    // Length of the register and the tap fed back with its last stage.
+    fn taps(self) -> (u32, u32) {
+        match self {
+            PrbsOrder::Prbs7 => (7, 6),
+            PrbsOrder::Prbs9 => (9, 5),
+            PrbsOrder::Prbs15 => (15, 14),
+            PrbsOrder::Prbs23 => (23, 18),
+            PrbsOrder::Prbs31 => (31, 28),
+        }
+    }
+}
+
+/// Band-limited white Gaussian noise centred on the tuned frequency, for
+/// noise-loading receivers and measuring shielding effectiveness with a
+/// flat test signal.
+pub struct NoiseSource {
+    rng: Rng,
+    // Filter and its recent input, or none for the full sample rate.
+    taps: Option<Vec<f32>>,
+    history: Vec<[f32; 2]>,
+    sigma: f64,
+}
+
+impl NoiseSource {
+    /// Noise `bandwidth_hz` wide at `sample_rate`, at a quarter of full
+    /// scale RMS.
+    pub fn new(sample_rate: f64, bandwidth_hz: f64) -> NoiseSource {
+        assert!(bandwidth_hz > 0.0 && bandwidth_hz <= sample_rate);
+        let taps = if bandwidth_hz < sample_rate {
+            let taps = lowpass(NOISE_TAPS, bandwidth_hz / sample_rate / 2.0);
+            // Keep the RMS level the same after filtering.
+            let gain = taps.iter().map(|&t| t * t).sum::<f32>().sqrt();
+            Some(taps.into_iter().map(|t| t / gain).collect())
+        } else {
+            None
+        };
+        NoiseSource {
+            rng: Rng(0x2545_f491_4f6c_dd1d),
+            taps,
+            history: vec![[0.0; 2]; NOISE_TAPS],
+            sigma: 0.0,
+        }
+        .amplitude(0.25)
+    }
+
+    /// RMS level relative to full scale, 0.0-1.0. Peaks run to about four
+    /// times this and are clipped.
+    pub fn amplitude(mut self, amplitude: f64) -> NoiseSource {
+        assert!((0.0..=1.0).contains(&amplitude));
+        self.sigma = 127.0 * amplitude / 2f64.sqrt();
+        self
+    }
+
+    /// Seed for the noise generator, so runs can be reproduced.
+    pub fn seed(mut self, seed: u64) -> NoiseSource {
+        // Xorshift never leaves zero.
+        self.rng = Rng(seed.max(1));
+        self
+    }
+
+    /// Fill `tx` with cs8 noise.
+    pub fn fill(&mut self, tx: &mut [u8]) {
+        for iq in tx.chunks_exact_mut(2) {
+            let (i, q) = self.rng.gaussian();
+            let sample = [(i * self.sigma) as f32, (q * self.sigma) as f32];
+            let [i, q] = match self.taps {
+                Some(ref taps) => {
+                    self.history.rotate_left(1);
+                    self.history[NOISE_TAPS - 1] = sample;
+                    taps.iter()
+                        .zip(&self.history)
+                        .fold([0.0; 2], |acc, (t, x)| {
+                            [acc[0] + t * x[0], acc[1] + t * x[1]]
+                        })
+                }
+                None => sample,
+            };
+            iq[0] = to_cs8(i);
+            iq[1] = to_cs8(q);
+        }
+    }
+
+    /// A TX callback that sends noise until the stream is stopped.
+    pub fn callback(mut self) -> TxCallback {
+        Box::new(move |tx: &mut [u8]| -> bool {
+            self.fill(tx);
+            true
+        })
+    }
+}
+
+/// A pseudo-random bit sequence sent as BPSK chips with square edges, whose
+/// spectrum is a sinc squared with its first nulls the chip rate either
+/// side of the tuned frequency, for bit error rate tests and as a wideband
+/// test signal.
+pub struct PrbsSource {
+    order: PrbsOrder,
+    state: u32,
+    // Chips per sample, and how far through the current chip.
+    step: f64,
+    position: f64,
+    level: f32,
+}
+
+impl PrbsSource {
+    /// Send `order` at `chip_rate` chips per second, for samples at
+    /// `sample_rate`, at 70% of full scale.
+    pub fn new(sample_rate: f64, chip_rate: f64, order: PrbsOrder) -> PrbsSource {
+        assert!(chip_rate > 0.0 && chip_rate <= sample_rate);
+        PrbsSource {
+            order,
+            state: (1 << order.taps().0) - 1,
+            step: chip_rate / sample_rate,
+            position: 0.0,
+            level: 0.0,
+        }
+        .amplitude(0.7)
+    }
+
+    /// Level relative to full scale, 0.0-1.0.
+    pub fn amplitude(mut self, amplitude: f64) -> PrbsSource {
+        assert!((0.0..=1.0).contains(&amplitude));
+        self.level = (127.0 * amplitude) as f32;
+        self
+    }
+
+    /// The next bit of the sequence.
+    pub fn next_bit(&mut self) -> bool {
+        let (length, tap) = self.order.taps();
+        let bit = ((self.state >> (length - 1)) ^ (self.state >> (tap - 1))) & 1;
+        self.state = ((self.state << 1) | bit) & ((1 << length) - 1);
+        bit == 1
+    }
+
+    /// Fill `tx` with cs8 chips, on I only.
+    pub fn fill(&mut self, tx: &mut [u8]) {
+        for iq in tx.chunks_exact_mut(2) {
+            let level = if self.state & 1 == 1 {
+                self.level
+            } else {
+                -self.level
+            };
+            iq[0] = to_cs8(level);
+            iq[1] = 0;
+            self.position += self.step;
+            if self.position >= 1.0 {
+                self.position -= 1.0;
+                self.next_bit();
+            }
+        }
+    }
+
+    /// A TX callback that sends the sequence until the stream is stopped.
+    pub fn callback(mut self) -> TxCallback {
+        Box::new(move |tx: &mut [u8]| -> bool {
+            self.fill(tx);
+            true
+        })
+    }
+}
@@ -0,0 +1,138 @@
+// Reading and writing the SPI flash that holds the firmware
+// Licensed under MIT license
+
+use super::{ffi, HackRFDevice, HackRFError};
+
+/// Size of the SPI flash, in bytes.
+pub const FLASH_SIZE: usize = 0x10_0000;
+
+// Largest transfer libhackrf accepts, and the flash's page size.
+const CHUNK_LEN: usize = 256;
+
+fn invalid(msg: String) -> HackRFError {
+    HackRFError {
+        errno: ffi::HACKRF_ERROR_INVALID_PARAM,
+        errstr: msg,
+        context: None,
+    }
+}
+
+fn check_range(address: u32, len: usize) -> Result<(), HackRFError> {
+    if address as usize > FLASH_SIZE || len > FLASH_SIZE - address as usize {
+        return Err(invalid(format!(
+            "{} bytes at 0x{:x} runs past the end of the {} byte flash",
+            len, address, FLASH_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// Erase the whole flash. Writes can only clear bits, so erase before
+/// writing a new image.
+pub fn erase(device: &mut HackRFDevice) -> Result<(), HackRFError> {
+    match unsafe { ffi::hackrf_spiflash_erase(device.ptr) } {
+        ffi::HACKRF_SUCCESS => Ok(()),
+        err => Err(device.error(err, "spiflash::erase", format_args!(""))),
+    }
+}
+
+/// Fill `buf` with flash contents starting at `address`.
+pub fn read(device: &mut HackRFDevice, address: u32, buf: &mut [u8]) -> Result<(), HackRFError> {
+    check_range(address, buf.len())?;
+    for (i, chunk) in buf.chunks_mut(CHUNK_LEN).enumerate() {
+        let addr = address + (i * CHUNK_LEN) as u32;
+        match unsafe {
+            ffi::hackrf_spiflash_read(device.ptr, addr, chunk.len() as u16, chunk.as_mut_ptr())
+        } {
+            ffi::HACKRF_SUCCESS => (),
+            err => {
+                return Err(device.error(
+                    err,
+                    "spiflash::read",
+                    format_args!("address={:#x}, len={}", addr, chunk.len()),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write `data` to the flash starting at `address`, which must have been
+/// erased first.
+pub fn write(device: &mut HackRFDevice, address: u32, data: &[u8]) -> Result<(), HackRFError> {
+    check_range(address, data.len())?;
+    for (i, chunk) in data.chunks(CHUNK_LEN).enumerate() {
+        let addr = address + (i * CHUNK_LEN) as u32;
+        match unsafe {
+            ffi::hackrf_spiflash_write(
+                device.ptr,
+                addr,
+                chunk.len() as u16,
+                chunk.as_ptr() as *mut u8,
+            )
+        } {
+            ffi::HACKRF_SUCCESS => (),
+            err => {
+                return Err(device.error(
+                    err,
+                    "spiflash::write",
+                    format_args!("address={:#x}, len={}", addr, chunk.len()),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read the flash's two status registers.
+pub fn status(device: &mut HackRFDevice) -> Result<[u8; 2], HackRFError> {
+    let mut data = [0; 2];
+    match unsafe { ffi::hackrf_spiflash_status(device.ptr, data.as_mut_ptr()) } {
+        ffi::HACKRF_SUCCESS => Ok(data),
+        err => Err(device.error(err, "spiflash::status", format_args!(""))),
+    }
+}
+
+/// Clear the flash's status registers.
+pub fn clear_status(device: &mut HackRFDevice) -> Result<(), HackRFError> {
+    match unsafe { ffi::hackrf_spiflash_clear_status(device.ptr) } {
+        ffi::HACKRF_SUCCESS => Ok(()),
+        err => Err(device.error(err, "spiflash::clear_status", format_args!(""))),
+    }
+}
+
+/// Check that `image` is plausibly firmware to be written at `address`,
+/// catching mistakes that would leave the board unbootable: images that
+/// don't fit, DFU files instead of the raw `.bin`, and (at address 0) data
+/// without a Cortex-M vector table at the start.
+pub fn check_image(address: u32, image: &[u8]) -> Result<(), HackRFError> {
+    if image.is_empty() {
+        return Err(invalid("image is empty".to_string()));
+    }
+    check_range(address, image.len())?;
+    // DFU files end in a 16 byte suffix containing "UFD".
+    if image.len() >= 16 && &image[image.len() - 8..image.len() - 5] == b"UFD" {
+        return Err(invalid(
+            "image is a DFU file; write the .bin firmware instead".to_string(),
+        ));
+    }
+    if address == 0 {
+        if image.len() < 8 {
+            return Err(invalid("image is too short to be firmware".to_string()));
+        }
+        let word = |i: usize| {
+            let mut b = [0; 4];
+            b.copy_from_slice(&image[i * 4..i * 4 + 4]);
+            u32::from_le_bytes(b)
+        };
+        // Initial stack pointer must be in SRAM, reset handler Thumb code.
+        let sp = word(0);
+        let reset = word(1);
+        if !(0x1000_0000..=0x2001_0000).contains(&sp) || reset & 1 == 0 {
+            return Err(invalid(
+                "image doesn't start with a vector table; is it HackRF firmware?".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,526 @@
+// Queue-based RX and TX streams
+// Licensed under MIT license
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TryRecvError, TrySendError};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::events::{self, EventSender, Events, Overruns, StopReason, StreamEvent};
+use super::format::{invert_spectrum, SampleFormat};
+use super::pool::BufferPool;
+use super::ramp::{Ramp, RampHandle};
+use super::sdr::{RxChannel, SdrDevice, TxCallback, TxChannel};
+use super::wake::{self, Consumer, Wake};
+use super::StopHandle;
+
+// Longest a ramped `TxStream` waits to fall silent when stopping.
+const RAMP_DOWN_TIMEOUT: Duration = Duration::from_secs(1);
+// How often `TxStream::stop_on` checks its `StopHandle`.
+const STOP_POLL: Duration = Duration::from_millis(10);
+
+// The bytes a stream may still carry, for `max_duration`.
+pub(crate) struct Budget {
+    remaining: Option<u64>,
+}
+
+impl Budget {
+    // Enough for `max_duration` at `rate`, or unlimited.
+    pub(crate) fn new(max_duration: Option<Duration>, rate: f64, format: SampleFormat) -> Budget {
+        Budget {
+            remaining: max_duration.map(|duration| {
+                let samples = (duration.as_secs_f64() * rate).round() as u64;
+                samples * format.bytes_per_sample() as u64
+            }),
+        }
+    }
+
+    // Budget for `device`'s current sample rate and format.
+    fn for_device<D: SdrDevice + ?Sized>(max_duration: Option<Duration>, device: &D) -> Budget {
+        Budget::new(max_duration, device.sample_rate(), device.native_format())
+    }
+
+    // How much of a transfer of `len` bytes to carry: all of it, part of
+    // the last one, or none once spent.
+    pub(crate) fn take(&mut self, len: usize) -> usize {
+        match self.remaining {
+            Some(ref mut remaining) => {
+                let n = (*remaining).min(len as u64);
+                *remaining -= n;
+                n as usize
+            }
+            None => len,
+        }
+    }
+
+    pub(crate) fn is_spent(&self) -> bool {
+        self.remaining == Some(0)
+    }
+}
+
+/// Receives into a bounded queue of buffers that the caller pulls from at
+/// its own pace, instead of processing samples inside the USB callback.
+/// When the queue is full, incoming transfers are dropped and counted.
+/// Works with any `RxChannel`.
+pub struct RxStream {
+    full: Consumer<Vec<u8>>,
+    free: Arc<BufferPool>,
+    dropped: Arc<AtomicU64>,
+    invert: Arc<AtomicBool>,
+    events: Events,
+    event_tx: EventSender,
+    stopped: Arc<AtomicBool>,
+}
+
+/// Configures and starts an `RxStream`.
+pub struct RxStreamBuilder {
+    buffers: usize,
+    wake: Wake,
+    preallocate: bool,
+    max_duration: Option<Duration>,
+}
+
+impl RxStreamBuilder {
+    /// How the thread calling `recv` is woken when a transfer arrives.
+    /// Defaults to `Wake::Condvar`.
+    pub fn wake(mut self, wake: Wake) -> RxStreamBuilder {
+        self.wake = wake;
+        self
+    }
+
+    /// Allocate every buffer the stream can use before it starts, so that
+    /// as long as the caller hands buffers back with `recycle`, receiving
+    /// never allocates. Otherwise they are allocated as needed, and reused
+    /// once recycled. Defaults to off.
+    pub fn preallocate(mut self, preallocate: bool) -> RxStreamBuilder {
+        self.preallocate = preallocate;
+        self
+    }
+
+    /// Stop after this long's worth of samples at the sample rate set when
+    /// the stream starts, so that a scripted capture ends even if nothing
+    /// stops it. The last transfer is cut short to deliver exactly that
+    /// many samples, counting any dropped, then `recv` returns `None` once
+    /// the queue is empty. Defaults to no limit.
+    pub fn max_duration(mut self, max_duration: Duration) -> RxStreamBuilder {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Begin receiving.
+    pub fn start<D: RxChannel + ?Sized>(self, device: &mut D) -> Result<RxStream, D::Error> {
+        let (full_tx, full_rx) = wake::channel(self.buffers, self.wake);
+        // Room for a full queue, one buffer being filled and one the
+        // caller is working on.
+        let free = Arc::new(BufferPool::new(self.buffers + 2, self.preallocate));
+        let cb_free = free.clone();
+        let dropped = Arc::new(AtomicU64::new(0));
+        let cb_dropped = dropped.clone();
+        let invert = Arc::new(AtomicBool::new(false));
+        let cb_invert = invert.clone();
+        let (event_tx, events) = events::channel();
+        let cb_events = event_tx.clone();
+        let mut overruns = Overruns::default();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let cb_stopped = stopped.clone();
+        let mut budget = Budget::for_device(self.max_duration, device);
+        // Dropped, ending the stream, once the budget is spent.
+        let mut full_tx = Some(full_tx);
+        let callback = move |rx: &[u8]| -> bool {
+            let queue = match full_tx {
+                Some(ref queue) => queue,
+                None => return false,
+            };
+            let rx = &rx[..budget.take(rx.len())];
+            let mut buf = cb_free.take().unwrap_or_default();
+            buf.clear();
+            buf.extend_from_slice(rx);
+            if cb_invert.load(Ordering::Relaxed) {
+                invert_spectrum(&mut buf);
+            }
+            let more = match queue.try_send(buf) {
+                Ok(()) => {
+                    overruns.delivered(&cb_events);
+                    true
+                }
+                Err(TrySendError::Full(buf)) => {
+                    cb_dropped.fetch_add(1, Ordering::Relaxed);
+                    overruns.lost(&cb_events);
+                    #[cfg(feature = "metrics")]
+                    super::metrics::dropped_buffer();
+                    #[cfg(feature = "log")]
+                    super::logging::overrun();
+                    cb_free.give(buf);
+                    true
+                }
+                // The stream has been dropped or closed.
+                Err(TrySendError::Disconnected(_)) => false,
+            };
+            if more && !budget.is_spent() {
+                return true;
+            }
+            full_tx = None;
+            if !cb_stopped.swap(true, Ordering::Relaxed) {
+                overruns.delivered(&cb_events);
+                cb_events.send(StreamEvent::Stopped {
+                    reason: StopReason::Finished,
+                });
+            }
+            false
+        };
+        // Sent first, as the callback may send events at once. Dropped
+        // with the stream if starting fails.
+        event_tx.send(StreamEvent::Started);
+        device.start_rx(Box::new(callback))?;
+        Ok(RxStream {
+            full: full_rx,
+            free,
+            dropped,
+            invert,
+            events,
+            event_tx,
+            stopped,
+        })
+    }
+}
+
+impl RxStream {
+    /// Configure a stream queueing up to `buffers` transfers; finish with
+    /// `RxStreamBuilder::start`.
+    pub fn builder(buffers: usize) -> RxStreamBuilder {
+        assert!(buffers > 0);
+        RxStreamBuilder {
+            buffers,
+            wake: Wake::default(),
+            preallocate: false,
+            max_duration: None,
+        }
+    }
+
+    /// Begin receiving, queueing up to `buffers` transfers.
+    pub fn start<D: RxChannel + ?Sized>(
+        device: &mut D,
+        buffers: usize,
+    ) -> Result<RxStream, D::Error> {
+        RxStream::builder(buffers).start(device)
+    }
+
+    /// Whether to invert the spectrum of samples delivered from now on, by
+    /// swapping I and Q, for tools that expect the opposite convention.
+    pub fn set_invert_spectrum(&self, invert: bool) {
+        self.invert.store(invert, Ordering::Relaxed);
+    }
+
+    /// Wait for the next buffer of samples. Returns `None` once the stream
+    /// has been stopped, has reached its `max_duration` or been closed, and
+    /// the queue is empty. A device that stops by itself, as on a USB
+    /// error, keeps the callback and so the queue open, so this would wait
+    /// for ever: use `recv_timeout` and check the device, as with
+    /// `is_streaming`, or have whatever notices call `close`.
+    pub fn recv(&self) -> Option<Vec<u8>> {
+        self.full.recv_deadline(None).ok()
+    }
+
+    /// As `recv`, giving up after `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Vec<u8>, RecvTimeoutError> {
+        self.full.recv_deadline(Some(Instant::now() + timeout))
+    }
+
+    /// Stop taking samples: waiting `recv` calls, on any thread, return
+    /// `None` once the queue drains, and the callback asks the device to
+    /// stop. For a watchdog that has found the device stopped.
+    pub fn close(&self) {
+        self.full.close();
+    }
+
+    /// Take the next buffer if one is queued, without waiting, for event
+    /// loops that check for samples once a frame.
+    pub fn try_recv(&self) -> Result<Vec<u8>, TryRecvError> {
+        self.full.try_recv()
+    }
+
+    /// Take the next buffer if one is queued, or have the task in `cx`
+    /// woken when one arrives. `Ready(None)` once the stream has stopped and
+    /// the queue is empty. Lets an async executor or hand-rolled event loop
+    /// wait on the stream without an async runtime built in.
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<Vec<u8>>> {
+        self.full.poll_recv(cx)
+    }
+
+    /// Wait until at least `samples` samples are queued, or `timeout`
+    /// passes, or the stream stops, and return the number queued, without
+    /// taking any. For loops that collect a block, process it and repeat.
+    /// Transfers dropped meanwhile because the queue was full are not
+    /// counted, so ask for no more than `buffers` transfers hold.
+    pub fn wait(&self, samples: usize, timeout: Duration) -> usize {
+        self.full.wait_queued(samples * 2, Instant::now() + timeout) / 2
+    }
+
+    /// Hand a buffer back for reuse, avoiding an allocation per transfer.
+    pub fn recycle(&self, buf: Vec<u8>) {
+        self.free.give(buf);
+    }
+
+    /// Number of transfers dropped so far because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The stream's `StreamEvent`s, starting with `Started`. Only the first
+    /// call returns the channel. Events go on being sent after the stream
+    /// is dropped, ending with `Stopped`.
+    pub fn events(&self) -> Option<Receiver<StreamEvent>> {
+        self.events.take()
+    }
+
+    /// Stop receiving. Buffers still queued are discarded.
+    pub fn stop<D: RxChannel + ?Sized>(self, device: &mut D) -> Result<(), D::Error> {
+        let result = device.stop_rx();
+        if !self.stopped.swap(true, Ordering::Relaxed) {
+            self.event_tx.send(StreamEvent::Stopped {
+                reason: StopReason::Requested,
+            });
+        }
+        result
+    }
+}
+
+/// Transmits from a bounded queue of buffers that the caller pushes at its
+/// own pace. Whenever the queue runs dry, silence is sent and counted as an
+/// underrun. Works with any `TxChannel`.
+pub struct TxStream {
+    full: SyncSender<Vec<u8>>,
+    underruns: Arc<AtomicU64>,
+    // Buffers sent but not yet fully handed to the device.
+    pending: Arc<AtomicU64>,
+    ramp: Option<RampHandle>,
+    invert: Arc<AtomicBool>,
+    events: Events,
+    event_tx: EventSender,
+}
+
+/// Configures and starts a `TxStream`.
+pub struct TxStreamBuilder {
+    buffers: usize,
+    ramp: Option<Ramp>,
+    max_duration: Option<Duration>,
+}
+
+impl TxStreamBuilder {
+    /// Shape the start of transmission with `ramp`, and its end when the
+    /// stream is stopped. Defaults to none.
+    pub fn ramp(mut self, ramp: Ramp) -> TxStreamBuilder {
+        self.ramp = Some(ramp);
+        self
+    }
+
+    /// Stop after this long's worth of samples at the sample rate set when
+    /// the stream starts, whether queued or silence sent on underruns, so
+    /// that a scripted transmission ends even if nothing stops it. The last
+    /// transfer is padded with silence after exactly that many samples.
+    /// Defaults to no limit.
+    pub fn max_duration(mut self, max_duration: Duration) -> TxStreamBuilder {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Begin transmitting.
+    pub fn start<D: TxChannel + ?Sized>(self, device: &mut D) -> Result<TxStream, D::Error> {
+        let (full_tx, full_rx) = mpsc::sync_channel::<Vec<u8>>(self.buffers);
+        let underruns = Arc::new(AtomicU64::new(0));
+        let cb_underruns = underruns.clone();
+        let pending = Arc::new(AtomicU64::new(0));
+        let cb_pending = pending.clone();
+        let invert = Arc::new(AtomicBool::new(false));
+        let cb_invert = invert.clone();
+        let (event_tx, events) = events::channel();
+        let cb_events = event_tx.clone();
+        let mut dry = false;
+        let mut budget = Budget::for_device(self.max_duration, device);
+        let mut current: Vec<u8> = Vec::new();
+        let mut pos = 0;
+        let callback = move |tx: &mut [u8]| -> bool {
+            // The transfer carrying the last samples is still submitted;
+            // only the following one, all silence, ends the stream.
+            if budget.is_spent() {
+                tx.fill(0);
+                return false;
+            }
+            let len = budget.take(tx.len());
+            let (tx, after) = tx.split_at_mut(len);
+            after.fill(0);
+            let mut filled = 0;
+            while filled < tx.len() {
+                if pos == current.len() {
+                    match full_rx.try_recv() {
+                        Ok(buf) => {
+                            if buf.is_empty() {
+                                cb_pending.fetch_sub(1, Ordering::Relaxed);
+                            }
+                            current = buf;
+                            pos = 0;
+                            continue;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                let n = (current.len() - pos).min(tx.len() - filled);
+                tx[filled..filled + n].copy_from_slice(&current[pos..pos + n]);
+                filled += n;
+                pos += n;
+                if pos == current.len() {
+                    cb_pending.fetch_sub(1, Ordering::Relaxed);
+                }
+            }
+            if filled < tx.len() {
+                cb_underruns.fetch_add(1, Ordering::Relaxed);
+                #[cfg(feature = "metrics")]
+                super::metrics::tx_underrun();
+                #[cfg(feature = "log")]
+                super::logging::underrun();
+                tx[filled..].fill(0);
+                if !dry {
+                    cb_events.send(StreamEvent::Underrun);
+                }
+            }
+            dry = filled < tx.len();
+            if cb_invert.load(Ordering::Relaxed) {
+                invert_spectrum(tx);
+            }
+            true
+        };
+        let handle = self.ramp.as_ref().map(Ramp::handle);
+        let callback: TxCallback = match self.ramp {
+            Some(ramp) => ramp.wrap(Box::new(callback)),
+            None => Box::new(callback),
+        };
+        // Before the callback can send anything, as for `RxStream`.
+        event_tx.send(StreamEvent::Started);
+        device.start_tx(callback)?;
+        Ok(TxStream {
+            full: full_tx,
+            underruns,
+            pending,
+            ramp: handle,
+            invert,
+            events,
+            event_tx,
+        })
+    }
+}
+
+impl TxStream {
+    /// Configure a stream queueing up to `buffers` buffers of any size;
+    /// finish with `TxStreamBuilder::start`.
+    pub fn builder(buffers: usize) -> TxStreamBuilder {
+        assert!(buffers > 0);
+        TxStreamBuilder {
+            buffers,
+            ramp: None,
+            max_duration: None,
+        }
+    }
+
+    /// Begin transmitting, queueing up to `buffers` buffers of any size.
+    pub fn start<D: TxChannel + ?Sized>(
+        device: &mut D,
+        buffers: usize,
+    ) -> Result<TxStream, D::Error> {
+        TxStream::builder(buffers).start(device)
+    }
+
+    /// As `start`, shaping the start of transmission with `ramp`, and its
+    /// end when the stream is stopped.
+    pub fn start_ramped<D: TxChannel + ?Sized>(
+        device: &mut D,
+        buffers: usize,
+        ramp: Ramp,
+    ) -> Result<TxStream, D::Error> {
+        TxStream::builder(buffers).ramp(ramp).start(device)
+    }
+
+    /// Whether to invert the spectrum of samples sent from now on, by
+    /// swapping I and Q, for sources that use the opposite convention.
+    pub fn set_invert_spectrum(&self, invert: bool) {
+        self.invert.store(invert, Ordering::Relaxed);
+    }
+
+    /// Queue samples for transmission, waiting while the queue is full.
+    /// Returns the buffer if the stream has stopped.
+    pub fn send(&self, buf: Vec<u8>) -> Result<(), Vec<u8>> {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.full.send(buf).map_err(|err| {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            err.0
+        })
+    }
+
+    /// Queue samples for transmission if there is room, otherwise return
+    /// them.
+    pub fn try_send(&self, buf: Vec<u8>) -> Result<(), Vec<u8>> {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.full.try_send(buf).map_err(|err| {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            match err {
+                TrySendError::Full(buf) | TrySendError::Disconnected(buf) => buf,
+            }
+        })
+    }
+
+    /// Wait up to `timeout` for everything queued to be handed to the
+    /// device, so that stopping does not cut off the end of a signal.
+    /// Returns `false` on timeout.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.pending.load(Ordering::Relaxed) > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        true
+    }
+
+    /// Number of USB transfers so far that had to be padded with silence.
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    /// The stream's `StreamEvent`s, starting with `Started`. Only the first
+    /// call returns the channel.
+    pub fn events(&self) -> Option<Receiver<StreamEvent>> {
+        self.events.take()
+    }
+
+    /// Keep transmitting until `stop` is triggered, as by `stop_on_ctrlc`,
+    /// then give what is queued up to `flush_timeout` to be sent, so the
+    /// end of the signal is not cut off, and stop. Whatever feeds the stream
+    /// should stop sending when `stop` is triggered too.
+    pub fn stop_on<D: TxChannel + ?Sized>(
+        self,
+        device: &mut D,
+        stop: &StopHandle,
+        flush_timeout: Duration,
+    ) -> Result<(), D::Error> {
+        while !stop.is_stopped() {
+            thread::sleep(STOP_POLL);
+        }
+        self.flush(flush_timeout);
+        self.stop(device)
+    }
+
+    /// Stop transmitting. Buffers still queued are discarded. A ramped
+    /// stream ramps down to silence first.
+    pub fn stop<D: TxChannel + ?Sized>(self, device: &mut D) -> Result<(), D::Error> {
+        if let Some(ref ramp) = self.ramp {
+            ramp.ramp_down_wait(RAMP_DOWN_TIMEOUT);
+        }
+        let result = device.stop_tx();
+        self.event_tx.send(StreamEvent::Stopped {
+            reason: StopReason::Requested,
+        });
+        result
+    }
+}
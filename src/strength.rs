@@ -0,0 +1,221 @@
+// Long-running signal strength logging
+// Licensed under MIT license
+
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::config::DeviceConfig;
+use super::stream::RxStream;
+use super::time::UtcTime;
+use super::{ffi, hackrf_error, open_by_serial, set_freq, HackRFDevice, HackRFError, StopHandle};
+
+// How long to wait for samples before counting the board as lost.
+const RECV_TIMEOUT: Duration = Duration::from_secs(2);
+// Bounds of the delay between attempts to reopen the board.
+const MIN_RETRY: Duration = Duration::from_millis(250);
+const MAX_RETRY: Duration = Duration::from_secs(5);
+// Granularity of waits, so that stopping is prompt.
+const POLL: Duration = Duration::from_millis(50);
+
+/// How a `StrengthLogger` writes its measurements.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Lines of `date, time, freq_hz, power_dbfs`, with times in UTC.
+    Csv,
+    /// One JSON object per line, with `time`, `freq_hz` and `power_dbfs`.
+    JsonLines,
+}
+
+/// Records the power received on a list of frequencies at a regular
+/// interval, for propagation and interference studies that run for days.
+/// Each measurement is the average power over a block of samples, across
+/// the whole band set by the sample rate and baseband filter, less the DC
+/// offset. If the board fails or is unplugged it is reopened by serial
+/// number, with the `DeviceConfig` reapplied, and logging carries on;
+/// measurements due meanwhile are skipped.
+#[derive(Clone, Debug)]
+pub struct StrengthLogger {
+    freqs_hz: Vec<u64>,
+    interval: Duration,
+    settle: Duration,
+    samples: usize,
+    format: LogFormat,
+    config: DeviceConfig,
+}
+
+impl StrengthLogger {
+    /// Measure each of `freqs_hz` in turn every `interval`.
+    pub fn new(freqs_hz: Vec<u64>, interval: Duration) -> StrengthLogger {
+        assert!(!freqs_hz.is_empty());
+        StrengthLogger {
+            freqs_hz,
+            interval,
+            settle: Duration::from_millis(20),
+            samples: 65536,
+            format: LogFormat::Csv,
+            config: DeviceConfig::default(),
+        }
+    }
+
+    /// Time to wait after retuning before measuring. Defaults to 20ms.
+    pub fn settle(mut self, settle: Duration) -> StrengthLogger {
+        self.settle = settle;
+        self
+    }
+
+    /// Number of samples averaged for each measurement. Defaults to 65536.
+    pub fn samples(mut self, samples: usize) -> StrengthLogger {
+        assert!(samples > 0);
+        self.samples = samples;
+        self
+    }
+
+    pub fn format(mut self, format: LogFormat) -> StrengthLogger {
+        self.format = format;
+        self
+    }
+
+    /// Settings for the board, applied when it is opened and reopened.
+    /// Its frequency is ignored.
+    pub fn config(mut self, config: DeviceConfig) -> StrengthLogger {
+        self.config = config;
+        self
+    }
+
+    /// Open the board with serial number `serial` and log to `out` until
+    /// `stop` is triggered. Fails if the board cannot be set up the first
+    /// time, or on an error writing to `out`.
+    pub fn run<W: Write>(
+        &self,
+        serial: &str,
+        mut out: W,
+        stop: &StopHandle,
+    ) -> Result<(), HackRFError> {
+        let mut device = Some(self.open(serial)?);
+        let mut next = Instant::now();
+        while !stop.is_stopped() {
+            for &freq_hz in &self.freqs_hz {
+                if stop.is_stopped() {
+                    break;
+                }
+                let measured = match device {
+                    Some(ref mut device) => self.measure_at(device, freq_hz),
+                    None => break,
+                };
+                match measured {
+                    Ok(power_dbfs) => self.write(&mut out, freq_hz, power_dbfs)?,
+                    Err(_) => {
+                        // Close the old handle before reopening.
+                        drop(device.take());
+                        device = self.reopen(serial, stop);
+                        break;
+                    }
+                }
+            }
+            out.flush()?;
+            next += self.interval;
+            while !stop.is_stopped() && Instant::now() < next {
+                thread::sleep(POLL.min(next.saturating_duration_since(Instant::now())));
+            }
+            // Skip rounds missed while the board was away.
+            next = next.max(Instant::now());
+        }
+        Ok(())
+    }
+
+    fn open(&self, serial: &str) -> Result<HackRFDevice, HackRFError> {
+        let mut device = open_by_serial(serial)?;
+        self.config.apply(&mut device)?;
+        Ok(device)
+    }
+
+    // Reopen the board, retrying until it works or logging is stopped.
+    fn reopen(&self, serial: &str, stop: &StopHandle) -> Option<HackRFDevice> {
+        let mut retry = MIN_RETRY;
+        loop {
+            let mut waited = Duration::from_secs(0);
+            while waited < retry {
+                if stop.is_stopped() {
+                    return None;
+                }
+                thread::sleep(POLL);
+                waited += POLL;
+            }
+            if let Ok(device) = self.open(serial) {
+                return Some(device);
+            }
+            retry = (retry * 2).min(MAX_RETRY);
+        }
+    }
+
+    fn write<W: Write>(&self, out: &mut W, freq_hz: u64, power_dbfs: f64) -> io::Result<()> {
+        let t = UtcTime::new(SystemTime::now());
+        let date = format!("{:04}-{:02}-{:02}", t.year, t.month, t.day);
+        let time = format!(
+            "{:02}:{:02}:{:02}.{:06}",
+            t.hour, t.minute, t.second, t.micros
+        );
+        match self.format {
+            LogFormat::Csv => writeln!(out, "{}, {}, {}, {:.2}", date, time, freq_hz, power_dbfs),
+            LogFormat::JsonLines => writeln!(
+                out,
+                "{{\"time\":\"{}T{}Z\",\"freq_hz\":{},\"power_dbfs\":{:.2}}}",
+                date, time, freq_hz, power_dbfs
+            ),
+        }
+    }
+
+    // Tune to `freq_hz`, let the synthesiser settle and measure.
+    fn measure_at(&self, device: &mut HackRFDevice, freq_hz: u64) -> Result<f64, HackRFError> {
+        set_freq(device, freq_hz)?;
+        thread::sleep(self.settle);
+        self.measure(device)
+    }
+
+    // Receive `samples` samples and return their power in dBFS, less DC.
+    fn measure(&self, device: &mut HackRFDevice) -> Result<f64, HackRFError> {
+        let collected = capture(device, self.samples)?;
+        let n = self.samples as f64;
+        let (mut mean_i, mut mean_q) = (0.0, 0.0);
+        for iq in collected.chunks_exact(2) {
+            mean_i += iq[0] as i8 as f64 / n;
+            mean_q += iq[1] as i8 as f64 / n;
+        }
+        let power: f64 = collected
+            .chunks_exact(2)
+            .map(|iq| {
+                let i = iq[0] as i8 as f64 - mean_i;
+                let q = iq[1] as i8 as f64 - mean_q;
+                i * i + q * q
+            })
+            .sum::<f64>()
+            / n;
+        Ok(10.0 * (power / (128.0 * 128.0)).log10())
+    }
+}
+
+// Receive `samples` cs8 samples, skipping the first transfer, which may
+// predate the last change of settings.
+pub(crate) fn capture(device: &mut HackRFDevice, samples: usize) -> Result<Vec<u8>, HackRFError> {
+    let stream = RxStream::start(device, 4)?;
+    let mut collected: Vec<u8> = Vec::with_capacity(samples * 2);
+    let mut result = Ok(());
+    let mut skip = true;
+    while collected.len() < samples * 2 {
+        let buf = match stream.recv_timeout(RECV_TIMEOUT) {
+            Ok(buf) => buf,
+            Err(_) => {
+                result = Err(hackrf_error(ffi::HACKRF_ERROR_STREAMING_STOPPED));
+                break;
+            }
+        };
+        if !std::mem::replace(&mut skip, false) {
+            let n = std::cmp::min(buf.len(), samples * 2 - collected.len());
+            collected.extend_from_slice(&buf[..n]);
+        }
+        stream.recycle(buf);
+    }
+    stream.stop(device)?;
+    result.map(|()| collected)
+}
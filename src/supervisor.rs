@@ -0,0 +1,246 @@
+// Receiving that survives USB errors by reopening the board
+// Licensed under MIT license
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::config::DeviceConfig;
+use super::events::{self, EventSender, Events, StopReason, StreamEvent};
+use super::watchdog::{Health, Stall};
+use super::{
+    is_streaming, open_by_serial, start_rx_owned, stop_rx, HackRFDevice, HackRFError, StopHandle,
+};
+
+// How often the stream is checked, and the bounds of the delay between
+// attempts to reopen the board.
+const POLL: Duration = Duration::from_millis(100);
+const MIN_RETRY: Duration = Duration::from_millis(250);
+const MAX_RETRY: Duration = Duration::from_secs(5);
+// A stream with no transfers for this long has stalled.
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What a `Supervisor` passes to its callback.
+#[derive(Debug)]
+pub enum Supervised<'a> {
+    /// A buffer of cs8 samples, as an RX callback receives.
+    Samples(&'a [u8]),
+    /// The stream failed and has been restarted: samples before and after
+    /// are not contiguous. Carries how long the stream was down.
+    Gap(Duration),
+}
+
+type Callback = Box<dyn FnMut(Supervised) -> bool + Send>;
+
+fn lock(callback: &Mutex<Callback>) -> MutexGuard<'_, Callback> {
+    callback.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Keeps a board receiving unattended. When streaming stops on its own,
+/// as it does on a USB error or when the board is unplugged, or stalls
+/// with no transfers arriving for a couple of seconds, the board is
+/// closed and reopened by serial number, the `DeviceConfig` is reapplied
+/// and receiving resumes, with a `Supervised::Gap` passed to the callback
+/// first. Reopening is retried, backing off to every few seconds, until it
+/// succeeds or the supervisor is stopped.
+pub struct Supervisor {
+    stop: StopHandle,
+    thread: Option<JoinHandle<()>>,
+    reconnects: Arc<AtomicU64>,
+    events: Events,
+}
+
+impl Supervisor {
+    /// Open the board with serial number `serial`, as listed by
+    /// `device_list`, apply `config` and start receiving into `callback`,
+    /// which runs on libhackrf's streaming thread. Receiving ends when the
+    /// callback returns false or the supervisor is stopped. Fails if the
+    /// board cannot be set up the first time.
+    pub fn start_rx<F>(
+        serial: &str,
+        config: DeviceConfig,
+        callback: F,
+    ) -> Result<Supervisor, HackRFError>
+    where
+        F: FnMut(Supervised) -> bool + Send + 'static,
+    {
+        let callback: Arc<Mutex<Callback>> = Arc::new(Mutex::new(Box::new(callback)));
+        let ended = Arc::new(AtomicBool::new(false));
+        let mut health = Health::new(STALL_TIMEOUT);
+        let mut device = open_by_serial(serial)?;
+        config.apply(&mut device)?;
+        start(&mut device, &health, &callback, &ended)?;
+        health.reset();
+        let (event_tx, events) = events::channel();
+        event_tx.send(StreamEvent::Started);
+
+        let stop = StopHandle::new();
+        let reconnects = Arc::new(AtomicU64::new(0));
+        let session = Session {
+            serial: serial.to_string(),
+            config,
+            callback,
+            ended,
+            stop: stop.clone(),
+            reconnects: reconnects.clone(),
+            health,
+            events: event_tx,
+        };
+        let thread = thread::Builder::new()
+            .name("hackrf-supervisor".to_string())
+            .spawn(move || session.run(device))?;
+        Ok(Supervisor {
+            stop,
+            thread: Some(thread),
+            reconnects,
+            events,
+        })
+    }
+
+    /// Number of times the board has been reopened.
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+
+    /// The stream's `StreamEvent`s: `Started`, then for each failure any
+    /// `UsbError` behind it, `Stopped` and, once the board has been
+    /// reopened, `Reconnected`, and a last `Stopped` if receiving ends while
+    /// the board is up. Only the first call returns the channel.
+    pub fn events(&self) -> Option<Receiver<StreamEvent>> {
+        self.events.take()
+    }
+
+    /// Whether receiving has ended, because the callback returned false.
+    pub fn is_finished(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_none_or(|thread| thread.is_finished())
+    }
+
+    /// Stop receiving and close the board.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.stop();
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("supervisor thread panicked");
+        }
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn start(
+    device: &mut HackRFDevice,
+    health: &Health,
+    callback: &Arc<Mutex<Callback>>,
+    ended: &Arc<AtomicBool>,
+) -> Result<(), HackRFError> {
+    let callback = callback.clone();
+    let ended = ended.clone();
+    start_rx_owned(
+        device,
+        health.watch(Box::new(move |buf: &[u8]| -> bool {
+            let more = lock(&callback)(Supervised::Samples(buf));
+            if !more {
+                ended.store(true, Ordering::SeqCst);
+            }
+            more
+        })),
+    )
+}
+
+struct Session {
+    serial: String,
+    config: DeviceConfig,
+    callback: Arc<Mutex<Callback>>,
+    ended: Arc<AtomicBool>,
+    stop: StopHandle,
+    reconnects: Arc<AtomicU64>,
+    health: Health,
+    events: EventSender,
+}
+
+impl Session {
+    fn run(mut self, mut device: HackRFDevice) {
+        loop {
+            // Watch the stream until it stops or stalls without being asked
+            // to. Shortfalls are lost samples, but the stream carries on.
+            while !self.stop.is_stopped()
+                && !self.ended.load(Ordering::SeqCst)
+                && !matches!(
+                    self.health.check(&mut device),
+                    Some(Stall::NotStreaming) | Some(Stall::NoProgress(_))
+                )
+            {
+                thread::sleep(POLL);
+            }
+            if self.stop.is_stopped() || self.ended.load(Ordering::SeqCst) {
+                let _ = stop_rx(&mut device);
+                let reason = match self.stop.is_stopped() {
+                    true => StopReason::Requested,
+                    false => StopReason::Finished,
+                };
+                self.events.send(StreamEvent::Stopped { reason });
+                return;
+            }
+            // Before stopping, after which libhackrf only reports that.
+            if let Err(err) = is_streaming(&mut device) {
+                self.events.send(StreamEvent::UsbError(err));
+            }
+            let _ = stop_rx(&mut device);
+            self.events.send(StreamEvent::Stopped {
+                reason: StopReason::Failed,
+            });
+            let down = Instant::now();
+            drop(device);
+            device = match self.reopen(down) {
+                Some(device) => device,
+                None => return,
+            };
+            self.events.send(StreamEvent::Reconnected {
+                gap: down.elapsed(),
+            });
+            self.reconnects.fetch_add(1, Ordering::Relaxed);
+            self.health.reset();
+        }
+    }
+
+    // Reopen and restart the board, retrying until it works or the
+    // supervisor is stopped, and report the gap since `down`.
+    fn reopen(&self, down: Instant) -> Option<HackRFDevice> {
+        let mut retry = MIN_RETRY;
+        loop {
+            let mut waited = Duration::from_secs(0);
+            while waited < retry {
+                if self.stop.is_stopped() {
+                    return None;
+                }
+                thread::sleep(POLL);
+                waited += POLL;
+            }
+            if let Ok(mut device) = open_by_serial(&self.serial) {
+                if self.config.apply(&mut device).is_ok() {
+                    // Hold the callback until the gap has been reported, so
+                    // no samples from the new stream can arrive before it.
+                    let mut callback = lock(&self.callback);
+                    if start(&mut device, &self.health, &self.callback, &self.ended).is_ok() {
+                        if !callback(Supervised::Gap(down.elapsed())) {
+                            self.ended.store(true, Ordering::SeqCst);
+                        }
+                        return Some(device);
+                    }
+                }
+            }
+            retry = (retry * 2).min(MAX_RETRY);
+        }
+    }
+}
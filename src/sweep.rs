@@ -0,0 +1,212 @@
+// Firmware frequency sweeps
+// Licensed under MIT license
+
+#[cfg(feature = "fft")]
+use std::io::{self, Write};
+#[cfg(feature = "fft")]
+use std::time::SystemTime;
+
+#[cfg(feature = "fft")]
+use super::fft::PowerSpectrum;
+#[cfg(feature = "fft")]
+use super::time::UtcTime;
+use super::{ffi, rx_cb, HackRFDevice, HackRFError};
+
+/// Size of each block of samples in a sweep transfer, including its header.
+pub const BYTES_PER_BLOCK: usize = ffi::BYTES_PER_BLOCK;
+
+/// Length of the header at the start of each sweep block.
+const BLOCK_HEADER_LEN: usize = 10;
+
+/// Order in which the firmware visits tuning steps within each range.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SweepStyle {
+    /// Step through each range in `step_width` increments.
+    Linear,
+    /// Alternate between two offsets within each step, as `hackrf_sweep`
+    /// does, so the whole range is covered while skipping the DC spike.
+    Interleaved,
+}
+
+/// Configure the firmware to sweep repeatedly over `ranges_mhz`, pairs of
+/// start and stop frequencies in MHz (at most 10 pairs).
+/// At each tuning step it captures `num_bytes` (a multiple of
+/// `BYTES_PER_BLOCK`) of samples, then retunes by `step_width_hz`.
+/// `offset_hz` is added to each tuning frequency so that the wanted part of
+/// the spectrum lands away from DC.
+/// Call `start_rx_sweep` afterwards to begin sweeping.
+pub fn init_sweep(
+    device: &mut HackRFDevice,
+    ranges_mhz: &[(u16, u16)],
+    num_bytes: u32,
+    step_width_hz: u32,
+    offset_hz: u32,
+    style: SweepStyle,
+) -> Result<(), HackRFError> {
+    assert!(!ranges_mhz.is_empty() && ranges_mhz.len() <= ffi::MAX_SWEEP_RANGES);
+    assert!(num_bytes > 0 && (num_bytes as usize).is_multiple_of(BYTES_PER_BLOCK));
+    let list: Vec<u16> = ranges_mhz.iter().flat_map(|&(a, b)| vec![a, b]).collect();
+    let style = match style {
+        SweepStyle::Linear => ffi::LINEAR,
+        SweepStyle::Interleaved => ffi::INTERLEAVED,
+    };
+    match unsafe {
+        ffi::hackrf_init_sweep(
+            device.ptr,
+            list.as_ptr(),
+            ranges_mhz.len() as i32,
+            num_bytes,
+            step_width_hz,
+            offset_hz,
+            style,
+        )
+    } {
+        ffi::HACKRF_SUCCESS => Ok(()),
+        err => Err(device.error(
+            err,
+            "init_sweep",
+            format_args!(
+                "ranges_mhz={:?}, num_bytes={}, step_width_hz={}, offset_hz={}",
+                ranges_mhz, num_bytes, step_width_hz, offset_hz
+            ),
+        )),
+    }
+}
+
+/// Begin a sweep configured with `init_sweep`.
+/// `callback` behaves as for `start_rx`, but each buffer it is given holds
+/// several blocks, each from a single tuning step; split them with
+/// `sweep_blocks`. Stop sweeping with `stop_rx`.
+pub fn start_rx_sweep(
+    device: &mut HackRFDevice,
+    callback: &mut dyn FnMut(&[u8]) -> bool,
+) -> Result<(), HackRFError> {
+    let reference = Box::leak(Box::new(callback));
+    let ctx = reference as *mut &mut dyn FnMut(&[u8]) -> bool as *mut std::ffi::c_void;
+    match unsafe { ffi::hackrf_start_rx_sweep(device.ptr, rx_cb, ctx) } {
+        ffi::HACKRF_SUCCESS => Ok(()),
+        err => Err(device.error(err, "start_rx_sweep", format_args!(""))),
+    }
+}
+
+/// One block of a sweep transfer: samples captured at a single tuning step.
+pub struct SweepBlock<'a> {
+    /// Frequency the radio was tuned to for this block, in Hz.
+    pub frequency_hz: u64,
+    /// The cs8 samples following the block header.
+    pub samples: &'a [u8],
+}
+
+/// Split a buffer from a sweep callback into its blocks, skipping any
+/// without a valid header.
+pub fn sweep_blocks<'a>(buffer: &'a [u8]) -> impl Iterator<Item = SweepBlock<'a>> {
+    buffer.chunks_exact(BYTES_PER_BLOCK).filter_map(|block| {
+        if block[0] != 0x7F || block[1] != 0x7F {
+            return None;
+        }
+        let mut freq = [0; 8];
+        freq.copy_from_slice(&block[2..10]);
+        Some(SweepBlock {
+            frequency_hz: u64::from_le_bytes(freq),
+            samples: &block[BLOCK_HEADER_LEN..],
+        })
+    })
+}
+
+/// One line of `hackrf_sweep` output: power across part of the spectrum.
+#[cfg(feature = "fft")]
+#[derive(Clone, Debug)]
+pub struct SweepRow {
+    pub timestamp: SystemTime,
+    pub hz_low: u64,
+    pub hz_high: u64,
+    pub bin_width_hz: f64,
+    pub fft_size: usize,
+    /// Power in each bin from `hz_low` upwards, in dB.
+    pub power_db: Vec<f32>,
+}
+
+#[cfg(feature = "fft")]
+impl SweepRow {
+    /// Write this row as a line of `hackrf_sweep` CSV:
+    /// `date, time, hz_low, hz_high, hz_bin_width, num_samples, dB, dB, ...`.
+    /// Times are in UTC.
+    pub fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let t = UtcTime::new(self.timestamp);
+        write!(
+            out,
+            "{:04}-{:02}-{:02}, {:02}:{:02}:{:02}.{:06}, {}, {}, {:.2}, {}",
+            t.year,
+            t.month,
+            t.day,
+            t.hour,
+            t.minute,
+            t.second,
+            t.micros,
+            self.hz_low,
+            self.hz_high,
+            self.bin_width_hz,
+            self.fft_size
+        )?;
+        for p in &self.power_db {
+            write!(out, ", {:.2}", p)?;
+        }
+        writeln!(out)
+    }
+}
+
+/// Turns sweep blocks into `hackrf_sweep`-style power rows.
+/// Expects an `Interleaved` sweep whose offset is 3/8 of the sample rate
+/// (7.5MHz at 20Msps), from which each block yields two quarter-bandwidth
+/// rows clear of the DC spike and the filter edges.
+#[cfg(feature = "fft")]
+pub struct SweepProcessor {
+    spectrum: PowerSpectrum,
+    sample_rate_hz: u32,
+}
+
+#[cfg(feature = "fft")]
+impl SweepProcessor {
+    /// Choose the FFT size for the requested bin width the same way
+    /// `hackrf_sweep` does.
+    pub fn new(sample_rate_hz: u32, bin_width_hz: u32) -> SweepProcessor {
+        let max = (BYTES_PER_BLOCK - BLOCK_HEADER_LEN) / 2;
+        let mut size = (sample_rate_hz / bin_width_hz.max(1)) as usize;
+        size = size.clamp(4, max - 8);
+        while !(size + 4).is_multiple_of(8) {
+            size += 1;
+        }
+        SweepProcessor {
+            spectrum: PowerSpectrum::new(size),
+            sample_rate_hz,
+        }
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.spectrum.size()
+    }
+
+    pub fn bin_width_hz(&self) -> f64 {
+        self.sample_rate_hz as f64 / self.fft_size() as f64
+    }
+
+    /// Compute the two rows for `block`, using the last `fft_size()` samples
+    /// so the radio has settled after retuning.
+    pub fn process(&mut self, block: &SweepBlock, timestamp: SystemTime) -> [SweepRow; 2] {
+        let n = self.fft_size();
+        let bin_width_hz = self.bin_width_hz();
+        let samples = &block.samples[block.samples.len() - n * 2..];
+        let power = self.spectrum.compute(samples);
+        let rate = self.sample_rate_hz as u64;
+        let f = block.frequency_hz;
+        let row = |hz_low: u64, first_bin: usize| SweepRow {
+            timestamp,
+            hz_low,
+            hz_high: hz_low + rate / 4,
+            bin_width_hz,
+            fft_size: n,
+            power_db: power[first_bin..first_bin + n / 4].to_vec(),
+        };
+        [row(f, 1 + n * 5 / 8), row(f + rate / 2, 1 + n / 8)]
+    }
+}
@@ -0,0 +1,31 @@
+// Raw bindings generated from the installed hackrf.h
+// Licensed under MIT license
+
+//! Raw libhackrf bindings generated at build time from the installed
+//! header, covering every entry point whether or not it is wrapped here.
+
+#![allow(
+    non_camel_case_types,
+    non_snake_case,
+    non_upper_case_globals,
+    dead_code,
+    clashing_extern_declarations,
+    clippy::all
+)]
+
+include!(concat!(env!("OUT_DIR"), "/hackrf_sys.rs"));
+
+// Fail the build if the hand-written bindings used by the safe API have
+// drifted from the installed library. Function signatures are checked
+// where they are declared, in ffi.rs.
+use std::mem::size_of;
+
+use super::ffi;
+
+const _: () = assert!(size_of::<ffi::hackrf_transfer>() == size_of::<hackrf_transfer>());
+const _: () =
+    assert!(size_of::<ffi::read_partid_serialno_t>() == size_of::<read_partid_serialno_t>());
+const _: () = assert!(size_of::<ffi::hackrf_device_list_t>() == size_of::<hackrf_device_list_t>());
+const _: () = assert!(size_of::<ffi::hackrf_m0_state>() == size_of::<hackrf_m0_state>());
+const _: () = assert!(ffi::HACKRF_ERROR_BUSY == HACKRF_ERROR_BUSY as i32);
+const _: () = assert!(ffi::HACKRF_ERROR_OTHER == HACKRF_ERROR_OTHER as i32);
@@ -0,0 +1,158 @@
+// Time-division duplex frames of RX and TX slots
+// Licensed under MIT license
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::turnaround::HalfDuplex;
+use super::{HackRFDevice, HackRFError, StopHandle};
+
+// Longest single sleep while waiting for a slot boundary, so that stopping
+// is prompt.
+const POLL: Duration = Duration::from_millis(10);
+
+fn lock<T>(callback: &Mutex<T>) -> MutexGuard<'_, T> {
+    callback.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// One slot in a `TddScheduler` frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Slot {
+    Rx(Duration),
+    Tx(Duration),
+}
+
+impl Slot {
+    fn duration(self) -> Duration {
+        match self {
+            Slot::Rx(duration) | Slot::Tx(duration) => duration,
+        }
+    }
+}
+
+/// Runs a fixed frame of RX and TX slots over and over on one board, for
+/// simple TDMA experiments. Each slot is followed by a guard interval with
+/// the board idle, during which it switches direction; the guard must
+/// cover that, which takes a few milliseconds over USB. Slot boundaries
+/// are kept to the host clock from the start of the first frame, to within
+/// the scheduling jitter of the host, and a slot whose start has already
+/// passed, after a slow switch, is skipped rather than shortened.
+#[derive(Clone, Debug)]
+pub struct TddScheduler {
+    slots: Vec<Slot>,
+    guard: Duration,
+}
+
+impl TddScheduler {
+    /// Run frames made of `slots`, in order.
+    pub fn new(slots: Vec<Slot>) -> TddScheduler {
+        assert!(!slots.is_empty());
+        TddScheduler {
+            slots,
+            guard: Duration::from_millis(5),
+        }
+    }
+
+    /// Idle time after each slot. Defaults to 5ms.
+    pub fn guard(mut self, guard: Duration) -> TddScheduler {
+        self.guard = guard;
+        self
+    }
+
+    /// Length of a frame: every slot and its guard interval.
+    pub fn frame_period(&self) -> Duration {
+        self.slots
+            .iter()
+            .map(|slot| slot.duration() + self.guard)
+            .sum()
+    }
+
+    /// Run frames until `stop` is triggered or a callback returns false.
+    /// `rx` is called with the frame number, the index of the slot in the
+    /// frame and the samples of each transfer in RX slots, and `tx` likewise
+    /// to fill each transfer in TX slots.
+    pub fn run<R, T>(
+        &self,
+        device: &mut HackRFDevice,
+        stop: &StopHandle,
+        rx: R,
+        tx: T,
+    ) -> Result<(), HackRFError>
+    where
+        R: FnMut(u64, usize, &[u8]) -> bool + Send + 'static,
+        T: FnMut(u64, usize, &mut [u8]) -> bool + Send + 'static,
+    {
+        let rx = Arc::new(Mutex::new(rx));
+        let tx = Arc::new(Mutex::new(tx));
+        let ended = Arc::new(AtomicBool::new(false));
+        let mut session = HalfDuplex::new();
+        // Leave a guard interval before the first slot too.
+        let mut start = Instant::now() + self.guard;
+        let mut result = Ok(());
+        'frames: for frame in 0u64.. {
+            for (index, &slot) in self.slots.iter().enumerate() {
+                let end = start + slot.duration();
+                if Instant::now() <= start {
+                    if !wait_until(start, stop, &ended) {
+                        break 'frames;
+                    }
+                    let switched = match slot {
+                        Slot::Rx(_) => {
+                            let (rx, ended) = (rx.clone(), ended.clone());
+                            session.switch_to_rx(
+                                device,
+                                Box::new(move |buf: &[u8]| -> bool {
+                                    let more = lock(&rx)(frame, index, buf);
+                                    ended.fetch_or(!more, Ordering::SeqCst);
+                                    more
+                                }),
+                            )
+                        }
+                        Slot::Tx(_) => {
+                            let (tx, ended) = (tx.clone(), ended.clone());
+                            session.switch_to_tx(
+                                device,
+                                Box::new(move |buf: &mut [u8]| -> bool {
+                                    let more = lock(&tx)(frame, index, buf);
+                                    ended.fetch_or(!more, Ordering::SeqCst);
+                                    more
+                                }),
+                            )
+                        }
+                    };
+                    if let Err(err) = switched {
+                        result = Err(err);
+                        break 'frames;
+                    }
+                    let waited = wait_until(end, stop, &ended);
+                    if let Err(err) = session.stop(device) {
+                        result = Err(err);
+                        break 'frames;
+                    }
+                    if !waited {
+                        break 'frames;
+                    }
+                }
+                start = end + self.guard;
+            }
+        }
+        let stopped = session.stop(device);
+        result.and(stopped)
+    }
+}
+
+// Sleep until `at`, returning false early if stopped or ended.
+fn wait_until(at: Instant, stop: &StopHandle, ended: &AtomicBool) -> bool {
+    loop {
+        if stop.is_stopped() || ended.load(Ordering::SeqCst) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= at {
+            return true;
+        }
+        thread::sleep((at - now).min(POLL));
+    }
+}
@@ -0,0 +1,147 @@
+// Fanning the RX stream out to several consumers
+// Licensed under MIT license
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::pool::SharedPool;
+use super::sdr::{RxCallback, RxChannel};
+use super::wake::{self, Consumer, Producer, Wake};
+
+/// What a `Tee` does with a transfer for a consumer whose queue is full.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Drop the new transfer, keeping the queue as it was. Suits consumers
+    /// that want unbroken runs of samples, such as demodulators.
+    #[default]
+    DropNewest,
+    /// Drop the oldest queued transfer to make room, so the consumer always
+    /// has the latest samples. Suits displays such as waterfalls.
+    DropOldest,
+}
+
+struct Output {
+    queue: Producer<Arc<[u8]>>,
+    capacity: usize,
+    overflow: Overflow,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Fans one RX stream out to several independent consumers, say a
+/// recorder, a demodulator and a waterfall, each pulling from its own
+/// queue at its own pace. A consumer that falls behind loses transfers as
+/// its `Overflow` says, counted in its `dropped`, without holding up the
+/// others. Each transfer is copied once and shared between the queues,
+/// into a buffer reused once every consumer has dropped it. Add consumers,
+/// then start the stream with `start` or `wrap`; receiving stops once every
+/// consumer has been dropped.
+#[derive(Default)]
+pub struct Tee {
+    outputs: Vec<Output>,
+    preallocate: bool,
+}
+
+/// One consumer's end of a `Tee`.
+pub struct TeeConsumer {
+    queue: Consumer<Arc<[u8]>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Tee {
+    pub fn new() -> Tee {
+        Tee::default()
+    }
+
+    /// Allocate every buffer the consumers' queues can hold before
+    /// streaming starts, so that receiving never allocates as long as
+    /// consumers drop transfers once done with them. Otherwise buffers are
+    /// allocated as the queues first fill. Defaults to off.
+    pub fn preallocate(mut self, preallocate: bool) -> Tee {
+        self.preallocate = preallocate;
+        self
+    }
+
+    /// Add a consumer queueing up to `buffers` transfers, handling overflow
+    /// as `overflow` says.
+    pub fn consumer(&mut self, buffers: usize, overflow: Overflow) -> TeeConsumer {
+        assert!(buffers > 0);
+        let (producer, consumer) = wake::channel(buffers, Wake::default());
+        let dropped = Arc::new(AtomicU64::new(0));
+        self.outputs.push(Output {
+            queue: producer,
+            capacity: buffers,
+            overflow,
+            dropped: dropped.clone(),
+        });
+        TeeConsumer {
+            queue: consumer,
+            dropped,
+        }
+    }
+
+    /// Turn the tee into an RX callback feeding its consumers, for use
+    /// with `RxChannel::start_rx` and the adapters built on it.
+    pub fn wrap(mut self) -> RxCallback {
+        // Each queue full, a transfer being worked on by each consumer and
+        // one being filled.
+        let queued: usize = self.outputs.iter().map(|output| output.capacity).sum();
+        let mut pool = SharedPool::new(queued + self.outputs.len() + 1, self.preallocate);
+        Box::new(move |rx: &[u8]| -> bool {
+            let shared = pool.copy(rx);
+            // Consumers that have been dropped are removed.
+            self.outputs.retain(|output| {
+                let (kept, dropped) = match output.overflow {
+                    Overflow::DropNewest => match output.queue.try_send(shared.clone()) {
+                        Ok(()) => (true, false),
+                        Err(TrySendError::Full(_)) => (true, true),
+                        Err(TrySendError::Disconnected(_)) => (false, false),
+                    },
+                    Overflow::DropOldest => match output.queue.send_evicting(shared.clone()) {
+                        Ok(evicted) => (true, evicted.is_some()),
+                        Err(_) => (false, false),
+                    },
+                };
+                if dropped {
+                    output.dropped.fetch_add(1, Ordering::Relaxed);
+                    #[cfg(feature = "metrics")]
+                    super::metrics::dropped_buffer();
+                    #[cfg(feature = "log")]
+                    super::logging::overrun();
+                }
+                kept
+            });
+            !self.outputs.is_empty()
+        })
+    }
+
+    /// Begin receiving into the consumers. Stop with `RxChannel::stop_rx`.
+    pub fn start<D: RxChannel + ?Sized>(self, device: &mut D) -> Result<(), D::Error> {
+        device.start_rx(self.wrap())
+    }
+}
+
+impl TeeConsumer {
+    /// Wait for the next transfer. Returns `None` once the stream has
+    /// stopped and the queue is empty.
+    pub fn recv(&self) -> Option<Arc<[u8]>> {
+        self.queue.recv_deadline(None).ok()
+    }
+
+    /// As `recv`, giving up after `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Arc<[u8]>, RecvTimeoutError> {
+        self.queue.recv_deadline(Some(Instant::now() + timeout))
+    }
+
+    /// Take the next transfer if one is queued, without waiting.
+    pub fn try_recv(&self) -> Result<Arc<[u8]>, TryRecvError> {
+        self.queue.try_recv()
+    }
+
+    /// Number of transfers this consumer has lost because its queue was
+    /// full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,203 @@
+// Scheduling of the crate's worker threads
+// Licensed under MIT license
+
+use std::io;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+/// Scheduling for a thread that must keep up with the sample stream.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Priority {
+    /// Whatever the OS gives ordinary threads.
+    #[default]
+    Normal,
+    /// Real-time scheduling, so that other busy processes cannot hold the
+    /// thread up long enough to drop samples: `SCHED_FIFO` on Linux, just
+    /// below the kernel's threaded interrupt handlers so USB is still
+    /// serviced first, and the MMCSS "Pro Audio" class on Windows. Linux
+    /// needs `CAP_SYS_NICE` or an `rtprio` limit in
+    /// `/etc/security/limits.conf`. Not available elsewhere.
+    RealTime,
+}
+
+// SCHED_FIFO priority, under the 50 that threaded IRQ handlers run at.
+#[cfg(target_os = "linux")]
+const FIFO_PRIORITY: i32 = 40;
+
+/// Move the calling thread to `priority`. Fails, leaving the thread as it
+/// was, if the OS refuses, as when the process lacks the permission for
+/// real-time scheduling, or on platforms without it. Useful for threads
+/// consuming an `RxStream`.
+pub fn set_thread_priority(priority: Priority) -> io::Result<()> {
+    match priority {
+        Priority::Normal => Ok(()),
+        Priority::RealTime => os::set_realtime(),
+    }
+}
+
+/// Restrict the calling thread to running on `cores`, numbered from 0 as
+/// the OS numbers them. Keeping a busy thread on one core, away from others
+/// doing heavy work, saves it being migrated and its caches going cold.
+/// Fails, leaving the thread as it was, if a core does not exist or on
+/// platforms other than Linux and Windows.
+pub fn set_thread_affinity(cores: &[usize]) -> io::Result<()> {
+    if cores.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no cores given",
+        ));
+    }
+    os::set_affinity(cores)
+}
+
+/// A thread started by `spawn`, with the scheduling it got.
+pub(crate) struct Spawned<T> {
+    pub thread: JoinHandle<T>,
+    pub priority: Priority,
+    pub pinned: bool,
+}
+
+/// Spawn a thread named `name` that runs `f` at `priority`, on `cores`
+/// unless empty, falling back to normal scheduling and any core if either
+/// is refused.
+pub(crate) fn spawn<F, T>(
+    name: &str,
+    priority: Priority,
+    cores: Vec<usize>,
+    f: F,
+) -> io::Result<Spawned<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (granted_tx, granted_rx) = mpsc::channel();
+    let thread = thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            let granted = match set_thread_priority(priority) {
+                Ok(()) => priority,
+                Err(_) => Priority::Normal,
+            };
+            let pinned = !cores.is_empty() && set_thread_affinity(&cores).is_ok();
+            let _ = granted_tx.send((granted, pinned));
+            f()
+        })?;
+    let (priority, pinned) = granted_rx.recv().unwrap_or_default();
+    Ok(Spawned {
+        thread,
+        priority,
+        pinned,
+    })
+}
+
+#[cfg(target_os = "linux")]
+mod os {
+    use std::io;
+    use std::os::raw::{c_int, c_ulong};
+
+    const SCHED_FIFO: c_int = 1;
+    // Cores in the kernel's default cpu_set_t.
+    const CPU_SETSIZE: usize = 1024;
+    const BITS: usize = c_ulong::BITS as usize;
+
+    #[repr(C)]
+    struct SchedParam {
+        sched_priority: c_int,
+    }
+
+    extern "C" {
+        fn pthread_self() -> c_ulong;
+        fn pthread_setschedparam(thread: c_ulong, policy: c_int, param: *const SchedParam)
+            -> c_int;
+        fn sched_setaffinity(pid: c_int, size: usize, mask: *const c_ulong) -> c_int;
+    }
+
+    pub(super) fn set_realtime() -> io::Result<()> {
+        let param = SchedParam {
+            sched_priority: super::FIFO_PRIORITY,
+        };
+        match unsafe { pthread_setschedparam(pthread_self(), SCHED_FIFO, &param) } {
+            0 => Ok(()),
+            err => Err(io::Error::from_raw_os_error(err)),
+        }
+    }
+
+    pub(super) fn set_affinity(cores: &[usize]) -> io::Result<()> {
+        let mut mask = [0 as c_ulong; CPU_SETSIZE / BITS];
+        for &core in cores {
+            if core >= CPU_SETSIZE {
+                return Err(io::Error::from(io::ErrorKind::InvalidInput));
+            }
+            mask[core / BITS] |= 1 << (core % BITS);
+        }
+        // A pid of 0 means the calling thread.
+        let size = std::mem::size_of_val(&mask);
+        match unsafe { sched_setaffinity(0, size, mask.as_ptr()) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod os {
+    use std::io;
+    use std::os::raw::c_void;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThread() -> *mut c_void;
+        fn SetThreadAffinityMask(thread: *mut c_void, mask: usize) -> usize;
+    }
+
+    #[link(name = "avrt")]
+    extern "system" {
+        fn AvSetMmThreadCharacteristicsW(
+            task_name: *const u16,
+            task_index: *mut u32,
+        ) -> *mut c_void;
+    }
+
+    pub(super) fn set_realtime() -> io::Result<()> {
+        let task: Vec<u16> = "Pro Audio\0".encode_utf16().collect();
+        let mut index = 0;
+        // The handle would only be needed to leave the class again.
+        match unsafe { AvSetMmThreadCharacteristicsW(task.as_ptr(), &mut index) }.is_null() {
+            true => Err(io::Error::last_os_error()),
+            false => Ok(()),
+        }
+    }
+
+    pub(super) fn set_affinity(cores: &[usize]) -> io::Result<()> {
+        let mut mask = 0usize;
+        for &core in cores {
+            if core >= usize::BITS as usize {
+                return Err(io::Error::from(io::ErrorKind::InvalidInput));
+            }
+            mask |= 1 << core;
+        }
+        match unsafe { SetThreadAffinityMask(GetCurrentThread(), mask) } {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod os {
+    use std::io;
+
+    pub(super) fn set_realtime() -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "real-time scheduling is not supported on this platform",
+        ))
+    }
+
+    pub(super) fn set_affinity(_cores: &[usize]) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "thread affinity is not supported on this platform",
+        ))
+    }
+}
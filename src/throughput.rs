@@ -0,0 +1,94 @@
+// Checking the USB link keeps up with the highest sample rate
+// Licensed under MIT license
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{
+    is_streaming, m0_state, set_sample_rate, start_rx_owned, stop_rx, HackRFDevice, HackRFError,
+};
+
+// The HackRF's highest supported sample rate.
+const TEST_RATE: f64 = 20e6;
+// Bytes per USB transfer, as libhackrf uses.
+const TRANSFER_LEN: u64 = 262_144;
+// How far below the sample rate the sustained rate may fall and still pass.
+const TOLERANCE: f64 = 0.01;
+
+/// The outcome of `throughput_test`.
+#[derive(Copy, Clone, Debug)]
+pub struct ThroughputReport {
+    /// Time measured over, from the first transfer to the last.
+    pub elapsed: Duration,
+    /// Samples received over `elapsed`.
+    pub samples: u64,
+    /// Samples per second received, averaged over `elapsed`.
+    pub sustained_rate: f64,
+    /// Transfers' worth of samples short of what the sample rate should
+    /// have delivered, lost between the board and the callback.
+    pub dropped_transfers: u64,
+    /// Times the board's M0 found no room for samples because the host
+    /// fell behind, and the longest such gap in bytes, or `None` if the
+    /// firmware is too old to report them.
+    pub shortfalls: Option<(u32, u32)>,
+    /// Whether the rate was sustained to within 1% with no shortfalls.
+    pub passed: bool,
+}
+
+/// Receive at 20Msps for `duration` into a callback that discards
+/// everything, and report the rate the host sustained and any samples lost
+/// on the way, like `hackrf_transfer`'s sanity check. A failing report
+/// points at the USB link rather than the application: a shared or slow
+/// hub, a poor cable or a busy host controller. The device is left at
+/// 20Msps with streaming stopped.
+pub fn throughput_test(
+    device: &mut HackRFDevice,
+    duration: Duration,
+) -> Result<ThroughputReport, HackRFError> {
+    set_sample_rate(device, TEST_RATE)?;
+    let counted = Arc::new(AtomicU64::new(0));
+    let first = Arc::new(OnceLock::new());
+    let cb_counted = counted.clone();
+    let cb_first = first.clone();
+    // The first transfer only marks the start, as streaming takes a moment
+    // to get going.
+    let callback = move |rx: &[u8]| -> bool {
+        if cb_first.set(Instant::now()).is_err() {
+            cb_counted.fetch_add(rx.len() as u64, Ordering::Relaxed);
+        }
+        true
+    };
+    start_rx_owned(device, Box::new(callback))?;
+    let started = Instant::now();
+    let mut streaming = Ok(true);
+    while started.elapsed() < duration && matches!(streaming, Ok(true)) {
+        thread::sleep(Duration::from_millis(100));
+        streaming = is_streaming(device);
+    }
+    // Read before stopping, while the counters still describe this stream.
+    let state = m0_state(device).ok();
+    let ended = Instant::now();
+    let bytes = counted.load(Ordering::Relaxed);
+    let result = stop_rx(device);
+    device.rx_callback = None;
+    result?;
+    streaming?;
+
+    let elapsed = first.get().map_or(Duration::ZERO, |&first| ended - first);
+    let samples = bytes / 2;
+    let sustained_rate = samples as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+    let expected = (elapsed.as_secs_f64() * TEST_RATE * 2.0) as u64;
+    let dropped_transfers = expected.saturating_sub(bytes) / TRANSFER_LEN;
+    let shortfalls = state.map(|state| (state.num_shortfalls, state.longest_shortfall));
+    Ok(ThroughputReport {
+        elapsed,
+        samples,
+        sustained_rate,
+        dropped_transfers,
+        shortfalls,
+        passed: sustained_rate >= TEST_RATE * (1.0 - TOLERANCE)
+            && shortfalls.is_none_or(|(count, _)| count == 0),
+    })
+}
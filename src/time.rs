@@ -0,0 +1,49 @@
+// UTC calendar conversion for timestamps written to files
+// Licensed under MIT license
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A `SystemTime` broken down into UTC calendar fields.
+pub(crate) struct UtcTime {
+    pub year: u32,
+    pub month: u32,
+    pub day: u32,
+    /// Days since Sunday.
+    pub weekday: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub micros: u32,
+}
+
+impl UtcTime {
+    pub fn new(time: SystemTime) -> UtcTime {
+        let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since.as_secs();
+        let days = (secs / 86400) as i64;
+        let rem = (secs % 86400) as u32;
+
+        // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        UtcTime {
+            year: year as u32,
+            month: month as u32,
+            day: day as u32,
+            // 1970-01-01 was a Thursday.
+            weekday: ((days + 4) % 7) as u32,
+            hour: rem / 3600,
+            minute: rem / 60 % 60,
+            second: rem % 60,
+            micros: since.subsec_micros(),
+        }
+    }
+}
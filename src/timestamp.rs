@@ -0,0 +1,124 @@
+// Host timestamps and sample indices for received buffers
+// Licensed under MIT license
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+use super::sdr::RxCallback;
+
+/// When a buffer of cs8 samples reached the host, and where it falls in the
+/// stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Stamp {
+    /// Host time the buffer was delivered to the callback.
+    pub at: Instant,
+    /// Index of the buffer's first sample since receiving started.
+    pub sample: u64,
+}
+
+/// Maps sample indices to host time and back, estimated from the buffers
+/// seen so far.
+#[derive(Copy, Clone, Debug)]
+pub struct SampleClock {
+    // Host time of sample 0.
+    epoch: Instant,
+    // `epoch` on the system clock, for comparing with logs.
+    system_epoch: SystemTime,
+    rate: f64,
+}
+
+impl SampleClock {
+    /// Host time at which sample `sample` was captured.
+    pub fn time_of(&self, sample: u64) -> Instant {
+        self.epoch + Duration::from_secs_f64(sample as f64 / self.rate)
+    }
+
+    /// As `time_of`, on the system clock, as log timestamps are.
+    pub fn system_time_of(&self, sample: u64) -> SystemTime {
+        self.system_epoch + Duration::from_secs_f64(sample as f64 / self.rate)
+    }
+
+    /// Index of the sample captured at host time `at`, or 0 if before the
+    /// stream started.
+    pub fn sample_at(&self, at: Instant) -> u64 {
+        (at.saturating_duration_since(self.epoch).as_secs_f64() * self.rate).round() as u64
+    }
+}
+
+struct State {
+    samples: u64,
+    // Earliest estimate of the host time of sample 0.
+    epoch: Option<Instant>,
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Stamps each received buffer with the host time it arrived and the index
+/// of its first sample, so RF events can be lined up with other logs.
+///
+/// Buffers arrive a little after their last sample was captured, and later
+/// still when the host is busy, so the clock takes sample 0 to be at the
+/// earliest time consistent with every buffer seen: the least delayed one
+/// sets the mapping, and jitter in the rest does not move it.
+pub struct Timestamper {
+    state: Arc<Mutex<State>>,
+    rate: f64,
+}
+
+impl Timestamper {
+    /// For a stream at `sample_rate` samples per second.
+    pub fn new(sample_rate: f64) -> Timestamper {
+        Timestamper {
+            state: Arc::new(Mutex::new(State {
+                samples: 0,
+                epoch: None,
+            })),
+            rate: sample_rate,
+        }
+    }
+
+    /// Wrap `callback` into an RX callback that passes it a `Stamp` with
+    /// each buffer. Use a new `Timestamper` for each stream.
+    pub fn wrap<F>(&self, mut callback: F) -> RxCallback
+    where
+        F: FnMut(&[u8], Stamp) -> bool + Send + 'static,
+    {
+        let state = self.state.clone();
+        let rate = self.rate;
+        Box::new(move |buf: &[u8]| -> bool {
+            let at = Instant::now();
+            let sample = {
+                let mut state = lock(&state);
+                let sample = state.samples;
+                state.samples += buf.len() as u64 / 2;
+                let span = Duration::from_secs_f64(state.samples as f64 / rate);
+                if let Some(epoch) = at.checked_sub(span) {
+                    if state.epoch.is_none_or(|e| epoch < e) {
+                        state.epoch = Some(epoch);
+                    }
+                }
+                sample
+            };
+            callback(buf, Stamp { at, sample })
+        })
+    }
+
+    /// Number of samples delivered so far.
+    pub fn samples(&self) -> u64 {
+        lock(&self.state).samples
+    }
+
+    /// The mapping between sample indices and host time, once a buffer has
+    /// arrived.
+    pub fn clock(&self) -> Option<SampleClock> {
+        let epoch = lock(&self.state).epoch?;
+        let now = Instant::now();
+        Some(SampleClock {
+            epoch,
+            system_epoch: SystemTime::now() - now.duration_since(epoch),
+            rate: self.rate,
+        })
+    }
+}
@@ -0,0 +1,97 @@
+// Half-duplex packet transceiver, receiving between bursts
+// Licensed under MIT license
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use super::sdr::RxCallback;
+use super::turnaround::{HalfDuplex, Turnaround};
+use super::{is_streaming, HackRFDevice, HackRFError};
+
+// How often a burst is checked for having been sent.
+const POLL: Duration = Duration::from_millis(1);
+
+fn lock(callback: &Mutex<RxCallback>) -> MutexGuard<'_, RxCallback> {
+    callback.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Receives by default and transmits bursts on request, for packet
+/// protocols: `send_burst` switches to TX, sends the samples, waits for
+/// them to go out and switches back to RX, so callers never handle stream
+/// state themselves. Built on `HalfDuplex`, so each switch is as quick as
+/// it can be. The RX callback is kept across bursts and misses the samples
+/// that arrive while transmitting.
+pub struct Transceiver {
+    session: HalfDuplex,
+    rx: Arc<Mutex<RxCallback>>,
+    last: Option<(Turnaround, Turnaround)>,
+}
+
+impl Transceiver {
+    /// Start receiving into `callback`.
+    pub fn start(
+        device: &mut HackRFDevice,
+        callback: RxCallback,
+    ) -> Result<Transceiver, HackRFError> {
+        let mut transceiver = Transceiver {
+            session: HalfDuplex::new(),
+            rx: Arc::new(Mutex::new(callback)),
+            last: None,
+        };
+        transceiver.receive(device)?;
+        Ok(transceiver)
+    }
+
+    /// Transmit `samples`, interleaved signed 8-bit I/Q, once, then go back
+    /// to receiving. Blocks until the burst has been sent.
+    pub fn send_burst(
+        &mut self,
+        device: &mut HackRFDevice,
+        samples: &[u8],
+    ) -> Result<(), HackRFError> {
+        assert!(samples.len().is_multiple_of(2));
+        let burst = samples.to_vec();
+        let mut pos = 0;
+        let callback = Box::new(move |tx: &mut [u8]| -> bool {
+            // As in `transmit_repeated`, the transfer with the last samples
+            // is still submitted and only the next one ends the stream.
+            if pos == burst.len() {
+                tx.fill(0);
+                return false;
+            }
+            let n = std::cmp::min(tx.len(), burst.len() - pos);
+            tx[..n].copy_from_slice(&burst[pos..pos + n]);
+            tx[n..].fill(0);
+            pos += n;
+            true
+        });
+        let to_tx = self.session.switch_to_tx(device, callback)?;
+        let sent = loop {
+            match is_streaming(device) {
+                Ok(true) => thread::sleep(POLL),
+                Ok(false) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
+        let to_rx = self.receive(device)?;
+        self.last = Some((to_tx, to_rx));
+        sent
+    }
+
+    /// How long the switches to TX and back took for the last burst.
+    pub fn last_turnaround(&self) -> Option<(Turnaround, Turnaround)> {
+        self.last
+    }
+
+    /// Stop receiving.
+    pub fn stop(mut self, device: &mut HackRFDevice) -> Result<(), HackRFError> {
+        self.session.stop(device)
+    }
+
+    fn receive(&mut self, device: &mut HackRFDevice) -> Result<Turnaround, HackRFError> {
+        let rx = self.rx.clone();
+        self.session
+            .switch_to_rx(device, Box::new(move |buf: &[u8]| lock(&rx)(buf)))
+    }
+}
@@ -0,0 +1,171 @@
+// Capturing bursts that cross a power threshold
+// Licensed under MIT license
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use super::config::DeviceConfig;
+use super::format::SampleFormat;
+use super::tx::{transmit_repeated, Repeat};
+use super::wav::WavWriter;
+use super::{is_streaming, start_rx, stop_rx, HackRFDevice, HackRFError, StopHandle};
+
+// Samples averaged for each power measurement.
+const WINDOW: usize = 64;
+
+/// A burst captured by `capture_burst`, with the tuning it was received at
+/// so it can be replayed as it was.
+#[derive(Clone, Debug)]
+pub struct Burst {
+    /// cs8 samples.
+    pub samples: Vec<u8>,
+    pub sample_rate: f64,
+    /// Centre frequency, or 0 if the device had not been tuned through its
+    /// handle.
+    pub freq_hz: u64,
+}
+
+impl Burst {
+    /// Drop the samples before the first and after the last few dozen whose
+    /// average power reaches `threshold_db` dBFS, leaving just the burst.
+    /// Leaves nothing if none do.
+    pub fn trim_silence(&mut self, threshold_db: f64) {
+        let threshold = 10f64.powf(threshold_db / 10.0) * 128.0 * 128.0;
+        let loud = |window: &[u8]| {
+            let power: f64 = window
+                .iter()
+                .map(|&x| (x as i8 as f64) * (x as i8 as f64))
+                .sum();
+            power >= threshold * (window.len() / 2) as f64
+        };
+        let windows: Vec<bool> = self.samples.chunks(WINDOW * 2).map(loud).collect();
+        let first = windows.iter().position(|&w| w).unwrap_or(windows.len());
+        let last = windows.iter().rposition(|&w| w).map_or(first, |w| w + 1);
+        let end = std::cmp::min(last * WINDOW * 2, self.samples.len());
+        self.samples.truncate(end);
+        self.samples.drain(..std::cmp::min(first * WINDOW * 2, end));
+    }
+
+    /// Save the samples as a cs8 WAV file whose header and SDR#-style
+    /// `auxi` chunk hold the sample rate and frequency.
+    pub fn save_wav<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        let rate = self.sample_rate.round() as u32;
+        let mut wav = WavWriter::new(file, SampleFormat::Cs8, rate, self.freq_hz)?;
+        wav.write_all(&self.samples)?;
+        wav.finish()?.sync_all()
+    }
+
+    /// Tune `device` to the frequency and sample rate the burst was
+    /// captured at, with the baseband filter to suit, and transmit it
+    /// `times` times back to back at the TX gains set on `device`. Blocks
+    /// until it has been sent.
+    pub fn replay(&self, device: &mut HackRFDevice, times: usize) -> Result<(), HackRFError> {
+        let config = DeviceConfig {
+            freq_hz: Some(self.freq_hz),
+            sample_rate: Some(self.sample_rate),
+            ..DeviceConfig::default()
+        };
+        config.apply(device)?;
+        if self.samples.is_empty() || times == 0 {
+            return Ok(());
+        }
+        transmit_repeated(
+            device,
+            &self.samples,
+            Repeat::Count(times),
+            &StopHandle::new(),
+        )
+    }
+}
+
+/// Receive until the average power over a few dozen samples reaches
+/// `threshold_db` dBFS, then return the burst: up to `pre_samples` samples
+/// from before the trigger followed by `post_samples` from it onwards, as
+/// cs8. Fewer samples precede the trigger if it fires before that many
+/// have been received. Blocks until the capture is complete, returning
+/// `None` if `stop` is triggered first.
+///
+/// The usual way to catch key fobs and other intermittent transmitters:
+/// set a threshold a few dB above the noise floor and a pre-trigger long
+/// enough to include the start of the preamble.
+pub fn capture_on_trigger(
+    device: &mut HackRFDevice,
+    threshold_db: f64,
+    pre_samples: usize,
+    post_samples: usize,
+    stop: &StopHandle,
+) -> Result<Option<Vec<u8>>, HackRFError> {
+    // Sum of I^2 + Q^2 over a window at the threshold, full scale being 128.
+    let threshold = 10f64.powf(threshold_db / 10.0) * 128.0 * 128.0 * WINDOW as f64;
+    let mut history: VecDeque<u8> = VecDeque::with_capacity(pre_samples * 2);
+    let mut capture: Option<Vec<u8>> = None;
+    // Length of the complete capture in bytes, once triggered.
+    let mut wanted = 0;
+    let mut callback = |rx: &[u8]| -> bool {
+        for window in rx.chunks(WINDOW * 2) {
+            match capture {
+                Some(ref mut burst) => {
+                    let n = std::cmp::min(window.len(), wanted - burst.len());
+                    burst.extend_from_slice(&window[..n]);
+                }
+                None => {
+                    let power: f64 = window
+                        .iter()
+                        .map(|&x| (x as i8 as f64) * (x as i8 as f64))
+                        .sum();
+                    if power >= threshold * window.len() as f64 / (WINDOW * 2) as f64 {
+                        wanted = history.len() + post_samples * 2;
+                        let mut burst = Vec::with_capacity(wanted);
+                        burst.extend(history.drain(..));
+                        let n = std::cmp::min(window.len(), post_samples * 2);
+                        burst.extend_from_slice(&window[..n]);
+                        capture = Some(burst);
+                    } else {
+                        history.extend(window);
+                        let excess = history.len().saturating_sub(pre_samples * 2);
+                        history.drain(..excess);
+                    }
+                }
+            }
+            if capture.as_ref().is_some_and(|burst| burst.len() == wanted) {
+                return false;
+            }
+        }
+        true
+    };
+    start_rx(device, &mut callback)?;
+    while !stop.is_stopped() {
+        match is_streaming(device) {
+            Ok(true) => thread::sleep(Duration::from_millis(10)),
+            Ok(false) => break,
+            Err(err) => {
+                let _ = stop_rx(device);
+                return Err(err);
+            }
+        }
+    }
+    stop_rx(device)?;
+    Ok(capture.filter(|burst| burst.len() == wanted))
+}
+
+/// As `capture_on_trigger`, returning the burst along with the sample rate
+/// and frequency `device` was set to, ready to `replay`.
+pub fn capture_burst(
+    device: &mut HackRFDevice,
+    threshold_db: f64,
+    pre_samples: usize,
+    post_samples: usize,
+    stop: &StopHandle,
+) -> Result<Option<Burst>, HackRFError> {
+    let samples = capture_on_trigger(device, threshold_db, pre_samples, post_samples, stop)?;
+    Ok(samples.map(|samples| Burst {
+        samples,
+        sample_rate: device.sample_rate_hz,
+        freq_hz: device.freq_hz,
+    }))
+}
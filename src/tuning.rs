@@ -0,0 +1,97 @@
+// The firmware's frequency plan, for explaining how a frequency is tuned
+// Licensed under MIT license
+
+use super::{set_freq_explicit, HackRFDevice, HackRFError, RFPathFilter};
+
+const MHZ: u64 = 1_000_000;
+// Below this the low pass path is used, above it bypass, then high pass.
+const MAX_LP_FREQ_MHZ: u64 = 2170;
+const MAX_BYPASS_FREQ_MHZ: u64 = 2740;
+const MID1_HP_FREQ_MHZ: u64 = 3600;
+const MID2_HP_FREQ_MHZ: u64 = 5100;
+const MAX_HP_FREQ_MHZ: u64 = 7250;
+
+/// How the firmware tunes an RF frequency, as `explain_tuning` works it
+/// out. Signals reach the MAX2837 transceiver, which tunes over 2.15-2.75GHz,
+/// either directly or through the RFFC5071 mixer, whose LO shifts them
+/// there from further down or up, with a filter ahead of it to reject the
+/// image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TuningPlan {
+    pub freq_hz: u64,
+    /// The RF filter in the path: low pass below 2170MHz, bypass with no
+    /// mixing up to 2740MHz and high pass above.
+    pub path: RFPathFilter,
+    /// The frequency the MAX2837 is tuned to.
+    pub if_hz: u64,
+    /// The mixer's LO, or none in bypass. Nominal: the mixer's synthesiser
+    /// lands within a few Hz of it, and the IF is adjusted to suit.
+    pub lo_hz: Option<u64>,
+    /// The other RF frequency the mixer brings to the same IF, which the
+    /// path's filter should reject. Strong signals there can still leak
+    /// through as spurs. None in bypass.
+    pub image_hz: Option<u64>,
+    /// Whether mixing inverts the spectrum, as the LO above the signal in
+    /// the low pass path does. The firmware swaps it back, so samples are
+    /// the right way round either way.
+    pub inverted: bool,
+}
+
+impl TuningPlan {
+    /// Tune `device` to this plan with `set_freq_explicit`, such as one
+    /// adjusted to move an image or spur away from a signal of interest.
+    pub fn apply(&self, device: &mut HackRFDevice) -> Result<(), HackRFError> {
+        set_freq_explicit(device, self.if_hz, self.lo_hz.unwrap_or(0), self.path)
+    }
+}
+
+/// Work out how the firmware would tune to `freq_hz`, mirroring its
+/// frequency planner: which RF path and filter it picks, and the IF and LO.
+/// Returns `None` above 7250MHz, which it refuses.
+pub fn explain_tuning(freq_hz: u64) -> Option<TuningPlan> {
+    let freq_mhz = freq_hz / MHZ;
+    if freq_mhz < MAX_LP_FREQ_MHZ {
+        // The IF slides from 2650MHz down to 2340MHz, with the LO above
+        // both.
+        let if_hz = 2_650_000_000 - freq_hz / 7;
+        let lo_hz = if_hz + freq_hz;
+        Some(TuningPlan {
+            freq_hz,
+            path: RFPathFilter::LowPass,
+            if_hz,
+            lo_hz: Some(lo_hz),
+            image_hz: Some(lo_hz + if_hz),
+            inverted: true,
+        })
+    } else if freq_mhz < MAX_BYPASS_FREQ_MHZ {
+        Some(TuningPlan {
+            freq_hz,
+            path: RFPathFilter::Bypass,
+            if_hz: freq_hz,
+            lo_hz: None,
+            image_hz: None,
+            inverted: false,
+        })
+    } else if freq_mhz <= MAX_HP_FREQ_MHZ {
+        // The IF slides up across each of three ranges, with the LO below
+        // the signal.
+        let if_hz = if freq_mhz < MID1_HP_FREQ_MHZ {
+            2_170_000_000 + (freq_hz - 2_740_000_000) * 57 / 86
+        } else if freq_mhz < MID2_HP_FREQ_MHZ {
+            2_350_000_000 + (freq_hz - 3_600_000_000) / 5
+        } else {
+            2_500_000_000 + (freq_hz - 5_100_000_000) / 9
+        };
+        let lo_hz = freq_hz - if_hz;
+        Some(TuningPlan {
+            freq_hz,
+            path: RFPathFilter::HighPass,
+            if_hz,
+            lo_hz: Some(lo_hz),
+            image_hz: Some(lo_hz.abs_diff(if_hz)),
+            inverted: false,
+        })
+    } else {
+        None
+    }
+}
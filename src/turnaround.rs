@@ -0,0 +1,140 @@
+// Fast switching between receiving and transmitting
+// Licensed under MIT license
+
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use super::sdr::{RxCallback, TxCallback};
+use super::{start_rx_owned, start_tx_owned, stop_rx, stop_tx, HackRFDevice, HackRFError};
+
+/// Which way a `HalfDuplex` session is streaming.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Idle,
+    Rx,
+    Tx,
+}
+
+/// How long a `HalfDuplex` switch took, measured from the call.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Turnaround {
+    /// Time to stop streaming the old way.
+    pub stop: Duration,
+    /// Time from then until streaming had started the new way.
+    pub start: Duration,
+}
+
+/// A streaming session that switches a board between receiving and
+/// transmitting with as little dead time as the USB stack allows, for
+/// request and response protocols. Each switch stops one direction and
+/// starts the other straight away: the new callback is boxed and wrapped
+/// before the old stream is stopped, so nothing is set up in between, and
+/// the board stays open and configured. Switches report how long they
+/// took, and `first_transfer` how long until the new stream got going.
+pub struct HalfDuplex {
+    direction: Direction,
+    // When the last switch began, and when its callback was first called.
+    began: Instant,
+    first: Arc<OnceLock<Instant>>,
+}
+
+impl HalfDuplex {
+    pub fn new() -> HalfDuplex {
+        HalfDuplex {
+            direction: Direction::Idle,
+            began: Instant::now(),
+            first: Arc::new(OnceLock::new()),
+        }
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Time from the start of the last switch until the new callback was
+    /// first called, for its first transfer, or none if it has not been yet.
+    pub fn first_transfer(&self) -> Option<Duration> {
+        self.first
+            .get()
+            .map(|at| at.saturating_duration_since(self.began))
+    }
+
+    /// Stop whatever is streaming and start receiving into `callback`.
+    pub fn switch_to_rx(
+        &mut self,
+        device: &mut HackRFDevice,
+        mut callback: RxCallback,
+    ) -> Result<Turnaround, HackRFError> {
+        let first = Arc::new(OnceLock::new());
+        let cb_first = first.clone();
+        let callback: RxCallback = Box::new(move |rx: &[u8]| -> bool {
+            cb_first.get_or_init(Instant::now);
+            callback(rx)
+        });
+        let turnaround = self.switch(device, first, |device| start_rx_owned(device, callback))?;
+        self.direction = Direction::Rx;
+        Ok(turnaround)
+    }
+
+    /// Stop whatever is streaming and start transmitting from `callback`.
+    pub fn switch_to_tx(
+        &mut self,
+        device: &mut HackRFDevice,
+        mut callback: TxCallback,
+    ) -> Result<Turnaround, HackRFError> {
+        let first = Arc::new(OnceLock::new());
+        let cb_first = first.clone();
+        let callback: TxCallback = Box::new(move |tx: &mut [u8]| -> bool {
+            cb_first.get_or_init(Instant::now);
+            callback(tx)
+        });
+        let turnaround = self.switch(device, first, |device| start_tx_owned(device, callback))?;
+        self.direction = Direction::Tx;
+        Ok(turnaround)
+    }
+
+    /// Stop streaming, whichever way.
+    pub fn stop(&mut self, device: &mut HackRFDevice) -> Result<(), HackRFError> {
+        let direction = std::mem::replace(&mut self.direction, Direction::Idle);
+        match direction {
+            Direction::Idle => Ok(()),
+            Direction::Rx => {
+                let result = stop_rx(device);
+                device.rx_callback = None;
+                result
+            }
+            Direction::Tx => {
+                let result = stop_tx(device);
+                device.tx_callback = None;
+                result
+            }
+        }
+    }
+
+    fn switch<F>(
+        &mut self,
+        device: &mut HackRFDevice,
+        first: Arc<OnceLock<Instant>>,
+        start: F,
+    ) -> Result<Turnaround, HackRFError>
+    where
+        F: FnOnce(&mut HackRFDevice) -> Result<(), HackRFError>,
+    {
+        let began = Instant::now();
+        self.stop(device)?;
+        let stopped = Instant::now();
+        self.began = began;
+        self.first = first;
+        start(device)?;
+        Ok(Turnaround {
+            stop: stopped - began,
+            start: stopped.elapsed(),
+        })
+    }
+}
+
+impl Default for HalfDuplex {
+    fn default() -> HalfDuplex {
+        HalfDuplex::new()
+    }
+}
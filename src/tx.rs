@@ -0,0 +1,290 @@
+// Blocking transmit helpers built on the TX stream
+// Licensed under MIT license
+
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+use super::hopper::{Dwell, Hopper};
+use super::ramp::RampLength;
+use super::{
+    is_streaming, start_rx, start_tx, stop_rx, stop_tx, HackRFDevice, HackRFError, StopHandle,
+};
+
+// Samples averaged for each power measurement while listening.
+const LISTEN_WINDOW: usize = 64;
+// Fade out and in around each retune of a sweep.
+const SWEEP_RAMP: Duration = Duration::from_millis(1);
+
+/// How many times `transmit_repeated` should send its buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Repeat {
+    Count(usize),
+    Forever,
+}
+
+/// Transmit `buffer` (interleaved signed 8-bit I/Q) back-to-back, as many
+/// times as `repeat` asks for. The buffer is copied into the TX transfers
+/// cyclically, so there is no gap or discontinuity between repetitions even
+/// when its length does not divide the USB transfer size.
+/// Blocks until every repetition has been sent or `stop` is triggered.
+pub fn transmit_repeated(
+    device: &mut HackRFDevice,
+    buffer: &[u8],
+    repeat: Repeat,
+    stop: &StopHandle,
+) -> Result<(), HackRFError> {
+    assert!(!buffer.is_empty() && buffer.len().is_multiple_of(2));
+    let mut remaining = match repeat {
+        // A count too large to hold in bytes would take centuries to send.
+        Repeat::Count(n) => Some(n.saturating_mul(buffer.len())),
+        Repeat::Forever => None,
+    };
+    let mut pos = 0;
+    let cb_stop = stop.clone();
+    let mut callback = |tx: &mut [u8]| -> bool {
+        // The transfer carrying the final samples is still submitted; only
+        // the following one (all zeros) ends the stream.
+        if remaining == Some(0) || cb_stop.is_stopped() {
+            tx.fill(0);
+            return false;
+        }
+        let mut filled = 0;
+        while filled < tx.len() && remaining != Some(0) && !cb_stop.is_stopped() {
+            let mut n = std::cmp::min(tx.len() - filled, buffer.len() - pos);
+            if let Some(left) = remaining {
+                n = std::cmp::min(n, left);
+                remaining = Some(left - n);
+            }
+            tx[filled..filled + n].copy_from_slice(&buffer[pos..pos + n]);
+            pos = (pos + n) % buffer.len();
+            filled += n;
+        }
+        tx[filled..].fill(0);
+        true
+    };
+    start_tx(device, &mut callback)?;
+    wait_tx_finished(device, stop)
+}
+
+/// Transmit `buffer` (interleaved signed 8-bit I/Q) once, starting at host
+/// time `at`. The TX stream is started straight away and sends zeros until
+/// then, so the board is already streaming when the samples are due and the
+/// start lands within a sample of `at` as the host sees it, at the last
+/// sample rate set on `device`. USB buffering adds a few milliseconds of
+/// latency before the samples reach the antenna, so this suits loosely
+/// time-coordinated transmissions between machines with synchronised
+/// clocks, not sample-accurate ones.
+/// Blocks until the buffer has been sent or `stop` is triggered, and
+/// returns the host time the first sample was due, which is later than `at`
+/// if `at` had passed by the time the stream started, or `None` if stopped
+/// before then.
+pub fn transmit_at(
+    device: &mut HackRFDevice,
+    at: Instant,
+    buffer: &[u8],
+    stop: &StopHandle,
+) -> Result<Option<Instant>, HackRFError> {
+    assert!(!buffer.is_empty() && buffer.len().is_multiple_of(2));
+    let rate = device.sample_rate_hz;
+    let mut started = None;
+    let mut pos = 0;
+    let cb_stop = stop.clone();
+    let mut callback = |tx: &mut [u8]| -> bool {
+        if pos == buffer.len() || cb_stop.is_stopped() {
+            tx.fill(0);
+            return false;
+        }
+        let mut filled = 0;
+        if started.is_none() {
+            // This transfer covers roughly the next tx.len() / 2 samples
+            // from now; begin at the sample nearest `at`, if it is in it.
+            let now = Instant::now();
+            let wait = at.saturating_duration_since(now).as_secs_f64();
+            let offset = (wait * rate).round() as usize * 2;
+            if offset >= tx.len() {
+                tx.fill(0);
+                return true;
+            }
+            started = Some(now + Duration::from_secs_f64(offset as f64 / 2.0 / rate));
+            tx[..offset].fill(0);
+            filled = offset;
+        }
+        let n = std::cmp::min(tx.len() - filled, buffer.len() - pos);
+        tx[filled..filled + n].copy_from_slice(&buffer[pos..pos + n]);
+        pos += n;
+        tx[filled + n..].fill(0);
+        true
+    };
+    start_tx(device, &mut callback)?;
+    wait_tx_finished(device, stop)?;
+    Ok(started)
+}
+
+/// Listen before talking: receive on the current frequency until the
+/// channel is clear, with the power over every few dozen samples of a whole
+/// USB transfer below `threshold_db` dBFS, then transmit `buffer` once.
+/// Gives up if the channel is still busy after `max_wait`. Blocks until the
+/// buffer has been sent, and returns the host time transmission started,
+/// or `None` if it never did because the channel stayed busy or `stop` was
+/// triggered. A basic courtesy for tools sharing the ISM bands; set the
+/// threshold a few dB above the noise floor at the gains in use.
+pub fn transmit_when_clear(
+    device: &mut HackRFDevice,
+    buffer: &[u8],
+    threshold_db: f64,
+    max_wait: Duration,
+    stop: &StopHandle,
+) -> Result<Option<Instant>, HackRFError> {
+    // Sum of I^2 + Q^2 over a window at the threshold, full scale being 128.
+    let threshold = 10f64.powf(threshold_db / 10.0) * 128.0 * 128.0 * LISTEN_WINDOW as f64;
+    let deadline = Instant::now() + max_wait;
+    let mut clear = false;
+    // The first transfer may predate the receiver settling.
+    let mut skip = true;
+    let cb_stop = stop.clone();
+    let mut callback = |rx: &[u8]| -> bool {
+        // Once clear, stay so through any calls while the stream winds down.
+        if clear || cb_stop.is_stopped() || Instant::now() >= deadline {
+            return false;
+        }
+        if std::mem::replace(&mut skip, false) {
+            return true;
+        }
+        clear = rx.chunks_exact(LISTEN_WINDOW * 2).all(|window| {
+            let power: f64 = window
+                .iter()
+                .map(|&x| (x as i8 as f64) * (x as i8 as f64))
+                .sum();
+            power < threshold
+        });
+        !clear
+    };
+    start_rx(device, &mut callback)?;
+    let listened = loop {
+        match is_streaming(device) {
+            Ok(true) if !stop.is_stopped() => std::thread::sleep(Duration::from_millis(1)),
+            Ok(_) => break Ok(()),
+            Err(err) => break Err(err),
+        }
+    };
+    let stopped = stop_rx(device);
+    listened.and(stopped)?;
+    if !clear || stop.is_stopped() {
+        return Ok(None);
+    }
+    let started = Instant::now();
+    transmit_repeated(device, buffer, Repeat::Count(1), stop)?;
+    Ok(Some(started))
+}
+
+/// Transmit a continuous tone `offset_hz` away from the current centre
+/// frequency for `duration`, at the last sample rate set on `device`.
+/// `amplitude` is relative to full scale, 0.0-1.0.
+/// Handy for checking an antenna is connected with a second receiver.
+pub fn transmit_tone(
+    device: &mut HackRFDevice,
+    offset_hz: f64,
+    amplitude: f32,
+    duration: Duration,
+) -> Result<(), HackRFError> {
+    assert!((0.0..=1.0).contains(&amplitude));
+    let rate = device.sample_rate_hz;
+    assert!(offset_hz.abs() < rate / 2.0);
+    let step = 2.0 * PI * offset_hz / rate;
+    let scale = 127.0 * amplitude as f64;
+    let mut remaining = (duration.as_secs_f64() * rate) as u64;
+    let mut phase = 0.0f64;
+    let mut callback = |tx: &mut [u8]| -> bool {
+        if remaining == 0 {
+            tx.fill(0);
+            return false;
+        }
+        for iq in tx.chunks_exact_mut(2) {
+            if remaining == 0 {
+                iq.fill(0);
+                continue;
+            }
+            iq[0] = (scale * phase.cos()).round() as i8 as u8;
+            iq[1] = (scale * phase.sin()).round() as i8 as u8;
+            phase = (phase + step) % (2.0 * PI);
+            remaining -= 1;
+        }
+        true
+    };
+    start_tx(device, &mut callback)?;
+    wait_tx_finished(device, &StopHandle::new())
+}
+
+/// Sweep a continuous tone from `start_hz` to `stop_hz` at `sweep_rate`
+/// Hz per second, over and over, until `stop` is triggered, as a signal
+/// generator for measuring filters and antennas with a second receiver.
+/// `amplitude` is relative to full scale, 0.0-1.0. The range is covered in
+/// segments half the sample rate wide: the board is tuned to the centre of
+/// each and a baseband chirp spans it, with the tone faded out and in
+/// around each retune. Chirps hold their top frequency if a retune comes
+/// late, so each frequency in the range is swept at least at `sweep_rate`.
+pub fn transmit_sweep(
+    device: &mut HackRFDevice,
+    start_hz: u64,
+    stop_hz: u64,
+    sweep_rate: f64,
+    amplitude: f32,
+    stop: &StopHandle,
+) -> Result<(), HackRFError> {
+    assert!(start_hz < stop_hz && sweep_rate > 0.0);
+    assert!((0.0..=1.0).contains(&amplitude));
+    let rate = device.sample_rate_hz;
+    let segment_hz = (rate / 2.0) as u64;
+    let freqs_hz: Vec<u64> = (start_hz..stop_hz)
+        .step_by(segment_hz as usize)
+        .map(|low| low + segment_hz / 2)
+        .collect();
+    // Samples for a chirp across a whole segment.
+    let dwell = (segment_hz as f64 / sweep_rate * rate).ceil() as u64;
+    // With one segment there are no retunes to restart the chirp at.
+    let single = freqs_hz.len() == 1;
+    let hopper = Hopper::new(freqs_hz, Dwell::Samples(dwell)).ramp(RampLength::Time(SWEEP_RAMP));
+    let scale = 127.0 * amplitude as f64;
+    let mut tuned_hz = 0;
+    let mut elapsed = 0u64;
+    let mut phase = 0.0f64;
+    hopper.transmit(device, stop, |freq_hz, tx| {
+        if freq_hz != tuned_hz {
+            tuned_hz = freq_hz;
+            elapsed = 0;
+        }
+        let low = -(segment_hz as f64) / 2.0;
+        let high = (segment_hz as f64 / 2.0).min(stop_hz as f64 - freq_hz as f64);
+        for iq in tx.chunks_exact_mut(2) {
+            if single && elapsed >= dwell {
+                elapsed = 0;
+            }
+            let offset = (low + sweep_rate * elapsed as f64 / rate).min(high);
+            iq[0] = (scale * phase.cos()).round() as i8 as u8;
+            iq[1] = (scale * phase.sin()).round() as i8 as u8;
+            phase = (phase + 2.0 * PI * offset / rate) % (2.0 * PI);
+            elapsed += 1;
+        }
+        true
+    })
+}
+
+/// Wait for a TX stream started by one of the helpers in this module to end,
+/// either because its callback returned `false` or because `stop` was
+/// triggered, then stop the stream.
+pub(crate) fn wait_tx_finished(
+    device: &mut HackRFDevice,
+    stop: &StopHandle,
+) -> Result<(), HackRFError> {
+    while !stop.is_stopped() {
+        match is_streaming(device) {
+            Ok(true) => std::thread::sleep(Duration::from_millis(10)),
+            Ok(false) => break,
+            Err(err) => {
+                let _ = stop_tx(device);
+                return Err(err);
+            }
+        }
+    }
+    stop_tx(device)
+}
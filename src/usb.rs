@@ -0,0 +1,410 @@
+// Pure-Rust driver speaking the HackRF's USB protocol directly
+// Licensed under MIT license
+
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use futures_lite::future::block_on;
+use nusb::transfer::{Control, ControlType, Recipient, RequestBuffer, TransferError};
+use nusb::{DeviceInfo, Interface};
+
+use super::format::SampleFormat;
+use super::sdr::{
+    find_stage, GainStage, RxCallback, RxChannel, SdrDevice, TxCallback, TxChannel, RX_GAIN_STAGES,
+    TX_GAIN_STAGES,
+};
+use super::{ffi, hackrf_error, HackRFError, StopHandle};
+
+const VENDOR_ID: u16 = 0x1d50;
+// HackRF One, Jawbreaker and rad1o.
+const PRODUCT_IDS: [u16; 3] = [0x6089, 0x604b, 0xcc15];
+
+const RX_ENDPOINT: u8 = 0x81;
+const TX_ENDPOINT: u8 = 0x02;
+// Bytes per bulk transfer, and transfers kept in flight, as libhackrf uses.
+const TRANSFER_LEN: usize = 262_144;
+const TRANSFERS: usize = 4;
+const CONTROL_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Vendor requests understood by the firmware, numbered as in libhackrf.
+const SET_TRANSCEIVER_MODE: u8 = 1;
+const SAMPLE_RATE_SET: u8 = 6;
+const BASEBAND_FILTER_BANDWIDTH_SET: u8 = 7;
+const BOARD_ID_READ: u8 = 14;
+const VERSION_STRING_READ: u8 = 15;
+const SET_FREQ: u8 = 16;
+const AMP_ENABLE: u8 = 17;
+const SET_LNA_GAIN: u8 = 19;
+const SET_VGA_GAIN: u8 = 20;
+const SET_TXVGA_GAIN: u8 = 21;
+
+// Transceiver modes for SET_TRANSCEIVER_MODE.
+const MODE_OFF: u16 = 0;
+const MODE_RECEIVE: u16 = 1;
+const MODE_TRANSMIT: u16 = 2;
+
+// Bandwidths of the MAX2837 baseband filter, in Hz.
+const FILTER_BANDWIDTHS: [u32; 16] = [
+    1_750_000, 2_500_000, 3_500_000, 5_000_000, 5_500_000, 6_000_000, 7_000_000, 8_000_000,
+    9_000_000, 10_000_000, 12_000_000, 14_000_000, 15_000_000, 20_000_000, 24_000_000, 28_000_000,
+];
+
+/// An experimental HackRF driver that talks to the board over USB with
+/// `nusb` instead of going through libhackrf, for platforms where the C
+/// library is hard to come by. It covers tuning, sample rate, gains and
+/// streaming through the `SdrDevice`, `RxChannel` and `TxChannel` traits;
+/// everything else needs a `HackRFDevice`. Build with the `dlopen` feature
+/// too so that libhackrf is not needed at all.
+///
+/// As with libhackrf, callbacks run on a streaming thread, and the board
+/// can receive or transmit but not both at once.
+pub struct UsbBackend {
+    interface: Interface,
+    freq_hz: u64,
+    sample_rate_hz: f64,
+    rx: Option<(StopHandle, JoinHandle<()>)>,
+    tx: Option<(StopHandle, JoinHandle<()>)>,
+}
+
+impl UsbBackend {
+    /// Open the first HackRF found.
+    pub fn open() -> Result<UsbBackend, HackRFError> {
+        UsbBackend::find(|_| true)
+    }
+
+    /// Open the HackRF whose serial number ends with `serial`, matching as
+    /// libhackrf does.
+    pub fn open_by_serial(serial: &str) -> Result<UsbBackend, HackRFError> {
+        UsbBackend::find(|info| info.serial_number().is_some_and(|s| s.ends_with(serial)))
+    }
+
+    fn find<F: Fn(&DeviceInfo) -> bool>(matches: F) -> Result<UsbBackend, HackRFError> {
+        let info = nusb::list_devices()?
+            .find(|info| {
+                info.vendor_id() == VENDOR_ID
+                    && PRODUCT_IDS.contains(&info.product_id())
+                    && matches(info)
+            })
+            .ok_or_else(|| hackrf_error(ffi::HACKRF_ERROR_NOT_FOUND))?;
+        let interface = info.open()?.claim_interface(0)?;
+        let backend = UsbBackend {
+            interface,
+            freq_hz: 0,
+            sample_rate_hz: 10e6,
+            rx: None,
+            tx: None,
+        };
+        backend.set_mode(MODE_OFF)?;
+        Ok(backend)
+    }
+
+    /// Read the board ID, one of the `BOARD_ID_` values libhackrf uses.
+    pub fn board_id(&self) -> Result<u8, HackRFError> {
+        let mut id = [0u8; 1];
+        self.control_in(BOARD_ID_READ, 0, 0, &mut id)?;
+        Ok(id[0])
+    }
+
+    /// Read the firmware version string.
+    pub fn version(&self) -> Result<String, HackRFError> {
+        let mut version = [0u8; 255];
+        let n = self.control_in(VERSION_STRING_READ, 0, 0, &mut version)?;
+        Ok(String::from_utf8_lossy(&version[..n]).into_owned())
+    }
+
+    /// Set the baseband filter bandwidth, rounded down to one the filter
+    /// supports. Setting the sample rate chooses one automatically.
+    pub fn set_baseband_filter_bandwidth(&mut self, hz: u32) -> Result<(), HackRFError> {
+        let hz = filter_bandwidth(hz);
+        self.control_out(
+            BASEBAND_FILTER_BANDWIDTH_SET,
+            hz as u16,
+            (hz >> 16) as u16,
+            &[],
+        )
+    }
+
+    fn set_mode(&self, mode: u16) -> Result<(), HackRFError> {
+        self.control_out(SET_TRANSCEIVER_MODE, mode, 0, &[])
+    }
+
+    fn set_amp_enable(&self, enable: bool) -> Result<(), HackRFError> {
+        self.control_out(AMP_ENABLE, enable as u16, 0, &[])
+    }
+
+    // The gain requests reply with a byte that is zero if the gain was
+    // rejected.
+    fn set_gain(&self, request: u8, db: u16) -> Result<(), HackRFError> {
+        let mut ok = [0u8; 1];
+        match self.control_in(request, 0, db, &mut ok)? {
+            1 if ok[0] != 0 => Ok(()),
+            _ => Err(hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM)),
+        }
+    }
+
+    fn control_out(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+    ) -> Result<(), HackRFError> {
+        self.interface
+            .control_out_blocking(vendor(request, value, index), data, CONTROL_TIMEOUT)
+            .map_err(usb_error)?;
+        Ok(())
+    }
+
+    fn control_in(
+        &self,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &mut [u8],
+    ) -> Result<usize, HackRFError> {
+        self.interface
+            .control_in_blocking(vendor(request, value, index), data, CONTROL_TIMEOUT)
+            .map_err(usb_error)
+    }
+
+    fn check_idle(&mut self) -> Result<(), HackRFError> {
+        // Threads whose callbacks returned false have finished.
+        if self.rx.as_ref().is_some_and(|rx| rx.1.is_finished()) {
+            self.stop_rx()?;
+        }
+        if self.tx.as_ref().is_some_and(|tx| tx.1.is_finished()) {
+            self.stop_tx()?;
+        }
+        if self.rx.is_some() || self.tx.is_some() {
+            return Err(hackrf_error(ffi::HACKRF_ERROR_BUSY));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UsbBackend {
+    fn drop(&mut self) {
+        let _ = self.stop_rx();
+        let _ = self.stop_tx();
+    }
+}
+
+fn vendor(request: u8, value: u16, index: u16) -> Control {
+    Control {
+        control_type: ControlType::Vendor,
+        recipient: Recipient::Device,
+        request,
+        value,
+        index,
+    }
+}
+
+fn usb_error(err: TransferError) -> HackRFError {
+    match err {
+        TransferError::Disconnected => hackrf_error(ffi::HACKRF_ERROR_NOT_FOUND),
+        _ => hackrf_error(ffi::HACKRF_ERROR_LIBUSB),
+    }
+}
+
+// The widest filter no wider than `hz`, or the narrowest, as
+// hackrf_compute_baseband_filter_bw chooses.
+fn filter_bandwidth(hz: u32) -> u32 {
+    FILTER_BANDWIDTHS
+        .iter()
+        .rev()
+        .find(|&&bw| bw <= hz)
+        .copied()
+        .unwrap_or(FILTER_BANDWIDTHS[0])
+}
+
+// Express `hz` as a frequency and divider, as hackrf_set_sample_rate does:
+// with the smallest divider below 32 that makes the frequency a whole
+// number of Hz, so that rates such as 10MHz/3 are exact.
+fn rate_fraction(hz: f64) -> (u32, u32) {
+    let divider = (1..32u32)
+        .find(|&d| {
+            let scaled = hz * d as f64;
+            (scaled - scaled.round()).abs() < 1e-4
+        })
+        .unwrap_or(1);
+    ((hz * divider as f64).round() as u32, divider)
+}
+
+// Receive on the bulk IN endpoint until stopped, the callback returns
+// false or a transfer fails.
+fn receive(interface: &Interface, stop: &StopHandle, callback: &mut RxCallback) {
+    let mut queue = interface.bulk_in_queue(RX_ENDPOINT);
+    while queue.pending() < TRANSFERS {
+        queue.submit(RequestBuffer::new(TRANSFER_LEN));
+    }
+    loop {
+        let completion = block_on(queue.next_complete());
+        if completion.status.is_err() || stop.is_stopped() || !callback(&completion.data) {
+            break;
+        }
+        queue.submit(RequestBuffer::reuse(completion.data, TRANSFER_LEN));
+    }
+    queue.cancel_all();
+    while queue.pending() > 0 {
+        block_on(queue.next_complete());
+    }
+}
+
+// Transmit on the bulk OUT endpoint until stopped, the callback returns
+// false or a transfer fails. Buffers already filled are sent regardless.
+fn transmit(interface: &Interface, stop: &StopHandle, callback: &mut TxCallback) {
+    let mut queue = interface.bulk_out_queue(TX_ENDPOINT);
+    let mut spare: Vec<Vec<u8>> = Vec::new();
+    let mut more = true;
+    loop {
+        while more && !stop.is_stopped() && queue.pending() < TRANSFERS {
+            let mut buf = spare.pop().unwrap_or_default();
+            buf.resize(TRANSFER_LEN, 0);
+            more = callback(&mut buf);
+            queue.submit(buf);
+        }
+        if queue.pending() == 0 {
+            break;
+        }
+        let completion = block_on(queue.next_complete());
+        if completion.status.is_err() {
+            break;
+        }
+        spare.push(completion.data.reuse());
+    }
+    queue.cancel_all();
+    while queue.pending() > 0 {
+        block_on(queue.next_complete());
+    }
+}
+
+impl SdrDevice for UsbBackend {
+    type Error = HackRFError;
+
+    fn set_frequency(&mut self, hz: u64) -> Result<(), HackRFError> {
+        // Sent as whole MHz and the remaining Hz.
+        let mut data = [0u8; 8];
+        data[..4].copy_from_slice(&((hz / 1_000_000) as u32).to_le_bytes());
+        data[4..].copy_from_slice(&((hz % 1_000_000) as u32).to_le_bytes());
+        self.control_out(SET_FREQ, 0, 0, &data)?;
+        self.freq_hz = hz;
+        Ok(())
+    }
+
+    fn frequency(&self) -> u64 {
+        self.freq_hz
+    }
+
+    /// Also sets the baseband filter to suit the new rate, as libhackrf
+    /// does.
+    fn set_sample_rate(&mut self, hz: f64) -> Result<(), HackRFError> {
+        let (freq_hz, divider) = rate_fraction(hz);
+        let mut data = [0u8; 8];
+        data[..4].copy_from_slice(&freq_hz.to_le_bytes());
+        data[4..].copy_from_slice(&divider.to_le_bytes());
+        self.control_out(SAMPLE_RATE_SET, 0, 0, &data)?;
+        self.sample_rate_hz = hz;
+        self.set_baseband_filter_bandwidth((0.75 * hz) as u32)
+    }
+
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate_hz
+    }
+
+    fn native_format(&self) -> SampleFormat {
+        SampleFormat::Cs8
+    }
+}
+
+impl RxChannel for UsbBackend {
+    fn rx_gain_stages(&self) -> &'static [GainStage] {
+        &RX_GAIN_STAGES
+    }
+
+    fn set_rx_gain(&mut self, name: &str, db: f64) -> Result<(), HackRFError> {
+        let db = find_stage(&RX_GAIN_STAGES, name, db)?;
+        match name.to_ascii_uppercase().as_str() {
+            "AMP" => self.set_amp_enable(db > 0.0),
+            "LNA" => self.set_gain(SET_LNA_GAIN, db as u16),
+            _ => self.set_gain(SET_VGA_GAIN, db as u16),
+        }
+    }
+
+    fn start_rx(&mut self, mut callback: RxCallback) -> Result<(), HackRFError> {
+        self.check_idle()?;
+        self.set_mode(MODE_RECEIVE)?;
+        let interface = self.interface.clone();
+        let stop = StopHandle::new();
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name("hackrf-usb-rx".to_string())
+            .spawn(move || receive(&interface, &thread_stop, &mut callback));
+        match thread {
+            Ok(thread) => {
+                self.rx = Some((stop, thread));
+                Ok(())
+            }
+            Err(err) => {
+                let _ = self.set_mode(MODE_OFF);
+                Err(err.into())
+            }
+        }
+    }
+
+    fn stop_rx(&mut self) -> Result<(), HackRFError> {
+        match self.rx.take() {
+            Some((stop, thread)) => {
+                stop.stop();
+                thread.join().expect("USB RX thread panicked");
+                self.set_mode(MODE_OFF)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl TxChannel for UsbBackend {
+    fn tx_gain_stages(&self) -> &'static [GainStage] {
+        &TX_GAIN_STAGES
+    }
+
+    fn set_tx_gain(&mut self, name: &str, db: f64) -> Result<(), HackRFError> {
+        let db = find_stage(&TX_GAIN_STAGES, name, db)?;
+        match name.to_ascii_uppercase().as_str() {
+            "AMP" => self.set_amp_enable(db > 0.0),
+            _ => self.set_gain(SET_TXVGA_GAIN, db as u16),
+        }
+    }
+
+    fn start_tx(&mut self, mut callback: TxCallback) -> Result<(), HackRFError> {
+        self.check_idle()?;
+        self.set_mode(MODE_TRANSMIT)?;
+        let interface = self.interface.clone();
+        let stop = StopHandle::new();
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name("hackrf-usb-tx".to_string())
+            .spawn(move || transmit(&interface, &thread_stop, &mut callback));
+        match thread {
+            Ok(thread) => {
+                self.tx = Some((stop, thread));
+                Ok(())
+            }
+            Err(err) => {
+                let _ = self.set_mode(MODE_OFF);
+                Err(err.into())
+            }
+        }
+    }
+
+    fn stop_tx(&mut self) -> Result<(), HackRFError> {
+        match self.tx.take() {
+            Some((stop, thread)) => {
+                stop.stop();
+                thread.join().expect("USB TX thread panicked");
+                self.set_mode(MODE_OFF)
+            }
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,350 @@
+// Queues of received transfers and how their consumers are woken
+// Licensed under MIT license
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError, TrySendError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// How a thread waiting on an `RxStream` learns that a transfer has
+/// arrived, chosen with `RxStreamBuilder::wake`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Wake {
+    /// Sleep on a condition variable until the USB thread signals it. Uses
+    /// no CPU while waiting, but each wakeup goes through the scheduler,
+    /// which can take long enough at 20Msps for the queue to overflow.
+    #[default]
+    Condvar,
+    /// Sleep in `poll` on a Linux eventfd that the USB thread writes to.
+    /// Wakes the consumer more promptly than a condition variable on most
+    /// kernels. Falls back to `Condvar` if no eventfd can be created.
+    #[cfg(target_os = "linux")]
+    EventFd,
+    /// Spin checking the queue for up to this long before sleeping as with
+    /// `Condvar`, so a consumer that keeps up never waits on the scheduler.
+    /// Burns a core while spinning, which costs battery on laptops; keep it
+    /// around a transfer's duration, 6.5ms at 20Msps.
+    BusyPoll(Duration),
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    // The producer has gone.
+    closed: bool,
+    // The consumer is asleep and must be woken.
+    sleeping: bool,
+    // The task that last polled an empty queue, to wake on the next change.
+    task: Option<Waker>,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    wake: Wake,
+    condvar: Condvar,
+    #[cfg(target_os = "linux")]
+    event: Option<eventfd::EventFd>,
+    // The consumer has gone.
+    gone: AtomicBool,
+}
+
+/// Sending half of a transfer queue, held by the RX callback. Dropping it
+/// ends the stream once the queue drains.
+pub(crate) struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Receiving half of a transfer queue.
+pub(crate) struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// A queue of up to `capacity` transfers, waking its consumer as `wake`
+/// says.
+pub(crate) fn channel<T>(capacity: usize, wake: Wake) -> (Producer<T>, Consumer<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::with_capacity(capacity),
+            closed: false,
+            sleeping: false,
+            task: None,
+        }),
+        capacity,
+        wake,
+        condvar: Condvar::new(),
+        #[cfg(target_os = "linux")]
+        event: match wake {
+            Wake::EventFd => eventfd::EventFd::new().ok(),
+            _ => None,
+        },
+        gone: AtomicBool::new(false),
+    });
+    (
+        Producer {
+            shared: shared.clone(),
+        },
+        Consumer { shared },
+    )
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Release the lock on `state`, after a change to it, and wake the
+    // consumer if it is waiting.
+    fn notify(&self, mut state: MutexGuard<'_, State<T>>) {
+        let sleeping = state.sleeping;
+        let task = state.task.take();
+        drop(state);
+        if let Some(task) = task {
+            task.wake();
+        }
+        if !sleeping {
+            return;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(ref event) = self.event {
+            event.signal();
+            return;
+        }
+        self.condvar.notify_one();
+    }
+}
+
+impl<T> Producer<T> {
+    /// Queue `buf` unless the queue is full or the consumer has gone.
+    pub(crate) fn try_send(&self, buf: T) -> Result<(), TrySendError<T>> {
+        if self.shared.gone.load(Ordering::Relaxed) {
+            return Err(TrySendError::Disconnected(buf));
+        }
+        let mut state = self.shared.lock();
+        if state.items.len() >= self.shared.capacity {
+            return Err(TrySendError::Full(buf));
+        }
+        state.items.push_back(buf);
+        self.shared.notify(state);
+        Ok(())
+    }
+
+    /// Queue `buf`, taking the oldest item out to make room if the queue
+    /// is full and returning it, unless the consumer has gone.
+    pub(crate) fn send_evicting(&self, buf: T) -> Result<Option<T>, T> {
+        if self.shared.gone.load(Ordering::Relaxed) {
+            return Err(buf);
+        }
+        let mut state = self.shared.lock();
+        let evicted = match state.items.len() >= self.shared.capacity {
+            true => state.items.pop_front(),
+            false => None,
+        };
+        state.items.push_back(buf);
+        self.shared.notify(state);
+        Ok(evicted)
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.closed = true;
+        self.shared.notify(state);
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Take the oldest transfer if there is one.
+    pub(crate) fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match state.items.pop_front() {
+            Some(buf) => Ok(buf),
+            None if state.closed => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Take the oldest transfer, or arrange for the task in `cx` to be woken
+    /// when there may be one.
+    pub(crate) fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.shared.lock();
+        match state.items.pop_front() {
+            Some(buf) => Poll::Ready(Some(buf)),
+            None if state.closed => Poll::Ready(None),
+            None => {
+                state.task = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// End the queue from this side: waiting stops once it drains, and the
+    /// producer is told the consumer has gone.
+    pub(crate) fn close(&self) {
+        self.shared.gone.store(true, Ordering::Relaxed);
+        let mut state = self.shared.lock();
+        state.closed = true;
+        self.shared.notify(state);
+    }
+
+    /// Wait for the oldest transfer until `deadline`, or for ever.
+    pub(crate) fn recv_deadline(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        if let Wake::BusyPoll(spin) = self.shared.wake {
+            let spin_until = Instant::now() + spin;
+            loop {
+                match self.try_recv() {
+                    Ok(buf) => return Ok(buf),
+                    Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                    Err(TryRecvError::Empty) => {}
+                }
+                let now = Instant::now();
+                if deadline.is_some_and(|deadline| now >= deadline) {
+                    return Err(RecvTimeoutError::Timeout);
+                }
+                if now >= spin_until {
+                    break;
+                }
+                std::hint::spin_loop();
+            }
+        }
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(buf) = state.items.pop_front() {
+                return Ok(buf);
+            }
+            if state.closed {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let timeout = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(timeout) if timeout > Duration::ZERO => Some(timeout),
+                    _ => return Err(RecvTimeoutError::Timeout),
+                },
+                None => None,
+            };
+            state.sleeping = true;
+            state = self.sleep(state, timeout);
+            state.sleeping = false;
+        }
+    }
+
+    // Release the lock until woken, `timeout` passes or a spurious wakeup.
+    fn sleep<'a>(
+        &'a self,
+        state: MutexGuard<'a, State<T>>,
+        timeout: Option<Duration>,
+    ) -> MutexGuard<'a, State<T>> {
+        #[cfg(target_os = "linux")]
+        if let Some(ref event) = self.shared.event {
+            drop(state);
+            event.wait(timeout);
+            return self.shared.lock();
+        }
+        match timeout {
+            Some(timeout) => {
+                self.shared
+                    .condvar
+                    .wait_timeout(state, timeout)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            }
+            None => self
+                .shared
+                .condvar
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner),
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> Consumer<T> {
+    /// Wait until at least `bytes` are queued, the producer has gone or
+    /// `deadline` passes, and return how many bytes are queued.
+    pub(crate) fn wait_queued(&self, bytes: usize, deadline: Instant) -> usize {
+        let mut state = self.shared.lock();
+        loop {
+            let queued = state.items.iter().map(|item| item.as_ref().len()).sum();
+            if queued >= bytes || state.closed {
+                return queued;
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout == Duration::ZERO {
+                return queued;
+            }
+            state.sleeping = true;
+            state = self.sleep(state, Some(timeout));
+            state.sleeping = false;
+        }
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.shared.gone.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod eventfd {
+    use std::fs::File;
+    use std::io::{self, Read, Write};
+    use std::os::raw::{c_int, c_short, c_uint, c_ulong};
+    use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+    use std::time::Duration;
+
+    #[repr(C)]
+    struct PollFd {
+        fd: c_int,
+        events: c_short,
+        revents: c_short,
+    }
+
+    const POLLIN: c_short = 1;
+
+    extern "C" {
+        fn eventfd(initval: c_uint, flags: c_int) -> c_int;
+        fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+    }
+
+    /// A Linux eventfd, readable while signalled.
+    pub(super) struct EventFd {
+        file: File,
+    }
+
+    impl EventFd {
+        pub(super) fn new() -> io::Result<EventFd> {
+            let fd = unsafe { eventfd(0, 0) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(EventFd {
+                file: File::from(unsafe { OwnedFd::from_raw_fd(fd) }),
+            })
+        }
+
+        pub(super) fn signal(&self) {
+            // Only fails if the counter would overflow, when it is
+            // signalled anyway.
+            let _ = (&self.file).write_all(&1u64.to_ne_bytes());
+        }
+
+        // Wait until signalled or `timeout` passes, clearing the signal.
+        pub(super) fn wait(&self, timeout: Option<Duration>) {
+            let timeout = timeout.map_or(-1, |t| {
+                t.as_micros().div_ceil(1000).min(c_int::MAX as u128) as c_int
+            });
+            let mut fd = PollFd {
+                fd: self.file.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            };
+            // Errors, such as EINTR, count as spurious wakeups.
+            if unsafe { poll(&mut fd, 1, timeout) } > 0 {
+                let mut count = [0; 8];
+                let _ = (&self.file).read(&mut count);
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+// Noticing streams that stall without reporting an error
+// Licensed under MIT license
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::sdr::RxCallback;
+use super::{is_streaming, m0_state, start_rx_owned, stop_rx, HackRFDevice, HackRFError};
+
+/// Why a `Watchdog` thinks a stream is unhealthy.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stall {
+    /// libhackrf reports the stream has stopped, though it was not asked to.
+    NotStreaming,
+    /// No transfer has reached the callback for this long.
+    NoProgress(Duration),
+    /// The board lost samples because the host fell behind: this many more
+    /// M0 shortfalls since the last check.
+    Shortfalls(u32),
+}
+
+// The checks a watchdog makes, shared with `Supervisor`.
+pub(crate) struct Health {
+    transfers: Arc<AtomicU64>,
+    seen: u64,
+    progressed: Instant,
+    shortfalls: Option<u32>,
+    timeout: Duration,
+}
+
+impl Health {
+    pub(crate) fn new(timeout: Duration) -> Health {
+        Health {
+            transfers: Arc::new(AtomicU64::new(0)),
+            seen: 0,
+            progressed: Instant::now(),
+            shortfalls: None,
+            timeout,
+        }
+    }
+
+    /// Wrap an RX callback so that its transfers count as progress.
+    pub(crate) fn watch(&self, mut callback: RxCallback) -> RxCallback {
+        let transfers = self.transfers.clone();
+        Box::new(move |buf: &[u8]| -> bool {
+            transfers.fetch_add(1, Ordering::Relaxed);
+            callback(buf)
+        })
+    }
+
+    /// Start timing progress afresh, as after (re)starting the stream.
+    pub(crate) fn reset(&mut self) {
+        self.seen = self.transfers.load(Ordering::Relaxed);
+        self.progressed = Instant::now();
+        self.shortfalls = None;
+    }
+
+    pub(crate) fn check(&mut self, device: &mut HackRFDevice) -> Option<Stall> {
+        let stall = self.stall(device);
+        #[cfg(feature = "metrics")]
+        if matches!(stall, Some(Stall::NotStreaming | Stall::NoProgress(_))) {
+            super::metrics::rx_stopped();
+        }
+        stall
+    }
+
+    fn stall(&mut self, device: &mut HackRFDevice) -> Option<Stall> {
+        if !is_streaming(device).unwrap_or(false) {
+            return Some(Stall::NotStreaming);
+        }
+        let transfers = self.transfers.load(Ordering::Relaxed);
+        if transfers != self.seen {
+            self.seen = transfers;
+            self.progressed = Instant::now();
+        } else if self.progressed.elapsed() >= self.timeout {
+            return Some(Stall::NoProgress(self.progressed.elapsed()));
+        }
+        // Older firmware cannot report shortfalls; skip the check.
+        if let Ok(state) = m0_state(device) {
+            let previous = self.shortfalls.replace(state.num_shortfalls);
+            if let Some(previous) = previous.filter(|&p| state.num_shortfalls > p) {
+                return Some(Stall::Shortfalls(state.num_shortfalls - previous));
+            }
+        }
+        None
+    }
+}
+
+fn lock(device: &Mutex<HackRFDevice>) -> MutexGuard<'_, HackRFDevice> {
+    device.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Receives on a board while checking from a background thread that the
+/// stream is still healthy: that libhackrf says it is streaming, that
+/// transfers keep arriving and that the board is not losing samples. A
+/// stream can otherwise stall without any error being reported. Problems
+/// are passed to a callback; for reopening the board after a failure, use
+/// a `Supervisor` instead.
+pub struct Watchdog {
+    device: Arc<Mutex<HackRFDevice>>,
+    stop: Sender<()>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Start receiving on `device` into `callback` and check the stream
+    /// every `interval`, counting it stalled if no transfer arrives for
+    /// `timeout`. `on_stall` runs on the watchdog thread for each problem
+    /// found; if it returns true after `NotStreaming` or `NoProgress`, the
+    /// stream is stopped and started again.
+    pub fn start_rx<F>(
+        mut device: HackRFDevice,
+        interval: Duration,
+        timeout: Duration,
+        callback: RxCallback,
+        mut on_stall: F,
+    ) -> Result<Watchdog, HackRFError>
+    where
+        F: FnMut(Stall) -> bool + Send + 'static,
+    {
+        let mut health = Health::new(timeout);
+        start_rx_owned(&mut device, health.watch(callback))?;
+        let device = Arc::new(Mutex::new(device));
+        let thread_device = device.clone();
+        let (stop, stop_rx_signal) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("hackrf-watchdog".to_string())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stop_rx_signal.recv_timeout(interval) {
+                    let mut device = lock(&thread_device);
+                    let stall = match health.check(&mut device) {
+                        Some(stall) => stall,
+                        None => continue,
+                    };
+                    // Restarting does not help a host that is too slow.
+                    if on_stall(stall) && !matches!(stall, Stall::Shortfalls(_)) {
+                        let _ = restart_rx(&mut device);
+                        health.reset();
+                    }
+                }
+            })?;
+        Ok(Watchdog {
+            device,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// The device, for changing settings while it streams. The watchdog
+    /// waits while it is held.
+    pub fn device(&self) -> MutexGuard<'_, HackRFDevice> {
+        lock(&self.device)
+    }
+
+    /// Stop the watchdog and receiving, and return the device.
+    pub fn stop(mut self) -> Result<HackRFDevice, HackRFError> {
+        self.shutdown();
+        // The watchdog thread has finished, so this is the only reference.
+        let placeholder = Arc::new(Mutex::new(HackRFDevice::unopened()));
+        let mut device = match Arc::try_unwrap(std::mem::replace(&mut self.device, placeholder)) {
+            Ok(device) => device.into_inner().unwrap_or_else(PoisonError::into_inner),
+            Err(_) => unreachable!("watchdog device still shared"),
+        };
+        let result = stop_rx(&mut device);
+        device.rx_callback = None;
+        result.map(|_| device)
+    }
+
+    fn shutdown(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("watchdog thread panicked");
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// Stop and restart the stream on the same handle, with the same callback.
+fn restart_rx(device: &mut HackRFDevice) -> Result<(), HackRFError> {
+    let _ = stop_rx(device);
+    match device.rx_callback.take() {
+        Some(callback) => start_rx_owned(device, callback),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,131 @@
+// Live spectrum rows for waterfall displays
+// Licensed under MIT license
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::fft::{average_db, PowerSpectrum, Spectrum, Window};
+use super::sdr::RxChannel;
+use super::stream::RxStream;
+use super::{ffi, hackrf_error, HackRFDevice, HackRFError};
+
+// Transfers queued for the FFT thread, and rows queued for the caller.
+const BUFFERS: usize = 16;
+const ROWS: usize = 16;
+
+/// Turns live RX into a steady stream of spectrum rows, `frame_rate` a
+/// second, for feeding waterfall displays in GUIs or web frontends. Each
+/// row averages the FFTs, overlapping by half, that fit in its share of
+/// the samples; at frame rates too high for that, consecutive rows' FFTs
+/// overlap instead. FFTs run on a background thread. Rows the caller does
+/// not collect in time are dropped and counted, as are transfers when the
+/// FFTs cannot keep up.
+pub struct Waterfall {
+    rows: Receiver<Spectrum>,
+    dropped: Arc<AtomicU64>,
+    thread: JoinHandle<()>,
+}
+
+impl Waterfall {
+    /// Start receiving on `device` at its current tuning, producing rows of
+    /// `fft_size` bins `frame_rate` times a second, using `window`.
+    pub fn start(
+        device: &mut HackRFDevice,
+        fft_size: usize,
+        frame_rate: f64,
+        window: Window,
+    ) -> Result<Waterfall, HackRFError> {
+        if frame_rate.is_nan() || frame_rate <= 0.0 {
+            return Err(hackrf_error(ffi::HACKRF_ERROR_INVALID_PARAM));
+        }
+        let rate = device.sample_rate_hz;
+        let center_hz = device.freq_hz;
+        let per_frame = ((rate / frame_rate) as usize).max(1);
+        let hop = std::cmp::min((fft_size / 2).max(1), per_frame);
+        let mut fft = PowerSpectrum::with_window(fft_size, window);
+        let stream = RxStream::start(device, BUFFERS)?;
+        let (rows_tx, rows) = mpsc::sync_channel(ROWS);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let thread_dropped = dropped.clone();
+        let thread = thread::Builder::new()
+            .name("hackrf-waterfall".to_string())
+            .spawn(move || {
+                let correction = fft.window_correction_db();
+                let mut pending: Vec<u8> = Vec::new();
+                let mut linear = vec![0.0f64; fft_size];
+                let mut averaged = 0;
+                // Samples into the current frame.
+                let mut elapsed = 0;
+                // Ends when the stream is stopped.
+                while let Some(buf) = stream.recv() {
+                    pending.extend_from_slice(&buf);
+                    stream.recycle(buf);
+                    let mut start = 0;
+                    while start + fft_size * 2 <= pending.len() {
+                        let block = &pending[start..start + fft_size * 2];
+                        for (sum, p) in linear.iter_mut().zip(fft.compute(block)) {
+                            *sum += 10f64.powf(*p as f64 / 10.0);
+                        }
+                        averaged += 1;
+                        start += hop * 2;
+                        elapsed += hop;
+                        if elapsed < per_frame {
+                            continue;
+                        }
+                        elapsed -= per_frame;
+                        let row = Spectrum {
+                            center_hz,
+                            bin_width_hz: rate / fft_size as f64,
+                            power_dbfs: average_db(&linear, averaged, correction),
+                        };
+                        linear.fill(0.0);
+                        averaged = 0;
+                        match rows_tx.try_send(row) {
+                            Ok(()) => (),
+                            Err(TrySendError::Full(_)) => {
+                                thread_dropped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Err(TrySendError::Disconnected(_)) => return,
+                        }
+                    }
+                    pending.drain(..start);
+                }
+            })?;
+        Ok(Waterfall {
+            rows,
+            dropped,
+            thread,
+        })
+    }
+
+    /// Wait for the next row. Returns `None` once the waterfall has stopped.
+    pub fn recv(&self) -> Option<Spectrum> {
+        self.rows.recv().ok()
+    }
+
+    /// As `recv`, giving up after `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Spectrum, RecvTimeoutError> {
+        self.rows.recv_timeout(timeout)
+    }
+
+    /// The next row if one is ready, for polling from a GUI's event loop.
+    pub fn try_recv(&self) -> Option<Spectrum> {
+        self.rows.try_recv().ok()
+    }
+
+    /// Number of rows dropped so far because they were not collected.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Stop receiving. Rows still queued are discarded.
+    pub fn stop(self, device: &mut HackRFDevice) -> Result<(), HackRFError> {
+        // Dropping the RX callback ends the stream, and so the FFT thread.
+        let result = device.stop_rx();
+        self.thread.join().expect("waterfall thread panicked");
+        result
+    }
+}
@@ -0,0 +1,197 @@
+// Writing I/Q as 2-channel WAV/RF64 files
+// Licensed under MIT license
+
+use std::io::{self, Seek, SeekFrom, Write};
+use std::time::SystemTime;
+
+use super::format::SampleFormat;
+use super::time::UtcTime;
+
+// Offsets from the start of the file. A JUNK chunk reserves room for the
+// ds64 chunk in case the file grows past 4GiB and must become RF64.
+const JUNK_OFFSET: u64 = 12;
+const AUXI_OFFSET: u64 = 72;
+const DATA_OFFSET: u64 = 148;
+const HEADER_LEN: u64 = 156;
+
+/// Writes interleaved I/Q samples as a 2-channel WAV file, switching to RF64
+/// on `finish` if the data outgrew plain WAV's 4GiB limit. An `auxi` chunk
+/// records the centre frequency and start/stop times in the layout SDR#,
+/// HDSDR and SpectraVue expect, so recordings open tuned correctly.
+/// Samples are written in the `SampleFormat` given to `new`; cs8 is stored
+/// as WAV's unsigned 8-bit PCM.
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    start: u64,
+    format: SampleFormat,
+    data_len: u64,
+    scratch: Vec<u8>,
+    comment: Option<String>,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Write a WAV header at the current position of `inner`.
+    /// The auxi chunk only has room for frequencies up to 4.29GHz; higher
+    /// centre frequencies are recorded as 0.
+    pub fn new(
+        mut inner: W,
+        format: SampleFormat,
+        sample_rate: u32,
+        center_freq_hz: u64,
+    ) -> io::Result<WavWriter<W>> {
+        let start = inner.stream_position()?;
+        let block_align = format.bytes_per_sample() as u32;
+        let (tag, bits): (u16, u16) = match format {
+            SampleFormat::Cs8 => (1, 8),
+            SampleFormat::Cs16 => (1, 16),
+            SampleFormat::Cf32 => (3, 32),
+        };
+        let now = systemtime(SystemTime::now());
+
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(b"RIFF\0\0\0\0WAVE");
+        header.extend_from_slice(b"JUNK");
+        header.extend_from_slice(&28u32.to_le_bytes());
+        header.extend_from_slice(&[0; 28]);
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&tag.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * block_align).to_le_bytes());
+        header.extend_from_slice(&(block_align as u16).to_le_bytes());
+        header.extend_from_slice(&bits.to_le_bytes());
+        header.extend_from_slice(b"auxi");
+        header.extend_from_slice(&68u32.to_le_bytes());
+        header.extend_from_slice(&now);
+        header.extend_from_slice(&now);
+        let center = if center_freq_hz <= u32::MAX as u64 {
+            center_freq_hz as u32
+        } else {
+            0
+        };
+        // CenterFreq, ADFrequency, IFFrequency, Bandwidth, IQOffset, 4 unused
+        for v in &[center, sample_rate, 0, sample_rate, 0, 0, 0, 0, 0] {
+            header.extend_from_slice(&v.to_le_bytes());
+        }
+        header.extend_from_slice(b"data\0\0\0\0");
+        debug_assert_eq!(header.len() as u64, HEADER_LEN);
+        inner.write_all(&header)?;
+
+        Ok(WavWriter {
+            inner,
+            start,
+            format,
+            data_len: 0,
+            scratch: Vec::new(),
+            comment: None,
+        })
+    }
+
+    /// Store `comment` in a LIST/INFO chunk after the samples when the file
+    /// is finished, where most audio tools show it as the file's comment.
+    pub fn comment(mut self, comment: String) -> WavWriter<W> {
+        self.comment = Some(comment);
+        self
+    }
+
+    /// Number of sample bytes written so far.
+    pub fn data_len(&self) -> u64 {
+        self.data_len
+    }
+
+    /// Fill in the chunk sizes and stop time, and return the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(comment) = self.comment.take() {
+            // Chunks start on even offsets, so pad after odd-length data.
+            if self.data_len % 2 == 1 {
+                self.inner.write_all(&[0])?;
+            }
+            self.inner.write_all(&info_chunk(&comment))?;
+        }
+        self.inner.flush()?;
+        let end = self.inner.stream_position()?;
+        let riff_len = end - self.start - 8;
+        if riff_len <= u32::MAX as u64 {
+            self.patch(4, &(riff_len as u32).to_le_bytes())?;
+            self.patch(DATA_OFFSET + 4, &(self.data_len as u32).to_le_bytes())?;
+        } else {
+            let samples = self.data_len / self.format.bytes_per_sample() as u64;
+            let mut ds64 = Vec::with_capacity(36);
+            ds64.extend_from_slice(b"ds64");
+            ds64.extend_from_slice(&28u32.to_le_bytes());
+            ds64.extend_from_slice(&riff_len.to_le_bytes());
+            ds64.extend_from_slice(&self.data_len.to_le_bytes());
+            ds64.extend_from_slice(&samples.to_le_bytes());
+            ds64.extend_from_slice(&0u32.to_le_bytes());
+            self.patch(0, b"RF64\xff\xff\xff\xff")?;
+            self.patch(JUNK_OFFSET, &ds64)?;
+            self.patch(DATA_OFFSET + 4, &u32::MAX.to_le_bytes())?;
+        }
+        self.patch(AUXI_OFFSET + 8 + 16, &systemtime(SystemTime::now()))?;
+        self.inner.seek(SeekFrom::Start(end))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn patch(&mut self, offset: u64, bytes: &[u8]) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(self.start + offset))?;
+        self.inner.write_all(bytes)
+    }
+}
+
+impl<W: Write + Seek> Write for WavWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.format == SampleFormat::Cs8 {
+            self.scratch.clear();
+            self.scratch.extend(buf.iter().map(|b| b ^ 0x80));
+            self.inner.write_all(&self.scratch)?;
+        } else {
+            self.inner.write_all(buf)?;
+        }
+        self.data_len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A LIST chunk holding `comment` as an INFO ICMT entry.
+fn info_chunk(comment: &str) -> Vec<u8> {
+    let mut text = comment.as_bytes().to_vec();
+    text.push(0);
+    let text_len = text.len() as u32;
+    if text.len() % 2 == 1 {
+        text.push(0);
+    }
+    let mut chunk = Vec::with_capacity(text.len() + 20);
+    chunk.extend_from_slice(b"LIST");
+    chunk.extend_from_slice(&(text.len() as u32 + 12).to_le_bytes());
+    chunk.extend_from_slice(b"INFO");
+    chunk.extend_from_slice(b"ICMT");
+    chunk.extend_from_slice(&text_len.to_le_bytes());
+    chunk.extend_from_slice(&text);
+    chunk
+}
+
+/// Encode `time` as a Windows SYSTEMTIME in UTC.
+fn systemtime(time: SystemTime) -> [u8; 16] {
+    let t = UtcTime::new(time);
+    let fields = [
+        t.year,
+        t.month,
+        t.weekday,
+        t.day,
+        t.hour,
+        t.minute,
+        t.second,
+        t.micros / 1000,
+    ];
+    let mut out = [0; 16];
+    for (chunk, &field) in out.chunks_exact_mut(2).zip(fields.iter()) {
+        chunk.copy_from_slice(&(field as u16).to_le_bytes());
+    }
+    out
+}
@@ -0,0 +1,155 @@
+// Baseband test waveforms for transmitting
+// Licensed under MIT license
+
+use std::f64::consts::PI;
+use std::time::Duration;
+
+/// Amplitude envelope applied to a `chirp`, to trade its range sidelobes
+/// and spectral splatter against energy.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChirpWindow {
+    Rectangular,
+    Hann,
+    /// Flat, with raised cosine tapers over this fraction of the chirp in
+    /// all, half at each end: 0.0 is rectangular and 1.0 is Hann.
+    Tukey(f64),
+}
+
+impl ChirpWindow {
+    // Gain at `x`, 0.0-1.0 through the chirp.
+    fn gain(self, x: f64) -> f64 {
+        let taper = match self {
+            ChirpWindow::Rectangular => return 1.0,
+            ChirpWindow::Hann => 1.0,
+            ChirpWindow::Tukey(taper) => taper.clamp(0.0, 1.0),
+        };
+        let edge = x.min(1.0 - x);
+        if edge >= taper / 2.0 {
+            1.0
+        } else {
+            0.5 - 0.5 * (2.0 * PI * edge / taper).cos()
+        }
+    }
+}
+
+/// Samples in one libhackrf USB transfer. Buffers this long, or a whole
+/// multiple of it, fill TX transfers exactly.
+pub const TRANSFER_SAMPLES: usize = 131_072;
+
+/// A tone `offset_hz` from the centre frequency, `samples` long at
+/// `sample_rate`, with `amplitude` relative to full scale. The offset is
+/// rounded to a whole number of cycles in the buffer, so that it repeats
+/// without a phase jump, as `transmit_repeated` sends it.
+pub fn tone(sample_rate: f64, offset_hz: f64, amplitude: f64, samples: usize) -> Vec<[f32; 2]> {
+    assert!(offset_hz.abs() < sample_rate / 2.0);
+    let cycles = (offset_hz / sample_rate * samples as f64).round();
+    (0..samples)
+        .map(|n| {
+            let phase = 2.0 * PI * cycles * n as f64 / samples as f64;
+            [
+                (amplitude * phase.cos()) as f32,
+                (amplitude * phase.sin()) as f32,
+            ]
+        })
+        .collect()
+}
+
+/// Tones at each of `offsets_hz`, as for `tone`, of equal level and with
+/// Schroeder phases to keep the crest factor low, scaled so the peak is
+/// `amplitude`. For measuring flatness and intermodulation in one go.
+pub fn comb(sample_rate: f64, offsets_hz: &[f64], amplitude: f64, samples: usize) -> Vec<[f32; 2]> {
+    assert!(!offsets_hz.is_empty());
+    let count = offsets_hz.len() as f64;
+    let tones: Vec<(f64, f64)> = offsets_hz
+        .iter()
+        .enumerate()
+        .map(|(k, &offset_hz)| {
+            assert!(offset_hz.abs() < sample_rate / 2.0);
+            let cycles = (offset_hz / sample_rate * samples as f64).round();
+            (cycles, -PI * (k * k) as f64 / count)
+        })
+        .collect();
+    let mut comb: Vec<[f64; 2]> = (0..samples)
+        .map(|n| {
+            tones.iter().fold([0.0; 2], |[i, q], &(cycles, start)| {
+                let phase = 2.0 * PI * cycles * n as f64 / samples as f64 + start;
+                [i + phase.cos(), q + phase.sin()]
+            })
+        })
+        .collect();
+    let peak = comb
+        .iter()
+        .map(|&[i, q]| i.hypot(q))
+        .fold(0.0, f64::max)
+        .max(f64::MIN_POSITIVE);
+    comb.iter_mut()
+        .map(|[i, q]| {
+            [
+                (*i * amplitude / peak) as f32,
+                (*q * amplitude / peak) as f32,
+            ]
+        })
+        .collect()
+}
+
+/// A linear FM chirp sweeping from `start_hz` to `stop_hz` from the centre
+/// frequency over `duration` at `sample_rate`, shaped by `window` with
+/// `amplitude` at its peak, for radar-style ranging and channel sounding.
+/// Either may be above the other, for up or down chirps.
+pub fn chirp(
+    sample_rate: f64,
+    start_hz: f64,
+    stop_hz: f64,
+    duration: Duration,
+    window: ChirpWindow,
+    amplitude: f64,
+) -> Vec<[f32; 2]> {
+    assert!(start_hz.abs() < sample_rate / 2.0 && stop_hz.abs() < sample_rate / 2.0);
+    let samples = (duration.as_secs_f64() * sample_rate).round() as usize;
+    let seconds = samples as f64 / sample_rate;
+    let sweep = (stop_hz - start_hz) / seconds;
+    (0..samples)
+        .map(|n| {
+            let t = n as f64 / sample_rate;
+            let phase = 2.0 * PI * (start_hz * t + sweep * t * t / 2.0);
+            let gain = amplitude * window.gain((n as f64 + 0.5) / samples as f64);
+            [(gain * phase.cos()) as f32, (gain * phase.sin()) as f32]
+        })
+        .collect()
+}
+
+/// I and Q both rising in a straight line from `-amplitude` to `amplitude`
+/// over `samples`, for checking the linearity of the transmit chain.
+pub fn ramp(amplitude: f64, samples: usize) -> Vec<[f32; 2]> {
+    let last = (samples.max(2) - 1) as f64;
+    (0..samples)
+        .map(|n| {
+            let x = (amplitude * (2.0 * n as f64 / last - 1.0)) as f32;
+            [x, x]
+        })
+        .collect()
+}
+
+/// A constant level of `i` and `q` relative to full scale, for measuring
+/// carrier leakage and DC offset.
+pub fn dc(i: f64, q: f64, samples: usize) -> Vec<[f32; 2]> {
+    vec![[i as f32, q as f32]; samples]
+}
+
+/// Convert a waveform to cs8 for transmitting, clipping beyond full scale.
+pub fn to_cs8(waveform: &[[f32; 2]]) -> Vec<u8> {
+    waveform
+        .iter()
+        .flat_map(|&[i, q]| [i, q])
+        .map(|x| (x * 127.0).round().clamp(-128.0, 127.0) as i8 as u8)
+        .collect()
+}
+
+/// Convert a waveform to little-endian cf32 bytes, as `SampleFormat::Cf32`.
+pub fn to_cf32(waveform: &[[f32; 2]]) -> Vec<u8> {
+    waveform
+        .iter()
+        .flat_map(|&[i, q]| [i, q])
+        .flat_map(f32::to_le_bytes)
+        .collect()
+}
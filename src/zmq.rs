@@ -0,0 +1,139 @@
+// ZeroMQ publisher for RX and subscriber for TX
+// Licensed under MIT license
+
+use std::sync::mpsc::{self, TrySendError};
+use std::thread;
+
+use libzmq;
+
+use super::format::SampleFormat;
+use super::tx::wait_tx_finished;
+use super::{ffi, start_rx_owned, start_tx, stop_rx, HackRFDevice, HackRFError, StopHandle};
+
+// Messages queued between the subscriber thread and the TX callback.
+const QUEUE_LEN: usize = 64;
+// How often the subscriber thread checks whether TX has stopped.
+const RECV_TIMEOUT_MS: i32 = 100;
+
+impl From<libzmq::Error> for HackRFError {
+    fn from(err: libzmq::Error) -> HackRFError {
+        HackRFError {
+            errno: ffi::HACKRF_ERROR_OTHER,
+            errstr: err.to_string(),
+            context: None,
+        }
+    }
+}
+
+/// Publishes the RX stream on a ZeroMQ PUB socket, one message per USB
+/// transfer, in any `SampleFormat`. Use `Cf32` for GNU Radio's ZMQ SUB
+/// Source with complex output, and leave its "pass tags" option off.
+/// Subscribers that fall behind lose messages at ZeroMQ's high water mark
+/// instead of stalling the radio.
+pub struct ZmqPublisher {
+    _private: (),
+}
+
+impl ZmqPublisher {
+    /// Bind a PUB socket to `endpoint` (e.g. `"tcp://*:5555"`) and begin
+    /// receiving into it.
+    pub fn start(
+        device: &mut HackRFDevice,
+        endpoint: &str,
+        format: SampleFormat,
+    ) -> Result<ZmqPublisher, HackRFError> {
+        let socket = libzmq::Context::new().socket(libzmq::PUB)?;
+        socket.bind(endpoint)?;
+        let mut converted = Vec::new();
+        let callback = move |rx: &[u8]| -> bool {
+            let out = match format {
+                SampleFormat::Cs8 => rx,
+                _ => {
+                    converted.clear();
+                    format.from_cs8(rx, &mut converted);
+                    &converted
+                }
+            };
+            // PUB sockets drop rather than block when subscribers are slow.
+            let _ = socket.send(out, libzmq::DONTWAIT);
+            true
+        };
+        start_rx_owned(device, Box::new(callback))?;
+        Ok(ZmqPublisher { _private: () })
+    }
+
+    /// Stop receiving and close the socket.
+    pub fn stop(self, device: &mut HackRFDevice) -> Result<(), HackRFError> {
+        let result = stop_rx(device);
+        device.rx_callback = None;
+        result
+    }
+}
+
+/// Connect a SUB socket to `endpoint` (e.g. `"tcp://localhost:5556"`) and
+/// transmit the samples published there, in `format`, until `stop` is
+/// triggered. Pairs with GNU Radio's ZMQ PUB Sink. Silence is sent whenever
+/// no samples are waiting, so the carrier stays up between messages.
+pub fn transmit_zmq(
+    device: &mut HackRFDevice,
+    endpoint: &str,
+    format: SampleFormat,
+    stop: &StopHandle,
+) -> Result<(), HackRFError> {
+    let socket = libzmq::Context::new().socket(libzmq::SUB)?;
+    socket.set_rcvtimeo(RECV_TIMEOUT_MS)?;
+    socket.connect(endpoint)?;
+    socket.set_subscribe(b"")?;
+
+    let (full_tx, full_rx) = mpsc::sync_channel::<Vec<u8>>(QUEUE_LEN);
+    let done = StopHandle::new();
+    let thread_done = done.clone();
+    let subscriber = thread::Builder::new()
+        .name("hackrf-zmq".to_string())
+        .spawn(move || -> Result<(), libzmq::Error> {
+            while !thread_done.is_stopped() {
+                let msg = match socket.recv_bytes(0) {
+                    Ok(msg) => msg,
+                    Err(libzmq::Error::EAGAIN) => continue,
+                    Err(err) => return Err(err),
+                };
+                let mut cs8 = vec![0; msg.len() / format.bytes_per_sample() * 2];
+                let n = format.to_cs8(&msg, &mut cs8);
+                cs8.truncate(n);
+                match full_tx.try_send(cs8) {
+                    Ok(()) | Err(TrySendError::Full(_)) => (),
+                    Err(TrySendError::Disconnected(_)) => break,
+                }
+            }
+            Ok(())
+        })?;
+
+    let mut current: Vec<u8> = Vec::new();
+    let mut pos = 0;
+    let mut callback = |tx: &mut [u8]| -> bool {
+        let mut filled = 0;
+        while filled < tx.len() {
+            if pos == current.len() {
+                match full_rx.try_recv() {
+                    Ok(buf) => {
+                        current = buf;
+                        pos = 0;
+                        continue;
+                    }
+                    Err(_) => break,
+                }
+            }
+            let n = (current.len() - pos).min(tx.len() - filled);
+            tx[filled..filled + n].copy_from_slice(&current[pos..pos + n]);
+            filled += n;
+            pos += n;
+        }
+        tx[filled..].fill(0);
+        true
+    };
+    let result = start_tx(device, &mut callback).and_then(|()| wait_tx_finished(device, stop));
+    done.stop();
+    let received = subscriber.join().expect("zmq subscriber thread panicked");
+    result?;
+    received.map_err(HackRFError::from)
+}